@@ -0,0 +1,15 @@
+//! Synchronous use with the default features, which don't pull in tokio.
+use alphamon_rs::device::cplus::{self, CPlusInterface as _};
+use std::time::Duration;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut iface = cplus::CPlusSerialInterface::connect("COM4")?; // Specify your port path
+
+    let autonomy: Duration = iface.query_ups_autonomy()?.time;
+    let battery_life: Duration = iface.query_ups_battery_life()?.time;
+
+    println!("Runtime on battery: {} min", autonomy.as_secs() / 60);
+    println!("Battery life: {} days", battery_life.as_secs() / 86400);
+
+    Ok(())
+}
@@ -1,13 +1,53 @@
 use crate::Result;
+use crate::device::framing::{FrameScanner, ReplyFormat};
+#[cfg(feature = "usb-hidapi")]
+use crate::device::framing::MessageAssembler;
 use crate::model::FromBytes;
 use crate::model::cplus;
-use std::ffi::CString;
+use serde::Serialize;
+#[cfg(feature = "usb-hidapi")]
+use std::ffi::{CStr, CString};
 use std::io::Write;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 
 /// End byte of CPlus messages.
 const END_BYTE: u8 = b'\r';
 
+/// How long to wait for a reply to a control command. The UPS usually
+/// doesn't reply to control commands at all, so the regular timeout would needlessly stall.
+const CONTROL_REPLY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Default limit of the length of a received message (without the end byte).
+/// The longest reply of the protocol (to the `I` command) is under 40 bytes long.
+const MAX_FRAME_LEN: usize = 64;
+
+/// Most bytes requested from the transport by a single read.
+const READ_CHUNK_LEN: usize = 64;
+
+/// How long to wait for a reply when probing whether a UPS is connected.
+#[cfg(feature = "serial")]
+const PROBE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Time allowed for the UPS to start replying, on top of transmitting the reply,
+/// see [`min_read_timeout`].
+const REPLY_LATENCY: Duration = Duration::from_millis(100);
+
+/// Bits sent per character with the protocol's 8N1 framing: start bit, 8 data bits, stop bit.
+const BITS_PER_CHAR: u64 = 10;
+
+/// Returns the shortest sensible timeout for reading a reply of `frame_len` bytes
+/// (including the terminator) at `baud_rate`: the time it takes to transmit, plus
+/// [`REPLY_LATENCY`]. E.g. the 47 bytes of the status take 196 ms at 2400 baud.
+pub fn min_read_timeout(baud_rate: u32, frame_len: usize) -> Duration {
+    let bits = frame_len as u64 * BITS_PER_CHAR;
+    let nanos = (u128::from(bits) * 1_000_000_000).div_ceil(u128::from(baud_rate.max(1)));
+
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX)) + REPLY_LATENCY
+}
+
+/// Default shortest time between two commands, see [`CPlusGenericInterface::set_command_gap`].
+pub const DEFAULT_COMMAND_GAP: Duration = Duration::from_millis(100);
+
 /// This USB HID feature report continuosly sends a carousel of messages
 const DATA_FEATURE_REPORT: u8 = 5;
 
@@ -17,7 +57,289 @@ const STATUS_MSG_PREFIX: u8 = b'(';
 /// Prefix of the UPSRating message.
 const RATING_MSG_PREFIX: u8 = b'#';
 
+/// How long the HID interface waits for a message to appear in the carousel.
+#[cfg(feature = "usb-hidapi")]
+const HID_READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Suggested window for [`CPlusHidInterface::set_stale_data_window`]. The status values
+/// normally jitter within seconds, so minutes without any change mean the UPS stopped
+/// updating the carousel.
+#[cfg(feature = "usb-hidapi")]
+pub const DEFAULT_STALE_DATA_WINDOW: Duration = Duration::from_secs(300);
+
+/// Default age up to which a status or rating message read from the carousel is reused,
+/// see [`CPlusHidInterface::set_frame_cache_window`].
+#[cfg(feature = "usb-hidapi")]
+pub const DEFAULT_FRAME_CACHE_WINDOW: Duration = Duration::from_secs(2);
+
+/// Default length of the buffer the data feature report is read into, if the device
+/// doesn't describe the report, see [`HidConnectOptions::report_len`].
+/// Fits the long status message of the three-phase models.
+#[cfg(feature = "usb-hidapi")]
+pub const DEFAULT_HID_REPORT_LEN: usize = 64;
+
+/// How long a read of the interrupt endpoint waits for a report, see [`ReadMode::Interrupt`].
+#[cfg(feature = "usb-hidapi")]
+const INTERRUPT_READ_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Interrupt endpoint reads timing out in a row after which the HID interface falls
+/// back to reading the feature report.
+#[cfg(feature = "usb-hidapi")]
+const INTERRUPT_FALLBACK_TIMEOUTS: u32 = 4;
+
+/// Default number of times a feature report read failing with a transient HID error
+/// is retried, see [`CPlusHidInterface::set_read_retries`].
+#[cfg(feature = "usb-hidapi")]
+pub const DEFAULT_HID_READ_RETRIES: u32 = 2;
+
+/// Default pause before retrying a failed feature report read.
+#[cfg(feature = "usb-hidapi")]
+pub const DEFAULT_HID_READ_RETRY_DELAY: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy)]
+/// Options for [`CPlusInterface::graceful_shutdown`].
+pub struct GracefulShutdownOptions {
+    /// Delay after which the UPS shuts its output off.
+    pub delay: cplus::ShutdownDelay,
+    /// Shut down even if utility power is present.
+    pub force: bool,
+    /// How long to wait for the UPS to report the pending shutdown.
+    pub confirm_timeout: Duration,
+    /// How often to query the UPS status while waiting for the confirmation.
+    pub poll_interval: Duration,
+}
+
+impl GracefulShutdownOptions {
+    /// Creates the options with the given delay, without forcing the shutdown
+    /// and waiting up to 10 seconds for the confirmation.
+    pub fn new(delay: cplus::ShutdownDelay) -> Self {
+        Self {
+            delay,
+            force: false,
+            confirm_timeout: Duration::from_secs(10),
+            poll_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// Describes what [`CPlusInterface::graceful_shutdown`] did.
+pub struct GracefulShutdownReport {
+    /// The autonomy reported before the shutdown, if the UPS supports the query.
+    pub autonomy: Option<Duration>,
+    /// Whether utility power was failing before the shutdown.
+    pub utility_fail: bool,
+    /// Whether the shutdown command was sent. It isn't sent if utility power is present,
+    /// unless the shutdown is forced.
+    pub shutdown_issued: bool,
+    /// Whether the UPS reported the pending shutdown within the confirmation timeout.
+    pub confirmed: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
+/// Result of [`CPlusInterface::health_check`].
+pub struct HealthReport {
+    /// Whether none of the alarm conditions below is present.
+    pub ok: bool,
+    /// How long the status query took.
+    pub latency: Duration,
+    pub utility_fail: bool,
+    pub battery_low: bool,
+    pub battery_abnormal: bool,
+}
+
+impl HealthReport {
+    fn new(latency: Duration, status: &cplus::UPSStatus) -> Self {
+        Self {
+            ok: !(status.utility_fail() || status.battery_low() || status.battery_abnormal()),
+            latency,
+            utility_fail: status.utility_fail(),
+            battery_low: status.battery_low(),
+            battery_abnormal: status.battery_abnormal(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+/// Latency statistics of a command, see [`QueryMetrics`].
+pub struct CommandMetrics {
+    /// Number of successful queries.
+    pub count: u64,
+    /// Number of failed queries, which aren't included in the latencies.
+    pub errors: u64,
+    /// Latency of the last successful query.
+    pub last: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    /// Sum of the latencies of the successful queries.
+    pub total: Duration,
+}
+
+impl CommandMetrics {
+    /// Mean latency of the successful queries, `None` if there were none.
+    pub fn mean(&self) -> Option<Duration> {
+        u32::try_from(self.count).ok().and_then(|count| self.total.checked_div(count))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+/// Latencies of the commands sent by an interface, from writing the command
+/// to parsing the reply.
+pub struct QueryMetrics {
+    /// Metrics by command, as sent (without the end byte).
+    commands: std::collections::BTreeMap<String, CommandMetrics>,
+}
+
+impl QueryMetrics {
+    /// Returns the metrics of `command`, e.g. `"Q1"`.
+    pub fn get(&self, command: &str) -> Option<&CommandMetrics> {
+        self.commands.get(command)
+    }
+
+    /// Iterates over the metrics of the commands sent so far, ordered by command.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &CommandMetrics)> {
+        self.commands.iter().map(|(command, metrics)| (command.as_str(), metrics))
+    }
+
+    pub fn reset(&mut self) {
+        self.commands.clear();
+    }
+
+    fn record<R>(&mut self, command: &[u8], latency: Duration, result: &Result<R>) {
+        let command = String::from_utf8_lossy(command);
+
+        let metrics = match self.commands.get_mut(command.as_ref()) {
+            Some(metrics) => metrics,
+            None => self.commands.entry(command.into_owned()).or_default(),
+        };
+
+        if result.is_err() {
+            metrics.errors += 1;
+            return;
+        }
+
+        metrics.min = if metrics.count == 0 { latency } else { metrics.min.min(latency) };
+        metrics.max = metrics.max.max(latency);
+        metrics.last = latency;
+        metrics.total += latency;
+        metrics.count += 1;
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// When an interface last got a successful reply, overall and by command.
+struct LastSuccess {
+    /// Also kept as an `Instant`, so the staleness isn't affected by changes of the clock.
+    at: Option<(Instant, SystemTime)>,
+    /// By command, as sent (without the end byte).
+    commands: std::collections::BTreeMap<String, SystemTime>,
+}
+
+impl LastSuccess {
+    fn record<R>(&mut self, command: &[u8], result: &Result<R>) {
+        if result.is_err() {
+            return;
+        }
+
+        let now = SystemTime::now();
+
+        self.at = Some((Instant::now(), now));
+        self.commands.insert(String::from_utf8_lossy(command).into_owned(), now);
+    }
+
+    fn get(&self) -> Option<SystemTime> {
+        self.at.map(|(_, at)| at)
+    }
+
+    fn command(&self, command: &str) -> Option<SystemTime> {
+        self.commands.get(command).copied()
+    }
+
+    fn staleness(&self) -> Option<Duration> {
+        self.at.map(|(at, _)| at.elapsed())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+/// An operation of [`CPlusInterface`] which an interface may not support.
+pub enum Capability {
+    Status,
+    ExtraPowerInfo,
+    Alarm,
+    Autonomy,
+    BatteryLife,
+    Info,
+    Rating,
+    TestResult,
+    Shutdown,
+    ShutdownAndRestore,
+    CancelShutdown,
+    SelfTest,
+    TimedSelfTest,
+    TestUntilBatteryLow,
+    CancelTest,
+    ToggleBeeper,
+    Outlet,
+}
+
+impl Capability {
+    /// Every capability, all supported by the serial interface.
+    pub const ALL: &'static [Self] = &[
+        Self::Status,
+        Self::ExtraPowerInfo,
+        Self::Alarm,
+        Self::Autonomy,
+        Self::BatteryLife,
+        Self::Info,
+        Self::Rating,
+        Self::TestResult,
+        Self::Shutdown,
+        Self::ShutdownAndRestore,
+        Self::CancelShutdown,
+        Self::SelfTest,
+        Self::TimedSelfTest,
+        Self::TestUntilBatteryLow,
+        Self::CancelTest,
+        Self::ToggleBeeper,
+        Self::Outlet,
+    ];
+
+    /// Runs the operation with this capability, returning whether it's supported
+    /// (that is, doesn't fail with [`crate::Error::Unsupported`]).
+    #[cfg(test)]
+    pub(crate) fn attempt(self, iface: &mut dyn CPlusInterface) -> bool {
+        let delay = || cplus::ShutdownDelay::from_minutes(1).unwrap();
+
+        let result = match self {
+            Self::Status => iface.query_ups_status().map(drop),
+            Self::ExtraPowerInfo => iface.query_extra_power_info().map(drop),
+            Self::Alarm => iface.query_alarm().map(drop),
+            Self::Autonomy => iface.query_ups_autonomy().map(drop),
+            Self::BatteryLife => iface.query_ups_battery_life().map(drop),
+            Self::Info => iface.query_ups_info().map(drop),
+            Self::Rating => iface.query_ups_rating().map(drop),
+            Self::TestResult => iface.query_test_result().map(drop),
+            Self::Shutdown => iface.shutdown(delay()).map(drop),
+            Self::ShutdownAndRestore => iface.shutdown_and_restore(delay(), 1).map(drop),
+            Self::CancelShutdown => iface.cancel_shutdown().map(drop),
+            Self::SelfTest => iface.start_self_test().map(drop),
+            Self::TimedSelfTest => iface.start_timed_self_test(1).map(drop),
+            Self::TestUntilBatteryLow => iface.start_test_until_battery_low().map(drop),
+            Self::CancelTest => iface.cancel_test().map(drop),
+            Self::ToggleBeeper => iface.toggle_beeper().map(drop),
+            Self::Outlet => iface.set_outlet(cplus::Outlet::A, true).map(drop),
+        };
+
+        !matches!(result, Err(crate::Error::Unsupported { .. }))
+    }
+}
+
 /// Generic interface for the Continuity Plus UPS communication.
+///
+/// Every interface supports [`CPlusInterface::query_ups_status`]. The other operations
+/// are optional: an interface which can't perform one (e.g. the USB HID interface, which
+/// has no extra power info or alarm query) fails with [`crate::Error::Unsupported`]
+/// instead, so generic code can skip it.
 pub trait CPlusInterface {
     /// Queries the input/output voltage, load percentage, input AC frequency,
     /// battery capacity temperature and the UPS status and errors/warnings (battery, etc.)
@@ -25,10 +347,14 @@ pub trait CPlusInterface {
 
     /// Queries the UPS output AC frequency, per-battery voltage, UPS load in watts,
     /// UPS error code, and the UPS load current in amperes.
+    ///
+    /// Optional, not supported over USB HID.
     fn query_extra_power_info(&mut self) -> Result<cplus::ExtraPowerInfoResponse>;
 
     /// Queries the UPS for alarm notifications: whether the inverter is on or off,
     /// or if the UPS itself is in the state of an alarm.
+    ///
+    /// Optional, not supported over USB HID.
     fn query_alarm(&mut self) -> Result<cplus::AlarmInquiryResponse>;
 
     /// Queries the UPS for the length of time during which the UPS can
@@ -43,239 +369,8437 @@ pub trait CPlusInterface {
 
     /// Queries the UPS for info about its rated output voltage, current, frequency and battery voltage.
     fn query_ups_rating(&mut self) -> Result<cplus::UPSRating>;
-}
 
-#[cfg(feature = "serial")]
-#[derive(Debug)]
-/// Serial port interface for the Continuity Plus UPSes.
-pub struct CPlusSerialInterface {
-    port: Box<dyn serialport::SerialPort>,
-}
+    /// Queries the UPS for the result of the last battery self-test.
+    fn query_test_result(&mut self) -> Result<cplus::TestResultResponse>;
 
-#[cfg(feature = "serial")]
-impl CPlusSerialInterface {
-    /// Connects to the serial port at the provided path with a 5s timeout.
-    pub fn connect(port_path: &str) -> Result<Self> {
-        let mut port = serialport::new(port_path, cplus::SERIAL_BAUD_RATE)
-            .timeout(Duration::from_millis(5000))
-            .open()?;
+    /// Queries the UPS for its firmware version.
+    ///
+    /// The protocol has no dedicated firmware command, so the version is taken
+    /// from the version field of [`CPlusInterface::query_ups_info`].
+    fn query_firmware_version(&mut self) -> Result<cplus::FirmwareVersion> {
+        let info = self.query_ups_info()?;
 
-        port.write_data_terminal_ready(true)?;
+        cplus::FirmwareVersion::from_bytes(info.version.as_bytes())
+    }
+
+    /// Checks that the UPS responds by querying its status (the lightest query),
+    /// measuring the round trip and reporting the alarm conditions.
+    ///
+    /// Fails if the UPS doesn't respond in time, like [`CPlusInterface::query_ups_status`].
+    fn health_check(&mut self) -> Result<HealthReport> {
+        let started = Instant::now();
+        let status = self.query_ups_status()?;
 
-        Ok(CPlusSerialInterface { port })
+        Ok(HealthReport::new(started.elapsed(), &status.ups_status))
     }
 
-    /// Writes data to the serial port along with the end byte.
-     fn write_data(&mut self, msg: &[u8]) -> Result<()> {
-        self.port.write_all(msg)?;
-        self.port.write_all(&[END_BYTE])?;
+    /// Queries the status, extra power info, autonomy and rating of the UPS at once.
+    ///
+    /// Fails only if the status query fails. The other queries failing (or not being
+    /// supported) leaves their fields empty, with the errors listed in the snapshot.
+    ///
+    /// The queries the interface doesn't [support](CPlusInterface::supports) aren't made.
+    fn query_snapshot(&mut self) -> Result<cplus::UpsSnapshot> {
+        let queried_at = std::time::SystemTime::now();
+        let mut snapshot = cplus::UpsSnapshot::new(self.query_ups_status()?, queried_at);
 
-        trace!("Wrote msg {:?}", String::from_utf8_lossy(msg));
+        if self.supports(Capability::ExtraPowerInfo) {
+            snapshot.extra_power_info = snapshot.section("extra_power_info", self.query_extra_power_info());
+        }
+
+        if self.supports(Capability::Autonomy) {
+            snapshot.autonomy = snapshot.section("autonomy", self.query_ups_autonomy());
+        }
+
+        if self.supports(Capability::Rating) {
+            snapshot.rating = snapshot.section("rating", self.query_ups_rating());
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Lists the operations this interface supports, the others fail with
+    /// [`crate::Error::Unsupported`]. All of them by default.
+    fn capabilities(&self) -> &'static [Capability] {
+        Capability::ALL
+    }
+
+    /// Whether this interface supports the operations with `capability`.
+    fn supports(&self, capability: Capability) -> bool {
+        self.capabilities().contains(&capability)
+    }
+
+    /// Shuts the UPS output off after the given delay, whether utility power is present or not.
+    /// If the UPS shuts down completely, it restarts automatically once utility power returns.
+    fn shutdown(&mut self, delay: cplus::ShutdownDelay) -> Result<cplus::CommandAck>;
+
+    /// Shuts the UPS output off after the given delay and turns it back on
+    /// after `restore_minutes` (1..=9999) minutes.
+    fn shutdown_and_restore(&mut self, shutdown: cplus::ShutdownDelay, restore_minutes: u16) -> Result<cplus::CommandAck>;
+
+    /// Cancels a pending shutdown (or shutdown and restore) and turns the UPS output back on,
+    /// unless the UPS is in an error state or already completely off.
+    /// `shutdown_active` in the UPS status is cleared once the shutdown is cancelled.
+    fn cancel_shutdown(&mut self) -> Result<cplus::CommandAck>;
+
+    /// Starts a 10 second battery test, after which the UPS returns to utility power
+    /// (or earlier, if the battery runs low).
+    ///
+    /// While the test runs, `test_in_progress` is set in the UPS status, so its progress
+    /// can be followed by polling [`CPlusInterface::query_ups_status`]:
+    ///
+    /// ```no_run
+    /// # use alphamon_rs::device::cplus::{CPlusInterface, CPlusSerialInterface};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut iface = CPlusSerialInterface::connect("/dev/ttyUSB0")?;
+    ///
+    /// iface.start_self_test()?;
+    ///
+    /// while iface.query_ups_status()?.ups_status.test_in_progress() {
+    ///     std::thread::sleep(std::time::Duration::from_secs(1));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn start_self_test(&mut self) -> Result<cplus::CommandAck>;
+
+    /// Starts a battery test lasting `minutes` (1..=99) minutes. The UPS returns to
+    /// utility power immediately if the battery runs low during the test.
+    fn start_timed_self_test(&mut self, minutes: u8) -> Result<cplus::CommandAck>;
+
+    /// Starts a battery test which runs until the battery is low, after which the UPS
+    /// returns to utility power. The test can take a long time, its completion can be
+    /// detected by polling [`CPlusInterface::query_ups_status`]:
+    ///
+    /// ```no_run
+    /// # use alphamon_rs::device::cplus::{CPlusInterface, CPlusSerialInterface};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mut iface = CPlusSerialInterface::connect("/dev/ttyUSB0")?;
+    ///
+    /// iface.start_test_until_battery_low()?;
+    ///
+    /// loop {
+    ///     let status = iface.query_ups_status()?.ups_status;
+    ///
+    ///     if !status.test_in_progress() {
+    ///         println!("Test finished, battery low: {}", status.battery_low());
+    ///         break;
+    ///     }
+    ///
+    ///     std::thread::sleep(std::time::Duration::from_secs(30));
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    fn start_test_until_battery_low(&mut self) -> Result<cplus::CommandAck>;
+
+    /// Cancels all running battery tests, returning the UPS to its previous state immediately.
+    fn cancel_test(&mut self) -> Result<cplus::CommandAck>;
+
+    /// Toggles the UPS beeper and returns whether it's on afterwards.
+    ///
+    /// On offline UPSes, the beeper can only be toggled when AC power failed and the
+    /// battery isn't low yet, on online UPSes only during an alarm condition.
+    fn toggle_beeper(&mut self) -> Result<bool>;
+
+    /// Silences the beeper if it's on, doing nothing otherwise.
+    ///
+    /// This is temporary: depending on the firmware, the beeper comes back on with the next alarm.
+    /// The protocol has no command to disable the beeper permanently.
+    fn mute_beeper(&mut self) -> Result<()> {
+        if self.query_ups_status()?.ups_status.beeper_on() {
+            self.toggle_beeper()?;
+        }
 
         Ok(())
     }
 
-    /// Reads data from the serial port until an end byte (CR) is encountered.
-     fn read_data(&mut self) -> Result<Vec<u8>> {
-        let mut buf = vec![];
+    /// Switches the programmable outlet on or off immediately.
+    fn set_outlet(&mut self, outlet: cplus::Outlet, on: bool) -> Result<cplus::CommandAck>;
 
-        trace!("Reading buffer");
+    /// Shuts the UPS output off after checking the autonomy and utility power state,
+    /// then waits until the UPS reports the pending shutdown.
+    ///
+    /// The shutdown is only issued while utility power is failing, unless `opts.force` is set.
+    fn graceful_shutdown(&mut self, opts: GracefulShutdownOptions) -> Result<GracefulShutdownReport> {
+        let autonomy = match self.query_ups_autonomy() {
+            Ok(autonomy) => Some(autonomy.time),
+            Err(e) => {
+                debug!("Couldn't query autonomy before shutdown: {e}");
+                None
+            }
+        };
+
+        let utility_fail = self.query_ups_status()?.ups_status.utility_fail();
+
+        let mut report = GracefulShutdownReport {
+            autonomy,
+            utility_fail,
+            shutdown_issued: false,
+            confirmed: false,
+        };
+
+        if !utility_fail && !opts.force {
+            info!("Utility power is present, not shutting down");
+            return Ok(report);
+        }
+
+        self.shutdown(opts.delay)?;
+        report.shutdown_issued = true;
+
+        report.confirmed = self.verify_shutdown_pending(opts.confirm_timeout, opts.poll_interval)?;
+
+        Ok(report)
+    }
+
+    /// Polls the UPS status every `poll_interval` until it reports a pending shutdown.
+    ///
+    /// Returns `Ok(false)` if the shutdown wasn't reported within `timeout`.
+    /// The status is queried at least once, even with a zero `timeout`.
+    fn verify_shutdown_pending(&mut self, timeout: Duration, poll_interval: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
 
-        let mut byte = [0u8];
+        loop {
+            let polled_at = Instant::now();
 
-        // Inefficient. Though it doesn't matter for small amounts of data.
-        // In addition, the `read_exact` function handles some benign I/O errors in itself.
-        while self.port.read_exact(&mut byte).is_ok() {
-            if byte[0] == END_BYTE {
-                break;
+            if self.query_ups_status()?.ups_status.shutdown_active() {
+                return Ok(true);
             }
 
-            buf.push(byte[0]);
+            let next_poll = polled_at + poll_interval;
+
+            if next_poll > deadline {
+                return Ok(false);
+            }
+
+            std::thread::sleep(next_poll.saturating_duration_since(Instant::now()));
         }
+    }
+}
 
-        trace!("Read buffer {:?}\n", String::from_utf8_lossy(&buf));
+/// Interfaces able to send arbitrary commands, see [`CPlusGenericInterface::raw_query`].
+pub trait RawQuery {
+    /// Sends `query` and returns the reply, without the end byte.
+    fn raw_query(&mut self, query: &[u8]) -> Result<Vec<u8>>;
+}
 
-        Ok(buf)
+#[derive(Debug, Clone, Copy)]
+/// When and how often [`RetryingInterface`] retries a failed query.
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub attempts: u32,
+    /// Delay between the attempts.
+    pub backoff: Duration,
+    /// Decides whether a query failing with the error is retried.
+    pub retry_on: fn(&crate::Error) -> bool,
+}
+
+impl Default for RetryPolicy {
+    /// 3 attempts, 100 ms apart, retrying on [`RetryPolicy::is_transient`] errors.
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(100),
+            retry_on: Self::is_transient,
+        }
     }
+}
 
-    /// Queries - writes a command and awaits its response
-     fn raw_query(&mut self, query: &[u8]) -> Result<Vec<u8>> {
-        trace!("Querying with message {:?}", String::from_utf8_lossy(query));
+impl RetryPolicy {
+    /// Whether the error can be caused by a garbled, incomplete or missing reply,
+    /// so a retry may succeed.
+    pub fn is_transient(error: &crate::Error) -> bool {
+        match error {
+            crate::Error::InvalidFormat { .. }
+            | crate::Error::InvalidLength { .. }
+            | crate::Error::InvalidStatusBit { .. }
+            | crate::Error::Timeout { .. }
+            | crate::Error::FrameTooLong { .. }
+            | crate::Error::IncompleteResponse { .. }
+            | crate::Error::FloatParse(_)
+            | crate::Error::IntParse(_)
+            | crate::Error::InvalidParameterLength(_) => true,
+            crate::Error::Io(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock | std::io::ErrorKind::UnexpectedEof
+            ),
+            _ => false,
+        }
+    }
+}
 
-        // A synchronization error can cause a partial packet to be in the input buffer
-        self.port.clear(serialport::ClearBuffer::All)?;
+#[derive(Debug)]
+/// Wraps an interface, retrying the queries according to a [`RetryPolicy`].
+///
+/// Control commands are sent only once, as repeating them isn't always safe
+/// (e.g. toggling the beeper twice).
+pub struct RetryingInterface<T> {
+    inner: T,
+    policy: RetryPolicy,
+}
 
-        self.write_data(query)?;
-        let output = self.read_data()?;
+impl<T: CPlusInterface> RetryingInterface<T> {
+    pub fn new(inner: T, policy: RetryPolicy) -> Self {
+        Self { inner, policy }
+    }
 
-        Ok(output)
+    /// Returns a reference to the wrapped interface.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
     }
 
-    /// Queries the device and returns the processed response as a struct
-    fn processed_query<T>(&mut self, query: &[u8]) -> Result<T>
-    where
-        T: FromBytes,
-        <T as FromBytes>::Err: Into<crate::Error>,
-    {
-        let raw_query = self.raw_query(query)?;
+    /// Returns the wrapped interface.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
 
-        // Remove the start byte
-        let Some(processed_bytes) = &raw_query.get(1..) else {
-            return Err(crate::Error::InvalidFormat);
-        };
+    fn retry<R>(&mut self, operation: &str, query: impl Fn(&mut T) -> Result<R>) -> Result<R> {
+        let mut attempt = 1;
+
+        loop {
+            let error = match query(&mut self.inner) {
+                Ok(response) => return Ok(response),
+                Err(e) => e,
+            };
+
+            if !(self.policy.retry_on)(&error) {
+                return Err(error);
+            }
+
+            if attempt >= self.policy.attempts {
+                return Err(match attempt {
+                    1 => error,
+                    attempts => crate::Error::RetriesExhausted { attempts, source: Box::new(error) },
+                });
+            }
+
+            debug!("{operation} failed (attempt {attempt}/{}): {error}, retrying", self.policy.attempts);
 
-        T::from_bytes(processed_bytes).map_err(|e| e.into())
+            std::thread::sleep(self.policy.backoff);
+            attempt += 1;
+        }
     }
 }
 
-#[cfg(feature = "serial")]
-impl CPlusInterface for CPlusSerialInterface {
+impl<T: CPlusInterface + RawQuery> RawQuery for RetryingInterface<T> {
+    /// Sent only once, as the command may be a control command.
+    fn raw_query(&mut self, query: &[u8]) -> Result<Vec<u8>> {
+        self.inner.raw_query(query)
+    }
+}
+
+impl<T: CPlusInterface> CPlusInterface for RetryingInterface<T> {
+    fn capabilities(&self) -> &'static [Capability] {
+        self.inner.capabilities()
+    }
+
     fn query_ups_status(&mut self) -> Result<cplus::StatusInquiryResponse> {
-        self.processed_query(cplus::CMD_STATUS_INQUIRY)
+        self.retry("query_ups_status", T::query_ups_status)
     }
 
     fn query_extra_power_info(&mut self) -> Result<cplus::ExtraPowerInfoResponse> {
-        self.processed_query(cplus::CMD_EXTRA_POWER_PARAMETERS_INFO)
-            
+        self.retry("query_extra_power_info", T::query_extra_power_info)
     }
 
     fn query_alarm(&mut self) -> Result<cplus::AlarmInquiryResponse> {
-        self.processed_query(cplus::CMD_ALARM_INQUIRY)
+        self.retry("query_alarm", T::query_alarm)
     }
 
     fn query_ups_autonomy(&mut self) -> Result<cplus::AutonomyResponse> {
-        self.processed_query(cplus::CMD_AUTONOMY)
+        self.retry("query_ups_autonomy", T::query_ups_autonomy)
     }
 
     fn query_ups_battery_life(&mut self) -> Result<cplus::BatteryLifeResponse> {
-        self.processed_query(cplus::CMD_BATTERY_LIFE)
+        self.retry("query_ups_battery_life", T::query_ups_battery_life)
     }
 
     fn query_ups_info(&mut self) -> Result<cplus::UPSInformation> {
-        self.processed_query(cplus::CMD_UPS_INFORMATION)
+        self.retry("query_ups_info", T::query_ups_info)
     }
 
     fn query_ups_rating(&mut self) -> Result<cplus::UPSRating> {
-        self.processed_query(cplus::CMD_RATING_INFORMATION)
+        self.retry("query_ups_rating", T::query_ups_rating)
     }
-}
-
-#[cfg(feature = "usb-hidapi")]
-/// USB HID interface for the Continuity Plus UPSes.
-pub struct CPlusHidInterface {
-    device: hidapi::HidDevice,
-}
 
-#[cfg(feature = "usb-hidapi")]
-impl CPlusHidInterface {
-    /// Connects to the given HID device at `path`.
-    pub fn connect_with_path(path: String) -> Result<Self> {
-        let api = hidapi::HidApi::new()?;
+    fn query_test_result(&mut self) -> Result<cplus::TestResultResponse> {
+        self.retry("query_test_result", T::query_test_result)
+    }
 
-        let path = path.replace("\0", "");
+    fn query_firmware_version(&mut self) -> Result<cplus::FirmwareVersion> {
+        self.retry("query_firmware_version", T::query_firmware_version)
+    }
 
-        // All null bytes were removed by the previous call.
-        let path = CString::new(path).unwrap();
+    fn health_check(&mut self) -> Result<HealthReport> {
+        self.retry("health_check", T::health_check)
+    }
 
-        let device = api.open_path(path.as_c_str())?;
+    /// Retried as a whole if the status query fails, the other sections failing
+    /// are reported in the snapshot.
+    fn query_snapshot(&mut self) -> Result<cplus::UpsSnapshot> {
+        self.retry("query_snapshot", T::query_snapshot)
+    }
 
-        Ok(Self { device })
+    fn shutdown(&mut self, delay: cplus::ShutdownDelay) -> Result<cplus::CommandAck> {
+        self.inner.shutdown(delay)
     }
 
-    /// Connects to the given HID device with the given `vid` and `pid`.
-    pub fn connect_with_vid_pid(vid: u16, pid: u16) -> Result<Self> {
-        let api = hidapi::HidApi::new()?;
+    fn shutdown_and_restore(&mut self, shutdown: cplus::ShutdownDelay, restore_minutes: u16) -> Result<cplus::CommandAck> {
+        self.inner.shutdown_and_restore(shutdown, restore_minutes)
+    }
 
-        let device = api.open(vid, pid)?;
+    fn cancel_shutdown(&mut self) -> Result<cplus::CommandAck> {
+        self.inner.cancel_shutdown()
+    }
 
-        Ok(Self { device })
+    fn start_self_test(&mut self) -> Result<cplus::CommandAck> {
+        self.inner.start_self_test()
     }
 
-    /// Reads raw data from the data feature report.
-    /// The buffer is expected to be at least 2 bytes long.
-    fn read_raw_data(&mut self, buf: &mut [u8]) -> Result<usize> {
-        let report_id = buf.get_mut(0).ok_or(crate::Error::BufferTooSmall {
-            expected: 1,
-            provided: 0,
-        })?;
+    fn start_timed_self_test(&mut self, minutes: u8) -> Result<cplus::CommandAck> {
+        self.inner.start_timed_self_test(minutes)
+    }
 
-        *report_id = DATA_FEATURE_REPORT;
+    fn start_test_until_battery_low(&mut self) -> Result<cplus::CommandAck> {
+        self.inner.start_test_until_battery_low()
+    }
 
-        // The doc of this function says that,
-        // "Upon return, the first byte will still contain the Report ID,
-        // and the report data will start in buf[1]."
-        // Which doesn't apply for this UPS, the data starts in buf[0]
-        let read = self.device.get_feature_report(buf)?;
+    fn cancel_test(&mut self) -> Result<cplus::CommandAck> {
+        self.inner.cancel_test()
+    }
 
-        Ok(read)
+    fn toggle_beeper(&mut self) -> Result<bool> {
+        self.inner.toggle_beeper()
     }
 
-    /// Reads data from the feature report. If a `start_idx` is provided,
-    /// the function will read until a complete message with the given `start_idx` is found.
-    /// The `start_idx` represents the type of message received.
-    /// 
-    /// Returns the position of the end byte (a carriage return character).
-    fn read_data(&mut self, buf: &mut [u8], start_idx: Option<u8>) -> Result<usize> {
-        let cr_idx = loop {
-            self.read_raw_data(buf)?;
+    fn set_outlet(&mut self, outlet: cplus::Outlet, on: bool) -> Result<cplus::CommandAck> {
+        self.inner.set_outlet(outlet, on)
+    }
+}
 
-            let Some(cr_idx) = buf.iter().position(|&b| b == END_BYTE) else {
-                continue;
-            };
+#[derive(Debug)]
+/// Interface shared between threads, locking it for the duration of each call.
+///
+/// Cloning it yields another handle to the same interface, so e.g. a metrics thread and
+/// a shutdown watchdog can both query one UPS without their commands interleaving.
+/// [`SharedInterface::try_lock`] fails instead of waiting if the interface is in use.
+pub struct SharedInterface<T>(std::sync::Arc<std::sync::Mutex<T>>);
 
-            if (cr_idx + 1 == buf.len() || *buf.get(cr_idx + 1).unwrap() == b'\0') 
-                && (start_idx.is_none() || buf.first() == start_idx.as_ref())
-            {
-                break cr_idx
-            }
-        };
+impl<T> Clone for SharedInterface<T> {
+    fn clone(&self) -> Self {
+        Self(std::sync::Arc::clone(&self.0))
+    }
+}
 
-        Ok(cr_idx)
+impl<T> SharedInterface<T> {
+    pub fn new(inner: T) -> Self {
+        Self(std::sync::Arc::new(std::sync::Mutex::new(inner)))
     }
 
-    fn read_processed_data<T>(&mut self, start_idx: Option<u8>) -> Result<T> 
-        where T: FromBytes, <T as FromBytes>::Err: Into<crate::Error> 
-    {
-        let mut buf = vec![0u8; 48];
+    /// Locks the interface, waiting until the current call on another thread finishes.
+    ///
+    /// Several calls can be made on the returned guard without another thread cutting in.
+    pub fn lock(&self) -> Result<std::sync::MutexGuard<'_, T>> {
+        self.0.lock().map_err(|_| poisoned())
+    }
 
-        let cr_idx = self.read_data(&mut buf, start_idx)?;
+    /// Locks the interface, failing with [`std::io::ErrorKind::WouldBlock`] if it's in use.
+    pub fn try_lock(&self) -> Result<std::sync::MutexGuard<'_, T>> {
+        self.0.try_lock().map_err(|e| match e {
+            std::sync::TryLockError::WouldBlock => std::io::Error::from(std::io::ErrorKind::WouldBlock).into(),
+            std::sync::TryLockError::Poisoned(_) => poisoned(),
+        })
+    }
+}
 
-        // First byte is the start_idx, the message ends with the end byte (carriage return)
-        let Some(processed_bytes) = &buf.get(1..cr_idx) else {
-            return Err(crate::Error::InvalidFormat);
-        };
+/// Error returned by a [`SharedInterface`] after a thread panicked while using it,
+/// possibly in the middle of a command.
+fn poisoned() -> crate::Error {
+    std::io::Error::other("a thread panicked while using the shared interface").into()
+}
 
-        T::from_bytes(processed_bytes).map_err(|e| e.into())
+impl<T: RawQuery> RawQuery for SharedInterface<T> {
+    fn raw_query(&mut self, query: &[u8]) -> Result<Vec<u8>> {
+        self.lock()?.raw_query(query)
     }
 }
 
-#[cfg(feature = "usb-hidapi")]
-impl CPlusInterface for CPlusHidInterface {
-     fn query_ups_status(&mut self) -> Result<cplus::StatusInquiryResponse> {
-        self.read_processed_data(Some(STATUS_MSG_PREFIX))
+impl<T: CPlusInterface> CPlusInterface for SharedInterface<T> {
+    fn capabilities(&self) -> &'static [Capability] {
+        // The capabilities don't change, even if a thread panicked while using the interface
+        self.0.lock().unwrap_or_else(std::sync::PoisonError::into_inner).capabilities()
     }
-    
-     fn query_ups_rating(&mut self) -> Result<cplus::UPSRating> {
-        self.read_processed_data(Some(RATING_MSG_PREFIX))
+
+    fn query_ups_status(&mut self) -> Result<cplus::StatusInquiryResponse> {
+        self.lock()?.query_ups_status()
     }
 
-     fn query_extra_power_info(&mut self) -> Result<cplus::ExtraPowerInfoResponse> {
-        unimplemented!("HID interface USB v0.2 only supports status and rating queries")
+    fn query_extra_power_info(&mut self) -> Result<cplus::ExtraPowerInfoResponse> {
+        self.lock()?.query_extra_power_info()
     }
 
-     fn query_alarm(&mut self) -> Result<cplus::AlarmInquiryResponse> {
-        unimplemented!("HID interface USB v0.2 only supports status and rating queries")
+    fn query_alarm(&mut self) -> Result<cplus::AlarmInquiryResponse> {
+        self.lock()?.query_alarm()
     }
 
-     fn query_ups_autonomy(&mut self) -> Result<cplus::AutonomyResponse> {
-        unimplemented!("HID interface USB v0.2 only supports status and rating queries")
+    fn query_ups_autonomy(&mut self) -> Result<cplus::AutonomyResponse> {
+        self.lock()?.query_ups_autonomy()
     }
 
-     fn query_ups_battery_life(&mut self) -> Result<cplus::BatteryLifeResponse> {
-        unimplemented!("HID interface USB v0.2 only supports status and rating queries")
+    fn query_ups_battery_life(&mut self) -> Result<cplus::BatteryLifeResponse> {
+        self.lock()?.query_ups_battery_life()
     }
 
-     fn query_ups_info(&mut self) -> Result<cplus::UPSInformation> {
-        unimplemented!("HID interface USB v0.2 only supports status and rating queries")
+    fn query_ups_info(&mut self) -> Result<cplus::UPSInformation> {
+        self.lock()?.query_ups_info()
+    }
+
+    fn query_ups_rating(&mut self) -> Result<cplus::UPSRating> {
+        self.lock()?.query_ups_rating()
+    }
+
+    fn query_test_result(&mut self) -> Result<cplus::TestResultResponse> {
+        self.lock()?.query_test_result()
+    }
+
+    fn query_firmware_version(&mut self) -> Result<cplus::FirmwareVersion> {
+        self.lock()?.query_firmware_version()
+    }
+
+    fn health_check(&mut self) -> Result<HealthReport> {
+        self.lock()?.health_check()
+    }
+
+    fn query_snapshot(&mut self) -> Result<cplus::UpsSnapshot> {
+        self.lock()?.query_snapshot()
+    }
+
+    fn shutdown(&mut self, delay: cplus::ShutdownDelay) -> Result<cplus::CommandAck> {
+        self.lock()?.shutdown(delay)
+    }
+
+    fn shutdown_and_restore(&mut self, shutdown: cplus::ShutdownDelay, restore_minutes: u16) -> Result<cplus::CommandAck> {
+        self.lock()?.shutdown_and_restore(shutdown, restore_minutes)
+    }
+
+    fn cancel_shutdown(&mut self) -> Result<cplus::CommandAck> {
+        self.lock()?.cancel_shutdown()
+    }
+
+    fn start_self_test(&mut self) -> Result<cplus::CommandAck> {
+        self.lock()?.start_self_test()
     }
-}
\ No newline at end of file
+
+    fn start_timed_self_test(&mut self, minutes: u8) -> Result<cplus::CommandAck> {
+        self.lock()?.start_timed_self_test(minutes)
+    }
+
+    fn start_test_until_battery_low(&mut self) -> Result<cplus::CommandAck> {
+        self.lock()?.start_test_until_battery_low()
+    }
+
+    fn cancel_test(&mut self) -> Result<cplus::CommandAck> {
+        self.lock()?.cancel_test()
+    }
+
+    fn toggle_beeper(&mut self) -> Result<bool> {
+        self.lock()?.toggle_beeper()
+    }
+
+    fn set_outlet(&mut self, outlet: cplus::Outlet, on: bool) -> Result<cplus::CommandAck> {
+        self.lock()?.set_outlet(outlet, on)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How long the replies cached by a [`CachedInterface`] are served before querying the UPS again.
+pub struct CacheTtl {
+    pub status: Duration,
+    pub extra_power_info: Duration,
+    pub alarm: Duration,
+    pub autonomy: Duration,
+    pub battery_life: Duration,
+    pub info: Duration,
+    pub rating: Duration,
+    pub test_result: Duration,
+}
+
+impl Default for CacheTtl {
+    /// One hour for the replies which don't change (information and rating),
+    /// one second for the others.
+    fn default() -> Self {
+        let second = Duration::from_secs(1);
+        let hour = Duration::from_secs(3600);
+
+        Self {
+            status: second,
+            extra_power_info: second,
+            alarm: second,
+            autonomy: second,
+            battery_life: second,
+            info: hour,
+            rating: hour,
+            test_result: second,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+/// Number of queries a [`CachedInterface`] served from its cache and from the UPS.
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Debug)]
+/// Reply cached by a [`CachedInterface`], with when it was received.
+struct CacheEntry<R>(Option<(Instant, R)>);
+
+impl<R> Default for CacheEntry<R> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<R: Clone> CacheEntry<R> {
+    /// Returns the cached reply if it's younger than `ttl`, otherwise runs `query` and
+    /// caches its reply. Errors aren't cached.
+    fn get_or_query(&mut self, ttl: Duration, stats: &mut CacheStats, query: impl FnOnce() -> Result<R>) -> Result<R> {
+        if let Some((received, reply)) = &self.0 && received.elapsed() < ttl {
+            stats.hits += 1;
+            return Ok(reply.clone());
+        }
+
+        stats.misses += 1;
+
+        let reply = query()?;
+        self.insert(reply.clone());
+
+        Ok(reply)
+    }
+
+    fn insert(&mut self, reply: R) {
+        self.0 = Some((Instant::now(), reply));
+    }
+
+    fn clear(&mut self) {
+        self.0 = None;
+    }
+}
+
+#[derive(Debug)]
+/// Wraps an interface, serving the replies to queries from a cache while they're
+/// fresher than their [`CacheTtl`], so that several consumers polling the same UPS
+/// don't multiply the slow round trips.
+///
+/// Control commands clear the cached replies that they could affect (all but the
+/// information and rating). Health checks and snapshots always query the UPS, the
+/// replies in a snapshot are cached.
+pub struct CachedInterface<T> {
+    inner: T,
+    ttl: CacheTtl,
+    stats: CacheStats,
+    status: CacheEntry<cplus::StatusInquiryResponse>,
+    extra_power_info: CacheEntry<cplus::ExtraPowerInfoResponse>,
+    alarm: CacheEntry<cplus::AlarmInquiryResponse>,
+    autonomy: CacheEntry<cplus::AutonomyResponse>,
+    battery_life: CacheEntry<cplus::BatteryLifeResponse>,
+    info: CacheEntry<cplus::UPSInformation>,
+    rating: CacheEntry<cplus::UPSRating>,
+    test_result: CacheEntry<cplus::TestResultResponse>,
+}
+
+impl<T: CPlusInterface> CachedInterface<T> {
+    pub fn new(inner: T, ttl: CacheTtl) -> Self {
+        Self {
+            inner,
+            ttl,
+            stats: CacheStats::default(),
+            status: CacheEntry::default(),
+            extra_power_info: CacheEntry::default(),
+            alarm: CacheEntry::default(),
+            autonomy: CacheEntry::default(),
+            battery_life: CacheEntry::default(),
+            info: CacheEntry::default(),
+            rating: CacheEntry::default(),
+            test_result: CacheEntry::default(),
+        }
+    }
+
+    /// Returns a reference to the wrapped interface.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the wrapped interface.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Clears all the cached replies.
+    pub fn invalidate(&mut self) {
+        self.invalidate_state();
+        self.info.clear();
+        self.rating.clear();
+    }
+
+    /// Clears the cached replies which can change after a control command.
+    fn invalidate_state(&mut self) {
+        self.status.clear();
+        self.extra_power_info.clear();
+        self.alarm.clear();
+        self.autonomy.clear();
+        self.battery_life.clear();
+        self.test_result.clear();
+    }
+
+    /// Sends a control command, clearing the cached replies it could affect.
+    fn control<R>(&mut self, command: impl FnOnce(&mut T) -> Result<R>) -> Result<R> {
+        let result = command(&mut self.inner);
+
+        self.invalidate_state();
+
+        result
+    }
+}
+
+impl<T: CPlusInterface + RawQuery> RawQuery for CachedInterface<T> {
+    /// Always sent to the UPS, as the command may be a control command.
+    fn raw_query(&mut self, query: &[u8]) -> Result<Vec<u8>> {
+        self.control(|inner| inner.raw_query(query))
+    }
+}
+
+impl<T: CPlusInterface> CPlusInterface for CachedInterface<T> {
+    fn capabilities(&self) -> &'static [Capability] {
+        self.inner.capabilities()
+    }
+
+    fn query_ups_status(&mut self) -> Result<cplus::StatusInquiryResponse> {
+        self.status.get_or_query(self.ttl.status, &mut self.stats, || self.inner.query_ups_status())
+    }
+
+    fn query_extra_power_info(&mut self) -> Result<cplus::ExtraPowerInfoResponse> {
+        self.extra_power_info.get_or_query(self.ttl.extra_power_info, &mut self.stats, || self.inner.query_extra_power_info())
+    }
+
+    fn query_alarm(&mut self) -> Result<cplus::AlarmInquiryResponse> {
+        self.alarm.get_or_query(self.ttl.alarm, &mut self.stats, || self.inner.query_alarm())
+    }
+
+    fn query_ups_autonomy(&mut self) -> Result<cplus::AutonomyResponse> {
+        self.autonomy.get_or_query(self.ttl.autonomy, &mut self.stats, || self.inner.query_ups_autonomy())
+    }
+
+    fn query_ups_battery_life(&mut self) -> Result<cplus::BatteryLifeResponse> {
+        self.battery_life.get_or_query(self.ttl.battery_life, &mut self.stats, || self.inner.query_ups_battery_life())
+    }
+
+    fn query_ups_info(&mut self) -> Result<cplus::UPSInformation> {
+        self.info.get_or_query(self.ttl.info, &mut self.stats, || self.inner.query_ups_info())
+    }
+
+    fn query_ups_rating(&mut self) -> Result<cplus::UPSRating> {
+        self.rating.get_or_query(self.ttl.rating, &mut self.stats, || self.inner.query_ups_rating())
+    }
+
+    fn query_test_result(&mut self) -> Result<cplus::TestResultResponse> {
+        self.test_result.get_or_query(self.ttl.test_result, &mut self.stats, || self.inner.query_test_result())
+    }
+
+    fn health_check(&mut self) -> Result<HealthReport> {
+        self.inner.health_check()
+    }
+
+    /// Always queried from the wrapped interface, caching the replies it contains.
+    fn query_snapshot(&mut self) -> Result<cplus::UpsSnapshot> {
+        let snapshot = self.inner.query_snapshot()?;
+
+        self.status.insert(snapshot.status.clone());
+
+        if let Some(extra_power_info) = &snapshot.extra_power_info {
+            self.extra_power_info.insert(extra_power_info.clone());
+        }
+
+        if let Some(autonomy) = snapshot.autonomy {
+            self.autonomy.insert(autonomy);
+        }
+
+        if let Some(rating) = &snapshot.rating {
+            self.rating.insert(rating.clone());
+        }
+
+        Ok(snapshot)
+    }
+
+    fn shutdown(&mut self, delay: cplus::ShutdownDelay) -> Result<cplus::CommandAck> {
+        self.control(|inner| inner.shutdown(delay))
+    }
+
+    fn shutdown_and_restore(&mut self, shutdown: cplus::ShutdownDelay, restore_minutes: u16) -> Result<cplus::CommandAck> {
+        self.control(|inner| inner.shutdown_and_restore(shutdown, restore_minutes))
+    }
+
+    fn cancel_shutdown(&mut self) -> Result<cplus::CommandAck> {
+        self.control(T::cancel_shutdown)
+    }
+
+    fn start_self_test(&mut self) -> Result<cplus::CommandAck> {
+        self.control(T::start_self_test)
+    }
+
+    fn start_timed_self_test(&mut self, minutes: u8) -> Result<cplus::CommandAck> {
+        self.control(|inner| inner.start_timed_self_test(minutes))
+    }
+
+    fn start_test_until_battery_low(&mut self) -> Result<cplus::CommandAck> {
+        self.control(T::start_test_until_battery_low)
+    }
+
+    fn cancel_test(&mut self) -> Result<cplus::CommandAck> {
+        self.control(T::cancel_test)
+    }
+
+    fn toggle_beeper(&mut self) -> Result<bool> {
+        self.control(T::toggle_beeper)
+    }
+
+    fn set_outlet(&mut self, outlet: cplus::Outlet, on: bool) -> Result<cplus::CommandAck> {
+        self.control(|inner| inner.set_outlet(outlet, on))
+    }
+}
+
+/// Interface of a unit managed by a [`MultiUpsManager`].
+pub type BoxedInterface = Box<dyn CPlusInterface + Send>;
+
+#[derive(Default)]
+/// Set of labeled UPSes queried together, e.g. all the units in a rack.
+///
+/// The units can use different interfaces (serial, HID, ...) and are queried one after
+/// another. A failing unit doesn't affect the others, its errors are returned as
+/// [`crate::Error::Unit`] tagged with its label.
+pub struct MultiUpsManager {
+    units: std::collections::HashMap<String, BoxedInterface>,
+}
+
+impl std::fmt::Debug for MultiUpsManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultiUpsManager")
+            .field("units", &self.labels())
+            .finish()
+    }
+}
+
+impl MultiUpsManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a unit with the given label, returning the interface it replaced, if any.
+    pub fn add(&mut self, label: impl Into<String>, interface: BoxedInterface) -> Option<BoxedInterface> {
+        self.units.insert(label.into(), interface)
+    }
+
+    /// Removes the unit with the given label, returning its interface.
+    pub fn remove(&mut self, label: &str) -> Option<BoxedInterface> {
+        self.units.remove(label)
+    }
+
+    /// Returns the interface of the unit with the given label.
+    pub fn get_mut(&mut self, label: &str) -> Option<&mut BoxedInterface> {
+        self.units.get_mut(label)
+    }
+
+    /// Returns the labels of the units, sorted.
+    pub fn labels(&self) -> Vec<&str> {
+        let mut labels: Vec<&str> = self.units.keys().map(String::as_str).collect();
+        labels.sort_unstable();
+        labels
+    }
+
+    pub fn len(&self) -> usize {
+        self.units.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.units.is_empty()
+    }
+
+    /// Runs `query` on every unit, returning its result for each label.
+    pub fn query_all<R>(
+        &mut self,
+        mut query: impl FnMut(&mut dyn CPlusInterface) -> Result<R>,
+    ) -> std::collections::HashMap<String, Result<R>> {
+        self.units
+            .iter_mut()
+            .map(|(label, interface)| {
+                let result = query(interface.as_mut()).map_err(|e| crate::Error::Unit {
+                    label: label.clone(),
+                    source: Box::new(e),
+                });
+
+                (label.clone(), result)
+            })
+            .collect()
+    }
+
+    /// Queries the status of every unit, see [`CPlusInterface::query_ups_status`].
+    pub fn query_status_all(&mut self) -> std::collections::HashMap<String, Result<cplus::StatusInquiryResponse>> {
+        self.query_all(|interface| interface.query_ups_status())
+    }
+
+    /// Takes a snapshot of every unit, see [`CPlusInterface::query_snapshot`].
+    pub fn query_snapshot_all(&mut self) -> std::collections::HashMap<String, Result<cplus::UpsSnapshot>> {
+        self.query_all(|interface| interface.query_snapshot())
+    }
+}
+
+/// Whether the error means the device is gone (unplugged, re-enumerated, connection
+/// closed), so the connection has to be re-opened.
+fn is_disconnect(error: &crate::Error) -> bool {
+    fn is_disconnect_kind(kind: std::io::ErrorKind) -> bool {
+        matches!(
+            kind,
+            std::io::ErrorKind::BrokenPipe
+                | std::io::ErrorKind::NotConnected
+                | std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::ConnectionAborted
+                | std::io::ErrorKind::NotFound
+        )
+    }
+
+    match error {
+        crate::Error::Io(e) => {
+            // EIO, ENXIO and ENODEV
+            is_disconnect_kind(e.kind()) || (cfg!(unix) && matches!(e.raw_os_error(), Some(5 | 6 | 19)))
+        }
+        #[cfg(feature = "serial")]
+        crate::Error::SerialPort(e) => match e.kind() {
+            serialport::ErrorKind::NoDevice => true,
+            serialport::ErrorKind::Io(kind) => is_disconnect_kind(kind),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Exponential backoff between the attempts to open a connection, see [`ReconnectingInterface::backoff`].
+///
+/// The delay starts at `initial` and is multiplied by `multiplier` after each attempt, up to `max`.
+pub struct Backoff {
+    pub initial: Duration,
+    pub multiplier: f64,
+    pub max: Duration,
+    /// Fraction of each delay randomly added or taken away (e.g. 0.1 for up to ±10 %), so that
+    /// several processes don't retry in lockstep. The delays still don't exceed `max`.
+    pub jitter: f64,
+    /// Seed of the jitter, making the delays deterministic. Seeded from the clock if `None`.
+    pub seed: Option<u64>,
+}
+
+impl Default for Backoff {
+    /// 500 ms, doubled after each attempt up to 30 s, with a jitter of ±10 %.
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(500),
+            multiplier: 2.0,
+            max: Duration::from_secs(30),
+            jitter: 0.1,
+            seed: None,
+        }
+    }
+}
+
+impl From<Duration> for Backoff {
+    /// A constant delay, without jitter.
+    fn from(delay: Duration) -> Self {
+        Self { initial: delay, multiplier: 1.0, max: delay, jitter: 0.0, seed: None }
+    }
+}
+
+impl Backoff {
+    pub fn seeded(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Returns the delays between the attempts, in order.
+    pub fn delays(self) -> BackoffDelays {
+        let seed = self.seed.unwrap_or_else(|| {
+            SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map_or(0, |since| since.as_nanos() as u64)
+        });
+
+        BackoffDelays { backoff: self, next: self.initial.min(self.max), rng: SplitMix64(seed) }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// The endless sequence of delays of a [`Backoff`].
+pub struct BackoffDelays {
+    backoff: Backoff,
+    /// The next delay, before the jitter.
+    next: Duration,
+    rng: SplitMix64,
+}
+
+impl BackoffDelays {
+    /// Starts over from the initial delay, e.g. after the connection succeeded.
+    pub fn reset(&mut self) {
+        self.next = self.backoff.initial.min(self.backoff.max);
+    }
+}
+
+impl Iterator for BackoffDelays {
+    type Item = Duration;
+
+    fn next(&mut self) -> Option<Duration> {
+        let Backoff { multiplier, max, jitter, .. } = self.backoff;
+        let delay = self.next;
+
+        self.next = delay.mul_f64(multiplier.max(1.0)).min(max);
+
+        if jitter <= 0.0 {
+            return Some(delay);
+        }
+
+        // Uniformly within [-jitter, jitter)
+        let offset = jitter * (2.0 * self.rng.unit() - 1.0);
+
+        Some(delay.mul_f64((1.0 + offset).max(0.0)).min(max))
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Small deterministic RNG (SplitMix64), e.g. for the backoff jitter.
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `[0, 1)`.
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+type Connect<T> = Box<dyn FnMut() -> Result<CPlusGenericInterface<T>> + Send>;
+
+/// Interface re-opening the connection when the device goes away, e.g. when
+/// a USB-serial adapter re-enumerates.
+///
+/// The connection is opened on first use. When a command fails because the device
+/// is gone, the connection is re-opened and a query is sent once more. Control commands
+/// aren't repeated, as it's unknown whether the UPS received them.
+///
+/// The attempts to open the connection are spaced out by a [`Backoff`], also across commands:
+/// a command sent while the device is missing waits for [`ReconnectingInterface::next_retry`].
+/// The backoff starts over after a command succeeds.
+pub struct ReconnectingInterface<T> {
+    connect: Connect<T>,
+    interface: Option<CPlusGenericInterface<T>>,
+    backoff: BackoffDelays,
+    /// When opening the connection may be attempted again after failing.
+    next_retry: Option<Instant>,
+    attempts: u32,
+    reconnects: u64,
+    failed_connects: u64,
+}
+
+impl<T> std::fmt::Debug for ReconnectingInterface<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ReconnectingInterface")
+            .field("connected", &self.interface.is_some())
+            .field("backoff", &self.backoff.backoff)
+            .field("next_retry", &self.next_retry)
+            .field("attempts", &self.attempts)
+            .field("reconnects", &self.reconnects)
+            .field("failed_connects", &self.failed_connects)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: Transport> ReconnectingInterface<T> {
+    /// Creates an interface opening the connections with `connect`, making up to 5 attempts
+    /// spaced out by the default [`Backoff`].
+    pub fn new(connect: impl FnMut() -> Result<CPlusGenericInterface<T>> + Send + 'static) -> Self {
+        Self {
+            connect: Box::new(connect),
+            interface: None,
+            backoff: Backoff::default().delays(),
+            next_retry: None,
+            attempts: 5,
+            reconnects: 0,
+            failed_connects: 0,
+        }
+    }
+
+    /// Sets the delays between the attempts to open the connection, a [`Duration`] for a constant one.
+    pub fn backoff(mut self, backoff: impl Into<Backoff>) -> Self {
+        self.backoff = backoff.into().delays();
+        self
+    }
+
+    /// Sets how many times opening the connection is attempted before giving up.
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts.max(1);
+        self
+    }
+
+    /// Number of times the connection was re-opened after the device went away.
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects
+    }
+
+    /// Number of failed attempts to open the connection.
+    pub fn failed_connects(&self) -> u64 {
+        self.failed_connects
+    }
+
+    /// Whether the connection is currently open.
+    pub fn is_connected(&self) -> bool {
+        self.interface.is_some()
+    }
+
+    /// Returns when opening the connection is attempted next, after the last attempt failed.
+    /// `None` if it didn't, or a command succeeded since.
+    pub fn next_retry(&self) -> Option<Instant> {
+        self.next_retry
+    }
+
+    fn open(&mut self) -> Result<CPlusGenericInterface<T>> {
+        let mut attempt = 1;
+
+        loop {
+            if let Some(next_retry) = self.next_retry {
+                std::thread::sleep(next_retry.saturating_duration_since(Instant::now()));
+            }
+
+            let error = match (self.connect)() {
+                Ok(interface) => return Ok(interface),
+                Err(e) => e,
+            };
+
+            self.failed_connects += 1;
+
+            let delay = self.backoff.next().unwrap_or_default();
+            self.next_retry = Some(Instant::now() + delay);
+
+            if attempt >= self.attempts {
+                return Err(error);
+            }
+
+            debug!("Couldn't open the connection (attempt {attempt}/{}), retrying in {delay:?}: {error}", self.attempts);
+
+            attempt += 1;
+        }
+    }
+
+    fn connection(&mut self) -> Result<&mut CPlusGenericInterface<T>> {
+        let interface = match self.interface.take() {
+            Some(interface) => interface,
+            None => self.open()?,
+        };
+
+        Ok(self.interface.insert(interface))
+    }
+
+    fn run<R>(&mut self, repeat: bool, command: impl Fn(&mut CPlusGenericInterface<T>) -> Result<R>) -> Result<R> {
+        let result = self.run_reconnecting(repeat, command);
+
+        if result.is_ok() {
+            self.backoff.reset();
+            self.next_retry = None;
+        }
+
+        result
+    }
+
+    fn run_reconnecting<R>(&mut self, repeat: bool, command: impl Fn(&mut CPlusGenericInterface<T>) -> Result<R>) -> Result<R> {
+        let error = match command(self.connection()?) {
+            Err(e) if is_disconnect(&e) => e,
+            result => return result,
+        };
+
+        warn!("Lost the connection to the UPS ({error}), reconnecting");
+
+        self.interface = None;
+        let interface = self.open()?;
+        self.reconnects += 1;
+        let interface = self.interface.insert(interface);
+
+        if repeat { command(interface) } else { Err(error) }
+    }
+}
+
+#[cfg(feature = "serial")]
+impl ReconnectingInterface<SerialTransport> {
+    /// Creates an interface (re-)opening the serial port with `options`.
+    pub fn serial(options: SerialConnectOptions) -> Self {
+        Self::new(move || options.clone().open())
+    }
+}
+
+impl<T: Transport> RawQuery for ReconnectingInterface<T> {
+    /// Not repeated after reconnecting, as the command may be a control command.
+    fn raw_query(&mut self, query: &[u8]) -> Result<Vec<u8>> {
+        self.run(false, |iface| iface.raw_query(query))
+    }
+}
+
+impl<T: Transport> CPlusInterface for ReconnectingInterface<T> {
+    fn query_ups_status(&mut self) -> Result<cplus::StatusInquiryResponse> {
+        self.run(true, CPlusGenericInterface::query_ups_status)
+    }
+
+    fn query_snapshot(&mut self) -> Result<cplus::UpsSnapshot> {
+        self.run(true, CPlusGenericInterface::query_snapshot)
+    }
+
+    fn query_extra_power_info(&mut self) -> Result<cplus::ExtraPowerInfoResponse> {
+        self.run(true, CPlusGenericInterface::query_extra_power_info)
+    }
+
+    fn query_alarm(&mut self) -> Result<cplus::AlarmInquiryResponse> {
+        self.run(true, CPlusGenericInterface::query_alarm)
+    }
+
+    fn query_ups_autonomy(&mut self) -> Result<cplus::AutonomyResponse> {
+        self.run(true, CPlusGenericInterface::query_ups_autonomy)
+    }
+
+    fn query_ups_battery_life(&mut self) -> Result<cplus::BatteryLifeResponse> {
+        self.run(true, CPlusGenericInterface::query_ups_battery_life)
+    }
+
+    fn query_ups_info(&mut self) -> Result<cplus::UPSInformation> {
+        self.run(true, CPlusGenericInterface::query_ups_info)
+    }
+
+    fn query_ups_rating(&mut self) -> Result<cplus::UPSRating> {
+        self.run(true, CPlusGenericInterface::query_ups_rating)
+    }
+
+    fn query_test_result(&mut self) -> Result<cplus::TestResultResponse> {
+        self.run(true, CPlusGenericInterface::query_test_result)
+    }
+
+    fn shutdown(&mut self, delay: cplus::ShutdownDelay) -> Result<cplus::CommandAck> {
+        self.run(false, |iface| iface.shutdown(delay))
+    }
+
+    fn shutdown_and_restore(&mut self, shutdown: cplus::ShutdownDelay, restore_minutes: u16) -> Result<cplus::CommandAck> {
+        self.run(false, |iface| iface.shutdown_and_restore(shutdown, restore_minutes))
+    }
+
+    fn cancel_shutdown(&mut self) -> Result<cplus::CommandAck> {
+        self.run(false, CPlusGenericInterface::cancel_shutdown)
+    }
+
+    fn start_self_test(&mut self) -> Result<cplus::CommandAck> {
+        self.run(false, CPlusGenericInterface::start_self_test)
+    }
+
+    fn start_timed_self_test(&mut self, minutes: u8) -> Result<cplus::CommandAck> {
+        self.run(false, |iface| iface.start_timed_self_test(minutes))
+    }
+
+    fn start_test_until_battery_low(&mut self) -> Result<cplus::CommandAck> {
+        self.run(false, CPlusGenericInterface::start_test_until_battery_low)
+    }
+
+    fn cancel_test(&mut self) -> Result<cplus::CommandAck> {
+        self.run(false, CPlusGenericInterface::cancel_test)
+    }
+
+    fn toggle_beeper(&mut self) -> Result<bool> {
+        self.run(false, CPlusGenericInterface::toggle_beeper)
+    }
+
+    fn set_outlet(&mut self, outlet: cplus::Outlet, on: bool) -> Result<cplus::CommandAck> {
+        self.run(false, |iface| iface.set_outlet(outlet, on))
+    }
+}
+
+#[derive(Debug)]
+/// UPSes sharing one multi-drop bus (e.g. RS-485), using the addressed variant of the protocol.
+///
+/// The commands are sent to one unit at a time, see [`CPlusBusInterface::run`]. Besides the
+/// command gap between any two commands on the bus, each unit gets its own gap between
+/// the commands sent to it, so a unit still busy with a command isn't sent the next one.
+pub struct CPlusBusInterface<T> {
+    interface: CPlusGenericInterface<T>,
+    unit_gap: Duration,
+    /// When the last command to each unit finished.
+    last_commands: std::collections::HashMap<BusAddress, Instant>,
+}
+
+impl<T: Transport> CPlusBusInterface<T> {
+    /// Creates an interface to the units on the bus connected over `port`, with a unit gap
+    /// of [`DEFAULT_COMMAND_GAP`].
+    pub fn new(port: T) -> Self {
+        Self::from_interface(CPlusGenericInterface::new(port))
+    }
+
+    /// Creates an interface to the units on the bus `interface` is connected to, keeping its settings.
+    pub fn from_interface(interface: CPlusGenericInterface<T>) -> Self {
+        Self { interface, unit_gap: DEFAULT_COMMAND_GAP, last_commands: std::collections::HashMap::new() }
+    }
+
+    /// Sets the shortest time between the end of a command to a unit and the start of the
+    /// next command to the same unit.
+    pub fn set_unit_gap(&mut self, gap: Duration) {
+        self.unit_gap = gap;
+    }
+
+    /// Returns the interface used for all the units, e.g. to change its settings.
+    pub fn interface_mut(&mut self) -> &mut CPlusGenericInterface<T> {
+        &mut self.interface
+    }
+
+    /// Returns the underlying interface, without a bus address set.
+    pub fn into_inner(mut self) -> CPlusGenericInterface<T> {
+        self.interface.address = None;
+        self.interface
+    }
+
+    /// Runs `command` with the interface addressing the unit at `address`, once the unit
+    /// gap since the last command to the unit elapses.
+    ///
+    /// Fails with [`crate::Error::InvalidParameter`] if the address can't be told apart
+    /// from the end of a message, see [`CPlusGenericInterface::set_bus_address`].
+    pub fn run<R>(&mut self, address: BusAddress, command: impl FnOnce(&mut CPlusGenericInterface<T>) -> Result<R>) -> Result<R> {
+        self.interface.set_bus_address(Some(address))?;
+
+        if let Some(last_command) = self.last_commands.get(&address) {
+            let remaining = self.unit_gap.saturating_sub(last_command.elapsed());
+
+            if !remaining.is_zero() {
+                trace!("Waiting {remaining:?} before the next command to unit {}", address.0);
+                std::thread::sleep(remaining);
+            }
+        }
+
+        let result = command(&mut self.interface);
+        self.last_commands.insert(address, Instant::now());
+
+        result
+    }
+}
+
+/// Whether the I/O error means the read timed out.
+fn is_timeout(error: &std::io::Error) -> bool {
+    matches!(error.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock)
+}
+
+/// Error for a reply which didn't arrive in time, `received` being the bytes read so far.
+fn reply_timeout(received: Vec<u8>) -> crate::Error {
+    if received.is_empty() {
+        crate::Error::Timeout { waiting_for: "a reply".to_string() }
+    } else {
+        crate::Error::IncompleteResponse { received }
+    }
+}
+
+/// Name of the response type `T` for [`crate::Error::InvalidFormat`], without its path.
+fn response_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Error for an empty reply, missing even the start byte.
+fn missing_start_byte<T>() -> crate::Error {
+    crate::Error::invalid_format(response_name::<T>(), &[], "missing the start byte")
+}
+
+/// Parses the reply to the query `C`, skipping its start byte.
+fn parse_reply<C>(reply: &[u8]) -> Result<C::Response>
+where
+    C: cplus::Command,
+    <C::Response as FromBytes>::Err: Into<crate::Error>,
+{
+    let Some(processed_bytes) = reply.get(1..) else {
+        return Err(missing_start_byte::<C::Response>());
+    };
+
+    C::Response::from_bytes(processed_bytes).map_err(|e| e.into())
+}
+
+/// Error for a reply not starting with the expected `prefix`.
+fn unexpected_reply(prefix: Option<u8>, reply: &[u8]) -> crate::Error {
+    crate::Error::UnexpectedResponse {
+        expected: prefix.map_or("a reply".to_string(), |prefix| format!("a reply starting with {:?}", prefix as char)),
+        got: String::from_utf8_lossy(reply).into_owned(),
+    }
+}
+
+/// Byte stream the [`CPlusGenericInterface`] communicates over.
+///
+/// Only [`Read`](std::io::Read) and [`Write`] are required, the other methods
+/// let transports which support them discard stale input and shorten the read timeout.
+pub trait Transport: std::io::Read + Write {
+    /// Discards any data waiting to be read, such as a partial reply to an earlier query.
+    fn clear_input(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns how many bytes can be read without waiting, `None` if the transport can't tell.
+    /// Lets the stale input be discarded without clearing all of it, which
+    /// [`Transport::clear_input`] does otherwise.
+    fn waiting_input(&mut self) -> Result<Option<usize>> {
+        Ok(None)
+    }
+
+    /// Returns the read timeout, if the transport has one.
+    fn timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Sets the read timeout. Transports without one ignore this.
+    fn set_timeout(&mut self, _timeout: Duration) -> Result<()> {
+        Ok(())
+    }
+
+    /// Returns the baud rate of the line, if the transport has one.
+    fn baud_rate(&self) -> Option<u32> {
+        None
+    }
+}
+
+#[cfg(feature = "serial")]
+impl Transport for Box<dyn serialport::SerialPort> {
+    fn clear_input(&mut self) -> Result<()> {
+        Ok(self.clear(serialport::ClearBuffer::Input)?)
+    }
+
+    fn waiting_input(&mut self) -> Result<Option<usize>> {
+        Ok(Some(serialport::SerialPort::bytes_to_read(self.as_ref())? as usize))
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Some(serialport::SerialPort::timeout(self.as_ref()))
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        Ok(serialport::SerialPort::set_timeout(self.as_mut(), timeout)?)
+    }
+
+    fn baud_rate(&self) -> Option<u32> {
+        serialport::SerialPort::baud_rate(self.as_ref()).ok()
+    }
+}
+
+#[derive(Debug)]
+/// Interface for the Continuity Plus UPSes over any [`Transport`].
+pub struct CPlusGenericInterface<T> {
+    port: T,
+    /// Longest message accepted before giving up on finding its end.
+    max_frame_len: usize,
+    /// Bytes read past the end of the last message, kept for the next read.
+    input: Vec<u8>,
+    /// The last message read, without the end byte. Reused by every read.
+    frame: Vec<u8>,
+    metrics: QueryMetrics,
+    last_success: LastSuccess,
+    /// Shortest time between the end of a command and the start of the next one.
+    command_gap: Duration,
+    /// When the last command finished.
+    last_command: Option<Instant>,
+    terminator: LineTerminator,
+    echo_mode: EchoMode,
+    /// The echo of the last command still to be skipped before its reply.
+    pending_echo: Option<Vec<u8>>,
+    /// The unit addressed on a multi-drop bus.
+    address: Option<BusAddress>,
+    /// Battery pack from the last rating read, which the battery capacity is computed for.
+    battery: Option<cplus::BatteryConfiguration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How the messages to and from the UPS are terminated.
+pub enum LineTerminator {
+    /// A carriage return, as in the protocol.
+    #[default]
+    Cr,
+    /// A carriage return followed by a line feed, sent by some OEM variants.
+    CrLf,
+    /// The given byte.
+    Custom(u8),
+}
+
+impl LineTerminator {
+    /// The byte ending a message. The line feed of [`LineTerminator::CrLf`] is
+    /// skipped at the start of the next message instead.
+    fn end_byte(self) -> u8 {
+        match self {
+            Self::Cr | Self::CrLf => END_BYTE,
+            Self::Custom(byte) => byte,
+        }
+    }
+
+    /// Writes the terminator following a command.
+    fn write(self, port: &mut impl Write) -> std::io::Result<()> {
+        match self {
+            Self::CrLf => port.write_all(b"\r\n"),
+            terminator => port.write_all(&[terminator.end_byte()]),
+        }
+    }
+
+    /// Strips the terminator from the end of `msg`, if present.
+    fn strip(self, msg: &[u8]) -> &[u8] {
+        let msg = match self {
+            Self::CrLf => msg.strip_suffix(b"\n").unwrap_or(msg),
+            _ => msg,
+        };
+
+        msg.strip_suffix(&[self.end_byte()]).unwrap_or(msg)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// Address of a unit on a multi-drop bus, sent before every command and reply.
+pub struct BusAddress(pub u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How the echo of a command, sent back by some serial adapters before the reply, is handled.
+pub enum EchoMode {
+    /// The UPS doesn't echo the commands.
+    #[default]
+    Off,
+    /// Every command is echoed, a reply not starting with the echo fails.
+    Expect,
+    /// The echo is stripped if the reply starts with it, the reply is read as is otherwise.
+    Detect,
+}
+
+#[cfg(feature = "serial")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// How a control line (DTR/RTS) of a serial port is set when opening the port.
+pub enum LineState {
+    Assert,
+    Deassert,
+    /// Left as it is, e.g. for cables using the line as a shutdown signal.
+    Leave,
+}
+
+#[cfg(feature = "serial")]
+impl LineState {
+    /// The level the line is set to, `None` if it's left as is.
+    pub fn level(self) -> Option<bool> {
+        match self {
+            Self::Assert => Some(true),
+            Self::Deassert => Some(false),
+            Self::Leave => None,
+        }
+    }
+}
+
+#[cfg(feature = "serial")]
+impl From<bool> for LineState {
+    fn from(asserted: bool) -> Self {
+        if asserted { Self::Assert } else { Self::Deassert }
+    }
+}
+
+#[cfg(feature = "serial")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Settings for opening a serial connection, see [`CPlusSerialInterface::builder`].
+pub struct SerialConnectOptions {
+    /// Path of the serial port.
+    pub path: String,
+    pub baud_rate: u32,
+    /// Read timeout of the port.
+    pub timeout: Duration,
+    pub parity: serialport::Parity,
+    pub stop_bits: serialport::StopBits,
+    pub flow_control: serialport::FlowControl,
+    /// State of the DTR line after opening the port.
+    pub dtr: LineState,
+    /// State of the RTS line after opening the port.
+    pub rts: LineState,
+    /// Whether the asserted DTR/RTS lines are de-asserted when the port is closed.
+    /// Some UPSes treat DTR as a shutdown signal, so this can be turned off.
+    pub deassert_on_close: bool,
+    /// How the commands and replies are terminated, see [`CPlusGenericInterface::set_line_terminator`].
+    pub line_terminator: LineTerminator,
+    /// How the echo of the commands is handled, see [`CPlusGenericInterface::set_echo_mode`].
+    pub echo_mode: EchoMode,
+}
+
+#[cfg(feature = "serial")]
+impl SerialConnectOptions {
+    /// Creates the default options for the port at `path`: the protocol's 2400 baud 8N1,
+    /// no flow control, a 5s timeout, DTR asserted (de-asserted on close) and RTS left as is.
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            baud_rate: cplus::SERIAL_BAUD_RATE,
+            timeout: Duration::from_millis(5000),
+            parity: serialport::Parity::None,
+            stop_bits: serialport::StopBits::One,
+            flow_control: serialport::FlowControl::None,
+            dtr: LineState::Assert,
+            rts: LineState::Leave,
+            deassert_on_close: true,
+            line_terminator: LineTerminator::Cr,
+            echo_mode: EchoMode::Off,
+        }
+    }
+
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.baud_rate = baud_rate;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn parity(mut self, parity: serialport::Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    pub fn stop_bits(mut self, stop_bits: serialport::StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    pub fn flow_control(mut self, flow_control: serialport::FlowControl) -> Self {
+        self.flow_control = flow_control;
+        self
+    }
+
+    /// Sets the DTR line state, a `bool` asserting or de-asserting it.
+    pub fn dtr(mut self, dtr: impl Into<LineState>) -> Self {
+        self.dtr = dtr.into();
+        self
+    }
+
+    /// Sets the RTS line state, a `bool` asserting or de-asserting it.
+    pub fn rts(mut self, rts: impl Into<LineState>) -> Self {
+        self.rts = rts.into();
+        self
+    }
+
+    pub fn deassert_on_close(mut self, deassert_on_close: bool) -> Self {
+        self.deassert_on_close = deassert_on_close;
+        self
+    }
+
+    pub fn line_terminator(mut self, line_terminator: LineTerminator) -> Self {
+        self.line_terminator = line_terminator;
+        self
+    }
+
+    pub fn echo_mode(mut self, echo_mode: EchoMode) -> Self {
+        self.echo_mode = echo_mode;
+        self
+    }
+
+    /// Checks that the options can be used to open a port.
+    fn validate(&self) -> Result<()> {
+        if self.baud_rate == 0 {
+            return Err(crate::Error::InvalidParameter("the baud rate must not be zero".to_string()));
+        }
+
+        if self.timeout.is_zero() {
+            return Err(crate::Error::InvalidParameter("the timeout must not be zero".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Opens the serial port with these options.
+    pub fn open(self) -> Result<CPlusSerialInterface> {
+        self.validate()?;
+
+        let (line_terminator, echo_mode, timeout) = (self.line_terminator, self.echo_mode, self.timeout);
+        let mut iface = CPlusSerialInterface::new(SerialTransport::open(self, Box::new(Self::open_port))?);
+        iface.set_line_terminator(line_terminator);
+        iface.set_echo_mode(echo_mode);
+        iface.set_query_timeout(timeout)?;
+
+        Ok(iface)
+    }
+
+    /// Opens the serial port, without setting the control lines.
+    fn open_port(&self) -> Result<Box<dyn serialport::SerialPort>> {
+        let port = serialport::new(os_port_path(&self.path), self.baud_rate)
+            .timeout(self.timeout)
+            .parity(self.parity)
+            .stop_bits(self.stop_bits)
+            .flow_control(self.flow_control)
+            .open()
+            .map_err(|e| open_error(&self.path, e))?;
+
+        Ok(port)
+    }
+
+    /// Sets the control lines of a newly opened `port`.
+    fn set_lines(&self, port: &mut dyn serialport::SerialPort) -> Result<()> {
+        if let Some(dtr) = self.dtr.level() {
+            port.write_data_terminal_ready(dtr)?;
+        }
+
+        if let Some(rts) = self.rts.level() {
+            port.write_request_to_send(rts)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Opens a serial port with the given options, replaceable for testing.
+#[cfg(feature = "serial")]
+type SerialPortFactory = Box<dyn Fn(&SerialConnectOptions) -> Result<Box<dyn serialport::SerialPort>> + Send>;
+
+#[cfg(feature = "serial")]
+/// Serial port along with the options it was opened with.
+///
+/// Dropping it releases the port like [`CPlusSerialInterface::close`], ignoring errors.
+pub struct SerialTransport {
+    /// `None` after a failed reconnect.
+    port: Option<Box<dyn serialport::SerialPort>>,
+    options: SerialConnectOptions,
+    /// Whether the port was already released.
+    closed: bool,
+    factory: SerialPortFactory,
+}
+
+#[cfg(feature = "serial")]
+impl std::fmt::Debug for SerialTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SerialTransport")
+            .field("options", &self.options)
+            .field("connected", &self.port.is_some())
+            .field("closed", &self.closed)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "serial")]
+impl SerialTransport {
+    #[cfg(test)]
+    fn new(port: Box<dyn serialport::SerialPort>, options: SerialConnectOptions) -> Self {
+        Self { port: Some(port), options, closed: false, factory: Box::new(SerialConnectOptions::open_port) }
+    }
+
+    fn open(options: SerialConnectOptions, factory: SerialPortFactory) -> Result<Self> {
+        let mut port = factory(&options)?;
+        options.set_lines(port.as_mut())?;
+
+        Ok(Self { port: Some(port), options, closed: false, factory })
+    }
+
+    fn port(&mut self) -> std::io::Result<&mut Box<dyn serialport::SerialPort>> {
+        self.port.as_mut().ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::NotConnected,
+            format!("the serial port {} isn't open", self.options.path),
+        ))
+    }
+
+    /// De-asserts the control lines asserted when opening the port (unless disabled by
+    /// [`SerialConnectOptions::deassert_on_close`]) and then flushes the output.
+    ///
+    /// The transport is only marked as closed once the lines are released, so a failed
+    /// release is retried when dropping it.
+    fn release(&mut self) -> Result<()> {
+        let Some(port) = &mut self.port else {
+            self.closed = true;
+
+            return Ok(());
+        };
+
+        if self.options.deassert_on_close {
+            if self.options.dtr == LineState::Assert {
+                port.write_data_terminal_ready(false)?;
+            }
+
+            if self.options.rts == LineState::Assert {
+                port.write_request_to_send(false)?;
+            }
+        }
+
+        self.closed = true;
+
+        port.flush()?;
+
+        Ok(())
+    }
+
+    /// Releases and closes the port, then opens it again with the same options.
+    fn reopen(&mut self) -> Result<()> {
+        if !self.closed && let Err(e) = self.release() {
+            // Expected when the adapter was unplugged
+            debug!("Couldn't release the serial port {}: {e}", self.options.path);
+        }
+
+        // Closed before opening again, the port is opened exclusively
+        self.port = None;
+
+        let mut port = (self.factory)(&self.options)?;
+        self.options.set_lines(port.as_mut())?;
+
+        self.port = Some(port);
+        self.closed = false;
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serial")]
+impl Drop for SerialTransport {
+    fn drop(&mut self) {
+        if !self.closed && let Err(e) = self.release() {
+            debug!("Couldn't release the serial port {}: {e}", self.options.path);
+        }
+    }
+}
+
+#[cfg(feature = "serial")]
+impl std::io::Read for SerialTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.port()?.read(buf)
+    }
+}
+
+#[cfg(feature = "serial")]
+impl Write for SerialTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.port()?.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.port()?.flush()
+    }
+}
+
+#[cfg(feature = "serial")]
+impl Transport for SerialTransport {
+    fn clear_input(&mut self) -> Result<()> {
+        self.port()?.clear_input()
+    }
+
+    fn waiting_input(&mut self) -> Result<Option<usize>> {
+        self.port()?.waiting_input()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Some(self.options.timeout)
+    }
+
+    /// Also kept in the options, so the timeout survives a reconnect.
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.options.timeout = timeout;
+
+        Transport::set_timeout(self.port()?, timeout)
+    }
+
+    fn baud_rate(&self) -> Option<u32> {
+        Some(self.options.baud_rate)
+    }
+}
+
+#[cfg(feature = "serial")]
+/// Serial port interface for the Continuity Plus UPSes.
+pub type CPlusSerialInterface = CPlusGenericInterface<SerialTransport>;
+
+#[cfg(feature = "serial")]
+impl CPlusSerialInterface {
+    /// Connects to the serial port at the provided path with a 5s timeout.
+    ///
+    /// The path can also be a symlink, e.g. the stable `/dev/serial/by-id` name of the port
+    /// (see [`stable_port_candidates`]).
+    pub fn connect(port_path: &str) -> Result<Self> {
+        Self::builder(port_path).open()
+    }
+
+    /// Returns the default options for connecting to the serial port at `port_path`,
+    /// which can be adjusted before opening it.
+    ///
+    /// ```no_run
+    /// # use alphamon_rs::device::cplus::CPlusSerialInterface;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let iface = CPlusSerialInterface::builder("/dev/ttyUSB0")
+    ///     .baud_rate(9600)
+    ///     .rts(true)
+    ///     .open()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn builder(port_path: &str) -> SerialConnectOptions {
+        SerialConnectOptions::new(port_path)
+    }
+
+    /// Returns the options the port was opened with, including the current timeout.
+    pub fn options(&self) -> &SerialConnectOptions {
+        &self.port.options
+    }
+
+    /// Sets the DTR line. The new state is kept in the [options](Self::options), so it's
+    /// also applied after a [reconnect](Self::reconnect) and de-asserted on close.
+    pub fn set_dtr(&mut self, asserted: bool) -> Result<()> {
+        self.port.port()?.write_data_terminal_ready(asserted)?;
+        self.port.options.dtr = asserted.into();
+
+        Ok(())
+    }
+
+    /// Sets the RTS line, see [`CPlusSerialInterface::set_dtr`].
+    pub fn set_rts(&mut self, asserted: bool) -> Result<()> {
+        self.port.port()?.write_request_to_send(asserted)?;
+        self.port.options.rts = asserted.into();
+
+        Ok(())
+    }
+
+    /// Closes the port and opens it again in place with the same options, setting the
+    /// DTR/RTS lines and the timeout again. Recovers from a USB serial adapter which
+    /// was briefly disconnected.
+    ///
+    /// If opening the port fails, the interface stays disconnected (its queries fail)
+    /// until a later reconnect succeeds.
+    pub fn reconnect(&mut self) -> Result<()> {
+        self.port.reopen()
+    }
+
+    /// Whether the port is open and still responds, checked by asking it for the number
+    /// of bytes waiting to be read.
+    pub fn is_connected(&self) -> bool {
+        self.port.port.as_ref().is_some_and(|port| port.bytes_to_read().is_ok())
+    }
+
+    /// De-asserts the DTR/RTS lines asserted when opening the port (see
+    /// [`SerialConnectOptions::deassert_on_close`]), flushes the output and closes the port.
+    ///
+    /// Dropping the interface does the same, but ignores the errors.
+    pub fn close(self) -> Result<()> {
+        self.into_inner().release()
+    }
+
+    /// Finds the UPSes connected to serial ports by sending `Q1` to each port.
+    ///
+    /// USB serial ports are tried first. Ports which can't be opened (e.g. because
+    /// they're busy) or don't reply are skipped.
+    ///
+    /// The ports are probed without touching the DTR/RTS lines, since asserting DTR cuts
+    /// the output of UPSes whose cable uses it as the shutdown signal.
+    pub fn discover() -> Result<Vec<DiscoveredUps>> {
+        Ok(Self::probe_ports()?.map(|(ups, _)| ups).collect())
+    }
+
+    /// Connects to the first UPS found like in [`CPlusSerialInterface::discover`].
+    ///
+    /// The DTR/RTS lines of the returned interface are left as they were, use
+    /// [`CPlusSerialInterface::set_dtr`] to assert them if the cable needs it.
+    pub fn discover_first() -> Result<Self> {
+        Self::probe_ports()?
+            .next()
+            .map(|(_, iface)| iface)
+            .ok_or(crate::Error::NoUpsFound)
+    }
+
+    /// Lazily opens and probes the available ports, yielding the ones with a UPS.
+    fn probe_ports() -> Result<impl Iterator<Item = (DiscoveredUps, Self)>> {
+        let mut ports = serialport::available_ports()?;
+        sort_probe_order(&mut ports);
+
+        Ok(ports.into_iter().filter_map(|port| {
+            let mut iface = Self::builder(&port.port_name)
+                .dtr(LineState::Leave)
+                .rts(LineState::Leave)
+                .open()
+                .inspect_err(|e| debug!("Skipping {}: {e}", port.port_name))
+                .ok()?;
+
+            let (status, info) = iface
+                .probe()
+                .inspect_err(|e| debug!("No UPS found on {}: {e}", port.port_name))
+                .ok()?;
+
+            Some((DiscoveredUps { port, status, info }, iface))
+        }))
+    }
+}
+
+#[cfg(feature = "serial")]
+#[derive(Debug, Clone)]
+/// UPS found by [`CPlusSerialInterface::discover`].
+pub struct DiscoveredUps {
+    /// Serial port the UPS is connected to.
+    pub port: serialport::SerialPortInfo,
+    /// Status the UPS replied with while probing.
+    pub status: cplus::StatusInquiryResponse,
+    /// Manufacturer information, if the UPS replied to the `I` command.
+    pub info: Option<cplus::UPSInformation>,
+}
+
+#[cfg(feature = "serial")]
+/// Orders the ports so that the USB ones (where UPSes are usually connected) are probed first.
+fn sort_probe_order(ports: &mut [serialport::SerialPortInfo]) {
+    ports.sort_by_key(|port| !matches!(port.port_type, serialport::SerialPortType::UsbPort(_)));
+}
+
+#[cfg(feature = "serial")]
+/// Adds the `\\.\` prefix to a bare `COMnn` path with `nn` ≥ 10, without which Windows
+/// can't open these ports. Other paths are returned as is.
+fn normalize_port_path(path: &str) -> std::borrow::Cow<'_, str> {
+    let is_high_com_port = path.split_at_checked(3).is_some_and(|(prefix, number)| {
+        prefix.eq_ignore_ascii_case("COM")
+            && number.bytes().all(|b| b.is_ascii_digit())
+            && number.parse::<u32>().is_ok_and(|n| n >= 10)
+    });
+
+    if is_high_com_port {
+        format!(r"\\.\{path}").into()
+    } else {
+        path.into()
+    }
+}
+
+#[cfg(feature = "serial")]
+/// Returns the path to pass to the OS when opening the serial port at `path`.
+fn os_port_path(path: &str) -> std::borrow::Cow<'_, str> {
+    if cfg!(windows) {
+        normalize_port_path(path)
+    } else {
+        path.into()
+    }
+}
+
+#[cfg(feature = "serial")]
+/// Adds the path of the port to the error from opening it.
+fn open_error(path: &str, e: serialport::Error) -> serialport::Error {
+    serialport::Error::new(e.kind(), format!("couldn't open {path}: {}", e.description))
+}
+
+#[cfg(feature = "serial")]
+/// Directory with the stable, per-device symlinks to the serial ports on Linux.
+const SERIAL_BY_ID_DIR: &str = "/dev/serial/by-id";
+
+#[cfg(feature = "serial")]
+/// Resolves the symlinks in a port path, e.g. `/dev/serial/by-id/usb-…-port0` to `/dev/ttyUSB0`.
+pub fn resolve_port_path(path: &str) -> Result<std::path::PathBuf> {
+    Ok(std::fs::canonicalize(path)?)
+}
+
+#[cfg(feature = "serial")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// Stable name of a serial port, see [`stable_port_candidates`].
+pub struct StablePortPath {
+    /// Path in `/dev/serial/by-id`, which stays the same across reboots.
+    pub stable_path: std::path::PathBuf,
+    /// Device the path currently points to, e.g. `/dev/ttyUSB0`.
+    pub target: std::path::PathBuf,
+}
+
+#[cfg(feature = "serial")]
+/// Lists the ports in `/dev/serial/by-id` with the devices they point to, so that the stable
+/// path of a port found by [`CPlusSerialInterface::discover`] can be persisted.
+///
+/// Returns an empty list if the directory doesn't exist (e.g. not on Linux or with no ports).
+pub fn stable_port_candidates() -> Result<Vec<StablePortPath>> {
+    stable_port_candidates_in(std::path::Path::new(SERIAL_BY_ID_DIR))
+}
+
+#[cfg(feature = "serial")]
+fn stable_port_candidates_in(dir: &std::path::Path) -> Result<Vec<StablePortPath>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut candidates = Vec::new();
+
+    for entry in entries {
+        let stable_path = entry?.path();
+
+        // Dangling links (of an unplugged device) are skipped
+        if let Ok(target) = std::fs::canonicalize(&stable_path) {
+            candidates.push(StablePortPath { stable_path, target });
+        }
+    }
+
+    candidates.sort_by(|a, b| a.stable_path.cmp(&b.stable_path));
+
+    Ok(candidates)
+}
+
+#[cfg(feature = "tcp")]
+#[derive(Debug)]
+/// TCP connection to a serial device server in raw TCP mode, see [`CPlusTcpInterface`].
+pub struct TcpTransport {
+    stream: std::net::TcpStream,
+    /// Address the connection was established to, used for reconnecting.
+    addr: std::net::SocketAddr,
+    connect_timeout: Duration,
+}
+
+#[cfg(feature = "tcp")]
+impl TcpTransport {
+    /// Connects to the first reachable address of `addr`.
+    fn connect(addr: impl std::net::ToSocketAddrs, connect_timeout: Duration, read_timeout: Duration) -> Result<Self> {
+        let mut last_error = None;
+
+        for addr in addr.to_socket_addrs()? {
+            match std::net::TcpStream::connect_timeout(&addr, connect_timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(read_timeout))?;
+                    stream.set_nodelay(true)?;
+
+                    return Ok(Self { stream, addr, connect_timeout });
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "no address to connect to"))
+            .into())
+    }
+}
+
+#[cfg(feature = "tcp")]
+impl std::io::Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        // Some platforms report an elapsed read timeout as `WouldBlock`
+        self.stream.read(buf).map_err(|e| match e.kind() {
+            std::io::ErrorKind::WouldBlock => std::io::ErrorKind::TimedOut.into(),
+            _ => e,
+        })
+    }
+}
+
+#[cfg(feature = "tcp")]
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[cfg(feature = "tcp")]
+impl Transport for TcpTransport {
+    fn clear_input(&mut self) -> Result<()> {
+        use std::io::Read;
+
+        self.stream.set_nonblocking(true)?;
+
+        let mut buf = [0u8; 64];
+
+        let drained = loop {
+            match self.stream.read(&mut buf) {
+                // The bridge closed the connection, the next read reports it
+                Ok(0) => break Ok(()),
+                Ok(_) => continue,
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.stream.set_nonblocking(false)?;
+
+        Ok(drained?)
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.stream.read_timeout().ok().flatten()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        Ok(self.stream.set_read_timeout(Some(timeout))?)
+    }
+}
+
+#[cfg(feature = "tcp")]
+/// Interface for the Continuity Plus UPSes behind a serial device server in raw TCP mode.
+pub type CPlusTcpInterface = CPlusGenericInterface<TcpTransport>;
+
+#[cfg(feature = "tcp")]
+impl CPlusTcpInterface {
+    /// Connects to the serial device server at `addr` with a 5s connect and read timeout.
+    pub fn connect(addr: impl std::net::ToSocketAddrs) -> Result<Self> {
+        Self::connect_with_timeouts(addr, Duration::from_millis(5000), Duration::from_millis(5000))
+    }
+
+    /// Connects to the serial device server at `addr` with the given timeouts.
+    pub fn connect_with_timeouts(
+        addr: impl std::net::ToSocketAddrs,
+        connect_timeout: Duration,
+        read_timeout: Duration,
+    ) -> Result<Self> {
+        Ok(Self::new(TcpTransport::connect(addr, connect_timeout, read_timeout)?))
+    }
+
+    /// Reestablishes the connection, e.g. after the device server dropped it.
+    /// The read timeout of the previous connection is kept.
+    pub fn reconnect(&mut self) -> Result<()> {
+        let read_timeout = self.port.timeout().unwrap_or(Duration::from_millis(5000));
+
+        self.port = TcpTransport::connect(self.port.addr, self.port.connect_timeout, read_timeout)?;
+
+        Ok(())
+    }
+}
+
+/// Longest COM port negotiation over a stream without a read timeout.
+#[cfg(feature = "rfc2217")]
+const NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Telnet protocol bytes used by the RFC 2217 transport.
+#[cfg(feature = "rfc2217")]
+mod telnet {
+    pub(super) const IAC: u8 = 255;
+    pub(super) const DONT: u8 = 254;
+    pub(super) const DO: u8 = 253;
+    pub(super) const WONT: u8 = 252;
+    pub(super) const WILL: u8 = 251;
+    pub(super) const SB: u8 = 250;
+    pub(super) const SE: u8 = 240;
+
+    pub(super) const BINARY: u8 = 0;
+    pub(super) const SUPPRESS_GO_AHEAD: u8 = 3;
+    pub(super) const COM_PORT_OPTION: u8 = 44;
+
+    pub(super) const SET_BAUDRATE: u8 = 1;
+    pub(super) const SET_DATASIZE: u8 = 2;
+    pub(super) const SET_PARITY: u8 = 3;
+    pub(super) const SET_STOPSIZE: u8 = 4;
+    /// Added to a COM-PORT-OPTION subcommand in the replies of the server.
+    pub(super) const SERVER_OFFSET: u8 = 100;
+
+    pub(super) const PARITY_NONE: u8 = 1;
+    pub(super) const STOPSIZE_1: u8 = 1;
+}
+
+/// State of the telnet decoder, kept between reads as sequences can be split.
+#[cfg(feature = "rfc2217")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TelnetState {
+    Data,
+    Iac,
+    /// Waiting for the option of a WILL/WONT/DO/DONT command.
+    Negotiation(u8),
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+#[cfg(feature = "rfc2217")]
+#[derive(Debug)]
+/// RFC 2217 (telnet COM port control) client over another transport, see [`CPlusRfc2217Interface`].
+pub struct Rfc2217Transport<S = TcpTransport> {
+    stream: S,
+    state: TelnetState,
+    /// Decoded data not read yet.
+    data: std::collections::VecDeque<u8>,
+    /// Contents of the subnegotiation being received.
+    subnegotiation: Vec<u8>,
+    /// Baud rate confirmed by the server.
+    baud_rate: Option<u32>,
+    /// Whether the server refused the COM-PORT-OPTION.
+    com_port_refused: bool,
+}
+
+#[cfg(feature = "rfc2217")]
+impl<S: Transport> Rfc2217Transport<S> {
+    /// Negotiates the COM port options over `stream`, setting the UPS baud rate, 8 data bits,
+    /// no parity and 1 stop bit. Fails if the server doesn't confirm the baud rate within the
+    /// read timeout of `stream` (or [`NEGOTIATION_TIMEOUT`] without one), or closes the connection.
+    pub fn negotiate(stream: S) -> Result<Self> {
+        use telnet::*;
+
+        let mut transport = Self {
+            stream,
+            state: TelnetState::Data,
+            data: Default::default(),
+            subnegotiation: vec![],
+            baud_rate: None,
+            com_port_refused: false,
+        };
+
+        let mut request = vec![
+            IAC, WILL, COM_PORT_OPTION,
+            IAC, WILL, BINARY,
+            IAC, DO, BINARY,
+            IAC, DO, SUPPRESS_GO_AHEAD,
+        ];
+
+        for (subcommand, value) in [
+            (SET_BAUDRATE, &cplus::SERIAL_BAUD_RATE.to_be_bytes()[..]),
+            (SET_DATASIZE, &[8]),
+            (SET_PARITY, &[PARITY_NONE]),
+            (SET_STOPSIZE, &[STOPSIZE_1]),
+        ] {
+            request.extend_from_slice(&[IAC, SB, COM_PORT_OPTION, subcommand]);
+            request.extend(escape(value));
+            request.extend_from_slice(&[IAC, SE]);
+        }
+
+        transport.stream.write_all(&request)?;
+        transport.stream.flush()?;
+
+        let timeout = transport.stream.timeout();
+        let deadline = Instant::now() + timeout.unwrap_or(NEGOTIATION_TIMEOUT);
+        let mut buf = [0u8; 64];
+
+        while transport.baud_rate.is_none() && !transport.com_port_refused {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            if remaining.is_zero() {
+                return Err(crate::Error::Timeout { waiting_for: "the COM port options to be confirmed".to_string() });
+            }
+
+            // No read outlasts the deadline, however the server keeps sending
+            transport.stream.set_timeout(remaining)?;
+
+            if transport.receive(&mut buf)? == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "the server closed the connection during the negotiation",
+                ).into());
+            }
+        }
+
+        if let Some(timeout) = timeout {
+            transport.stream.set_timeout(timeout)?;
+        }
+
+        match transport.baud_rate {
+            Some(baud_rate) if baud_rate == cplus::SERIAL_BAUD_RATE => Ok(transport),
+            baud_rate => Err(crate::Error::UnexpectedResponse {
+                expected: format!("COM port baud rate {}", cplus::SERIAL_BAUD_RATE),
+                got: baud_rate.map_or("COM-PORT-OPTION refused".to_string(), |b| format!("baud rate {b}")),
+            }),
+        }
+    }
+
+    /// Reads from the stream and decodes the received bytes.
+    /// Returns the number of bytes read from the stream (0 if it was closed).
+    fn receive(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.stream.read(buf)?;
+
+        for &byte in buf.get(..read).unwrap_or_default() {
+            self.decode(byte)?;
+        }
+
+        Ok(read)
+    }
+
+    fn decode(&mut self, byte: u8) -> std::io::Result<()> {
+        use telnet::*;
+
+        self.state = match (self.state, byte) {
+            (TelnetState::Data, IAC) => TelnetState::Iac,
+            (TelnetState::Data, _) => {
+                self.data.push_back(byte);
+                TelnetState::Data
+            }
+            (TelnetState::Iac, IAC) => {
+                self.data.push_back(IAC);
+                TelnetState::Data
+            }
+            (TelnetState::Iac, WILL | WONT | DO | DONT) => TelnetState::Negotiation(byte),
+            (TelnetState::Iac, SB) => {
+                self.subnegotiation.clear();
+                TelnetState::Subnegotiation
+            }
+            // Other commands (NOP, GA, ...) carry no data
+            (TelnetState::Iac, _) => TelnetState::Data,
+            (TelnetState::Negotiation(verb), option) => {
+                self.negotiate_option(verb, option)?;
+                TelnetState::Data
+            }
+            (TelnetState::Subnegotiation, IAC) => TelnetState::SubnegotiationIac,
+            (TelnetState::Subnegotiation, _) => {
+                self.subnegotiation.push(byte);
+                TelnetState::Subnegotiation
+            }
+            (TelnetState::SubnegotiationIac, SE) => {
+                self.subnegotiation_done();
+                TelnetState::Data
+            }
+            (TelnetState::SubnegotiationIac, _) => {
+                self.subnegotiation.push(byte);
+                TelnetState::Subnegotiation
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Answers the option negotiation of the server, refusing options which weren't requested.
+    fn negotiate_option(&mut self, verb: u8, option: u8) -> std::io::Result<()> {
+        use telnet::*;
+
+        let reply = match (verb, option) {
+            (DO, COM_PORT_OPTION | BINARY) | (WILL, BINARY | SUPPRESS_GO_AHEAD) => None,
+            (DONT, COM_PORT_OPTION) => {
+                self.com_port_refused = true;
+                None
+            }
+            (DO, _) => Some(WONT),
+            (WILL, _) => Some(DONT),
+            _ => None,
+        };
+
+        if let Some(reply) = reply {
+            self.stream.write_all(&[IAC, reply, option])?;
+        }
+
+        Ok(())
+    }
+
+    fn subnegotiation_done(&mut self) {
+        use telnet::*;
+
+        if let [COM_PORT_OPTION, subcommand, value @ ..] = self.subnegotiation.as_slice()
+            && *subcommand == SET_BAUDRATE + SERVER_OFFSET
+            && let Ok(value) = <[u8; 4]>::try_from(value)
+        {
+            self.baud_rate = Some(u32::from_be_bytes(value));
+        }
+    }
+}
+
+/// Doubles the IAC bytes in `data`, as required for sending them over telnet.
+#[cfg(feature = "rfc2217")]
+fn escape(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+
+    for &b in data {
+        if b == telnet::IAC {
+            escaped.push(telnet::IAC);
+        }
+
+        escaped.push(b);
+    }
+
+    escaped
+}
+
+#[cfg(feature = "rfc2217")]
+impl<S: Transport> std::io::Read for Rfc2217Transport<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut raw = [0u8; 64];
+
+        while self.data.is_empty() {
+            if self.receive(&mut raw)? == 0 {
+                return Ok(0);
+            }
+        }
+
+        let read = buf.len().min(self.data.len());
+
+        for (b, d) in buf.iter_mut().zip(self.data.drain(..read)) {
+            *b = d;
+        }
+
+        Ok(read)
+    }
+}
+
+#[cfg(feature = "rfc2217")]
+impl<S: Transport> Write for Rfc2217Transport<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.stream.write_all(&escape(buf))?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[cfg(feature = "rfc2217")]
+impl<S: Transport> Transport for Rfc2217Transport<S> {
+    fn clear_input(&mut self) -> Result<()> {
+        self.stream.clear_input()?;
+
+        // A partially received telnet sequence was discarded with the input
+        self.data.clear();
+        self.state = TelnetState::Data;
+
+        Ok(())
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.stream.timeout()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.stream.set_timeout(timeout)
+    }
+
+    /// The baud rate confirmed by the server.
+    fn baud_rate(&self) -> Option<u32> {
+        self.baud_rate
+    }
+}
+
+#[cfg(feature = "rfc2217")]
+/// Interface for the Continuity Plus UPSes behind a serial device server speaking RFC 2217.
+pub type CPlusRfc2217Interface = CPlusGenericInterface<Rfc2217Transport>;
+
+#[cfg(feature = "rfc2217")]
+impl CPlusRfc2217Interface {
+    /// Connects to the RFC 2217 server at `addr` with a 5s connect and read timeout.
+    pub fn connect(addr: impl std::net::ToSocketAddrs) -> Result<Self> {
+        Self::connect_with_timeouts(addr, Duration::from_millis(5000), Duration::from_millis(5000))
+    }
+
+    /// Connects to the RFC 2217 server at `addr` with the given timeouts.
+    pub fn connect_with_timeouts(
+        addr: impl std::net::ToSocketAddrs,
+        connect_timeout: Duration,
+        read_timeout: Duration,
+    ) -> Result<Self> {
+        let stream = TcpTransport::connect(addr, connect_timeout, read_timeout)?;
+
+        Ok(Self::new(Rfc2217Transport::negotiate(stream)?))
+    }
+
+    /// Reestablishes the connection and negotiates the COM port options again.
+    pub fn reconnect(&mut self) -> Result<()> {
+        let tcp = &self.port.stream;
+        let read_timeout = tcp.timeout().unwrap_or(Duration::from_millis(5000));
+
+        let stream = TcpTransport::connect(tcp.addr, tcp.connect_timeout, read_timeout)?;
+
+        self.port = Rfc2217Transport::negotiate(stream)?;
+
+        Ok(())
+    }
+}
+
+impl<T: Transport> CPlusGenericInterface<T> {
+    /// Creates an interface communicating over `port`.
+    pub fn new(port: T) -> Self {
+        Self {
+            port,
+            max_frame_len: MAX_FRAME_LEN,
+            input: vec![],
+            frame: vec![],
+            metrics: QueryMetrics::default(),
+            last_success: LastSuccess::default(),
+            command_gap: DEFAULT_COMMAND_GAP,
+            last_command: None,
+            terminator: LineTerminator::Cr,
+            echo_mode: EchoMode::Off,
+            pending_echo: None,
+            address: None,
+            battery: None,
+        }
+    }
+
+    /// Returns the battery pack derived from the last rating read with
+    /// [`CPlusInterface::query_ups_rating`] (or a snapshot), which the battery capacity of
+    /// the following status queries is computed for.
+    pub fn battery_configuration(&self) -> Option<cplus::BatteryConfiguration> {
+        self.battery
+    }
+
+    /// Stores the battery pack of `rating` for the following status queries.
+    fn record_rating(&mut self, rating: Result<cplus::UPSRating>) -> Result<cplus::UPSRating> {
+        if let Ok(rating) = &rating {
+            self.battery = rating.battery_configuration().or(self.battery);
+        }
+
+        rating
+    }
+
+    /// Computes the battery capacity of `status` for the known battery pack.
+    fn with_battery(&self, mut status: cplus::StatusInquiryResponse) -> cplus::StatusInquiryResponse {
+        status.resolve_battery_capacity(self.battery.as_ref());
+
+        status
+    }
+
+    /// Returns a reference to the underlying transport.
+    pub fn get_ref(&self) -> &T {
+        &self.port
+    }
+
+    /// Returns the underlying transport.
+    pub fn into_inner(self) -> T {
+        self.port
+    }
+
+    /// Writes data to the port along with the line terminator.
+     fn write_data(&mut self, msg: &[u8]) -> Result<()> {
+        let address = self.address.map(|address| address.0);
+
+        self.port.write_all(address.as_slice())?;
+        self.port.write_all(msg)?;
+        self.terminator.write(&mut self.port)?;
+
+        trace!("Wrote msg {:?}", String::from_utf8_lossy(msg));
+
+        // The line feed of CR LF is skipped along with any other leading one
+        self.pending_echo = (self.echo_mode != EchoMode::Off)
+            .then(|| [address.as_slice(), msg, &[self.terminator.end_byte()]].concat());
+
+        Ok(())
+    }
+
+    /// Sets how long a query waits for its complete reply. A timeout shorter than
+    /// [`CPlusGenericInterface::min_query_timeout`] is raised to it, with a warning.
+    pub fn set_query_timeout(&mut self, timeout: Duration) -> Result<()> {
+        if timeout.is_zero() {
+            return Err(crate::Error::InvalidParameter("the timeout must not be zero".to_string()));
+        }
+
+        let timeout = self.clamp_timeout(timeout);
+
+        self.port.set_timeout(timeout)
+    }
+
+    /// Returns the shortest query timeout accepted, long enough for the longest message
+    /// (see [`CPlusGenericInterface::set_max_frame_len`]) to arrive at the baud rate of
+    /// the transport. `None` if the transport has no baud rate, e.g. a TCP connection.
+    pub fn min_query_timeout(&self) -> Option<Duration> {
+        let terminator_len = if matches!(self.terminator, LineTerminator::CrLf) { 2 } else { 1 };
+        let frame_len = usize::from(self.address.is_some()) + self.max_frame_len + terminator_len;
+
+        Some(min_read_timeout(self.port.baud_rate()?, frame_len))
+    }
+
+    /// Raises `timeout` to [`CPlusGenericInterface::min_query_timeout`], warning if it's shorter.
+    fn clamp_timeout(&self, timeout: Duration) -> Duration {
+        match self.min_query_timeout() {
+            Some(min) if timeout < min => {
+                warn!("The timeout of {timeout:?} is too short for the baud rate, using {min:?}");
+                min
+            }
+            _ => timeout,
+        }
+    }
+
+    /// Sets how the commands and replies are terminated, [`LineTerminator::Cr`] by default.
+    ///
+    /// A line feed following the end byte is skipped in any case (unless it's the end
+    /// byte itself), so it isn't taken for the start of the next reply.
+    pub fn set_line_terminator(&mut self, terminator: LineTerminator) {
+        self.terminator = terminator;
+    }
+
+    /// Sets the unit addressed on a multi-drop bus, `None` (the default) for a UPS which
+    /// isn't on one. Its address is sent before every command, and the replies starting
+    /// with another address are discarded. See [`CPlusBusInterface`] for several units.
+    ///
+    /// Fails with [`crate::Error::InvalidParameter`] if the address is the end byte or
+    /// a line feed, which can't start a message.
+    pub fn set_bus_address(&mut self, address: Option<BusAddress>) -> Result<()> {
+        if let Some(BusAddress(byte)) = address
+            && (byte == self.terminator.end_byte() || byte == b'\n')
+        {
+            return Err(crate::Error::InvalidParameter(format!("the bus address {byte:#04x} ends a message")));
+        }
+
+        self.address = address;
+
+        Ok(())
+    }
+
+    /// Sets how the echo of the commands is handled, [`EchoMode::Off`] by default.
+    ///
+    /// The echo is the command along with its line terminator, skipped before reading the
+    /// reply. It shares the time budget of the reply.
+    pub fn set_echo_mode(&mut self, echo_mode: EchoMode) {
+        self.echo_mode = echo_mode;
+    }
+
+    /// Sets the shortest time between the end of a command and the start of the next one,
+    /// which is waited out before sending the next command. The UPS can drop or garble
+    /// its replies when polled back-to-back. Defaults to [`DEFAULT_COMMAND_GAP`].
+    pub fn set_command_gap(&mut self, gap: Duration) {
+        self.command_gap = gap;
+    }
+
+    /// Waits until the command gap since the last command elapses.
+    fn wait_for_command_gap(&self) {
+        let Some(last_command) = self.last_command else {
+            return;
+        };
+
+        let remaining = self.command_gap.saturating_sub(last_command.elapsed());
+
+        if !remaining.is_zero() {
+            trace!("Waiting {remaining:?} before the next command");
+            std::thread::sleep(remaining);
+        }
+    }
+
+    /// Returns the latencies of the commands sent so far.
+    pub fn metrics(&self) -> &QueryMetrics {
+        &self.metrics
+    }
+
+    pub fn reset_metrics(&mut self) {
+        self.metrics.reset();
+    }
+
+    /// Returns when a query last got a successful reply, `None` if none did yet.
+    /// Control commands, which the UPS usually doesn't reply to, don't count.
+    pub fn last_success(&self) -> Option<SystemTime> {
+        self.last_success.get()
+    }
+
+    /// Returns when the `command` query (e.g. `"Q1"`) last got a successful reply.
+    pub fn last_success_of(&self, command: &str) -> Option<SystemTime> {
+        self.last_success.command(command)
+    }
+
+    /// Returns how long ago a query last got a successful reply, `None` if none did yet.
+    pub fn staleness(&self) -> Option<Duration> {
+        self.last_success.staleness()
+    }
+
+    /// Sets the length of the longest message accepted from the UPS (without the end byte).
+    /// Longer messages, e.g. noise due to a wrong baud rate, fail with [`crate::Error::FrameTooLong`].
+    pub fn set_max_frame_len(&mut self, limit: usize) -> Result<()> {
+        if limit == 0 {
+            return Err(crate::Error::InvalidParameter("the frame length limit must not be zero".to_string()));
+        }
+
+        self.max_frame_len = limit;
+
+        Ok(())
+    }
+
+    /// Runs `f` with the transport timeout set to `timeout` (at least the
+    /// [minimum](Self::min_query_timeout)), restoring it afterwards.
+    fn with_timeout<R>(&mut self, timeout: Duration, f: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        let previous = self.port.timeout();
+        self.port.set_timeout(timeout.max(self.min_query_timeout().unwrap_or_default()))?;
+
+        let result = f(self);
+
+        if let Some(previous) = previous {
+            self.port.set_timeout(previous)?;
+        }
+
+        result
+    }
+
+    /// Reads data from the port into the frame buffer until an end byte (CR) is encountered.
+    /// The timeout of the transport applies to the whole message, not to each byte.
+    ///
+    /// The port is read in chunks, the bytes received after the end byte are kept
+    /// for the next message until the input is cleared before the next command.
+    ///
+    /// Fails with [`crate::Error::Timeout`] if the UPS doesn't send anything before the port
+    /// times out, or with [`crate::Error::IncompleteResponse`] if the end byte doesn't arrive.
+     fn read_data(&mut self) -> Result<()> {
+        self.read_reply(None)
+    }
+
+    /// Reads messages like [`CPlusGenericInterface::read_data`] until one in the `reply` format
+    /// arrives, discarding stale replies (e.g. to an earlier query which timed out).
+    ///
+    /// The bytes before the start of a reply (e.g. the tail of a message sent before the port
+    /// was opened) are skipped, see [`FrameScanner`].
+    ///
+    /// Fails with [`crate::Error::UnexpectedResponse`] if only stale replies arrive in time, or
+    /// with [`crate::Error::InvalidFormat`] if the reply can't be found among the garbage.
+    fn read_reply(&mut self, reply: Option<ReplyFormat>) -> Result<()> {
+        // A zero timeout makes the reads non-blocking, there's no time budget to enforce
+        let Some(timeout) = self.port.timeout().filter(|timeout| !timeout.is_zero()) else {
+            return self.read_reply_until(None, reply);
+        };
+
+        let result = self.read_reply_until(Some(Instant::now() + timeout), reply);
+
+        self.port.set_timeout(timeout)?;
+
+        result
+    }
+
+    fn read_reply_until(&mut self, deadline: Option<Instant>, reply: Option<ReplyFormat>) -> Result<()> {
+        let prefix = reply.map(|reply| reply.prefix);
+        let mut stale: Option<Vec<u8>> = None;
+
+        loop {
+            match (self.read_data_until(deadline, reply), &stale) {
+                (Err(crate::Error::Timeout { .. }), Some(stale)) => return Err(unexpected_reply(prefix, stale)),
+                (result, _) => result?,
+            }
+
+            if let Some(BusAddress(address)) = self.address {
+                match self.frame.first() {
+                    Some(&first) if first == address => {
+                        self.frame.remove(0);
+                    }
+                    _ => {
+                        warn!("Discarding a reply from a unit other than {address}: {:?}", String::from_utf8_lossy(&self.frame));
+                        stale = Some(self.frame.clone());
+                        continue;
+                    }
+                }
+            }
+
+            match prefix {
+                Some(prefix) if self.frame.first() != Some(&prefix) => {
+                    debug!("Discarding stale reply {:?}", String::from_utf8_lossy(&self.frame));
+                    stale = Some(self.frame.clone());
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn read_data_until(&mut self, deadline: Option<Instant>, reply: Option<ReplyFormat>) -> Result<()> {
+        trace!("Reading buffer");
+
+        // Nothing of an earlier message must be left over if this read fails
+        self.frame.clear();
+
+        let mut chunk = [0u8; READ_CHUNK_LEN];
+
+        let end_byte = self.terminator.end_byte();
+        let mut scanner = FrameScanner::new(reply, end_byte, self.max_frame_len);
+
+        if self.address.is_some() {
+            scanner = scanner.addressed();
+        }
+
+        let mut echo = self.pending_echo.take();
+
+        loop {
+            if let Some(expected) = &echo {
+                if end_byte != b'\n' {
+                    let line_feeds = self.input.iter().take_while(|&&b| b == b'\n').count();
+                    self.input.drain(..line_feeds);
+                }
+
+                // Until then, the input is only a part of the echo and more of it is to be read
+                if self.skip_echo(expected)? {
+                    echo = None;
+                }
+            }
+
+            if echo.is_none() && !self.input.is_empty() {
+                match scanner.push(&self.input, &mut self.frame) {
+                    Ok(Some(used)) => {
+                        self.input.drain(..used);
+
+                        trace!("Read buffer {:?}\n", String::from_utf8_lossy(&self.frame));
+
+                        return Ok(());
+                    }
+                    Ok(None) => self.input.clear(),
+                    Err(crate::Error::FrameTooLong { .. }) => return Err(self.discard_frame()),
+                    Err(e) => {
+                        self.input.clear();
+                        return Err(e);
+                    }
+                }
+            }
+
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+
+                if remaining.is_zero() {
+                    return Err(self.incomplete_frame(&scanner));
+                }
+
+                self.port.set_timeout(remaining)?;
+            }
+
+            // Not reading past the byte making the frame too long, what follows is left to the transport
+            let held = scanner.pending() + self.input.len();
+            let wanted = (self.max_frame_len + 1).saturating_sub(held).clamp(1, READ_CHUNK_LEN);
+            let chunk = chunk.get_mut(..wanted).unwrap_or(&mut []);
+
+            match self.port.read(chunk) {
+                Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+                Ok(read) => self.input.extend(chunk.iter().take(read)),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) if is_timeout(&e) => return Err(self.incomplete_frame(&scanner)),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// The error of a read timing out, with the part of the frame (or of the echo) read so far.
+    /// Only garbage arriving instead of the reply fails with [`crate::Error::InvalidFormat`].
+    fn incomplete_frame(&mut self, scanner: &FrameScanner) -> crate::Error {
+        let mut received = std::mem::take(&mut self.frame);
+        received.append(&mut self.input);
+
+        if received.is_empty() && scanner.skipped_any() {
+            return crate::Error::invalid_format("frame", &[], "only garbage arrived instead of the reply");
+        }
+
+        reply_timeout(received)
+    }
+
+    /// Skips the echo `expected` at the start of the input. Returns `false` while the input
+    /// is only a part of the echo, and `true` once it's skipped or turns out to be missing.
+    ///
+    /// Fails with [`crate::Error::UnexpectedResponse`] if the echo is expected but the input
+    /// differs from it, discarding the input.
+    fn skip_echo(&mut self, expected: &[u8]) -> Result<bool> {
+        let len = self.input.len().min(expected.len());
+
+        if self.input.get(..len) == expected.get(..len) {
+            if len < expected.len() {
+                return Ok(false);
+            }
+
+            trace!("Skipping echo {:?}", String::from_utf8_lossy(expected));
+            self.input.drain(..len);
+
+            return Ok(true);
+        }
+
+        if self.echo_mode == EchoMode::Expect {
+            let got = std::mem::take(&mut self.input);
+
+            return Err(crate::Error::UnexpectedResponse {
+                expected: format!("the echo {:?}", String::from_utf8_lossy(expected)),
+                got: String::from_utf8_lossy(&got).into_owned(),
+            });
+        }
+
+        debug!("No echo of {:?}, reading the reply as is", String::from_utf8_lossy(expected));
+
+        Ok(true)
+    }
+
+    /// Discards the overlong frame being read, along with the input waiting in the transport.
+    fn discard_frame(&mut self) -> crate::Error {
+        trace!("Discarding {} bytes without an end byte", self.input.len());
+
+        self.input.clear();
+
+        match self.port.clear_input() {
+            Ok(()) => crate::Error::FrameTooLong { limit: self.max_frame_len },
+            Err(e) => e,
+        }
+    }
+
+    /// Discards any data waiting to be read, including the bytes read past the last message.
+    fn clear_input(&mut self) -> Result<()> {
+        self.input.clear();
+        self.port.clear_input()
+    }
+
+    /// Discards the stale input before sending a command expecting a reply starting with `prefix`.
+    ///
+    /// The complete messages waiting to be read (e.g. late replies to earlier queries) are
+    /// dropped. A partial message is kept when the reply has a known prefix, to be completed
+    /// and discarded as a stale reply by [`CPlusGenericInterface::read_reply`]. Transports
+    /// which can't tell what's waiting have their whole input cleared instead.
+    fn resync(&mut self, prefix: Option<u8>) -> Result<()> {
+        let Some(mut waiting) = self.port.waiting_input()? else {
+            return self.clear_input();
+        };
+
+        let mut chunk = [0u8; READ_CHUNK_LEN];
+
+        while waiting > 0 {
+            let chunk = chunk.get_mut(..waiting.min(READ_CHUNK_LEN)).unwrap_or(&mut []);
+
+            match self.port.read(chunk) {
+                Ok(0) => break,
+                Ok(read) => {
+                    self.input.extend(chunk.iter().take(read));
+                    waiting = waiting.saturating_sub(read);
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) if is_timeout(&e) => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        let end_byte = self.terminator.end_byte();
+
+        if let Some(end) = self.input.iter().rposition(|&b| b == end_byte) {
+            let stale: Vec<u8> = self.input.drain(..=end).collect();
+
+            debug!("Discarding stale input {:?}", String::from_utf8_lossy(&stale));
+        }
+
+        // Without a prefix, the rest of the partial message couldn't be told apart from the reply
+        if !self.input.is_empty() && (prefix.is_none() || self.input.len() > self.max_frame_len) {
+            debug!("Discarding partial stale message {:?}", String::from_utf8_lossy(&self.input));
+
+            self.input.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Sends `query` and returns the reply, without the end byte. Escape hatch for
+    /// commands this crate doesn't implement, such as vendor-specific ones.
+    ///
+    /// The line terminator is appended (unless `query` already ends with it) and stale
+    /// input is discarded beforehand, the reply is left for the caller to interpret.
+    pub fn raw_query(&mut self, query: &[u8]) -> Result<Vec<u8>> {
+        let query = self.terminator.strip(query);
+
+        self.wait_for_command_gap();
+
+        let started = Instant::now();
+        let result = self.send_query(query).map(|()| self.frame.clone());
+        self.metrics.record(query, started.elapsed(), &result);
+        self.last_success.record(query, &result);
+        self.last_command = Some(Instant::now());
+
+        result
+    }
+
+    /// Sends several queries like [`CPlusGenericInterface::raw_query`], returning their replies
+    /// in the same order.
+    ///
+    /// The stale input is discarded once, before the first query, while the command gap is
+    /// still waited out between the queries. Each reply is checked against the prefix of its
+    /// own query, so a garbled or missing reply fails only its slot, not the following ones.
+    ///
+    /// Fails only if the stale input can't be discarded.
+    pub fn query_many(&mut self, queries: &[&[u8]]) -> Result<Vec<Result<Vec<u8>>>> {
+        let mut replies = Vec::with_capacity(queries.len());
+
+        for (i, query) in queries.iter().enumerate() {
+            let query = self.terminator.strip(query);
+            let reply = ReplyFormat::of(query);
+
+            self.wait_for_command_gap();
+
+            if i == 0 {
+                self.resync(reply.map(|reply| reply.prefix))?;
+            }
+
+            trace!("Querying with message {:?}", String::from_utf8_lossy(query));
+
+            let started = Instant::now();
+            let result = self.write_data(query).and_then(|()| self.read_reply(reply)).map(|()| self.frame.clone());
+            self.metrics.record(query, started.elapsed(), &result);
+            self.last_success.record(query, &result);
+            self.last_command = Some(Instant::now());
+
+            replies.push(result);
+        }
+
+        Ok(replies)
+    }
+
+    /// Sends `query` (without the end byte) and reads the reply into the frame buffer,
+    /// see [`CPlusGenericInterface::raw_query`].
+    fn send_query(&mut self, query: &[u8]) -> Result<()> {
+        trace!("Querying with message {:?}", String::from_utf8_lossy(query));
+
+        let reply = ReplyFormat::of(query);
+
+        // A synchronization error can cause a partial packet to be in the input buffer
+        self.resync(reply.map(|reply| reply.prefix))?;
+
+        self.write_data(query)?;
+
+        self.read_reply(reply)
+    }
+
+    /// Sends a control command and reads the reply into the frame buffer, which is left empty
+    /// without one. The UPS usually doesn't reply to control commands, so the reply is only
+    /// awaited for a short time.
+    fn control_query(&mut self, command: &[u8]) -> Result<()> {
+        self.resync(None)?;
+
+        self.write_data(command)?;
+
+        let reply = self.with_timeout(CONTROL_REPLY_TIMEOUT, Self::read_data);
+
+        match reply {
+            Err(crate::Error::Timeout { .. }) => Ok(()),
+            reply => reply,
+        }
+    }
+
+    /// Sends the command to the device and returns its processed response.
+    pub fn execute<C>(&mut self, command: &C) -> Result<C::Response>
+    where
+        C: cplus::Command,
+        <C::Response as FromBytes>::Err: Into<crate::Error>,
+    {
+        let command = command.to_bytes();
+
+        self.wait_for_command_gap();
+
+        let started = Instant::now();
+        let result = self.send_command::<C>(&command);
+        self.metrics.record(&command, started.elapsed(), &result);
+
+        if C::EXPECTS_REPLY {
+            self.last_success.record(&command, &result);
+        }
+        self.last_command = Some(Instant::now());
+
+        result
+    }
+
+    /// Sends the encoded command `C` and returns its processed response.
+    fn send_command<C>(&mut self, command: &[u8]) -> Result<C::Response>
+    where
+        C: cplus::Command,
+        <C::Response as FromBytes>::Err: Into<crate::Error>,
+    {
+        if !C::EXPECTS_REPLY {
+            self.control_query(command)?;
+
+            return C::Response::from_bytes(&self.frame).map_err(|e| e.into());
+        }
+
+        self.send_query(command)?;
+
+        parse_reply::<C>(&self.frame)
+    }
+
+    /// Sends the command like [`CPlusGenericInterface::execute`], but waits at most
+    /// `timeout` for the reply instead of the query timeout. Raised to
+    /// [`CPlusGenericInterface::min_query_timeout`] like the query timeout.
+    pub fn query_with_timeout<C>(&mut self, command: &C, timeout: Duration) -> Result<C::Response>
+    where
+        C: cplus::Command,
+        <C::Response as FromBytes>::Err: Into<crate::Error>,
+    {
+        let timeout = self.clamp_timeout(timeout);
+
+        self.with_timeout(timeout, |iface| iface.execute(command))
+    }
+
+    /// Checks whether a UPS is connected by querying its status, then its information.
+    /// Waits at most [`PROBE_TIMEOUT`] for each reply.
+    #[cfg(feature = "serial")]
+    fn probe(&mut self) -> Result<(cplus::StatusInquiryResponse, Option<cplus::UPSInformation>)> {
+        let status = self.query_with_timeout(&cplus::StatusInquiry, PROBE_TIMEOUT)?;
+        let info = self.query_with_timeout(&cplus::InformationInquiry, PROBE_TIMEOUT).ok();
+
+        Ok((status, info))
+    }
+}
+
+impl<T: Transport> RawQuery for CPlusGenericInterface<T> {
+    fn raw_query(&mut self, query: &[u8]) -> Result<Vec<u8>> {
+        CPlusGenericInterface::raw_query(self, query)
+    }
+}
+
+impl<T: Transport> CPlusInterface for CPlusGenericInterface<T> {
+    fn query_ups_status(&mut self) -> Result<cplus::StatusInquiryResponse> {
+        let status = self.execute(&cplus::StatusInquiry)?;
+
+        Ok(self.with_battery(status))
+    }
+
+    /// Sends the queries in one batch, see [`CPlusGenericInterface::query_many`].
+    fn query_snapshot(&mut self) -> Result<cplus::UpsSnapshot> {
+        use cplus::Command;
+
+        let queried_at = SystemTime::now();
+        let queries = [
+            cplus::StatusInquiry.to_bytes(),
+            cplus::ExtraPowerInfoInquiry.to_bytes(),
+            cplus::Autonomy.to_bytes(),
+            cplus::RatingInquiry.to_bytes(),
+        ];
+
+        let replies = self.query_many(&queries.each_ref().map(Vec::as_slice))?;
+        let [status, extra_power_info, autonomy, rating] = <[_; 4]>::try_from(replies).map_err(|replies| {
+            crate::Error::invalid_format("UpsSnapshot", &[], format!("expected 4 replies, got {}", replies.len()))
+        })?;
+
+        let rating = self.record_rating(rating.and_then(|reply| parse_reply::<cplus::RatingInquiry>(&reply)));
+        let status = status.and_then(|reply| parse_reply::<cplus::StatusInquiry>(&reply))?;
+        let mut snapshot = cplus::UpsSnapshot::new(self.with_battery(status), queried_at);
+
+        snapshot.extra_power_info = snapshot.section(
+            "extra_power_info",
+            extra_power_info.and_then(|reply| parse_reply::<cplus::ExtraPowerInfoInquiry>(&reply)),
+        );
+        snapshot.autonomy = snapshot.section("autonomy", autonomy.and_then(|reply| parse_reply::<cplus::Autonomy>(&reply)));
+        snapshot.rating = snapshot.section("rating", rating);
+
+        Ok(snapshot)
+    }
+
+    fn query_extra_power_info(&mut self) -> Result<cplus::ExtraPowerInfoResponse> {
+        self.execute(&cplus::ExtraPowerInfoInquiry)
+    }
+
+    fn query_alarm(&mut self) -> Result<cplus::AlarmInquiryResponse> {
+        self.execute(&cplus::AlarmInquiry)
+    }
+
+    fn query_ups_autonomy(&mut self) -> Result<cplus::AutonomyResponse> {
+        self.execute(&cplus::Autonomy)
+    }
+
+    fn query_ups_battery_life(&mut self) -> Result<cplus::BatteryLifeResponse> {
+        self.execute(&cplus::BatteryLife)
+    }
+
+    fn query_ups_info(&mut self) -> Result<cplus::UPSInformation> {
+        self.execute(&cplus::InformationInquiry)
+    }
+
+    fn query_ups_rating(&mut self) -> Result<cplus::UPSRating> {
+        let rating = self.execute(&cplus::RatingInquiry);
+
+        self.record_rating(rating)
+    }
+
+    fn query_test_result(&mut self) -> Result<cplus::TestResultResponse> {
+        self.execute(&cplus::TestResultInquiry)
+    }
+
+    fn shutdown(&mut self, delay: cplus::ShutdownDelay) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::Shutdown { delay })
+    }
+
+    fn shutdown_and_restore(&mut self, shutdown: cplus::ShutdownDelay, restore_minutes: u16) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::ShutdownAndRestore::new(shutdown, restore_minutes)?)
+    }
+
+    fn cancel_shutdown(&mut self) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::CancelShutdown)
+    }
+
+    fn start_self_test(&mut self) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::SelfTest)
+    }
+
+    fn start_timed_self_test(&mut self, minutes: u8) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::TimedSelfTest::new(minutes)?)
+    }
+
+    fn start_test_until_battery_low(&mut self) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::TestUntilBatteryLow)
+    }
+
+    fn cancel_test(&mut self) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::CancelTest)
+    }
+
+    fn toggle_beeper(&mut self) -> Result<bool> {
+        let beeper_on = self.query_ups_status()?.ups_status.beeper_on();
+
+        self.execute(&cplus::ToggleBeeper)?;
+
+        let toggled = self.query_ups_status()?.ups_status.beeper_on();
+
+        if toggled == beeper_on {
+            return Err(crate::Error::UnexpectedResponse {
+                expected: format!("beeper_on = {}", !beeper_on),
+                got: format!("beeper_on = {toggled}"),
+            });
+        }
+
+        Ok(toggled)
+    }
+
+    fn set_outlet(&mut self, outlet: cplus::Outlet, on: bool) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::OutletSwitch { outlet, on })
+    }
+}
+
+/// Asynchronous counterpart of [`CPlusInterface`], see its methods for details.
+#[cfg(feature = "async-core")]
+#[async_trait::async_trait]
+pub trait AsyncCPlusInterface: Send {
+    /// See [`CPlusInterface::query_ups_status`].
+    async fn query_ups_status(&mut self) -> Result<cplus::StatusInquiryResponse>;
+
+    /// See [`CPlusInterface::query_extra_power_info`].
+    async fn query_extra_power_info(&mut self) -> Result<cplus::ExtraPowerInfoResponse>;
+
+    /// See [`CPlusInterface::query_alarm`].
+    async fn query_alarm(&mut self) -> Result<cplus::AlarmInquiryResponse>;
+
+    /// See [`CPlusInterface::query_ups_autonomy`].
+    async fn query_ups_autonomy(&mut self) -> Result<cplus::AutonomyResponse>;
+
+    /// See [`CPlusInterface::query_ups_battery_life`].
+    async fn query_ups_battery_life(&mut self) -> Result<cplus::BatteryLifeResponse>;
+
+    /// See [`CPlusInterface::query_ups_info`].
+    async fn query_ups_info(&mut self) -> Result<cplus::UPSInformation>;
+
+    /// See [`CPlusInterface::query_ups_rating`].
+    async fn query_ups_rating(&mut self) -> Result<cplus::UPSRating>;
+
+    /// See [`CPlusInterface::query_test_result`].
+    async fn query_test_result(&mut self) -> Result<cplus::TestResultResponse>;
+
+    /// See [`CPlusInterface::query_firmware_version`].
+    async fn query_firmware_version(&mut self) -> Result<cplus::FirmwareVersion> {
+        let info = self.query_ups_info().await?;
+
+        cplus::FirmwareVersion::from_bytes(info.version.as_bytes())
+    }
+
+    /// See [`CPlusInterface::health_check`].
+    async fn health_check(&mut self) -> Result<HealthReport> {
+        let started = Instant::now();
+        let status = self.query_ups_status().await?;
+
+        Ok(HealthReport::new(started.elapsed(), &status.ups_status))
+    }
+
+    /// See [`CPlusInterface::query_snapshot`].
+    async fn query_snapshot(&mut self) -> Result<cplus::UpsSnapshot> {
+        let queried_at = std::time::SystemTime::now();
+        let mut snapshot = cplus::UpsSnapshot::new(self.query_ups_status().await?, queried_at);
+
+        if self.supports(Capability::ExtraPowerInfo) {
+            snapshot.extra_power_info = snapshot.section("extra_power_info", self.query_extra_power_info().await);
+        }
+
+        if self.supports(Capability::Autonomy) {
+            snapshot.autonomy = snapshot.section("autonomy", self.query_ups_autonomy().await);
+        }
+
+        if self.supports(Capability::Rating) {
+            snapshot.rating = snapshot.section("rating", self.query_ups_rating().await);
+        }
+
+        Ok(snapshot)
+    }
+
+    /// See [`CPlusInterface::capabilities`].
+    fn capabilities(&self) -> &'static [Capability] {
+        Capability::ALL
+    }
+
+    /// See [`CPlusInterface::supports`].
+    fn supports(&self, capability: Capability) -> bool {
+        self.capabilities().contains(&capability)
+    }
+
+    /// See [`CPlusInterface::shutdown`].
+    async fn shutdown(&mut self, delay: cplus::ShutdownDelay) -> Result<cplus::CommandAck>;
+
+    /// See [`CPlusInterface::shutdown_and_restore`].
+    async fn shutdown_and_restore(&mut self, shutdown: cplus::ShutdownDelay, restore_minutes: u16) -> Result<cplus::CommandAck>;
+
+    /// See [`CPlusInterface::cancel_shutdown`].
+    async fn cancel_shutdown(&mut self) -> Result<cplus::CommandAck>;
+
+    /// See [`CPlusInterface::start_self_test`].
+    async fn start_self_test(&mut self) -> Result<cplus::CommandAck>;
+
+    /// See [`CPlusInterface::start_timed_self_test`].
+    async fn start_timed_self_test(&mut self, minutes: u8) -> Result<cplus::CommandAck>;
+
+    /// See [`CPlusInterface::start_test_until_battery_low`].
+    async fn start_test_until_battery_low(&mut self) -> Result<cplus::CommandAck>;
+
+    /// See [`CPlusInterface::cancel_test`].
+    async fn cancel_test(&mut self) -> Result<cplus::CommandAck>;
+
+    /// See [`CPlusInterface::toggle_beeper`].
+    async fn toggle_beeper(&mut self) -> Result<bool>;
+
+    /// See [`CPlusInterface::mute_beeper`].
+    async fn mute_beeper(&mut self) -> Result<()> {
+        if self.query_ups_status().await?.ups_status.beeper_on() {
+            self.toggle_beeper().await?;
+        }
+
+        Ok(())
+    }
+
+    /// See [`CPlusInterface::set_outlet`].
+    async fn set_outlet(&mut self, outlet: cplus::Outlet, on: bool) -> Result<cplus::CommandAck>;
+
+    /// See [`CPlusInterface::graceful_shutdown`].
+    async fn graceful_shutdown(&mut self, opts: GracefulShutdownOptions) -> Result<GracefulShutdownReport> {
+        let autonomy = match self.query_ups_autonomy().await {
+            Ok(autonomy) => Some(autonomy.time),
+            Err(e) => {
+                debug!("Couldn't query autonomy before shutdown: {e}");
+                None
+            }
+        };
+
+        let utility_fail = self.query_ups_status().await?.ups_status.utility_fail();
+
+        let mut report = GracefulShutdownReport {
+            autonomy,
+            utility_fail,
+            shutdown_issued: false,
+            confirmed: false,
+        };
+
+        if !utility_fail && !opts.force {
+            info!("Utility power is present, not shutting down");
+            return Ok(report);
+        }
+
+        self.shutdown(opts.delay).await?;
+        report.shutdown_issued = true;
+
+        report.confirmed = self.verify_shutdown_pending(opts.confirm_timeout, opts.poll_interval).await?;
+
+        Ok(report)
+    }
+
+    /// See [`CPlusInterface::verify_shutdown_pending`].
+    async fn verify_shutdown_pending(&mut self, timeout: Duration, poll_interval: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let polled_at = Instant::now();
+
+            if self.query_ups_status().await?.ups_status.shutdown_active() {
+                return Ok(true);
+            }
+
+            let next_poll = polled_at + poll_interval;
+
+            if next_poll > deadline {
+                return Ok(false);
+            }
+
+            self.timer().sleep(next_poll.saturating_duration_since(Instant::now())).await;
+        }
+    }
+
+    /// Returns the timer of the executor the interface runs on, for waiting between queries.
+    fn timer(&self) -> &dyn AsyncTimer;
+}
+
+/// Byte stream the asynchronous serial interface communicates over, using the
+/// executor-neutral `futures-io` traits. See [`TokioIo`] for tokio's streams,
+/// smol's `Async` streams implement it directly.
+#[cfg(feature = "async-core")]
+pub trait AsyncTransport: futures_io::AsyncRead + futures_io::AsyncWrite + Unpin + Send {
+    /// Discards any data waiting to be read, such as a partial reply to an earlier query.
+    fn clear_input(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Timer of the executor running the asynchronous interface, which the reply timeouts
+/// are measured with, e.g. [`TokioTimer`] or [`SmolTimer`].
+#[cfg(feature = "async-core")]
+pub trait AsyncTimer: Send + Sync {
+    /// Returns a future completing after `duration`.
+    fn sleep(&self, duration: Duration) -> std::pin::Pin<Box<dyn Future<Output = ()> + Send>>;
+}
+
+/// Runs `future` for at most `duration` of `timer`, `None` if it doesn't complete in time.
+#[cfg(feature = "async-core")]
+async fn timeout<F: Future>(timer: &dyn AsyncTimer, duration: Duration, future: F) -> Option<F::Output> {
+    let mut future = std::pin::pin!(future);
+    let mut sleep = timer.sleep(duration);
+
+    std::future::poll_fn(|cx| {
+        if let std::task::Poll::Ready(output) = future.as_mut().poll(cx) {
+            return std::task::Poll::Ready(Some(output));
+        }
+
+        sleep.as_mut().poll(cx).map(|()| None)
+    })
+    .await
+}
+
+/// Reads a single byte from `port`, failing at the end of the stream.
+#[cfg(feature = "async-core")]
+async fn read_byte(port: &mut (impl futures_io::AsyncRead + Unpin)) -> std::io::Result<u8> {
+    let mut byte = [0u8];
+    let read = std::future::poll_fn(|cx| std::pin::Pin::new(&mut *port).poll_read(cx, &mut byte)).await?;
+
+    match (read, byte) {
+        (0, _) => Err(std::io::ErrorKind::UnexpectedEof.into()),
+        (_, [byte]) => Ok(byte),
+    }
+}
+
+/// Writes all of `buf` to `port`.
+#[cfg(feature = "async-core")]
+async fn write_all(port: &mut (impl futures_io::AsyncWrite + Unpin), mut buf: &[u8]) -> std::io::Result<()> {
+    while !buf.is_empty() {
+        let written = std::future::poll_fn(|cx| std::pin::Pin::new(&mut *port).poll_write(cx, buf)).await?;
+
+        if written == 0 {
+            return Err(std::io::ErrorKind::WriteZero.into());
+        }
+
+        buf = buf.get(written..).unwrap_or_default();
+    }
+
+    Ok(())
+}
+
+/// [`AsyncTimer`] of the tokio runtime.
+#[cfg(feature = "async")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioTimer;
+
+#[cfg(feature = "async")]
+impl AsyncTimer for TokioTimer {
+    fn sleep(&self, duration: Duration) -> std::pin::Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+}
+
+/// [`AsyncTimer`] of the smol runtime (and async-std, which runs on the same reactor).
+#[cfg(feature = "smol")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SmolTimer;
+
+#[cfg(feature = "smol")]
+impl AsyncTimer for SmolTimer {
+    fn sleep(&self, duration: Duration) -> std::pin::Pin<Box<dyn Future<Output = ()> + Send>> {
+        let timer = smol::Timer::after(duration);
+
+        Box::pin(async move {
+            timer.await;
+        })
+    }
+}
+
+#[cfg(feature = "smol")]
+impl<T> AsyncTransport for smol::Async<T>
+where
+    Self: futures_io::AsyncRead + futures_io::AsyncWrite + Unpin + Send,
+{
+}
+
+/// Byte stream of the tokio runtime, which [`TokioIo`] adapts to an [`AsyncTransport`].
+#[cfg(feature = "async")]
+pub trait TokioTransport: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send {
+    /// See [`AsyncTransport::clear_input`].
+    fn clear_input(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "async")]
+impl TokioTransport for tokio_serial::SerialStream {
+    fn clear_input(&mut self) -> Result<()> {
+        Ok(tokio_serial::SerialPort::clear(self, tokio_serial::ClearBuffer::Input)?)
+    }
+}
+
+#[cfg(feature = "async")]
+impl TokioTransport for tokio::net::TcpStream {}
+
+#[cfg(feature = "async")]
+impl TokioTransport for tokio::io::DuplexStream {}
+
+/// Adapts a tokio byte stream to the `futures-io` traits of [`AsyncTransport`].
+#[cfg(feature = "async")]
+#[derive(Debug)]
+pub struct TokioIo<T>(pub T);
+
+#[cfg(feature = "async")]
+impl<T: TokioTransport> futures_io::AsyncRead for TokioIo<T> {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        let mut buf = tokio::io::ReadBuf::new(buf);
+
+        std::pin::Pin::new(&mut self.0).poll_read(cx, &mut buf).map_ok(|()| buf.filled().len())
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: TokioTransport> futures_io::AsyncWrite for TokioIo<T> {
+    fn poll_write(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        std::pin::Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_close(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<std::io::Result<()>> {
+        std::pin::Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<T: TokioTransport> AsyncTransport for TokioIo<T> {
+    fn clear_input(&mut self) -> Result<()> {
+        self.0.clear_input()
+    }
+}
+
+#[cfg(feature = "async-core")]
+/// Asynchronous serial port interface for the Continuity Plus UPSes, running on any executor
+/// given its [`AsyncTimer`].
+pub struct AsyncCPlusSerialInterface<T> {
+    port: T,
+    timer: Box<dyn AsyncTimer>,
+    /// How long to wait for a complete reply to a query.
+    timeout: Duration,
+    /// Longest message accepted before giving up on finding its end.
+    max_frame_len: usize,
+    metrics: QueryMetrics,
+    last_success: LastSuccess,
+    /// See [`CPlusGenericInterface::battery_configuration`].
+    battery: Option<cplus::BatteryConfiguration>,
+}
+
+#[cfg(feature = "async-core")]
+impl<T: std::fmt::Debug> std::fmt::Debug for AsyncCPlusSerialInterface<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncCPlusSerialInterface")
+            .field("port", &self.port)
+            .field("timeout", &self.timeout)
+            .field("max_frame_len", &self.max_frame_len)
+            .field("metrics", &self.metrics)
+            .field("last_success", &self.last_success)
+            .field("battery", &self.battery)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "async")]
+impl AsyncCPlusSerialInterface<TokioIo<tokio_serial::SerialStream>> {
+    /// Connects to the serial port at the provided path with a 5s timeout, on the tokio runtime.
+    pub fn connect(port_path: &str) -> Result<Self> {
+        let builder = tokio_serial::new(os_port_path(port_path), cplus::SERIAL_BAUD_RATE);
+
+        let mut port = tokio_serial::SerialStream::open(&builder).map_err(|e| open_error(port_path, e))?;
+
+        tokio_serial::SerialPort::write_data_terminal_ready(&mut port, true)?;
+
+        Ok(Self::with_transport(port, Duration::from_millis(5000)))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<P: TokioTransport> AsyncCPlusSerialInterface<TokioIo<P>> {
+    /// Creates an interface communicating over the tokio stream `port`, waiting at most `timeout` for each reply.
+    pub fn with_transport(port: P, timeout: Duration) -> Self {
+        Self::with_timer(TokioIo(port), TokioTimer, timeout)
+    }
+}
+
+#[cfg(feature = "smol")]
+impl<T: AsyncTransport> AsyncCPlusSerialInterface<T> {
+    /// Creates an interface communicating over `port` on the smol (or async-std) runtime,
+    /// waiting at most `timeout` for each reply.
+    pub fn with_smol_transport(port: T, timeout: Duration) -> Self {
+        Self::with_timer(port, SmolTimer, timeout)
+    }
+}
+
+#[cfg(feature = "async-core")]
+impl<T: AsyncTransport> AsyncCPlusSerialInterface<T> {
+    /// Creates an interface communicating over `port`, waiting at most `timeout` (measured
+    /// with `timer`) for each reply.
+    pub fn with_timer(port: T, timer: impl AsyncTimer + 'static, timeout: Duration) -> Self {
+        Self {
+            port,
+            timer: Box::new(timer),
+            timeout,
+            max_frame_len: MAX_FRAME_LEN,
+            metrics: QueryMetrics::default(),
+            last_success: LastSuccess::default(),
+            battery: None,
+        }
+    }
+
+    /// See [`CPlusGenericInterface::battery_configuration`].
+    pub fn battery_configuration(&self) -> Option<cplus::BatteryConfiguration> {
+        self.battery
+    }
+
+    /// See [`CPlusGenericInterface::metrics`].
+    pub fn metrics(&self) -> &QueryMetrics {
+        &self.metrics
+    }
+
+    pub fn reset_metrics(&mut self) {
+        self.metrics.reset();
+    }
+
+    /// See [`CPlusGenericInterface::last_success`].
+    pub fn last_success(&self) -> Option<SystemTime> {
+        self.last_success.get()
+    }
+
+    /// See [`CPlusGenericInterface::last_success_of`].
+    pub fn last_success_of(&self, command: &str) -> Option<SystemTime> {
+        self.last_success.command(command)
+    }
+
+    /// See [`CPlusGenericInterface::staleness`].
+    pub fn staleness(&self) -> Option<Duration> {
+        self.last_success.staleness()
+    }
+
+    /// See [`CPlusGenericInterface::set_max_frame_len`].
+    pub fn set_max_frame_len(&mut self, limit: usize) -> Result<()> {
+        if limit == 0 {
+            return Err(crate::Error::InvalidParameter("the frame length limit must not be zero".to_string()));
+        }
+
+        self.max_frame_len = limit;
+
+        Ok(())
+    }
+
+    /// Writes data to the port along with the end byte.
+    async fn write_data(&mut self, msg: &[u8]) -> Result<()> {
+        write_all(&mut self.port, msg).await?;
+        write_all(&mut self.port, &[END_BYTE]).await?;
+        std::future::poll_fn(|cx| std::pin::Pin::new(&mut self.port).poll_flush(cx)).await?;
+
+        trace!("Wrote msg {:?}", String::from_utf8_lossy(msg));
+
+        Ok(())
+    }
+
+    /// Reads data from the port until an end byte (CR) is encountered. If a `prefix`
+    /// is provided, messages not starting with it (stale replies) are discarded.
+    ///
+    /// Fails if the complete message doesn't arrive within `duration`.
+    async fn read_data(&mut self, duration: Duration, prefix: Option<u8>) -> Result<Vec<u8>> {
+        let max_frame_len = self.max_frame_len;
+        let (port, timer) = (&mut self.port, self.timer.as_ref());
+        let mut buf = vec![];
+        let mut stale = None;
+        let read = async {
+            loop {
+                match read_byte(port).await? {
+                    END_BYTE if prefix.is_some_and(|prefix| buf.first() != Some(&prefix)) => {
+                        debug!("Discarding stale reply {:?}", String::from_utf8_lossy(&buf));
+                        stale = Some(std::mem::take(&mut buf));
+                        continue;
+                    }
+                    END_BYTE => break,
+                    byte => buf.push(byte),
+                }
+
+                if buf.len() > max_frame_len {
+                    trace!("Discarding {} bytes without an end byte", buf.len());
+
+                    port.clear_input()?;
+
+                    return Err(crate::Error::FrameTooLong { limit: max_frame_len });
+                }
+            }
+
+            Ok(())
+        };
+
+        match timeout(timer, duration, read).await {
+            Some(Ok(())) => {}
+            Some(Err(e)) => return Err(e),
+            None => return Err(match stale {
+                Some(stale) if buf.is_empty() => unexpected_reply(prefix, &stale),
+                _ => reply_timeout(buf),
+            }),
+        }
+
+        trace!("Read buffer {:?}\n", String::from_utf8_lossy(&buf));
+
+        Ok(buf)
+    }
+
+    /// See [`CPlusGenericInterface::raw_query`].
+    pub async fn raw_query(&mut self, query: &[u8]) -> Result<Vec<u8>> {
+        let query = query.strip_suffix(&[END_BYTE]).unwrap_or(query);
+
+        let started = Instant::now();
+        let result = self.send_query(query).await;
+        self.metrics.record(query, started.elapsed(), &result);
+        self.last_success.record(query, &result);
+
+        result
+    }
+
+    /// See [`CPlusGenericInterface::send_query`].
+    async fn send_query(&mut self, query: &[u8]) -> Result<Vec<u8>> {
+        trace!("Querying with message {:?}", String::from_utf8_lossy(query));
+
+        // A synchronization error can cause a partial packet to be in the input buffer
+        self.port.clear_input()?;
+
+        self.write_data(query).await?;
+
+        self.read_data(self.timeout, cplus::reply_prefix(query)).await
+    }
+
+    /// Sends a control command and reads the reply, see [`CONTROL_REPLY_TIMEOUT`].
+    async fn control_query(&mut self, command: &[u8]) -> Result<Vec<u8>> {
+        self.port.clear_input()?;
+
+        self.write_data(command).await?;
+
+        match self.read_data(CONTROL_REPLY_TIMEOUT, None).await {
+            Err(crate::Error::Timeout { .. }) => Ok(vec![]),
+            reply => reply,
+        }
+    }
+
+    /// Sends the command to the device and returns its processed response.
+    pub async fn execute<C>(&mut self, command: &C) -> Result<C::Response>
+    where
+        C: cplus::Command + Sync,
+        <C::Response as FromBytes>::Err: Into<crate::Error>,
+    {
+        let command = command.to_bytes();
+
+        let started = Instant::now();
+        let result = self.send_command::<C>(&command).await;
+        self.metrics.record(&command, started.elapsed(), &result);
+
+        if C::EXPECTS_REPLY {
+            self.last_success.record(&command, &result);
+        }
+
+        result
+    }
+
+    /// See [`CPlusGenericInterface::send_command`].
+    async fn send_command<C>(&mut self, command: &[u8]) -> Result<C::Response>
+    where
+        C: cplus::Command,
+        <C::Response as FromBytes>::Err: Into<crate::Error>,
+    {
+        if !C::EXPECTS_REPLY {
+            let reply = self.control_query(command).await?;
+
+            return C::Response::from_bytes(&reply).map_err(|e| e.into());
+        }
+
+        let raw_query = self.send_query(command).await?;
+
+        // Remove the start byte
+        let Some(processed_bytes) = &raw_query.get(1..) else {
+            return Err(missing_start_byte::<C::Response>());
+        };
+
+        C::Response::from_bytes(processed_bytes).map_err(|e| e.into())
+    }
+}
+
+#[cfg(feature = "async-core")]
+#[async_trait::async_trait]
+impl<T: AsyncTransport> AsyncCPlusInterface for AsyncCPlusSerialInterface<T> {
+    fn timer(&self) -> &dyn AsyncTimer {
+        self.timer.as_ref()
+    }
+
+    async fn query_ups_status(&mut self) -> Result<cplus::StatusInquiryResponse> {
+        let mut status = self.execute(&cplus::StatusInquiry).await?;
+        status.resolve_battery_capacity(self.battery.as_ref());
+
+        Ok(status)
+    }
+
+    async fn query_extra_power_info(&mut self) -> Result<cplus::ExtraPowerInfoResponse> {
+        self.execute(&cplus::ExtraPowerInfoInquiry).await
+    }
+
+    async fn query_alarm(&mut self) -> Result<cplus::AlarmInquiryResponse> {
+        self.execute(&cplus::AlarmInquiry).await
+    }
+
+    async fn query_ups_autonomy(&mut self) -> Result<cplus::AutonomyResponse> {
+        self.execute(&cplus::Autonomy).await
+    }
+
+    async fn query_ups_battery_life(&mut self) -> Result<cplus::BatteryLifeResponse> {
+        self.execute(&cplus::BatteryLife).await
+    }
+
+    async fn query_ups_info(&mut self) -> Result<cplus::UPSInformation> {
+        self.execute(&cplus::InformationInquiry).await
+    }
+
+    async fn query_ups_rating(&mut self) -> Result<cplus::UPSRating> {
+        let rating = self.execute(&cplus::RatingInquiry).await?;
+
+        self.battery = rating.battery_configuration().or(self.battery);
+
+        Ok(rating)
+    }
+
+    async fn query_test_result(&mut self) -> Result<cplus::TestResultResponse> {
+        self.execute(&cplus::TestResultInquiry).await
+    }
+
+    async fn shutdown(&mut self, delay: cplus::ShutdownDelay) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::Shutdown { delay }).await
+    }
+
+    async fn shutdown_and_restore(&mut self, shutdown: cplus::ShutdownDelay, restore_minutes: u16) -> Result<cplus::CommandAck> {
+        let command = cplus::ShutdownAndRestore::new(shutdown, restore_minutes)?;
+
+        self.execute(&command).await
+    }
+
+    async fn cancel_shutdown(&mut self) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::CancelShutdown).await
+    }
+
+    async fn start_self_test(&mut self) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::SelfTest).await
+    }
+
+    async fn start_timed_self_test(&mut self, minutes: u8) -> Result<cplus::CommandAck> {
+        let command = cplus::TimedSelfTest::new(minutes)?;
+
+        self.execute(&command).await
+    }
+
+    async fn start_test_until_battery_low(&mut self) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::TestUntilBatteryLow).await
+    }
+
+    async fn cancel_test(&mut self) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::CancelTest).await
+    }
+
+    async fn toggle_beeper(&mut self) -> Result<bool> {
+        let beeper_on = self.query_ups_status().await?.ups_status.beeper_on();
+
+        self.execute(&cplus::ToggleBeeper).await?;
+
+        let toggled = self.query_ups_status().await?.ups_status.beeper_on();
+
+        if toggled == beeper_on {
+            return Err(crate::Error::UnexpectedResponse {
+                expected: format!("beeper_on = {}", !beeper_on),
+                got: format!("beeper_on = {toggled}"),
+            });
+        }
+
+        Ok(toggled)
+    }
+
+    async fn set_outlet(&mut self, outlet: cplus::Outlet, on: bool) -> Result<cplus::CommandAck> {
+        self.execute(&cplus::OutletSwitch { outlet, on }).await
+    }
+}
+
+/// Feature report access of a HID device.
+/// Abstracted so the carousel handling can be tested without hardware.
+#[cfg(feature = "usb-hidapi")]
+pub(crate) trait FeatureReportDevice: Send {
+    /// Reads a feature report into `buf`, whose first byte holds the report ID.
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize>;
+
+    /// Sends a feature report. The first byte of `data` holds the report ID.
+    fn send_feature_report(&self, data: &[u8]) -> Result<()>;
+
+    /// Returns the serial number string of the device, if it has one.
+    fn serial_number(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the HID report descriptor of the device, if it can be read.
+    fn report_descriptor(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Reads an input report from the interrupt endpoint, waiting at most `timeout`.
+    /// Returns the length of the report, 0 if none arrived in time.
+    fn read_interrupt(&self, buf: &mut [u8], timeout: Duration) -> Result<usize>;
+}
+
+#[cfg(feature = "usb-hidapi")]
+impl FeatureReportDevice for hidapi::HidDevice {
+    fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+        Ok(hidapi::HidDevice::get_feature_report(self, buf)?)
+    }
+
+    fn send_feature_report(&self, data: &[u8]) -> Result<()> {
+        Ok(hidapi::HidDevice::send_feature_report(self, data)?)
+    }
+
+    fn serial_number(&self) -> Option<String> {
+        self.get_serial_number_string().ok().flatten()
+    }
+
+    fn read_interrupt(&self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        let timeout = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+
+        Ok(self.read_timeout(buf, timeout)?)
+    }
+
+    fn report_descriptor(&self) -> Option<Vec<u8>> {
+        let mut descriptor = vec![0u8; hidapi::MAX_REPORT_DESCRIPTOR_SIZE];
+        let len = self.get_report_descriptor(&mut descriptor).ok()?;
+
+        descriptor.truncate(len);
+
+        Some(descriptor)
+    }
+}
+
+/// Opens HID devices, like [`hidapi::HidApi`].
+/// Abstracted so sharing a single handle can be tested without hardware.
+#[cfg(feature = "usb-hidapi")]
+pub(crate) trait HidBackend {
+    /// Lists the HID devices known to the handle.
+    fn devices(&self) -> Vec<HidUpsInfo>;
+
+    fn open_path(&self, path: &CStr) -> Result<Box<dyn FeatureReportDevice>>;
+
+    fn open(&self, vid: u16, pid: u16) -> Result<Box<dyn FeatureReportDevice>>;
+
+    /// Sets whether the devices opened next are opened exclusively, failing with
+    /// [`crate::Error::Unsupported`] if the platform can't open them that way.
+    fn set_open_exclusive(&self, exclusive: bool) -> Result<()>;
+}
+
+#[cfg(feature = "usb-hidapi")]
+impl HidBackend for hidapi::HidApi {
+    fn devices(&self) -> Vec<HidUpsInfo> {
+        self.device_list().map(HidUpsInfo::from).collect()
+    }
+
+    fn open_path(&self, path: &CStr) -> Result<Box<dyn FeatureReportDevice>> {
+        Ok(Box::new(hidapi::HidApi::open_path(self, path)?))
+    }
+
+    fn open(&self, vid: u16, pid: u16) -> Result<Box<dyn FeatureReportDevice>> {
+        Ok(Box::new(hidapi::HidApi::open(self, vid, pid)?))
+    }
+
+    /// Applies to the whole process on macOS, where devices are opened exclusively by default.
+    #[cfg(target_os = "macos")]
+    fn set_open_exclusive(&self, exclusive: bool) -> Result<()> {
+        hidapi::HidApi::set_open_exclusive(self, exclusive);
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn set_open_exclusive(&self, exclusive: bool) -> Result<()> {
+        // hidraw and Windows always share the device, libusb always claims its interface
+        let always_exclusive = !cfg!(any(target_os = "linux", target_os = "windows"));
+
+        if exclusive != always_exclusive {
+            return Err(crate::Error::Unsupported {
+                operation: if exclusive { "opening a HID device exclusively" } else { "opening a HID device non-exclusively" },
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// A message type in the feature report carousel.
+///
+/// Several messages share the same prefix (e.g. the status and autonomy messages both
+/// start with `(`), so they're told apart by the length of their payload as well.
+#[cfg(feature = "usb-hidapi")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CarouselFrame {
+    /// Name of the message, used in errors.
+    name: &'static str,
+    /// First byte of the message.
+    prefix: u8,
+    /// Shortest accepted payload (the bytes between the prefix and the end byte).
+    min_len: usize,
+    /// Longest accepted payload.
+    max_len: usize,
+}
+
+#[cfg(feature = "usb-hidapi")]
+impl CarouselFrame {
+    const STATUS: Self = Self { name: "the status message", prefix: STATUS_MSG_PREFIX, min_len: 5, max_len: usize::MAX };
+    const RATING: Self = Self { name: "the rating message", prefix: RATING_MSG_PREFIX, min_len: 5, max_len: 30 };
+    const AUTONOMY: Self = Self { name: "the autonomy message", prefix: b'(', min_len: 4, max_len: 4 };
+    const BATTERY_LIFE: Self = Self { name: "the battery life message", prefix: b'!', min_len: 4, max_len: 4 };
+    const INFORMATION: Self = Self { name: "the information message", prefix: b'#', min_len: 35, max_len: usize::MAX };
+    const TEST_RESULT: Self = Self { name: "the test result message", prefix: b'#', min_len: 4, max_len: 4 };
+
+    /// Describes what a read for `frame` waits for.
+    fn waiting_for(frame: Option<Self>) -> String {
+        frame.map_or("a message", |frame| frame.name).to_string()
+    }
+
+    /// Whether `message` (starting with the prefix, without the end byte) is of this type.
+    fn matches(&self, message: &[u8]) -> bool {
+        let payload_len = message.len().saturating_sub(1);
+
+        message.first() == Some(&self.prefix)
+            && (self.min_len..=self.max_len).contains(&payload_len)
+    }
+}
+
+/// Builds the feature report writing `cmd` (followed by the end byte) to the UPS.
+#[cfg(feature = "usb-hidapi")]
+fn command_report(cmd: &[u8]) -> Vec<u8> {
+    let mut report = Vec::with_capacity(cmd.len() + 2);
+
+    report.push(DATA_FEATURE_REPORT);
+    report.extend_from_slice(cmd);
+    report.push(END_BYTE);
+
+    report
+}
+
+/// Whether a HID error is likely to go away by reading the report again (a stalled or
+/// interrupted transfer), unlike the device being gone, which needs it to be reopened.
+#[cfg(feature = "usb-hidapi")]
+fn is_transient_hid_error(error: &hidapi::HidError) -> bool {
+    /// Parts of the messages of hidapi meaning the device was unplugged or re-enumerated.
+    const GONE: [&str; 6] = [
+        "no such device",
+        "no such file",
+        "not found",
+        "disconnected",
+        "not connected",
+        "input/output error",
+    ];
+
+    match error {
+        hidapi::HidError::HidApiError { message } => {
+            let message = message.to_lowercase();
+
+            !GONE.iter().any(|gone| message.contains(gone))
+        }
+        hidapi::HidError::HidApiErrorEmpty => true,
+        hidapi::HidError::IoError { error } => matches!(
+            error.kind(),
+            std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+        ),
+        _ => false,
+    }
+}
+
+/// How a feature report read failing with a transient HID error is retried.
+#[cfg(feature = "usb-hidapi")]
+#[derive(Debug, Clone, Copy)]
+struct ReadRetry {
+    /// Retries after the first attempt.
+    retries: u32,
+    /// Pause before each retry.
+    delay: Duration,
+}
+
+#[cfg(feature = "usb-hidapi")]
+impl Default for ReadRetry {
+    fn default() -> Self {
+        Self {
+            retries: DEFAULT_HID_READ_RETRIES,
+            delay: DEFAULT_HID_READ_RETRY_DELAY,
+        }
+    }
+}
+
+#[cfg(feature = "usb-hidapi")]
+impl ReadRetry {
+    /// Reads the data feature report like [`read_report`], retrying transient HID errors.
+    /// Fails with [`crate::Error::RetriesExhausted`] if they persist through all the retries.
+    /// Other errors, such as the device being gone, are returned right away.
+    fn read(self, device: &dyn FeatureReportDevice, buf: &mut [u8]) -> Result<usize> {
+        let mut attempts = 1;
+
+        loop {
+            match read_report(device, buf) {
+                Err(crate::Error::HidApi(e)) if is_transient_hid_error(&e) => {
+                    if attempts > self.retries {
+                        let error = crate::Error::HidApi(e);
+
+                        return Err(if self.retries == 0 {
+                            error
+                        } else {
+                            crate::Error::RetriesExhausted { attempts, source: Box::new(error) }
+                        });
+                    }
+
+                    debug!("Reading the feature report failed ({e}), retrying ({attempts}/{})", self.retries);
+
+                    attempts += 1;
+                    std::thread::sleep(self.delay);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Returns the length of the feature report `report_id` in bytes (including the report ID)
+/// as declared by the HID report `descriptor`, or `None` if it doesn't declare the report.
+#[cfg(feature = "usb-hidapi")]
+fn feature_report_len(descriptor: &[u8], report_id: u8) -> Option<usize> {
+    // Global items: Report Size, Report ID, Report Count, Push and Pop
+    const REPORT_SIZE: u8 = 0x74;
+    const REPORT_ID: u8 = 0x84;
+    const REPORT_COUNT: u8 = 0x94;
+    const PUSH: u8 = 0xa4;
+    const POP: u8 = 0xb4;
+    // Main item: Feature
+    const FEATURE: u8 = 0xb0;
+    const LONG_ITEM: u8 = 0xfe;
+
+    // Report Size and Report Count, the report ID isn't pushed
+    let mut globals = (0u32, 0u32);
+    let mut stack = Vec::new();
+    let mut current_id = 0u8;
+    let mut bits = 0u64;
+    let mut rest = descriptor;
+
+    while let Some((&prefix, items)) = rest.split_first() {
+        if prefix == LONG_ITEM {
+            let data_len = usize::from(*items.first()?);
+            rest = items.get(2 + data_len..)?;
+
+            continue;
+        }
+
+        let data_len = match prefix & 0x03 {
+            3 => 4,
+            len => usize::from(len),
+        };
+        let data = items.get(..data_len)?;
+        let value = data.iter().rev().fold(0u32, |value, &b| (value << 8) | u32::from(b));
+        rest = items.get(data_len..)?;
+
+        match prefix & 0xfc {
+            REPORT_SIZE => globals.0 = value,
+            REPORT_COUNT => globals.1 = value,
+            REPORT_ID => current_id = u8::try_from(value).ok()?,
+            PUSH => stack.push(globals),
+            POP => globals = stack.pop()?,
+            FEATURE if current_id == report_id => bits += u64::from(globals.0) * u64::from(globals.1),
+            _ => {}
+        }
+    }
+
+    let len = usize::try_from(bits.div_ceil(8)).ok()?;
+
+    (len > 0).then_some(len + 1)
+}
+
+/// Reads raw data from the data feature report.
+/// The buffer is expected to be at least 2 bytes long.
+#[cfg(feature = "usb-hidapi")]
+fn read_report(device: &dyn FeatureReportDevice, buf: &mut [u8]) -> Result<usize> {
+    let report_id = buf.get_mut(0).ok_or(crate::Error::BufferTooSmall {
+        expected: 1,
+        provided: 0,
+    })?;
+
+    *report_id = DATA_FEATURE_REPORT;
+
+    // The doc of this function says that,
+    // "Upon return, the first byte will still contain the Report ID,
+    // and the report data will start in buf[1]."
+    // Which doesn't apply for this UPS, the data starts in buf[0]
+    device.get_feature_report(buf)
+}
+
+/// Returns the position of the end byte if `buf` holds a complete message
+/// (of the given type, if a `frame` is provided).
+#[cfg(feature = "usb-hidapi")]
+fn message_end(buf: &[u8], frame: Option<CarouselFrame>) -> Option<usize> {
+    let cr_idx = buf.iter().position(|&b| b == END_BYTE)?;
+
+    let complete = buf.get(cr_idx + 1).is_none_or(|&b| b == b'\0');
+
+    (complete && frame.is_none_or(|frame| buf.get(..cr_idx).is_some_and(|msg| frame.matches(msg))))
+        .then_some(cr_idx)
+}
+
+/// Whether the report filled all of `buf` without an end byte, meaning the message
+/// was cut off by a report buffer too short for it.
+#[cfg(feature = "usb-hidapi")]
+fn is_truncated(buf: &[u8]) -> bool {
+    buf.last().is_some_and(|&b| b != b'\0') && !buf.contains(&END_BYTE)
+}
+
+/// Tracks since when each message type of the carousel has been repeated unchanged,
+/// to detect a wedged UPS firmware serving the same data forever.
+#[cfg(feature = "usb-hidapi")]
+#[derive(Debug, Default)]
+struct StaleDataWatch {
+    /// Last message of each frame type, and when it was first read.
+    frames: std::collections::HashMap<&'static str, (Vec<u8>, Instant)>,
+}
+
+#[cfg(feature = "usb-hidapi")]
+impl StaleDataWatch {
+    fn record(&mut self, frame: CarouselFrame, message: &[u8], now: Instant) {
+        match self.frames.get_mut(frame.name) {
+            Some((last, _)) if last == message => {}
+            _ => {
+                self.frames.insert(frame.name, (message.to_vec(), now));
+            }
+        }
+    }
+
+    /// How long `frame` has been unchanged, `None` if it was never read.
+    fn unchanged_for(&self, frame: CarouselFrame, now: Instant) -> Option<Duration> {
+        self.frames.get(frame.name).map(|(_, since)| now.saturating_duration_since(*since))
+    }
+}
+
+/// The status and rating messages last read from the carousel, so that a query for one of
+/// them shortly after it passed by (e.g. while waiting for another message) doesn't need
+/// to read the carousel again.
+#[cfg(feature = "usb-hidapi")]
+#[derive(Debug, Default)]
+struct CarouselCache {
+    /// Last message of each cached frame type, and when it was read.
+    frames: std::collections::HashMap<&'static str, (Vec<u8>, Instant)>,
+}
+
+#[cfg(feature = "usb-hidapi")]
+impl CarouselCache {
+    /// The messages the carousel repeats by itself, without a command.
+    const FRAMES: &[CarouselFrame] = &[CarouselFrame::STATUS, CarouselFrame::RATING];
+
+    /// Stores `message` if it's of a cached type, returning the type.
+    fn store(&mut self, message: &[u8], now: Instant) -> Option<CarouselFrame> {
+        let frame = *Self::FRAMES.iter().find(|frame| frame.matches(message))?;
+
+        match self.frames.get_mut(frame.name) {
+            Some((last, read_at)) => {
+                last.clear();
+                last.extend_from_slice(message);
+                *read_at = now;
+            }
+            None => {
+                self.frames.insert(frame.name, (message.to_vec(), now));
+            }
+        }
+
+        Some(frame)
+    }
+
+    /// The last message of the type `frame`, if it was read at most `max_age` ago.
+    fn get(&self, frame: CarouselFrame, max_age: Duration, now: Instant) -> Option<&[u8]> {
+        self.frames
+            .get(frame.name)
+            .filter(|(_, read_at)| now.saturating_duration_since(*read_at) <= max_age)
+            .map(|(message, _)| message.as_slice())
+    }
+
+    fn clear(&mut self) {
+        self.frames.clear();
+    }
+}
+
+/// Parses the message in `buf` ending with the end byte at `cr_idx`.
+#[cfg(feature = "usb-hidapi")]
+fn parse_message<T>(buf: &[u8], cr_idx: usize) -> Result<T>
+    where T: FromBytes, <T as FromBytes>::Err: Into<crate::Error>
+{
+    // First byte is the prefix, the message ends with the end byte (carriage return)
+    let Some(processed_bytes) = buf.get(1..cr_idx) else {
+        return Err(missing_start_byte::<T>());
+    };
+
+    T::from_bytes(processed_bytes).map_err(|e| e.into())
+}
+
+/// VID/PID pairs of the USB HID interfaces the Continuity Plus UPSes ship with.
+#[cfg(feature = "usb-hidapi")]
+pub const KNOWN_VID_PIDS: &[(u16, u16)] = &[(0x0665, 0x5161)];
+
+#[cfg(feature = "usb-hidapi")]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+/// HID device found by [`CPlusHidInterface::enumerate`].
+pub struct HidUpsInfo {
+    /// Platform-specific path, see [`CPlusHidInterface::connect_with_path`].
+    pub path: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub serial_number: Option<String>,
+    pub product: Option<String>,
+    /// Device release number in binary-coded decimal.
+    pub release_number: u16,
+}
+
+#[cfg(feature = "usb-hidapi")]
+impl HidUpsInfo {
+    /// Returns the options for connecting to this device by its path.
+    pub fn connect_options(&self) -> HidConnectOptions {
+        HidConnectOptions::path(&self.path)
+    }
+}
+
+#[cfg(feature = "usb-hidapi")]
+impl From<&hidapi::DeviceInfo> for HidUpsInfo {
+    fn from(info: &hidapi::DeviceInfo) -> Self {
+        Self {
+            path: info.path().to_string_lossy().into_owned(),
+            vendor_id: info.vendor_id(),
+            product_id: info.product_id(),
+            serial_number: info.serial_number().map(str::to_string),
+            product: info.product_string().map(str::to_string),
+            release_number: info.release_number(),
+        }
+    }
+}
+
+/// Finds the device with the given serial number.
+#[cfg(feature = "usb-hidapi")]
+fn find_serial_number(devices: Vec<HidUpsInfo>, serial: &str) -> Result<HidUpsInfo> {
+    let found = devices.iter().filter_map(|d| d.serial_number.clone()).collect();
+
+    devices
+        .into_iter()
+        .find(|d| d.serial_number.as_deref() == Some(serial))
+        .ok_or_else(|| crate::Error::SerialNumberNotFound { serial: serial.to_string(), found })
+}
+
+/// Keeps the devices with one of the `known` VID/PID pairs, skipping duplicates
+/// (a device is listed once for each of its interfaces on some platforms).
+#[cfg(feature = "usb-hidapi")]
+fn filter_known_devices(devices: impl IntoIterator<Item = HidUpsInfo>, known: &[(u16, u16)]) -> Vec<HidUpsInfo> {
+    let mut found: Vec<HidUpsInfo> = vec![];
+
+    for device in devices {
+        if known.contains(&(device.vendor_id, device.product_id)) && !found.iter().any(|d| d.path == device.path) {
+            found.push(device);
+        }
+    }
+
+    found
+}
+
+/// How the HID interface reads the message carousel, see [`HidConnectOptions::read_mode`].
+#[cfg(feature = "usb-hidapi")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadMode {
+    /// Polls the data feature report, which holds the message of the carousel the UPS
+    /// is showing when it's read.
+    #[default]
+    FeatureReport,
+    /// Reads the carousel streamed on the interrupt IN endpoint, missing no messages
+    /// between polls. The messages are split over several input reports.
+    ///
+    /// Falls back to [`ReadMode::FeatureReport`] if the endpoint stays silent.
+    /// [`AsyncCPlusHidInterface`] always reads the feature report.
+    Interrupt,
+}
+
+/// How a [`CPlusHidInterface`] was opened, so [`CPlusHidInterface::reopen`] finds the same device.
+#[cfg(feature = "usb-hidapi")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum HidSelector {
+    Path(String),
+    VidPid(u16, u16),
+    Serial { vid: u16, pid: u16, serial: String },
+}
+
+#[cfg(feature = "usb-hidapi")]
+impl HidSelector {
+    fn open(&self, api: &dyn HidBackend) -> Result<Box<dyn FeatureReportDevice>> {
+        match self {
+            Self::Path(path) => {
+                // All null bytes are removed before the conversion.
+                let path = CString::new(path.replace("\0", "")).unwrap();
+
+                api.open_path(path.as_c_str())
+            }
+            Self::VidPid(vid, pid) => api.open(*vid, *pid),
+            Self::Serial { vid, pid, serial } => {
+                let devices = filter_known_devices(api.devices(), &[(*vid, *pid)]);
+
+                Self::Path(find_serial_number(devices, serial)?.path).open(api)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "usb-hidapi")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Settings for opening a HID device, e.g. to share it with another program reading the UPS.
+///
+/// ```no_run
+/// # use alphamon_rs::device::cplus::HidConnectOptions;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let iface = HidConnectOptions::vid_pid(0x0665, 0x5161)
+///     .open_exclusive(false)
+///     .open()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct HidConnectOptions {
+    selector: HidSelector,
+    /// Whether the device is opened exclusively, left to the platform if `None`: macOS opens
+    /// devices exclusively, hidraw on Linux and Windows don't. Opening fails with
+    /// [`crate::Error::Unsupported`] if the platform can't open the device as requested.
+    pub open_exclusive: Option<bool>,
+    /// Length of the buffer the data feature report is read into. Taken from the report
+    /// descriptor of the device if `None`, falling back to [`DEFAULT_HID_REPORT_LEN`].
+    pub report_len: Option<usize>,
+    pub read_mode: ReadMode,
+}
+
+#[cfg(feature = "usb-hidapi")]
+impl HidConnectOptions {
+    /// Selects the device at `path`.
+    pub fn path(path: impl Into<String>) -> Self {
+        Self::new(HidSelector::Path(path.into()))
+    }
+
+    /// Selects the first device with the given `vid` and `pid`.
+    pub fn vid_pid(vid: u16, pid: u16) -> Self {
+        Self::new(HidSelector::VidPid(vid, pid))
+    }
+
+    /// Selects the device with the given `vid`, `pid` and serial number.
+    pub fn serial(vid: u16, pid: u16, serial: impl Into<String>) -> Self {
+        Self::new(HidSelector::Serial { vid, pid, serial: serial.into() })
+    }
+
+    fn new(selector: HidSelector) -> Self {
+        Self { selector, open_exclusive: None, report_len: None, read_mode: ReadMode::default() }
+    }
+
+    pub fn open_exclusive(mut self, open_exclusive: bool) -> Self {
+        self.open_exclusive = Some(open_exclusive);
+        self
+    }
+
+    pub fn report_len(mut self, report_len: usize) -> Self {
+        self.report_len = Some(report_len);
+        self
+    }
+
+    pub fn read_mode(mut self, read_mode: ReadMode) -> Self {
+        self.read_mode = read_mode;
+        self
+    }
+
+    /// Opens the device with these options.
+    ///
+    /// hidapi allows only one [`hidapi::HidApi`] per process, so to connect to
+    /// several devices use [`HidConnectOptions::open_with_api`] instead.
+    pub fn open(self) -> Result<CPlusHidInterface> {
+        self.open_with_api(&hidapi::HidApi::new()?)
+    }
+
+    /// Opens the device with these options using a caller-owned `api` handle.
+    pub fn open_with_api(self, api: &hidapi::HidApi) -> Result<CPlusHidInterface> {
+        CPlusHidInterface::open_options(api, self)
+    }
+
+    fn open_device(&self, api: &dyn HidBackend) -> Result<Box<dyn FeatureReportDevice>> {
+        if let Some(exclusive) = self.open_exclusive {
+            api.set_open_exclusive(exclusive)?;
+        }
+
+        self.selector.open(api)
+    }
+}
+
+/// Creates the HID API handle used for reopening a device.
+#[cfg(feature = "usb-hidapi")]
+type HidApiFactory = Box<dyn Fn() -> Result<Box<dyn HidBackend>> + Send>;
+
+#[cfg(feature = "usb-hidapi")]
+fn new_hidapi() -> Result<Box<dyn HidBackend>> {
+    Ok(Box::new(hidapi::HidApi::new()?))
+}
+
+#[cfg(feature = "usb-hidapi")]
+/// USB HID interface for the Continuity Plus UPSes.
+pub struct CPlusHidInterface {
+    device: Box<dyn FeatureReportDevice>,
+    serial_number: Option<String>,
+    /// How long to wait for a message to appear in the carousel.
+    timeout: Duration,
+    /// How the device was opened.
+    options: HidConnectOptions,
+    /// Whether a HID error triggers an attempt to reopen the device.
+    auto_reopen: bool,
+    api: HidApiFactory,
+    metrics: QueryMetrics,
+    stale_data: StaleDataWatch,
+    /// How long an unchanged status makes [`CPlusInterface::query_ups_status`] fail.
+    stale_data_window: Option<Duration>,
+    last_success: LastSuccess,
+    cache: CarouselCache,
+    /// Age up to which a cached message is reused, zero if the cache is off.
+    frame_cache_window: Duration,
+    /// How reads failing with a transient HID error are retried.
+    read_retry: ReadRetry,
+    /// Length of the buffer the data feature report is read into.
+    report_len: usize,
+    read_mode: ReadMode,
+    /// Messages read from the interrupt endpoint, in [`ReadMode::Interrupt`].
+    assembler: MessageAssembler,
+    /// Interrupt endpoint reads timed out in a row.
+    interrupt_timeouts: u32,
+    /// See [`CPlusGenericInterface::battery_configuration`].
+    battery: Option<cplus::BatteryConfiguration>,
+}
+
+#[cfg(feature = "usb-hidapi")]
+impl CPlusHidInterface {
+    /// Connects to the given HID device at `path`.
+    ///
+    /// hidapi allows only one [`hidapi::HidApi`] per process, so to connect to
+    /// several devices use [`CPlusHidInterface::with_api_path`] instead.
+    /// See [`HidConnectOptions`] for opening the device non-exclusively.
+    pub fn connect_with_path(path: String) -> Result<Self> {
+        Self::with_api_path(&hidapi::HidApi::new()?, &path)
+    }
+
+    /// Connects to the given HID device with the given `vid` and `pid`.
+    pub fn connect_with_vid_pid(vid: u16, pid: u16) -> Result<Self> {
+        Self::with_api_vid_pid(&hidapi::HidApi::new()?, vid, pid)
+    }
+
+    /// Connects to the HID device with the given `vid`, `pid` and serial number,
+    /// telling apart several UPSes of the same model.
+    pub fn connect_with_serial(vid: u16, pid: u16, serial: &str) -> Result<Self> {
+        Self::with_api_serial(&hidapi::HidApi::new()?, vid, pid, serial)
+    }
+
+    /// Connects to the HID device at `path` using a caller-owned `api` handle.
+    pub fn with_api_path(api: &hidapi::HidApi, path: &str) -> Result<Self> {
+        Self::open_path(api, path)
+    }
+
+    /// Connects to the HID device with the given `vid` and `pid` using a caller-owned `api` handle.
+    pub fn with_api_vid_pid(api: &hidapi::HidApi, vid: u16, pid: u16) -> Result<Self> {
+        Self::open_options(api, HidConnectOptions::vid_pid(vid, pid))
+    }
+
+    /// Connects to the HID device with the given `vid`, `pid` and serial number
+    /// using a caller-owned `api` handle.
+    ///
+    /// The devices are looked up in the device list of the handle, which is
+    /// only updated by [`hidapi::HidApi::refresh_devices`].
+    pub fn with_api_serial(api: &hidapi::HidApi, vid: u16, pid: u16, serial: &str) -> Result<Self> {
+        Self::open_serial(api, vid, pid, serial)
+    }
+
+    fn open_path(api: &impl HidBackend, path: &str) -> Result<Self> {
+        Self::open_options(api, HidConnectOptions::path(path))
+    }
+
+    fn open_serial(api: &impl HidBackend, vid: u16, pid: u16, serial: &str) -> Result<Self> {
+        Self::open_options(api, HidConnectOptions::serial(vid, pid, serial))
+    }
+
+    fn open_options(api: &dyn HidBackend, options: HidConnectOptions) -> Result<Self> {
+        let device = options.open_device(api)?;
+        let serial_number = device.serial_number();
+        let report_len = options.report_len
+            .or_else(|| feature_report_len(&device.report_descriptor()?, DATA_FEATURE_REPORT))
+            .unwrap_or(DEFAULT_HID_REPORT_LEN);
+
+        if report_len < 2 {
+            return Err(crate::Error::InvalidParameter("the report length must be at least 2 bytes".to_string()));
+        }
+
+        Ok(Self {
+            device,
+            serial_number,
+            timeout: HID_READ_TIMEOUT,
+            read_mode: options.read_mode,
+            options,
+            auto_reopen: false,
+            api: Box::new(new_hidapi),
+            metrics: QueryMetrics::default(),
+            stale_data: StaleDataWatch::default(),
+            stale_data_window: None,
+            last_success: LastSuccess::default(),
+            cache: CarouselCache::default(),
+            frame_cache_window: DEFAULT_FRAME_CACHE_WINDOW,
+            read_retry: ReadRetry::default(),
+            report_len,
+            assembler: MessageAssembler::new(END_BYTE, report_len),
+            interrupt_timeouts: 0,
+            battery: None,
+        })
+    }
+
+    /// Opens the device again the same way it was connected to (by its path, VID/PID
+    /// or serial number, with the same options), replacing the handle left stale after
+    /// the UPS was unplugged.
+    pub fn reopen(&mut self) -> Result<()> {
+        let api = (self.api)()?;
+
+        self.device = self.options.open_device(api.as_ref())?;
+        self.serial_number = self.device.serial_number();
+
+        Ok(())
+    }
+
+    /// Sets how many times a feature report read failing with a transient HID error (e.g. a stalled
+    /// transfer) is retried, pausing for `delay` before each retry, see [`DEFAULT_HID_READ_RETRIES`].
+    /// Once the retries run out, the read fails with [`crate::Error::RetriesExhausted`].
+    /// Errors meaning the device is gone aren't retried, but left to [auto-reopen](Self::set_auto_reopen).
+    pub fn set_read_retries(&mut self, retries: u32, delay: Duration) {
+        self.read_retry = ReadRetry { retries, delay };
+    }
+
+    /// Sets the length of the buffer the data feature report is read into, which has to fit
+    /// the longest message of the UPS. A message cut off by a shorter buffer fails with
+    /// [`crate::Error::FrameTooLong`]. See [`HidConnectOptions::report_len`] for the default.
+    pub fn set_report_len(&mut self, report_len: usize) -> Result<()> {
+        if report_len < 2 {
+            return Err(crate::Error::InvalidParameter("the report length must be at least 2 bytes".to_string()));
+        }
+
+        self.report_len = report_len;
+        self.assembler = MessageAssembler::new(END_BYTE, report_len);
+
+        Ok(())
+    }
+
+    /// Returns how the carousel is read, [`ReadMode::FeatureReport`] after falling back
+    /// from [`ReadMode::Interrupt`].
+    pub fn read_mode(&self) -> ReadMode {
+        self.read_mode
+    }
+
+    /// Sets whether a HID error makes the interface try to [reopen](Self::reopen) the device
+    /// once, before the error is returned. Off by default.
+    pub fn set_auto_reopen(&mut self, auto_reopen: bool) {
+        self.auto_reopen = auto_reopen;
+    }
+
+    /// Reopens the device if `result` is a HID error and auto-reopen is on.
+    fn reopen_on_error<T>(&mut self, result: Result<T>) -> Result<T> {
+        if self.auto_reopen && matches!(result, Err(crate::Error::HidApi(_))) && let Err(e) = self.reopen() {
+            debug!("Couldn't reopen the HID device: {e}");
+        }
+
+        result
+    }
+
+    /// Sets how long a query waits for its reply to appear in the carousel.
+    pub fn set_query_timeout(&mut self, timeout: Duration) -> Result<()> {
+        if timeout.is_zero() {
+            return Err(crate::Error::InvalidParameter("the timeout must not be zero".to_string()));
+        }
+
+        self.timeout = timeout;
+
+        Ok(())
+    }
+
+    /// Returns the serial number the device reports, if any.
+    pub fn serial_number(&self) -> Option<&str> {
+        self.serial_number.as_deref()
+    }
+
+    /// Whether the status message read from the carousel hasn't changed at all for longer
+    /// than `window`, meaning the UPS most likely stopped updating it.
+    /// `false` until the status was read.
+    pub fn is_data_stale(&self, window: Duration) -> bool {
+        self.stale_data.unchanged_for(CarouselFrame::STATUS, Instant::now()).is_some_and(|unchanged| unchanged > window)
+    }
+
+    /// Sets how long the status may stay unchanged before [`CPlusInterface::query_ups_status`]
+    /// fails with [`crate::Error::StaleData`], see [`DEFAULT_STALE_DATA_WINDOW`]. Off (`None`) by default.
+    pub fn set_stale_data_window(&mut self, window: Option<Duration>) {
+        self.stale_data_window = window;
+    }
+
+    /// Sets the age up to which a status or rating message already read from the carousel
+    /// (e.g. while waiting for another message) is returned by the next query for it instead
+    /// of reading the carousel again. Defaults to [`DEFAULT_FRAME_CACHE_WINDOW`], zero turns
+    /// the cache off. Writing a command empties the cache, as the UPS state may change.
+    pub fn set_frame_cache_window(&mut self, window: Duration) {
+        self.frame_cache_window = window;
+    }
+
+    /// Returns the latencies of the queries so far. The status and rating, read from
+    /// the carousel without sending a command, are recorded as `Q1` and `F`.
+    pub fn metrics(&self) -> &QueryMetrics {
+        &self.metrics
+    }
+
+    /// See [`CPlusGenericInterface::battery_configuration`].
+    pub fn battery_configuration(&self) -> Option<cplus::BatteryConfiguration> {
+        self.battery
+    }
+
+    pub fn reset_metrics(&mut self) {
+        self.metrics.reset();
+    }
+
+    /// See [`CPlusGenericInterface::last_success`].
+    pub fn last_success(&self) -> Option<SystemTime> {
+        self.last_success.get()
+    }
+
+    /// See [`CPlusGenericInterface::last_success_of`]. The status and rating are recorded as `Q1` and `F`.
+    pub fn last_success_of(&self, command: &str) -> Option<SystemTime> {
+        self.last_success.command(command)
+    }
+
+    /// See [`CPlusGenericInterface::staleness`].
+    pub fn staleness(&self) -> Option<Duration> {
+        self.last_success.staleness()
+    }
+
+    /// Runs the query `f`, recording its latency as `command`.
+    fn recorded<R>(&mut self, command: &[u8], f: impl FnOnce(&mut Self) -> Result<R>) -> Result<R> {
+        let started = Instant::now();
+        let result = f(self);
+        self.metrics.record(command, started.elapsed(), &result);
+        self.last_success.record(command, &result);
+
+        result
+    }
+
+    /// Lists the connected UPSes, recognized by their VID/PID being one of [`KNOWN_VID_PIDS`].
+    pub fn enumerate() -> Result<Vec<HidUpsInfo>> {
+        Self::enumerate_with_vid_pids(KNOWN_VID_PIDS)
+    }
+
+    /// Lists the connected HID devices having one of the given VID/PID pairs.
+    pub fn enumerate_with_vid_pids(vid_pids: &[(u16, u16)]) -> Result<Vec<HidUpsInfo>> {
+        Ok(Self::enumerate_with_api(&hidapi::HidApi::new()?, vid_pids))
+    }
+
+    /// Lists the HID devices having one of the given VID/PID pairs in the device list
+    /// of a caller-owned `api` handle (see [`hidapi::HidApi::refresh_devices`]).
+    pub fn enumerate_with_api(api: &hidapi::HidApi, vid_pids: &[(u16, u16)]) -> Vec<HidUpsInfo> {
+        filter_known_devices(api.devices(), vid_pids)
+    }
+
+    /// Connects to the first UPS listed by [`CPlusHidInterface::enumerate`].
+    pub fn connect_first() -> Result<Self> {
+        let api = hidapi::HidApi::new()?;
+
+        let ups = Self::enumerate_with_api(&api, KNOWN_VID_PIDS).into_iter().next().ok_or(crate::Error::NoUpsFound)?;
+
+        ups.connect_options().open_with_api(&api)
+    }
+
+    /// Writes a command to the UPS through the data feature report.
+    /// The end byte is appended to the command.
+    pub fn write_command(&mut self, cmd: &[u8]) -> Result<()> {
+        self.cache.clear();
+
+        self.send_command_report(cmd)
+    }
+
+    /// Writes `cmd` like [`CPlusHidInterface::write_command`], keeping the cached messages.
+    /// Only for queries, which don't change the status or rating.
+    fn send_command_report(&mut self, cmd: &[u8]) -> Result<()> {
+        let result = self.device.send_feature_report(&command_report(cmd));
+
+        self.reopen_on_error(result)
+    }
+
+    /// Reads data from the feature report. If a `frame` is provided,
+    /// the function will read until a complete message of the given type is found,
+    /// failing with [`crate::Error::Timeout`] if none arrives within the timeout.
+    /// A fresh enough cached message of the type is returned without reading.
+    /// 
+    /// Returns the position of the end byte (a carriage return character).
+    fn read_data(&mut self, buf: &mut [u8], frame: Option<CarouselFrame>) -> Result<usize> {
+        if let Some(cr_idx) = self.read_cached(buf, frame) {
+            return Ok(cr_idx);
+        }
+
+        let deadline = Instant::now() + self.timeout;
+
+        loop {
+            match self.read_mode {
+                ReadMode::FeatureReport => {
+                    let result = self.read_retry.read(self.device.as_ref(), buf);
+                    self.reopen_on_error(result)?;
+
+                    if is_truncated(buf) {
+                        return Err(crate::Error::FrameTooLong { limit: buf.len() });
+                    }
+                }
+                ReadMode::Interrupt => self.read_interrupt(buf, deadline)?,
+            }
+
+            if let Some(cr_idx) = message_end(buf, None) && let Some(message) = buf.get(..cr_idx) {
+                let now = Instant::now();
+
+                if let Some(cached) = self.cache.store(message, now) {
+                    self.stale_data.record(cached, message, now);
+                }
+
+                if frame.is_none_or(|frame| frame.matches(message)) {
+                    if let Some(frame) = frame {
+                        self.stale_data.record(frame, message, now);
+                    }
+
+                    return Ok(cr_idx);
+                }
+            }
+
+            if Instant::now() >= deadline {
+                return Err(crate::Error::Timeout { waiting_for: CarouselFrame::waiting_for(frame) });
+            }
+        }
+    }
+
+    /// Copies the next message streamed on the interrupt endpoint (followed by the end byte)
+    /// to `buf`, like a feature report. Leaves `buf` empty if no complete message arrived
+    /// before the `deadline` or a single read timing out.
+    ///
+    /// Switches to [`ReadMode::FeatureReport`] once [`INTERRUPT_FALLBACK_TIMEOUTS`] reads
+    /// timed out in a row.
+    fn read_interrupt(&mut self, buf: &mut [u8], deadline: Instant) -> Result<()> {
+        buf.fill(0);
+
+        let message = match self.assembler.pop() {
+            Some(message) => message,
+            None => {
+                let mut report = vec![0u8; self.report_len];
+                let timeout = deadline.saturating_duration_since(Instant::now()).min(INTERRUPT_READ_TIMEOUT);
+
+                let result = self.device.read_interrupt(&mut report, timeout);
+                let len = self.reopen_on_error(result)?;
+
+                if len == 0 {
+                    // Not counting a read that didn't wait, the deadline having passed
+                    if !timeout.is_zero() {
+                        self.interrupt_timeouts += 1;
+                    }
+
+                    if self.interrupt_timeouts >= INTERRUPT_FALLBACK_TIMEOUTS {
+                        warn!("No reports on the interrupt endpoint, reading the feature report instead");
+                        self.read_mode = ReadMode::FeatureReport;
+                    }
+
+                    return Ok(());
+                }
+
+                self.interrupt_timeouts = 0;
+                self.assembler.push(report.get(..len).unwrap_or_default())?;
+
+                let Some(message) = self.assembler.pop() else {
+                    return Ok(());
+                };
+
+                message
+            }
+        };
+
+        let limit = buf.len();
+        let (end, dest) = buf.get_mut(..=message.len())
+            .and_then(|dest| dest.split_last_mut())
+            .ok_or(crate::Error::FrameTooLong { limit })?;
+
+        dest.copy_from_slice(&message);
+        *end = END_BYTE;
+
+        Ok(())
+    }
+
+    /// Copies the cached message of the type `frame` (followed by the end byte) to `buf`,
+    /// returning the position of the end byte. `None` if there's no fresh one or it doesn't fit.
+    fn read_cached(&self, buf: &mut [u8], frame: Option<CarouselFrame>) -> Option<usize> {
+        if self.frame_cache_window.is_zero() {
+            return None;
+        }
+
+        let message = self.cache.get(frame?, self.frame_cache_window, Instant::now())?;
+        let (dest, rest) = buf.split_at_mut_checked(message.len())?;
+        let (end, rest) = rest.split_first_mut()?;
+
+        dest.copy_from_slice(message);
+        *end = END_BYTE;
+        rest.fill(0);
+
+        Some(message.len())
+    }
+
+    /// Reads the carousel until a fresh message of each of `frames` is cached.
+    fn sweep(&mut self, frames: &[CarouselFrame]) -> Result<()> {
+        let mut buf = vec![0u8; self.report_len];
+        let deadline = Instant::now() + self.timeout;
+
+        while let Some(missing) = frames.iter().find(|&&frame| self.cache.get(frame, self.frame_cache_window, Instant::now()).is_none()) {
+            if Instant::now() >= deadline {
+                return Err(crate::Error::Timeout { waiting_for: missing.name.to_string() });
+            }
+
+            self.read_data(&mut buf, None)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the next complete message of the carousel, including its prefix but without
+    /// the end byte. Escape hatch for messages this crate doesn't parse.
+    pub fn raw_read(&mut self) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; self.report_len];
+
+        let cr_idx = self.read_data(&mut buf, None)?;
+        buf.truncate(cr_idx);
+
+        Ok(buf)
+    }
+
+    fn read_processed_data<T>(&mut self, frame: Option<CarouselFrame>) -> Result<T> 
+        where T: FromBytes, <T as FromBytes>::Err: Into<crate::Error> 
+    {
+        let mut buf = vec![0u8; self.report_len];
+
+        let cr_idx = self.read_data(&mut buf, frame)?;
+
+        parse_message(&buf, cr_idx)
+    }
+
+    /// Writes the `cmd` query, then waits for its reply in the carousel.
+    fn query<C>(&mut self, cmd: &C, frame: CarouselFrame) -> Result<C::Response>
+        where C: cplus::Command, <C::Response as FromBytes>::Err: Into<crate::Error>
+    {
+        let command = cmd.to_bytes();
+
+        self.recorded(&command, |iface| {
+            iface.send_command_report(&command)?;
+
+            iface.read_processed_data(Some(frame))
+        })
+    }
+
+    /// Writes the `cmd` control command. The UPS doesn't reply to these over USB.
+    /// The cached messages are dropped, see [`CPlusHidInterface::write_command`].
+    fn control<C: cplus::Command>(&mut self, cmd: &C) -> Result<cplus::CommandAck> {
+        self.write_command(&cmd.to_bytes())?;
+
+        Ok(cplus::CommandAck::NoReply)
+    }
+}
+
+/// Operations of the HID interface, which can't query the extra power info or alarms.
+#[cfg(feature = "usb-hidapi")]
+const HID_CAPABILITIES: &[Capability] = &[
+    Capability::Status,
+    Capability::Autonomy,
+    Capability::BatteryLife,
+    Capability::Info,
+    Capability::Rating,
+    Capability::TestResult,
+    Capability::Shutdown,
+    Capability::ShutdownAndRestore,
+    Capability::CancelShutdown,
+    Capability::SelfTest,
+    Capability::TimedSelfTest,
+    Capability::TestUntilBatteryLow,
+    Capability::CancelTest,
+    Capability::ToggleBeeper,
+    Capability::Outlet,
+];
+
+#[cfg(feature = "usb-hidapi")]
+impl CPlusInterface for CPlusHidInterface {
+    fn capabilities(&self) -> &'static [Capability] {
+        HID_CAPABILITIES
+    }
+
+    /// Reads the status and rating in one sweep over the carousel, unless the
+    /// [frame cache](CPlusHidInterface::set_frame_cache_window) is off.
+    /// The rating is read first, for the battery capacity of the status.
+    fn query_snapshot(&mut self) -> Result<cplus::UpsSnapshot> {
+        if !self.frame_cache_window.is_zero()
+            && let Err(e) = self.sweep(&[CarouselFrame::STATUS, CarouselFrame::RATING])
+        {
+            debug!("Couldn't read the status and rating in one sweep: {e}");
+        }
+
+        let queried_at = SystemTime::now();
+        let rating = self.query_ups_rating();
+        let mut snapshot = cplus::UpsSnapshot::new(self.query_ups_status()?, queried_at);
+
+        snapshot.autonomy = snapshot.section("autonomy", self.query_ups_autonomy());
+        snapshot.rating = snapshot.section("rating", rating);
+
+        Ok(snapshot)
+    }
+
+     fn query_ups_status(&mut self) -> Result<cplus::StatusInquiryResponse> {
+        self.recorded(b"Q1", |iface| {
+            let mut status: cplus::StatusInquiryResponse = iface.read_processed_data(Some(CarouselFrame::STATUS))?;
+            status.resolve_battery_capacity(iface.battery.as_ref());
+
+            let unchanged_for = iface.stale_data.unchanged_for(CarouselFrame::STATUS, Instant::now());
+
+            if let (Some(window), Some(unchanged_for)) = (iface.stale_data_window, unchanged_for)
+                && unchanged_for > window
+            {
+                return Err(crate::Error::StaleData { unchanged_for });
+            }
+
+            Ok(status)
+        })
+    }
+
+     fn query_ups_rating(&mut self) -> Result<cplus::UPSRating> {
+        let rating: cplus::UPSRating = self.recorded(b"F", |iface| iface.read_processed_data(Some(CarouselFrame::RATING)))?;
+
+        self.battery = rating.battery_configuration().or(self.battery);
+
+        Ok(rating)
+    }
+
+     fn query_extra_power_info(&mut self) -> Result<cplus::ExtraPowerInfoResponse> {
+        Err(crate::Error::Unsupported { operation: "query_extra_power_info" })
+    }
+
+     fn query_alarm(&mut self) -> Result<cplus::AlarmInquiryResponse> {
+        Err(crate::Error::Unsupported { operation: "query_alarm" })
+    }
+
+     fn query_ups_autonomy(&mut self) -> Result<cplus::AutonomyResponse> {
+        self.query(&cplus::Autonomy, CarouselFrame::AUTONOMY)
+    }
+
+     fn query_ups_battery_life(&mut self) -> Result<cplus::BatteryLifeResponse> {
+        self.query(&cplus::BatteryLife, CarouselFrame::BATTERY_LIFE)
+    }
+
+     fn query_ups_info(&mut self) -> Result<cplus::UPSInformation> {
+        self.query(&cplus::InformationInquiry, CarouselFrame::INFORMATION)
+    }
+
+     fn query_test_result(&mut self) -> Result<cplus::TestResultResponse> {
+        self.query(&cplus::TestResultInquiry, CarouselFrame::TEST_RESULT)
+    }
+
+     fn shutdown(&mut self, delay: cplus::ShutdownDelay) -> Result<cplus::CommandAck> {
+        self.control(&cplus::Shutdown { delay })
+    }
+
+     fn shutdown_and_restore(&mut self, shutdown: cplus::ShutdownDelay, restore_minutes: u16) -> Result<cplus::CommandAck> {
+        self.control(&cplus::ShutdownAndRestore::new(shutdown, restore_minutes)?)
+    }
+
+     fn cancel_shutdown(&mut self) -> Result<cplus::CommandAck> {
+        self.control(&cplus::CancelShutdown)
+    }
+
+     fn start_self_test(&mut self) -> Result<cplus::CommandAck> {
+        self.control(&cplus::SelfTest)
+    }
+
+     fn start_timed_self_test(&mut self, minutes: u8) -> Result<cplus::CommandAck> {
+        self.control(&cplus::TimedSelfTest::new(minutes)?)
+    }
+
+     fn start_test_until_battery_low(&mut self) -> Result<cplus::CommandAck> {
+        self.control(&cplus::TestUntilBatteryLow)
+    }
+
+     fn cancel_test(&mut self) -> Result<cplus::CommandAck> {
+        self.control(&cplus::CancelTest)
+    }
+
+     fn toggle_beeper(&mut self) -> Result<bool> {
+        let beeper_on = self.query_ups_status()?.ups_status.beeper_on();
+
+        self.control(&cplus::ToggleBeeper)?;
+
+        let toggled = self.query_ups_status()?.ups_status.beeper_on();
+
+        if toggled == beeper_on {
+            return Err(crate::Error::UnexpectedResponse {
+                expected: format!("beeper_on = {}", !beeper_on),
+                got: format!("beeper_on = {toggled}"),
+            });
+        }
+
+        Ok(toggled)
+    }
+
+     fn set_outlet(&mut self, outlet: cplus::Outlet, on: bool) -> Result<cplus::CommandAck> {
+        self.control(&cplus::OutletSwitch { outlet, on })
+    }
+}
+
+#[cfg(all(feature = "async", feature = "usb-hidapi"))]
+/// Asynchronous USB HID interface for the Continuity Plus UPSes.
+///
+/// The feature reports are read on blocking tasks, one report at a time,
+/// so dropping a pending query stops it reading the carousel.
+pub struct AsyncCPlusHidInterface {
+    device: std::sync::Arc<std::sync::Mutex<Box<dyn FeatureReportDevice>>>,
+    serial_number: Option<String>,
+    /// How long to wait for a reply to appear in the carousel.
+    timeout: Duration,
+    /// How reads failing with a transient HID error are retried.
+    read_retry: ReadRetry,
+    /// Length of the buffer the data feature report is read into.
+    report_len: usize,
+    /// See [`CPlusGenericInterface::battery_configuration`].
+    battery: Option<cplus::BatteryConfiguration>,
+}
+
+#[cfg(all(feature = "async", feature = "usb-hidapi"))]
+impl AsyncCPlusHidInterface {
+    /// Wraps a connected HID interface, waiting at most `timeout` for each reply.
+    pub fn new(interface: CPlusHidInterface, timeout: Duration) -> Self {
+        Self {
+            device: std::sync::Arc::new(std::sync::Mutex::new(interface.device)),
+            serial_number: interface.serial_number,
+            timeout,
+            read_retry: interface.read_retry,
+            report_len: interface.report_len,
+            battery: interface.battery,
+        }
+    }
+
+    /// Returns the serial number the device reports, if any.
+    pub fn serial_number(&self) -> Option<&str> {
+        self.serial_number.as_deref()
+    }
+
+    /// See [`CPlusGenericInterface::battery_configuration`].
+    pub fn battery_configuration(&self) -> Option<cplus::BatteryConfiguration> {
+        self.battery
+    }
+
+    /// Runs `f` with the device on a blocking task.
+    async fn with_device<R, F>(&self, f: F) -> Result<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&dyn FeatureReportDevice) -> Result<R> + Send + 'static,
+    {
+        let device = std::sync::Arc::clone(&self.device);
+
+        tokio::task::spawn_blocking(move || {
+            let device = device.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+
+            f(device.as_ref())
+        })
+        .await
+        .map_err(std::io::Error::other)?
+    }
+
+    /// Writes a command to the UPS through the data feature report.
+    /// The end byte is appended to the command.
+    pub async fn write_command(&mut self, cmd: &[u8]) -> Result<()> {
+        let report = command_report(cmd);
+
+        self.with_device(move |device| device.send_feature_report(&report)).await
+    }
+
+    /// Reads the carousel until a complete message of the given type is found,
+    /// failing if none arrives within the timeout.
+    async fn read_processed_data<T>(&mut self, frame: CarouselFrame) -> Result<T>
+        where T: FromBytes, <T as FromBytes>::Err: Into<crate::Error>
+    {
+        let read = async {
+            loop {
+                let (read_retry, report_len) = (self.read_retry, self.report_len);
+                let buf = self.with_device(move |device| {
+                    let mut buf = vec![0u8; report_len];
+                    read_retry.read(device, &mut buf)?;
+
+                    if is_truncated(&buf) {
+                        return Err(crate::Error::FrameTooLong { limit: buf.len() });
+                    }
+
+                    Ok(buf)
+                }).await?;
+
+                if let Some(cr_idx) = message_end(&buf, Some(frame)) {
+                    return parse_message(&buf, cr_idx);
+                }
+            }
+        };
+
+        tokio::time::timeout(self.timeout, read)
+            .await
+            .map_err(|_| crate::Error::Timeout { waiting_for: CarouselFrame::waiting_for(Some(frame)) })?
+    }
+
+    /// Writes the `cmd` query, then waits for its reply in the carousel.
+    async fn query<C>(&mut self, cmd: &C, frame: CarouselFrame) -> Result<C::Response>
+        where C: cplus::Command + Sync, <C::Response as FromBytes>::Err: Into<crate::Error>
+    {
+        self.write_command(&cmd.to_bytes()).await?;
+
+        self.read_processed_data(frame).await
+    }
+
+    /// Writes the `cmd` control command. The UPS doesn't reply to these over USB.
+    async fn control<C: cplus::Command + Sync>(&mut self, cmd: &C) -> Result<cplus::CommandAck> {
+        self.write_command(&cmd.to_bytes()).await?;
+
+        Ok(cplus::CommandAck::NoReply)
+    }
+}
+
+#[cfg(all(feature = "async", feature = "usb-hidapi"))]
+#[async_trait::async_trait]
+impl AsyncCPlusInterface for AsyncCPlusHidInterface {
+    fn timer(&self) -> &dyn AsyncTimer {
+        &TokioTimer
+    }
+
+    fn capabilities(&self) -> &'static [Capability] {
+        HID_CAPABILITIES
+    }
+
+    async fn query_ups_status(&mut self) -> Result<cplus::StatusInquiryResponse> {
+        let mut status: cplus::StatusInquiryResponse = self.read_processed_data(CarouselFrame::STATUS).await?;
+        status.resolve_battery_capacity(self.battery.as_ref());
+
+        Ok(status)
+    }
+
+    async fn query_extra_power_info(&mut self) -> Result<cplus::ExtraPowerInfoResponse> {
+        Err(crate::Error::Unsupported { operation: "query_extra_power_info" })
+    }
+
+    async fn query_alarm(&mut self) -> Result<cplus::AlarmInquiryResponse> {
+        Err(crate::Error::Unsupported { operation: "query_alarm" })
+    }
+
+    async fn query_ups_autonomy(&mut self) -> Result<cplus::AutonomyResponse> {
+        self.query(&cplus::Autonomy, CarouselFrame::AUTONOMY).await
+    }
+
+    async fn query_ups_battery_life(&mut self) -> Result<cplus::BatteryLifeResponse> {
+        self.query(&cplus::BatteryLife, CarouselFrame::BATTERY_LIFE).await
+    }
+
+    async fn query_ups_info(&mut self) -> Result<cplus::UPSInformation> {
+        self.query(&cplus::InformationInquiry, CarouselFrame::INFORMATION).await
+    }
+
+    async fn query_ups_rating(&mut self) -> Result<cplus::UPSRating> {
+        let rating: cplus::UPSRating = self.read_processed_data(CarouselFrame::RATING).await?;
+
+        self.battery = rating.battery_configuration().or(self.battery);
+
+        Ok(rating)
+    }
+
+    async fn query_test_result(&mut self) -> Result<cplus::TestResultResponse> {
+        self.query(&cplus::TestResultInquiry, CarouselFrame::TEST_RESULT).await
+    }
+
+    async fn shutdown(&mut self, delay: cplus::ShutdownDelay) -> Result<cplus::CommandAck> {
+        self.control(&cplus::Shutdown { delay }).await
+    }
+
+    async fn shutdown_and_restore(&mut self, shutdown: cplus::ShutdownDelay, restore_minutes: u16) -> Result<cplus::CommandAck> {
+        let command = cplus::ShutdownAndRestore::new(shutdown, restore_minutes)?;
+
+        self.control(&command).await
+    }
+
+    async fn cancel_shutdown(&mut self) -> Result<cplus::CommandAck> {
+        self.control(&cplus::CancelShutdown).await
+    }
+
+    async fn start_self_test(&mut self) -> Result<cplus::CommandAck> {
+        self.control(&cplus::SelfTest).await
+    }
+
+    async fn start_timed_self_test(&mut self, minutes: u8) -> Result<cplus::CommandAck> {
+        let command = cplus::TimedSelfTest::new(minutes)?;
+
+        self.control(&command).await
+    }
+
+    async fn start_test_until_battery_low(&mut self) -> Result<cplus::CommandAck> {
+        self.control(&cplus::TestUntilBatteryLow).await
+    }
+
+    async fn cancel_test(&mut self) -> Result<cplus::CommandAck> {
+        self.control(&cplus::CancelTest).await
+    }
+
+    async fn toggle_beeper(&mut self) -> Result<bool> {
+        let beeper_on = self.query_ups_status().await?.ups_status.beeper_on();
+
+        self.control(&cplus::ToggleBeeper).await?;
+
+        let toggled = self.query_ups_status().await?.ups_status.beeper_on();
+
+        if toggled == beeper_on {
+            return Err(crate::Error::UnexpectedResponse {
+                expected: format!("beeper_on = {}", !beeper_on),
+                got: format!("beeper_on = {toggled}"),
+            });
+        }
+
+        Ok(toggled)
+    }
+
+    async fn set_outlet(&mut self, outlet: cplus::Outlet, on: bool) -> Result<cplus::CommandAck> {
+        self.control(&cplus::OutletSwitch { outlet, on }).await
+    }
+}
+
+/// Tests running the generic interface over an in-memory transport.
+#[cfg(test)]
+mod generic_tests {
+    use super::*;
+    use std::io::{BufRead, Cursor, Read};
+
+    /// Transport reading the pre-loaded replies and recording everything written.
+    /// A read returns at most one reply, like the UPS answering each command.
+    struct Loopback {
+        input: Cursor<Vec<u8>>,
+        written: Vec<u8>,
+    }
+
+    impl Loopback {
+        fn new(input: &[u8]) -> CPlusGenericInterface<Self> {
+            CPlusGenericInterface::new(Self { input: Cursor::new(input.to_vec()), written: vec![] })
+        }
+    }
+
+    impl Read for Loopback {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let pending = self.input.fill_buf()?;
+            let reply_len = pending.iter().position(|&b| b == END_BYTE).map_or(pending.len(), |end| end + 1);
+
+            match (&mut self.input).take(reply_len as u64).read(buf)? {
+                0 => Err(std::io::ErrorKind::TimedOut.into()),
+                read => Ok(read),
+            }
+        }
+    }
+
+    impl Write for Loopback {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Loopback {}
+
+    #[test]
+    fn query_test() {
+        let mut iface = Loopback::new(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110001\r#230.0 008 072.0 50.0\r");
+
+        assert!(iface.query_ups_status().unwrap().ups_status.beeper_on());
+        assert_eq!(iface.query_ups_rating().unwrap().output_rating_current, 8);
+        assert_eq!(iface.into_inner().written, b"Q1\rF\r");
+    }
+
+    #[test]
+    fn battery_configuration_test() {
+        // On battery, reporting the voltage of a 24 V pack
+        let status: &[u8] = b"(208.4 140.0 208.4 034 59.9 25.2 35.0 10001000\r";
+        let mut iface = Loopback::new(&[status, b"#230.0 004 024.0 50.0\r", status].concat());
+
+        assert_eq!(iface.query_ups_status().unwrap().battery_capacity, None);
+        assert_eq!(iface.query_ups_rating().unwrap().battery_voltage, 24.0);
+        assert_eq!(iface.battery_configuration().map(|battery| battery.blocks), Some(2));
+        assert_eq!(iface.query_ups_status().unwrap().battery_capacity, Some(72));
+    }
+
+    /// Transport delivering one byte every `byte_delay`, honoring its read timeout.
+    struct SlowLoopback {
+        input: std::collections::VecDeque<u8>,
+        byte_delay: Duration,
+        timeout: Duration,
+    }
+
+    impl Read for SlowLoopback {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let (Some(slot), Some(&byte)) = (buf.first_mut(), self.input.front()) else {
+                std::thread::sleep(self.timeout);
+                return Err(std::io::ErrorKind::TimedOut.into());
+            };
+
+            if self.byte_delay > self.timeout {
+                std::thread::sleep(self.timeout);
+                return Err(std::io::ErrorKind::TimedOut.into());
+            }
+
+            std::thread::sleep(self.byte_delay);
+
+            *slot = byte;
+            self.input.pop_front();
+
+            Ok(1)
+        }
+    }
+
+    impl Write for SlowLoopback {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for SlowLoopback {
+        fn timeout(&self) -> Option<Duration> {
+            Some(self.timeout)
+        }
+
+        fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+            self.timeout = timeout;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn query_timeout_test() {
+        let rating = b"#230.0 008 072.0 50.0\r";
+        let slow = |timeout| CPlusGenericInterface::new(SlowLoopback {
+            input: rating.iter().copied().collect(),
+            byte_delay: Duration::from_millis(5),
+            timeout,
+        });
+
+        // Each byte arrives within the timeout, but the whole reply doesn't
+        let mut iface = slow(Duration::from_millis(50));
+
+        assert!(iface.query_ups_rating().is_err());
+        assert_eq!(iface.get_ref().timeout, Duration::from_millis(50));
+
+        let mut iface = slow(Duration::from_millis(50));
+
+        let rating = iface.query_with_timeout(&cplus::RatingInquiry, Duration::from_secs(1)).unwrap();
+
+        assert_eq!(rating.output_rating_current, 8);
+        assert_eq!(iface.get_ref().timeout, Duration::from_millis(50));
+
+        iface.set_query_timeout(Duration::from_millis(200)).unwrap();
+
+        assert_eq!(iface.get_ref().timeout, Duration::from_millis(200));
+        assert!(matches!(iface.set_query_timeout(Duration::ZERO), Err(crate::Error::InvalidParameter(_))));
+    }
+
+    #[test]
+    fn control_command_test() {
+        let mut iface = Loopback::new(b"");
+
+        assert_eq!(iface.start_self_test().unwrap(), cplus::CommandAck::NoReply);
+        assert!(matches!(iface.query_ups_status(), Err(crate::Error::Timeout { .. })));
+        assert_eq!(iface.into_inner().written, b"T\rQ1\r");
+    }
+
+    #[test]
+    fn raw_query_test() {
+        let mut iface = Loopback::new(b"(12.3 VENDOR\r(45.6 VENDOR\r");
+
+        assert_eq!(iface.raw_query(b"X1").unwrap(), b"(12.3 VENDOR");
+        // The end byte isn't doubled
+        assert_eq!(iface.raw_query(b"X1\r").unwrap(), b"(45.6 VENDOR");
+        assert_eq!(iface.into_inner().written, b"X1\rX1\r");
+    }
+
+    #[test]
+    fn stale_reply_test() {
+        // A late reply to an earlier status query arrives first
+        let mut iface = Loopback::new(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r#230.0 008 072.0 50.0\r");
+
+        assert_eq!(iface.query_ups_rating().unwrap().output_rating_current, 8);
+        assert_eq!(iface.into_inner().written, b"F\r");
+
+        // Only the stale reply arrives
+        let mut iface = Loopback::new(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r");
+
+        assert!(matches!(
+            iface.query_ups_rating(),
+            Err(crate::Error::UnexpectedResponse { expected, got })
+                if expected == "a reply starting with '#'" && got.starts_with("(208.4")
+        ));
+
+        // Replies to commands without a known prefix aren't checked
+        let mut iface = Loopback::new(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r");
+
+        assert!(iface.raw_query(b"X1").is_ok());
+        assert_eq!(cplus::reply_prefix(b"BL"), Some(b'!'));
+    }
+
+    #[test]
+    fn read_timeout_test() {
+        // Complete frame
+        let mut iface = Loopback::new(b"#230.0 008 072.0 50.0\r");
+
+        assert_eq!(iface.query_ups_rating().unwrap().output_rating_current, 8);
+
+        // Nothing received
+        let mut iface = Loopback::new(b"");
+
+        assert!(matches!(iface.query_ups_rating(), Err(crate::Error::Timeout { .. })));
+
+        // The end byte never arrives
+        let mut iface = Loopback::new(b"#230.0 008");
+
+        assert!(matches!(
+            iface.query_ups_rating(),
+            Err(crate::Error::IncompleteResponse { received }) if received == b"#230.0 008"
+        ));
+    }
+
+    #[test]
+    fn frame_too_long_test() {
+        let noise: Vec<u8> = (0..10_000u32).map(|i| b'0' + (i % 10) as u8).collect();
+        let mut iface = Loopback::new(&noise);
+
+        let result = iface.query_ups_status();
+
+        assert!(matches!(result, Err(crate::Error::FrameTooLong { limit: MAX_FRAME_LEN })));
+        assert_eq!(iface.get_ref().input.position(), MAX_FRAME_LEN as u64 + 1);
+
+        // Replies up to the limit are accepted
+        let mut iface = Loopback::new(b"#230.0 008 072.0 50.0\r");
+
+        iface.set_max_frame_len(21).unwrap();
+        assert!(iface.query_ups_rating().is_ok());
+
+        let mut iface = Loopback::new(b"#230.0 008 072.0 50.0\r");
+
+        iface.set_max_frame_len(20).unwrap();
+        assert!(matches!(iface.query_ups_rating(), Err(crate::Error::FrameTooLong { limit: 20 })));
+        assert!(matches!(iface.set_max_frame_len(0), Err(crate::Error::InvalidParameter(_))));
+    }
+
+    /// Transport delivering the pre-loaded input in chunks of at most `chunk_len` bytes.
+    struct Chunked {
+        input: std::collections::VecDeque<u8>,
+        chunk_len: usize,
+    }
+
+    impl Chunked {
+        fn new(input: &[u8], chunk_len: usize) -> CPlusGenericInterface<Self> {
+            CPlusGenericInterface::new(Self { input: input.iter().copied().collect(), chunk_len })
+        }
+    }
+
+    impl Read for Chunked {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.chunk_len).min(self.input.len());
+
+            if len == 0 {
+                return Err(std::io::ErrorKind::TimedOut.into());
+            }
+
+            for (b, i) in buf.iter_mut().zip(self.input.drain(..len)) {
+                *b = i;
+            }
+
+            Ok(len)
+        }
+    }
+
+    impl Write for Chunked {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Chunked {}
+
+    /// Reads messages until the input runs out, returning them along with the final error.
+    fn read_all<T: Transport>(iface: &mut CPlusGenericInterface<T>) -> (Vec<Vec<u8>>, crate::Error) {
+        let mut messages = vec![];
+
+        loop {
+            match iface.read_data() {
+                Ok(()) => messages.push(iface.frame.clone()),
+                Err(e) => return (messages, e),
+            }
+        }
+    }
+
+    #[test]
+    fn chunked_read_test() {
+        let input = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r#230.0 008 072.0 50.0\r\r!0123\r#230.0";
+
+        // Read one byte at a time, like the reads used to be
+        let (expected, _) = read_all(&mut Chunked::new(input, 1));
+
+        assert_eq!(expected, [&input[..46], &input[47..68], b"", b"!0123"]);
+
+        // Several frames in one read, and frames split across reads
+        for chunk_len in [2, 3, 7, 20, 47, 48, READ_CHUNK_LEN] {
+            let (messages, error) = read_all(&mut Chunked::new(input, chunk_len));
+
+            assert_eq!(messages, expected, "chunks of {chunk_len}");
+            assert!(matches!(error, crate::Error::IncompleteResponse { received } if received == b"#230.0"), "chunks of {chunk_len}");
+        }
+    }
+
+    #[test]
+    fn line_terminator_test() {
+        let status = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110001";
+        let rating = b"#230.0 008 072.0 50.0";
+
+        for chunk_len in [1, 5, READ_CHUNK_LEN] {
+            // A stray line feed doesn't end up at the start of the next frame
+            let mut iface = Chunked::new(b"(208.4 140.0\r\n#230.0 008\r\n\n!0123\r", chunk_len);
+
+            let (messages, error) = read_all(&mut iface);
+
+            assert_eq!(messages, [&b"(208.4 140.0"[..], b"#230.0 008", b"!0123"], "chunks of {chunk_len}");
+            assert!(matches!(error, crate::Error::Timeout { .. }), "chunks of {chunk_len}");
+
+            let mut iface = Chunked::new(b"(208.4 140.0\r\n#230.0 008\r\n", chunk_len);
+            iface.set_line_terminator(LineTerminator::CrLf);
+
+            let (messages, error) = read_all(&mut iface);
+
+            assert_eq!(messages, [&b"(208.4 140.0"[..], b"#230.0 008"], "chunks of {chunk_len}");
+            assert!(matches!(error, crate::Error::Timeout { .. }), "chunks of {chunk_len}");
+
+            // A carriage return is part of the message with another end byte
+            let mut iface = Chunked::new(b"(208.4\r140.0\x03#230.0 008\x03", chunk_len);
+            iface.set_line_terminator(LineTerminator::Custom(0x03));
+
+            let (messages, _) = read_all(&mut iface);
+
+            assert_eq!(messages, [&b"(208.4\r140.0"[..], b"#230.0 008"], "chunks of {chunk_len}");
+        }
+
+        // The commands are terminated the same way
+        let mut iface = Loopback::new(&[&status[..], b"\r\n", rating, b"\r\n"].concat());
+        iface.set_line_terminator(LineTerminator::CrLf);
+
+        assert!(iface.query_ups_status().unwrap().ups_status.beeper_on());
+        assert_eq!(iface.query_ups_rating().unwrap().output_rating_current, 8);
+        assert!(matches!(iface.raw_query(b"X1\r\n"), Err(crate::Error::Timeout { .. })));
+        assert_eq!(iface.into_inner().written, b"Q1\r\nF\r\nX1\r\n");
+
+        let mut iface = Chunked::new(&[&status[..], b"\x03"].concat(), READ_CHUNK_LEN);
+        iface.set_line_terminator(LineTerminator::Custom(0x03));
+
+        assert!(iface.query_ups_status().unwrap().ups_status.beeper_on());
+
+        let mut iface = Loopback::new(b"");
+        iface.set_line_terminator(LineTerminator::Custom(0x03));
+
+        assert!(matches!(iface.raw_query(b"X1\x03"), Err(crate::Error::Timeout { .. })));
+        assert_eq!(iface.into_inner().written, b"X1\x03");
+    }
+
+    #[test]
+    fn echo_test() {
+        let status = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110001\r";
+
+        for chunk_len in [1, 2, READ_CHUNK_LEN] {
+            for echo_mode in [EchoMode::Expect, EchoMode::Detect] {
+                let mut iface = Chunked::new(&[&b"Q1\r"[..], status].concat(), chunk_len);
+                iface.set_echo_mode(echo_mode);
+
+                assert!(iface.query_ups_status().unwrap().ups_status.beeper_on(), "{echo_mode:?}, chunks of {chunk_len}");
+
+                let mut iface = Chunked::new(b"X1\r\n#1234\r\n", chunk_len);
+                iface.set_line_terminator(LineTerminator::CrLf);
+                iface.set_echo_mode(echo_mode);
+
+                assert_eq!(iface.raw_query(b"X1").unwrap(), b"#1234", "{echo_mode:?}, chunks of {chunk_len}");
+            }
+
+            // Without an echo
+            let mut iface = Chunked::new(status, chunk_len);
+            iface.set_echo_mode(EchoMode::Detect);
+
+            assert!(iface.query_ups_status().is_ok(), "chunks of {chunk_len}");
+
+            let mut iface = Chunked::new(status, chunk_len);
+            iface.set_echo_mode(EchoMode::Expect);
+
+            assert!(matches!(iface.query_ups_status(), Err(crate::Error::UnexpectedResponse { .. })), "chunks of {chunk_len}");
+            assert!(iface.input.is_empty());
+
+            // A corrupted echo
+            let mut iface = Chunked::new(&[&b"Q\r"[..], status].concat(), chunk_len);
+            iface.set_echo_mode(EchoMode::Expect);
+
+            let result = iface.query_ups_status();
+
+            assert!(matches!(result, Err(crate::Error::UnexpectedResponse { got, .. }) if got.starts_with("Q\r")), "chunks of {chunk_len}");
+
+            // A truncated echo isn't taken for the echo, but skipped before the start of the reply
+            let mut iface = Chunked::new(&[&b"Q"[..], status].concat(), chunk_len);
+            iface.set_echo_mode(EchoMode::Detect);
+
+            assert!(iface.query_ups_status().is_ok(), "chunks of {chunk_len}");
+        }
+
+        // The echo is taken for the reply when the mode is off
+        let mut iface = Chunked::new(b"X1\r#1234\r", READ_CHUNK_LEN);
+
+        assert_eq!(iface.raw_query(b"X1").unwrap(), b"X1");
+
+        // A control command the UPS doesn't reply to
+        let mut iface = Chunked::new(b"CT\r", READ_CHUNK_LEN);
+        iface.set_echo_mode(EchoMode::Expect);
+
+        assert!(iface.cancel_test().is_ok());
+        assert!(iface.input.is_empty());
+    }
+
+    #[test]
+    fn chunked_query_test() {
+        // The stale reply and the reply arrive in the same read
+        let mut iface = Chunked::new(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r#230.0 008 072.0 50.0\r", READ_CHUNK_LEN);
+
+        assert_eq!(iface.query_ups_rating().unwrap().output_rating_current, 8);
+
+        // What was read past the reply is discarded before the next query
+        let mut iface = Chunked::new(b"#230.0 008 072.0 50.0\r#230.0 008 072.0 50.0\r", READ_CHUNK_LEN);
+
+        assert!(iface.query_ups_rating().is_ok());
+        assert!(!iface.input.is_empty());
+        assert!(matches!(iface.query_ups_rating(), Err(crate::Error::Timeout { .. })));
+        assert!(iface.input.is_empty());
+
+        // An overlong frame is detected even when it ends in the middle of a read
+        let mut iface = Chunked::new(b"#230.0 008 072.0 50.0\r", READ_CHUNK_LEN);
+
+        iface.set_max_frame_len(20).unwrap();
+        assert!(matches!(iface.query_ups_rating(), Err(crate::Error::FrameTooLong { limit: 20 })));
+        assert!(iface.input.is_empty());
+    }
+
+    /// Transport with stale input waiting to be read, answering each command with the next reply.
+    struct Stale {
+        input: std::collections::VecDeque<u8>,
+        replies: std::collections::VecDeque<&'static [u8]>,
+        written: Vec<u8>,
+    }
+
+    impl Stale {
+        fn new(stale: &[u8], replies: &[&'static [u8]]) -> CPlusGenericInterface<Self> {
+            CPlusGenericInterface::new(Self {
+                input: stale.iter().copied().collect(),
+                replies: replies.iter().copied().collect(),
+                written: vec![],
+            })
+        }
+    }
+
+    impl Read for Stale {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.input.len());
+
+            if len == 0 {
+                return Err(std::io::ErrorKind::TimedOut.into());
+            }
+
+            for (b, i) in buf.iter_mut().zip(self.input.drain(..len)) {
+                *b = i;
+            }
+
+            Ok(len)
+        }
+    }
+
+    impl Write for Stale {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if buf.contains(&END_BYTE) && let Some(reply) = self.replies.pop_front() {
+                self.input.extend(reply);
+            }
+
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Stale {
+        fn clear_input(&mut self) -> Result<()> {
+            unreachable!("the stale input is discarded selectively")
+        }
+
+        fn waiting_input(&mut self) -> Result<Option<usize>> {
+            Ok(Some(self.input.len()))
+        }
+    }
+
+    #[test]
+    fn resync_test() {
+        // A stale reply to an earlier rating query is waiting
+        let mut iface = Stale::new(b"#230.0 007 072.0 50.0\r", &[b"#230.0 008 072.0 50.0\r"]);
+
+        assert_eq!(iface.query_ups_rating().unwrap().output_rating_current, 8);
+        assert_eq!(iface.into_inner().written, b"F\r");
+
+        // A stale complete frame and the start of another one, its rest arriving after the command
+        let mut iface = Stale::new(
+            b"#230.0 007 072.0 50.0\r(208.4 140.0 208.4",
+            &[b" 034 59.9 2.05 35.0 00110000\r#230.0 008 072.0 50.0\r"],
+        );
+
+        assert_eq!(iface.query_ups_rating().unwrap().output_rating_current, 8);
+
+        // Without a known prefix, the partial frame is discarded too
+        let mut iface = Stale::new(b"(12.3 VEN", &[b"(45.6 VENDOR\r"]);
+
+        assert_eq!(iface.raw_query(b"X1").unwrap(), b"(45.6 VENDOR");
+
+        // Nor does a stale reply pass for the reply to a control command
+        let mut iface = Stale::new(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r(208.4", &[b""]);
+
+        assert_eq!(iface.start_self_test().unwrap(), cplus::CommandAck::NoReply);
+        assert!(iface.get_ref().input.is_empty());
+    }
+
+    #[test]
+    fn bus_test() {
+        let status = b"\x01(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r";
+        let other_status = b"\x02(208.4 140.0 208.4 034 59.9 2.05 35.0 00110001\r";
+        let (first, second) = (BusAddress(0x01), BusAddress(0x02));
+
+        let mut bus = CPlusBusInterface::from_interface(Stale::new(b"", &[
+            status,
+            b"\x02#230.0 008 072.0 50.0\r",
+            b"\x02(208.4 140.0 208.4 034 59.9 2.05 35.0 00110001\r\x01(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r",
+            b"\x01ACK\r",
+            other_status,
+        ]));
+        bus.interface_mut().set_command_gap(Duration::ZERO);
+        bus.set_unit_gap(Duration::from_millis(50));
+
+        let started = Instant::now();
+
+        assert!(!bus.run(first, CPlusGenericInterface::query_ups_status).unwrap().ups_status.beeper_on());
+        assert_eq!(bus.run(second, CPlusGenericInterface::query_ups_rating).unwrap().output_rating_current, 8);
+
+        // The reply of the other unit is discarded
+        assert!(!bus.run(first, CPlusGenericInterface::query_ups_status).unwrap().ups_status.beeper_on());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        assert_eq!(bus.run(first, CPlusGenericInterface::cancel_test).unwrap(), cplus::CommandAck::Acknowledged);
+
+        // Only the other unit replies
+        assert!(matches!(bus.run(first, CPlusGenericInterface::query_ups_status), Err(crate::Error::UnexpectedResponse { .. })));
+
+        // An address which can't be told apart from the end of a message
+        assert!(matches!(bus.run(BusAddress(b'\r'), CPlusGenericInterface::query_ups_status), Err(crate::Error::InvalidParameter(_))));
+
+        assert_eq!(bus.into_inner().into_inner().written, b"\x01Q1\r\x02F\r\x01Q1\r\x01CT\r\x01Q1\r");
+    }
+
+    #[test]
+    fn backoff_test() {
+        let ms = Duration::from_millis;
+        let backoff = Backoff { jitter: 0.0, ..Backoff::default() };
+
+        let delays: Vec<_> = backoff.delays().take(9).collect();
+
+        assert_eq!(delays, [ms(500), ms(1000), ms(2000), ms(4000), ms(8000), ms(16000), ms(30000), ms(30000), ms(30000)]);
+
+        // The delays start over after a reset
+        let mut delays = backoff.delays();
+        delays.nth(3);
+        delays.reset();
+
+        assert_eq!(delays.take(2).collect::<Vec<_>>(), [ms(500), ms(1000)]);
+
+        // A constant delay
+        assert!(Backoff::from(ms(100)).delays().take(3).all(|delay| delay == ms(100)));
+
+        // The jitter is the same for the same seed, and keeps the delays within the bounds
+        let jittered: Vec<_> = Backoff::default().seeded(42).delays().take(9).collect();
+
+        assert_eq!(jittered, Backoff::default().seeded(42).delays().take(9).collect::<Vec<_>>());
+        assert_ne!(jittered, Backoff::default().seeded(43).delays().take(9).collect::<Vec<_>>());
+
+        for (jittered, delay) in jittered.iter().zip(backoff.delays()) {
+            assert!(*jittered >= delay.mul_f64(0.9) && *jittered <= delay.mul_f64(1.1).min(ms(30000)), "{jittered:?} for {delay:?}");
+        }
+    }
+
+    #[test]
+    fn query_many_test() {
+        let status: &[u8] = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r";
+        let mut iface = Stale::new(
+            b"#230.0 007 072.0 50.0\r",
+            &[status, b"\xfe\xff0.0 00\r", b"#Company_Name01 UPS_Model1 V2.16     \r"],
+        );
+        iface.set_command_gap(Duration::ZERO);
+
+        let replies = iface.query_many(&[b"Q1", b"F\r", b"I"]).unwrap();
+
+        assert!(matches!(&replies[..], [Ok(status), Err(crate::Error::InvalidFormat { parser: "frame", .. }), Ok(info)]
+            if status.starts_with(b"(208.4") && info.starts_with(b"#Company")));
+        assert_eq!(iface.metrics().get("F").unwrap().errors, 1);
+        assert_eq!(iface.into_inner().written, b"Q1\rF\rI\r");
+
+        assert!(Stale::new(b"", &[]).query_many(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn batched_snapshot_test() {
+        let mut iface = Stale::new(
+            b"",
+            &[
+                b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r",
+                b"(\x01\x02\r",
+                b"(\x00\x00\x05\x44\r",
+                b"#230.0 008 072.0 50.0\r",
+            ],
+        );
+        iface.set_command_gap(Duration::ZERO);
+
+        let snapshot = iface.query_snapshot().unwrap();
+
+        // The garbled extra power info doesn't shift the following replies
+        assert!(snapshot.extra_power_info.is_none());
+        assert_eq!(snapshot.autonomy.unwrap().time, Duration::from_secs(0x0544));
+        assert_eq!(snapshot.rating.unwrap().output_rating_current, 8);
+        assert!(matches!(&snapshot.errors[..], [error] if error.section == "extra_power_info"));
+        assert_eq!(iface.into_inner().written, b"Q1\rQ5\rAt\rF\r");
+
+        // Only the status is required
+        let mut iface = Stale::new(b"", &[b"#230.0 008 072.0 50.0\r"]);
+        iface.set_command_gap(Duration::ZERO);
+
+        assert!(iface.query_snapshot().is_err());
+    }
+
+    #[test]
+    fn frame_reuse_test() {
+        let mut iface = Loopback::new(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110001\r#230.0 008 072.0 50.0\r(12.3\r");
+
+        assert!(iface.query_ups_status().unwrap().ups_status.beeper_on());
+        let frame = iface.frame.as_ptr();
+
+        // The shorter reply is parsed on its own, in the same buffer
+        assert_eq!(iface.query_ups_rating().unwrap().output_rating_current, 8);
+        assert_eq!(iface.frame, b"#230.0 008 072.0 50.0");
+        assert_eq!(iface.frame.as_ptr(), frame);
+
+        assert_eq!(iface.raw_query(b"X1").unwrap(), b"(12.3");
+
+        // Neither a control command without a reply nor a failed query sees the last reply
+        assert_eq!(iface.start_self_test().unwrap(), cplus::CommandAck::NoReply);
+        assert!(iface.frame.is_empty());
+        assert!(matches!(iface.raw_query(b"X1"), Err(crate::Error::Timeout { .. })));
+
+        let mut iface = Loopback::new(b"#230.0 008 072.0 50.0\r#230.0 008");
+
+        assert!(iface.query_ups_rating().is_ok());
+        assert!(matches!(
+            iface.query_ups_rating(),
+            Err(crate::Error::IncompleteResponse { received }) if received == b"#230.0 008"
+        ));
+        assert!(iface.frame.is_empty());
+    }
+
+    #[cfg(feature = "serial")]
+    #[test]
+    fn probe_test() {
+        let mut iface = Loopback::new(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r#Company_Name01 UPS_Model1 V2.16     \r");
+
+        let (status, info) = iface.probe().unwrap();
+
+        assert_eq!(status.input_frequency, 59.9);
+        assert_eq!(info.unwrap().model, "UPS_Model1");
+        assert_eq!(iface.into_inner().written, b"Q1\rI\r");
+
+        // The information is optional, the status isn't
+        let (_, info) = Loopback::new(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r").probe().unwrap();
+
+        assert!(info.is_none());
+        assert!(Loopback::new(b"").probe().is_err());
+        assert!(Loopback::new(b"#Company_Name01 UPS_Model1 V2.16     \r").probe().is_err());
+    }
+}
+
+/// Tests running the serial interface against a scripted port.
+#[cfg(all(test, feature = "serial"))]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::io::Read;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Default)]
+    struct Script {
+        /// Replies to the written messages, in order. An empty reply means the UPS stays silent.
+        replies: VecDeque<Vec<u8>>,
+        /// Bytes waiting to be read.
+        input: VecDeque<u8>,
+        /// Messages written so far (including the end byte).
+        written: Vec<Vec<u8>>,
+        /// The message currently being written.
+        pending: Vec<u8>,
+        timeout: Duration,
+        /// Whether the port behaves like an unplugged device.
+        disconnected: bool,
+        /// Changes of the control lines, in order.
+        lines: Vec<(&'static str, bool)>,
+        flushed: bool,
+    }
+
+    /// Serial port which answers every written message with the next scripted reply.
+    #[derive(Debug, Clone, Default)]
+    struct ScriptedPort(Arc<Mutex<Script>>);
+
+    impl ScriptedPort {
+        fn new(replies: &[&[u8]]) -> Self {
+            let port = Self::default();
+
+            port.0.lock().unwrap().replies = replies.iter().map(|r| r.to_vec()).collect();
+
+            port
+        }
+
+        fn interface(&self) -> CPlusSerialInterface {
+            let mut iface = CPlusSerialInterface::new(SerialTransport::new(Box::new(self.clone()), SerialConnectOptions::new("scripted")));
+            iface.set_command_gap(Duration::ZERO);
+            iface
+        }
+
+        fn written(&self) -> Vec<Vec<u8>> {
+            self.0.lock().unwrap().written.clone()
+        }
+
+        fn disconnect(&self) {
+            self.0.lock().unwrap().disconnected = true;
+        }
+    }
+
+    impl Read for ScriptedPort {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let mut script = self.0.lock().unwrap();
+
+            if script.disconnected {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+
+            let (Some(byte), Some(slot)) = (script.input.pop_front(), buf.first_mut()) else {
+                return Err(std::io::ErrorKind::TimedOut.into());
+            };
+
+            *slot = byte;
+
+            Ok(1)
+        }
+    }
+
+    impl Write for ScriptedPort {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let mut script = self.0.lock().unwrap();
+
+            if script.disconnected {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+
+            for &byte in buf {
+                script.pending.push(byte);
+
+                if byte == END_BYTE {
+                    let message = std::mem::take(&mut script.pending);
+                    script.written.push(message);
+
+                    let reply = script.replies.pop_front().unwrap_or_default();
+                    if !reply.is_empty() {
+                        script.input.extend(reply);
+                        script.input.push_back(END_BYTE);
+                    }
+                }
+            }
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            let mut script = self.0.lock().unwrap();
+
+            if script.disconnected {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+
+            script.flushed = true;
+            Ok(())
+        }
+    }
+
+    impl serialport::SerialPort for ScriptedPort {
+        fn name(&self) -> Option<String> { Some("scripted".to_string()) }
+        fn baud_rate(&self) -> serialport::Result<u32> { Ok(cplus::SERIAL_BAUD_RATE) }
+        fn data_bits(&self) -> serialport::Result<serialport::DataBits> { Ok(serialport::DataBits::Eight) }
+        fn flow_control(&self) -> serialport::Result<serialport::FlowControl> { Ok(serialport::FlowControl::None) }
+        fn parity(&self) -> serialport::Result<serialport::Parity> { Ok(serialport::Parity::None) }
+        fn stop_bits(&self) -> serialport::Result<serialport::StopBits> { Ok(serialport::StopBits::One) }
+        fn timeout(&self) -> Duration { self.0.lock().unwrap().timeout }
+        fn set_baud_rate(&mut self, _: u32) -> serialport::Result<()> { Ok(()) }
+        fn set_data_bits(&mut self, _: serialport::DataBits) -> serialport::Result<()> { Ok(()) }
+        fn set_flow_control(&mut self, _: serialport::FlowControl) -> serialport::Result<()> { Ok(()) }
+        fn set_parity(&mut self, _: serialport::Parity) -> serialport::Result<()> { Ok(()) }
+        fn set_stop_bits(&mut self, _: serialport::StopBits) -> serialport::Result<()> { Ok(()) }
+        fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+            self.0.lock().unwrap().timeout = timeout;
+            Ok(())
+        }
+        fn write_request_to_send(&mut self, level: bool) -> serialport::Result<()> {
+            self.0.lock().unwrap().lines.push(("RTS", level));
+            Ok(())
+        }
+        fn write_data_terminal_ready(&mut self, level: bool) -> serialport::Result<()> {
+            self.0.lock().unwrap().lines.push(("DTR", level));
+            Ok(())
+        }
+        fn read_clear_to_send(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn read_data_set_ready(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn read_ring_indicator(&mut self) -> serialport::Result<bool> { Ok(false) }
+        fn read_carrier_detect(&mut self) -> serialport::Result<bool> { Ok(true) }
+        fn bytes_to_read(&self) -> serialport::Result<u32> {
+            let script = self.0.lock().unwrap();
+
+            if script.disconnected {
+                return Err(serialport::Error::new(serialport::ErrorKind::NoDevice, "disconnected"));
+            }
+
+            Ok(script.input.len() as u32)
+        }
+        fn bytes_to_write(&self) -> serialport::Result<u32> { Ok(0) }
+        fn clear(&self, _: serialport::ClearBuffer) -> serialport::Result<()> {
+            self.0.lock().unwrap().input.clear();
+            Ok(())
+        }
+        fn try_clone(&self) -> serialport::Result<Box<dyn serialport::SerialPort>> { Ok(Box::new(self.clone())) }
+        fn set_break(&self) -> serialport::Result<()> { Ok(()) }
+        fn clear_break(&self) -> serialport::Result<()> { Ok(()) }
+    }
+
+    #[test]
+    fn shutdown_test() {
+        let port = ScriptedPort::new(&[b""]);
+        let mut iface = port.interface();
+
+        iface.shutdown(cplus::ShutdownDelay::from_tenths(3).unwrap()).unwrap();
+
+        assert_eq!(port.written(), [b"S.3\r"]);
+    }
+
+    #[test]
+    fn shutdown_and_restore_test() {
+        let port = ScriptedPort::new(&[b"", b""]);
+        let mut iface = port.interface();
+
+        iface.shutdown_and_restore(cplus::ShutdownDelay::from_tenths(3).unwrap(), 5).unwrap();
+        iface.shutdown_and_restore(cplus::ShutdownDelay::from_minutes(10).unwrap(), 9999).unwrap();
+
+        assert_eq!(port.written(), [&b"S.3R0005\r"[..], b"S10R9999\r"]);
+    }
+
+    #[test]
+    fn shutdown_and_restore_out_of_range_test() {
+        let port = ScriptedPort::new(&[]);
+        let mut iface = port.interface();
+        let delay = cplus::ShutdownDelay::from_minutes(1).unwrap();
+
+        assert!(matches!(iface.shutdown_and_restore(delay, 0), Err(crate::Error::InvalidParameter(_))));
+        assert!(matches!(iface.shutdown_and_restore(delay, 10000), Err(crate::Error::InvalidParameter(_))));
+        assert!(port.written().is_empty());
+    }
+
+    #[test]
+    fn cancel_shutdown_test() {
+        let port = ScriptedPort::new(&[
+            b"",
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000",
+        ]);
+        let mut iface = port.interface();
+
+        iface.cancel_shutdown().unwrap();
+        let status = iface.query_ups_status().unwrap();
+
+        assert!(!status.ups_status.shutdown_active());
+        assert_eq!(port.written(), [&b"C\r"[..], b"Q1\r"]);
+    }
+
+    #[test]
+    fn cancel_shutdown_rejected_test() {
+        let port = ScriptedPort::new(&[b"NAK"]);
+        let mut iface = port.interface();
+
+        let res = iface.cancel_shutdown();
+
+        assert!(matches!(res, Err(crate::Error::CommandRejected)));
+        assert_eq!(port.written(), [b"C\r"]);
+    }
+
+    #[test]
+    fn self_test_test() {
+        let port = ScriptedPort::new(&[
+            b"",
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00000101",
+        ]);
+        let mut iface = port.interface();
+
+        iface.start_self_test().unwrap();
+        let status = iface.query_ups_status().unwrap();
+
+        assert!(status.ups_status.test_in_progress());
+        assert_eq!(port.written(), [&b"T\r"[..], b"Q1\r"]);
+    }
+
+    #[test]
+    fn self_test_rejected_test() {
+        let port = ScriptedPort::new(&[b"NAK"]);
+        let mut iface = port.interface();
+
+        assert!(matches!(iface.start_self_test(), Err(crate::Error::CommandRejected)));
+    }
+
+    #[test]
+    fn timed_self_test_test() {
+        let port = ScriptedPort::new(&[b"", b""]);
+        let mut iface = port.interface();
+
+        iface.start_timed_self_test(5).unwrap();
+        iface.start_timed_self_test(99).unwrap();
+
+        assert!(matches!(iface.start_timed_self_test(0), Err(crate::Error::InvalidParameter(_))));
+        assert!(matches!(iface.start_timed_self_test(100), Err(crate::Error::InvalidParameter(_))));
+        assert_eq!(port.written(), [&b"T05\r"[..], b"T99\r"]);
+    }
+
+    #[test]
+    fn test_until_battery_low_test() {
+        let port = ScriptedPort::new(&[
+            b"",
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000101",
+            b"(208.4 140.0 208.4 034 59.9 1.67 35.0 01000001",
+        ]);
+        let mut iface = port.interface();
+
+        iface.start_test_until_battery_low().unwrap();
+
+        let running = iface.query_ups_status().unwrap().ups_status;
+        assert!(running.test_in_progress() && !running.battery_low());
+
+        let finished = iface.query_ups_status().unwrap().ups_status;
+        assert!(!finished.test_in_progress() && finished.battery_low());
+
+        assert_eq!(port.written(), [&b"TL\r"[..], b"Q1\r", b"Q1\r"]);
+    }
+
+    #[test]
+    fn cancel_test_test() {
+        // The UPS doesn't reply to control commands, so the read times out without any data
+        let port = ScriptedPort::new(&[b"", b"ACK"]);
+        let mut iface = port.interface();
+
+        assert_eq!(iface.cancel_test().unwrap(), cplus::CommandAck::NoReply);
+        assert_eq!(iface.cancel_test().unwrap(), cplus::CommandAck::Acknowledged);
+
+        assert_eq!(port.written(), [b"CT\r", b"CT\r"]);
+    }
+
+    #[test]
+    fn cancel_test_rejected_test() {
+        let port = ScriptedPort::new(&[b"NAK"]);
+        let mut iface = port.interface();
+
+        assert!(matches!(iface.cancel_test(), Err(crate::Error::CommandRejected)));
+    }
+
+    #[test]
+    fn toggle_beeper_test() {
+        let port = ScriptedPort::new(&[
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000001",
+            b"",
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000000",
+        ]);
+        let mut iface = port.interface();
+
+        assert!(!iface.toggle_beeper().unwrap());
+        assert_eq!(port.written(), [&b"Q1\r"[..], b"Q\r", b"Q1\r"]);
+    }
+
+    #[test]
+    fn mute_beeper_test() {
+        let port = ScriptedPort::new(&[
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000001",
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000001",
+            b"",
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000000",
+        ]);
+
+        port.interface().mute_beeper().unwrap();
+
+        assert_eq!(port.written(), [&b"Q1\r"[..], b"Q1\r", b"Q\r", b"Q1\r"]);
+
+        // Already silent, so nothing is toggled
+        let port = ScriptedPort::new(&[b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000000"]);
+
+        port.interface().mute_beeper().unwrap();
+
+        assert_eq!(port.written(), [b"Q1\r"]);
+    }
+
+    #[test]
+    fn toggle_beeper_not_toggled_test() {
+        let port = ScriptedPort::new(&[
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000001",
+            b"",
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000001",
+        ]);
+        let mut iface = port.interface();
+
+        assert!(matches!(iface.toggle_beeper(), Err(crate::Error::UnexpectedResponse { .. })));
+    }
+
+    #[test]
+    fn execute_custom_command_test() {
+        /// Vendor-specific command answered like the rating inquiry.
+        struct VendorRating;
+
+        impl cplus::Command for VendorRating {
+            type Response = cplus::UPSRating;
+
+            fn to_bytes(&self) -> Vec<u8> {
+                b"VF".to_vec()
+            }
+        }
+
+        let port = ScriptedPort::new(&[b"#230.0 008 072.0 50.0"]);
+        let mut iface = port.interface();
+
+        let rating = iface.execute(&VendorRating).unwrap();
+
+        assert_eq!(rating.output_rating_current, 8);
+        assert_eq!(port.written(), [b"VF\r"]);
+    }
+
+    #[test]
+    fn graceful_shutdown_test() {
+        let port = ScriptedPort::new(&[
+            b"(\x00\x00\x05\x44",
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000000",
+            b"",
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000000",
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000010",
+        ]);
+        let mut iface = port.interface();
+        let mut opts = GracefulShutdownOptions::new(cplus::ShutdownDelay::from_minutes(2).unwrap());
+        opts.poll_interval = Duration::ZERO;
+
+        let report = iface.graceful_shutdown(opts).unwrap();
+
+        assert_eq!(report.autonomy, Some(Duration::from_secs(1348)));
+        assert!(report.utility_fail && report.shutdown_issued && report.confirmed);
+        assert_eq!(port.written(), [&b"At\r"[..], b"Q1\r", b"S02\r", b"Q1\r", b"Q1\r"]);
+    }
+
+    #[test]
+    fn graceful_shutdown_utility_present_test() {
+        let status = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00000000";
+
+        // Without forcing, nothing is sent after the status query
+        let port = ScriptedPort::new(&[b"", status]);
+        let mut iface = port.interface();
+        let opts = GracefulShutdownOptions::new(cplus::ShutdownDelay::from_minutes(1).unwrap());
+
+        let report = iface.graceful_shutdown(opts).unwrap();
+
+        assert!(report.autonomy.is_none() && !report.utility_fail && !report.shutdown_issued);
+        assert_eq!(port.written(), [&b"At\r"[..], b"Q1\r"]);
+
+        // Forced, but the UPS never reports the pending shutdown
+        let port = ScriptedPort::new(&[b"", status, b"", status]);
+        let mut iface = port.interface();
+        let opts = GracefulShutdownOptions {
+            force: true,
+            confirm_timeout: Duration::ZERO,
+            ..opts
+        };
+
+        let report = iface.graceful_shutdown(opts).unwrap();
+
+        assert!(report.shutdown_issued && !report.confirmed);
+        assert_eq!(port.written(), [&b"At\r"[..], b"Q1\r", b"S01\r", b"Q1\r"]);
+    }
+
+    #[test]
+    fn verify_shutdown_pending_test() {
+        let idle = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000000";
+        let pending = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000010";
+
+        let port = ScriptedPort::new(&[idle, idle, idle, pending]);
+
+        assert!(port.interface().verify_shutdown_pending(Duration::from_secs(1), Duration::ZERO).unwrap());
+        assert_eq!(port.written().len(), 4);
+
+        // The bit is never set, the status is polled at most every 20ms
+        let port = ScriptedPort::new(&[idle, idle, idle, idle]);
+        let start = Instant::now();
+
+        let pending = port
+            .interface()
+            .verify_shutdown_pending(Duration::from_millis(50), Duration::from_millis(20))
+            .unwrap();
+
+        let polls = port.written().len();
+
+        assert!(!pending);
+        assert!((1..=3).contains(&polls));
+        assert!(start.elapsed() >= Duration::from_millis(20) * (polls as u32 - 1));
+    }
+
+    #[test]
+    fn verify_shutdown_pending_failure_test() {
+        let idle = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 10000000";
+
+        // The UPS stops answering after the first poll
+        let port = ScriptedPort::new(&[idle, b""]);
+
+        assert!(port.interface().verify_shutdown_pending(Duration::from_secs(1), Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn query_test_result_test() {
+        let port = ScriptedPort::new(&[b"#  OK", b"#fail"]);
+        let mut iface = port.interface();
+
+        assert_eq!(iface.query_test_result().unwrap().result, cplus::TestResult::Passed);
+        assert_eq!(iface.query_test_result().unwrap().result, cplus::TestResult::Failed);
+        assert_eq!(port.written(), [b"TR\r", b"TR\r"]);
+    }
+
+    #[test]
+    fn set_outlet_test() {
+        let port = ScriptedPort::new(&[b"", b"", b"", b""]);
+        let mut iface = port.interface();
+
+        iface.set_outlet(cplus::Outlet::A, true).unwrap();
+        iface.set_outlet(cplus::Outlet::A, false).unwrap();
+        iface.set_outlet(cplus::Outlet::B, true).unwrap();
+        iface.set_outlet(cplus::Outlet::B, false).unwrap();
+
+        assert_eq!(port.written(), [&b"pa1\r"[..], b"pa0\r", b"pb1\r", b"pb0\r"]);
+    }
+
+    #[test]
+    fn connect_options_test() {
+        let defaults = CPlusSerialInterface::builder("/dev/ttyS0");
+
+        assert_eq!(defaults.baud_rate, 2_400);
+        assert_eq!(defaults.timeout, Duration::from_secs(5));
+        assert_eq!(defaults.parity, serialport::Parity::None);
+        assert_eq!((defaults.dtr, defaults.rts), (LineState::Assert, LineState::Leave));
+
+        let options = defaults
+            .baud_rate(9_600)
+            .timeout(Duration::from_millis(200))
+            .parity(serialport::Parity::Even)
+            .stop_bits(serialport::StopBits::Two)
+            .flow_control(serialport::FlowControl::Hardware)
+            .dtr(false)
+            .rts(true)
+            .line_terminator(LineTerminator::CrLf)
+            .echo_mode(EchoMode::Detect);
+
+        assert_eq!(options, SerialConnectOptions {
+            path: "/dev/ttyS0".to_string(),
+            baud_rate: 9_600,
+            timeout: Duration::from_millis(200),
+            parity: serialport::Parity::Even,
+            stop_bits: serialport::StopBits::Two,
+            flow_control: serialport::FlowControl::Hardware,
+            dtr: LineState::Deassert,
+            rts: LineState::Assert,
+            deassert_on_close: true,
+            line_terminator: LineTerminator::CrLf,
+            echo_mode: EchoMode::Detect,
+        });
+        assert!(options.validate().is_ok());
+
+        // The chosen options are kept by the interface
+        let iface = CPlusSerialInterface::new(SerialTransport::new(Box::new(ScriptedPort::default()), options.clone()));
+
+        assert_eq!(iface.options(), &options);
+    }
+
+    #[test]
+    fn connect_options_validation_test() {
+        let zero_timeout = CPlusSerialInterface::builder("/dev/ttyS0").timeout(Duration::ZERO);
+        let zero_baud_rate = CPlusSerialInterface::builder("/dev/ttyS0").baud_rate(0);
+
+        assert!(matches!(zero_timeout.validate(), Err(crate::Error::InvalidParameter(_))));
+        assert!(matches!(zero_baud_rate.validate(), Err(crate::Error::InvalidParameter(_))));
+
+        // Rejected before trying to open the port
+        assert!(matches!(zero_timeout.open(), Err(crate::Error::InvalidParameter(_))));
+    }
+
+    #[test]
+    fn normalize_port_path_test() {
+        assert_eq!(normalize_port_path("COM3"), "COM3");
+        assert_eq!(normalize_port_path("COM10"), r"\\.\COM10");
+        assert_eq!(normalize_port_path("com12"), r"\\.\com12");
+        assert_eq!(normalize_port_path(r"\\.\COM10"), r"\\.\COM10");
+        assert_eq!(normalize_port_path("/dev/ttyUSB10"), "/dev/ttyUSB10");
+        assert_eq!(normalize_port_path("COM1x"), "COM1x");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stable_port_candidates_test() {
+        let dir = std::env::temp_dir().join(format!("alphamon-by-id-{}", std::process::id()));
+        let by_id = dir.join("by-id");
+        std::fs::create_dir_all(&by_id).unwrap();
+
+        let device = dir.join("ttyUSB0");
+        std::fs::write(&device, b"").unwrap();
+        let link = by_id.join("usb-Alpha_UPS_0001-if00-port0");
+        std::os::unix::fs::symlink(&device, &link).unwrap();
+        std::os::unix::fs::symlink(dir.join("ttyUSB9"), by_id.join("usb-unplugged")).unwrap();
+
+        let device = std::fs::canonicalize(&device).unwrap();
+        let resolved = resolve_port_path(link.to_str().unwrap());
+        let candidates = stable_port_candidates_in(&by_id);
+        let missing = stable_port_candidates_in(&dir.join("missing"));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(resolved.unwrap(), device);
+        assert_eq!(candidates.unwrap(), [StablePortPath { stable_path: link, target: device }]);
+        assert!(missing.unwrap().is_empty());
+    }
+
+    #[test]
+    fn open_error_test() {
+        let result = CPlusSerialInterface::connect("/nonexistent/ttyUPS0");
+
+        let Err(crate::Error::SerialPort(e)) = result else { panic!("expected a serial port error") };
+        assert!(e.description.contains("/nonexistent/ttyUPS0"), "{}", e.description);
+    }
+
+    #[test]
+    fn close_test() {
+        let port = ScriptedPort::default();
+        let iface = CPlusSerialInterface::new(SerialTransport::new(
+            Box::new(port.clone()),
+            SerialConnectOptions::new("scripted").rts(true),
+        ));
+
+        iface.close().unwrap();
+
+        let script = port.0.lock().unwrap();
+        assert!(script.flushed);
+        // Released only once, not again when dropped
+        assert_eq!(script.lines, [("DTR", false), ("RTS", false)]);
+    }
+
+    #[test]
+    fn reconnect_test() {
+        let old_port = ScriptedPort::default();
+        let new_port = ScriptedPort::new(&[b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r"]);
+        let opens = Arc::new(Mutex::new(vec![]));
+
+        let mut iface = old_port.interface();
+        iface.port.factory = {
+            let (new_port, opens) = (new_port.clone(), Arc::clone(&opens));
+
+            Box::new(move |options: &SerialConnectOptions| {
+                opens.lock().unwrap().push((options.path.clone(), options.timeout));
+                Ok(Box::new(new_port.clone()) as Box<dyn serialport::SerialPort>)
+            })
+        };
+        iface.set_query_timeout(Duration::from_secs(2)).unwrap();
+
+        assert!(iface.is_connected());
+
+        old_port.disconnect();
+
+        assert!(!iface.is_connected());
+        assert!(iface.query_ups_status().is_err());
+
+        iface.reconnect().unwrap();
+
+        // Reopened with the changed timeout, the DTR line asserted again
+        assert!(iface.is_connected());
+        assert_eq!(*opens.lock().unwrap(), [("scripted".to_string(), Duration::from_secs(2))]);
+        assert_eq!(new_port.0.lock().unwrap().lines, [("DTR", true)]);
+        assert!(iface.query_ups_status().is_ok());
+        assert_eq!(new_port.written(), [b"Q1\r"]);
+
+        // A failed reopen leaves the interface disconnected
+        iface.port.factory = Box::new(|_| Err(std::io::Error::from(std::io::ErrorKind::NotFound).into()));
+
+        assert!(iface.reconnect().is_err());
+        assert!(!iface.is_connected());
+        assert!(matches!(iface.query_ups_status(), Err(crate::Error::Io(e)) if e.kind() == std::io::ErrorKind::NotConnected));
+    }
+
+    #[test]
+    fn line_state_test() {
+        let port = ScriptedPort::default();
+        let factory_port = port.clone();
+        let options = SerialConnectOptions::new("scripted").dtr(LineState::Leave).rts(false);
+
+        let transport = SerialTransport::open(options, Box::new(move |_| Ok(Box::new(factory_port.clone()) as _))).unwrap();
+        let mut iface = CPlusSerialInterface::new(transport);
+
+        // DTR isn't touched, e.g. when it's the shutdown signal
+        assert_eq!(port.0.lock().unwrap().lines, [("RTS", false)]);
+
+        iface.set_dtr(true).unwrap();
+        iface.set_rts(true).unwrap();
+
+        assert_eq!((iface.options().dtr, iface.options().rts), (LineState::Assert, LineState::Assert));
+
+        iface.close().unwrap();
+
+        assert_eq!(
+            port.0.lock().unwrap().lines,
+            [("RTS", false), ("DTR", true), ("RTS", true), ("DTR", false), ("RTS", false)]
+        );
+        assert_eq!(LineState::from(false).level(), Some(false));
+    }
+
+    #[test]
+    fn drop_test() {
+        let port = ScriptedPort::default();
+
+        drop(port.interface());
+
+        assert_eq!(port.0.lock().unwrap().lines, [("DTR", false)]);
+
+        // DTR is left asserted if it's used as a shutdown signal
+        let port = ScriptedPort::default();
+        let options = SerialConnectOptions::new("scripted").deassert_on_close(false);
+
+        CPlusSerialInterface::new(SerialTransport::new(Box::new(port.clone()), options)).close().unwrap();
+
+        assert!(port.0.lock().unwrap().lines.is_empty());
+
+        // The lines are released even if the output can't be flushed
+        let port = ScriptedPort::default();
+        let iface = port.interface();
+
+        port.disconnect();
+
+        assert!(iface.close().is_err());
+        assert_eq!(port.0.lock().unwrap().lines, [("DTR", false)]);
+    }
+
+    #[test]
+    fn query_firmware_version_test() {
+        let port = ScriptedPort::new(&[b"#Company_Name01 UPS_Model1 V2.16     "]);
+
+        let version = port.interface().query_firmware_version().unwrap();
+
+        assert_eq!(version.raw, "V2.16");
+        assert_eq!((version.major, version.minor), (Some(2), Some(16)));
+        assert_eq!(port.written(), [b"I\r"]);
+    }
+
+    #[test]
+    fn query_timeout_test() {
+        let port = ScriptedPort::new(&[b""]);
+
+        let result = port.interface().query_firmware_version();
+
+        assert!(matches!(result, Err(crate::Error::Timeout { .. })));
+    }
+
+    #[test]
+    fn min_query_timeout_test() {
+        // The 46 bytes of the status and the end byte
+        assert_eq!(min_read_timeout(2_400, 47).as_micros(), 295_833);
+        assert_eq!(min_read_timeout(9_600, 47).as_micros(), 148_958);
+
+        let port = ScriptedPort::default();
+        let mut iface = port.interface();
+
+        // The longest message accepted, 64 bytes and the end byte
+        assert_eq!(iface.min_query_timeout(), Some(min_read_timeout(2_400, 65)));
+
+        iface.set_query_timeout(Duration::from_millis(100)).unwrap();
+        assert_eq!(iface.get_ref().options.timeout, min_read_timeout(2_400, 65));
+
+        iface.set_max_frame_len(46).unwrap();
+        iface.set_query_timeout(Duration::from_millis(100)).unwrap();
+        assert_eq!(iface.get_ref().options.timeout.as_millis(), 295);
+
+        let options = SerialConnectOptions::new("scripted").baud_rate(9_600);
+        let mut iface = CPlusSerialInterface::new(SerialTransport::new(Box::new(port), options));
+        iface.set_max_frame_len(46).unwrap();
+        iface.set_query_timeout(Duration::from_millis(100)).unwrap();
+        assert_eq!(iface.get_ref().options.timeout.as_millis(), 148);
+
+        // Longer timeouts are kept
+        iface.set_query_timeout(Duration::from_millis(500)).unwrap();
+        assert_eq!(iface.get_ref().options.timeout, Duration::from_millis(500));
+    }
+
+    fn retry_policy(attempts: u32) -> RetryPolicy {
+        RetryPolicy { attempts, backoff: Duration::ZERO, ..RetryPolicy::default() }
+    }
+
+    #[test]
+    fn retry_query_test() {
+        let port = ScriptedPort::new(&[
+            b"(208.4 140.0",
+            b"",
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000",
+        ]);
+        let mut iface = RetryingInterface::new(port.interface(), retry_policy(3));
+
+        let status = iface.query_ups_status().unwrap();
+
+        assert_eq!(status.input_voltage, 208.4);
+        assert_eq!(port.written(), [b"Q1\r"; 3]);
+    }
+
+    #[test]
+    fn retries_exhausted_test() {
+        let port = ScriptedPort::new(&[b"(garbage", b"(garbage", b"(garbage", b"(garbage"]);
+        let mut iface = RetryingInterface::new(port.interface(), retry_policy(3));
+
+        let result = iface.query_ups_status();
+
+        assert!(matches!(result, Err(crate::Error::RetriesExhausted { attempts: 3, .. })));
+        assert_eq!(port.written().len(), 3);
+    }
+
+    #[test]
+    fn retry_not_retryable_test() {
+        let port = ScriptedPort::new(&[b"(garbage", b"(garbage"]);
+        let policy = RetryPolicy { retry_on: |_| false, ..retry_policy(3) };
+        let mut iface = RetryingInterface::new(port.interface(), policy);
+
+        let result = iface.query_ups_status();
+
+        assert!(matches!(
+            result,
+            Err(crate::Error::InvalidFormat { parser: "StatusInquiryResponse", input, reason })
+                if input == b"garbage" && reason == "expected 8 fields, got 1"
+        ));
+        assert_eq!(port.written().len(), 1);
+    }
+
+    #[test]
+    fn retry_control_command_test() {
+        let port = ScriptedPort::new(&[b"NAK", b""]);
+        let mut iface = RetryingInterface::new(port.interface(), retry_policy(3));
+
+        assert!(matches!(iface.cancel_shutdown(), Err(crate::Error::CommandRejected)));
+        assert_eq!(port.written(), [b"C\r"]);
+    }
+
+    #[test]
+    fn retry_snapshot_test() {
+        let status: &[u8] = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000";
+        let port = ScriptedPort::new(&[b"(garbage", b"", b"", b"", status, b"", b"", b""]);
+        let mut iface = RetryingInterface::new(port.interface(), retry_policy(3));
+
+        let snapshot = iface.query_snapshot().unwrap();
+
+        // The whole batch is sent again, the failed sections alone aren't retried
+        assert_eq!(snapshot.status.input_voltage, 208.4);
+        assert_eq!(snapshot.errors.len(), 3);
+        assert_eq!(port.written(), [&b"Q1\r"[..], b"Q5\r", b"At\r", b"F\r"].repeat(2));
+    }
+
+    #[test]
+    fn transient_error_test() {
+        assert!(RetryPolicy::is_transient(&crate::Error::invalid_format("UPSStatus", b"0", "expected 8 bits, got 1")));
+        assert!(RetryPolicy::is_transient(&std::io::Error::from(std::io::ErrorKind::TimedOut).into()));
+        assert!(!RetryPolicy::is_transient(&crate::Error::CommandRejected));
+        assert!(!RetryPolicy::is_transient(&crate::Error::InvalidParameter("minutes".to_string())));
+        assert!(!RetryPolicy::is_transient(&std::io::Error::from(std::io::ErrorKind::PermissionDenied).into()));
+    }
+
+    #[test]
+    fn probe_order_test() {
+        let port = |name: &str, port_type| serialport::SerialPortInfo { port_name: name.to_string(), port_type };
+        let usb = serialport::SerialPortType::UsbPort(serialport::UsbPortInfo {
+            vid: 0x0665,
+            pid: 0x5161,
+            serial_number: None,
+            manufacturer: None,
+            product: None,
+        });
+        let mut ports = vec![
+            port("COM1", serialport::SerialPortType::Unknown),
+            port("COM4", usb.clone()),
+            port("COM2", serialport::SerialPortType::PciPort),
+            port("COM3", usb),
+        ];
+
+        sort_probe_order(&mut ports);
+
+        let names: Vec<_> = ports.iter().map(|port| port.port_name.as_str()).collect();
+        assert_eq!(names, ["COM4", "COM3", "COM1", "COM2"]);
+    }
+
+    const STATUS: &[u8] = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000";
+
+    /// Interface opening the given connections in order, an `Err` being a failed open.
+    fn reconnecting(opens: Vec<Result<ScriptedPort>>) -> ReconnectingInterface<SerialTransport> {
+        let mut opens = opens.into_iter();
+
+        ReconnectingInterface::new(move || opens.next().unwrap().map(|port| port.interface()))
+            .backoff(Duration::ZERO)
+    }
+
+    fn no_device() -> crate::Error {
+        serialport::Error::new(serialport::ErrorKind::NoDevice, "no such device").into()
+    }
+
+    #[test]
+    fn reconnect_query_test() {
+        let old = ScriptedPort::new(&[STATUS]);
+        let new = ScriptedPort::new(&[STATUS]);
+        let mut iface = reconnecting(vec![Ok(old.clone()), Err(no_device()), Ok(new.clone())]);
+
+        iface.query_ups_status().unwrap();
+        old.disconnect();
+        iface.query_ups_status().unwrap();
+
+        assert_eq!(old.written(), [b"Q1\r"]);
+        assert_eq!(new.written(), [b"Q1\r"]);
+        assert_eq!((iface.reconnects(), iface.failed_connects()), (1, 1));
+    }
+
+    #[test]
+    fn reconnect_initial_open_test() {
+        let port = ScriptedPort::new(&[STATUS]);
+        let mut iface = reconnecting(vec![Err(no_device()), Ok(port.clone())]);
+
+        assert!(!iface.is_connected());
+        iface.query_ups_status().unwrap();
+
+        assert!(iface.is_connected());
+        assert_eq!((iface.reconnects(), iface.failed_connects()), (0, 1));
+    }
+
+    #[test]
+    fn reconnect_gives_up_test() {
+        let mut iface = reconnecting(vec![Err(no_device()), Err(no_device())]).attempts(2);
+
+        let result = iface.query_ups_status();
+
+        assert!(matches!(result, Err(crate::Error::SerialPort(e)) if e.kind() == serialport::ErrorKind::NoDevice));
+        assert!(!iface.is_connected());
+        assert_eq!(iface.failed_connects(), 2);
+    }
+
+    #[test]
+    fn reconnect_control_command_test() {
+        let old = ScriptedPort::new(&[]);
+        let new = ScriptedPort::new(&[]);
+        old.disconnect();
+        let mut iface = reconnecting(vec![Ok(old), Ok(new.clone())]);
+
+        let result = iface.cancel_shutdown();
+
+        // Reconnected after checking the stale input failed, but the command isn't repeated
+        assert!(matches!(result, Err(crate::Error::SerialPort(e)) if e.kind() == serialport::ErrorKind::NoDevice));
+        assert!(iface.is_connected());
+        assert!(new.written().is_empty());
+        assert_eq!(iface.reconnects(), 1);
+    }
+
+    #[test]
+    fn reconnect_backoff_test() {
+        let old = ScriptedPort::new(&[STATUS]);
+        let backoff = Backoff { initial: Duration::from_millis(20), multiplier: 5.0, max: Duration::from_secs(1), jitter: 0.0, seed: None };
+        let mut iface = reconnecting(vec![Err(no_device()), Err(no_device()), Ok(old.clone()), Err(no_device())])
+            .backoff(backoff)
+            .attempts(1);
+
+        assert!(iface.query_ups_status().is_err());
+        let first_retry = iface.next_retry().unwrap();
+        assert!(first_retry <= Instant::now() + Duration::from_millis(20));
+
+        // Waits for the retry before opening the connection again
+        assert!(iface.query_ups_status().is_err());
+        assert!(Instant::now() >= first_retry);
+        assert!(iface.next_retry().unwrap() > Instant::now() + Duration::from_millis(20));
+
+        iface.query_ups_status().unwrap();
+        assert_eq!(iface.next_retry(), None);
+
+        // The backoff started over after the query succeeded
+        old.disconnect();
+
+        assert!(iface.query_ups_status().is_err());
+        assert!(iface.next_retry().unwrap() <= Instant::now() + Duration::from_millis(20));
+        assert_eq!(iface.failed_connects(), 3);
+    }
+
+    #[test]
+    fn reconnect_ignores_protocol_errors_test() {
+        let port = ScriptedPort::new(&[b"(garbage"]);
+        let mut iface = reconnecting(vec![Ok(port)]);
+
+        assert!(matches!(iface.query_ups_status(), Err(crate::Error::InvalidFormat { parser: "StatusInquiryResponse", .. })));
+        assert_eq!(iface.reconnects(), 0);
+    }
+}
+
+/// Tests running the HID interface against a fake device cycling through a carousel.
+#[cfg(all(test, feature = "usb-hidapi"))]
+mod hid_tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Default)]
+    struct Carousel {
+        /// Feature reports returned by the next reads, in order.
+        frames: VecDeque<Vec<u8>>,
+        /// Feature reports sent so far.
+        sent: Vec<Vec<u8>>,
+        /// Whether the frames repeat, like the real carousel, instead of running out.
+        cycle: bool,
+        /// Whether the device was unplugged, failing all reports.
+        unplugged: bool,
+        /// Feature reports read so far.
+        reads: usize,
+        /// Errors failing the next reads, before any frame is returned.
+        errors: VecDeque<crate::Error>,
+        /// Report descriptor of the device, if it can be read.
+        descriptor: Option<Vec<u8>>,
+        /// Input reports returned by the next interrupt endpoint reads, which time out once they run out.
+        interrupt: VecDeque<Vec<u8>>,
+    }
+
+    /// HID device returning the scripted feature reports, then failing once they run out.
+    #[derive(Debug, Clone, Default)]
+    struct FakeHid(Arc<Mutex<Carousel>>);
+
+    impl FakeHid {
+        fn new(frames: &[&[u8]]) -> Self {
+            let device = Self::default();
+
+            device.0.lock().unwrap().frames = frames.iter().map(|f| f.to_vec()).collect();
+
+            device
+        }
+
+        fn cycling(frames: &[&[u8]]) -> Self {
+            let device = Self::new(frames);
+
+            device.0.lock().unwrap().cycle = true;
+
+            device
+        }
+
+        fn interface(&self) -> CPlusHidInterface {
+            CPlusHidInterface {
+                device: Box::new(self.clone()),
+                serial_number: Some("FAKE0001".to_string()),
+                timeout: Duration::from_millis(50),
+                options: HidConnectOptions::path("/dev/hidraw0"),
+                auto_reopen: false,
+                api: Box::new(|| Ok(Box::new(FakeApi::new(vec![])))),
+                metrics: QueryMetrics::default(),
+                stale_data: StaleDataWatch::default(),
+                stale_data_window: None,
+                last_success: LastSuccess::default(),
+                cache: CarouselCache::default(),
+                frame_cache_window: DEFAULT_FRAME_CACHE_WINDOW,
+                read_retry: ReadRetry::default(),
+                report_len: DEFAULT_HID_REPORT_LEN,
+                read_mode: ReadMode::FeatureReport,
+                assembler: MessageAssembler::new(END_BYTE, DEFAULT_HID_REPORT_LEN),
+                interrupt_timeouts: 0,
+                battery: None,
+            }
+        }
+
+        #[cfg(feature = "async")]
+        fn async_interface(&self) -> AsyncCPlusHidInterface {
+            AsyncCPlusHidInterface::new(self.interface(), Duration::from_millis(100))
+        }
+
+        fn sent(&self) -> Vec<Vec<u8>> {
+            self.0.lock().unwrap().sent.clone()
+        }
+
+        fn reads(&self) -> usize {
+            self.0.lock().unwrap().reads
+        }
+
+        /// Streams `messages` on the interrupt endpoint, split into padded 8-byte reports.
+        fn stream(&self, messages: &[&[u8]]) {
+            let reports = messages.concat().chunks(8).map(|chunk| {
+                let mut report = chunk.to_vec();
+                report.resize(8, b'\0');
+
+                report
+            }).collect::<Vec<_>>();
+
+            self.0.lock().unwrap().interrupt.extend(reports);
+        }
+
+        /// Makes the next reads fail with `errors`, in order.
+        fn fail_reads(&self, errors: impl IntoIterator<Item = crate::Error>) {
+            self.0.lock().unwrap().errors.extend(errors);
+        }
+    }
+
+    impl FeatureReportDevice for FakeHid {
+        fn get_feature_report(&self, buf: &mut [u8]) -> Result<usize> {
+            let mut carousel = self.0.lock().unwrap();
+
+            if carousel.unplugged {
+                return Err(hid_error("device disconnected"));
+            }
+
+            carousel.reads += 1;
+
+            if let Some(error) = carousel.errors.pop_front() {
+                return Err(error);
+            }
+
+            let frame = carousel.frames.pop_front()
+                .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::TimedOut))?;
+
+            if carousel.cycle {
+                carousel.frames.push_back(frame.clone());
+            }
+
+            buf.fill(0);
+            buf.iter_mut().zip(&frame).for_each(|(b, f)| *b = *f);
+
+            Ok(frame.len())
+        }
+
+        fn send_feature_report(&self, data: &[u8]) -> Result<()> {
+            let mut carousel = self.0.lock().unwrap();
+
+            if carousel.unplugged {
+                return Err(hid_error("device disconnected"));
+            }
+
+            carousel.sent.push(data.to_vec());
+
+            Ok(())
+        }
+
+        fn report_descriptor(&self) -> Option<Vec<u8>> {
+            self.0.lock().unwrap().descriptor.clone()
+        }
+
+        fn read_interrupt(&self, buf: &mut [u8], _timeout: Duration) -> Result<usize> {
+            let mut carousel = self.0.lock().unwrap();
+
+            if carousel.unplugged {
+                return Err(hid_error("device disconnected"));
+            }
+
+            let Some(report) = carousel.interrupt.pop_front() else {
+                return Ok(0);
+            };
+
+            buf.iter_mut().zip(&report).for_each(|(b, r)| *b = *r);
+
+            Ok(report.len().min(buf.len()))
+        }
+    }
+
+    fn hid_error(message: &str) -> crate::Error {
+        hidapi::HidError::HidApiError { message: message.to_string() }.into()
+    }
+
+    const STATUS: &[u8] = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r";
+    const RATING: &[u8] = b"#230.0 008 072.0 50.0\r";
+    const INFORMATION: &[u8] = b"#Company_Name01 UPS_Model1 Version001\r";
+
+    #[test]
+    fn write_command_test() {
+        let device = FakeHid::new(&[]);
+
+        device.interface().write_command(b"T").unwrap();
+
+        assert_eq!(device.sent(), vec![b"\x05T\r".to_vec()]);
+    }
+
+    #[test]
+    fn status_skips_other_frames_test() {
+        let device = FakeHid::cycling(&[RATING, b"(\x00\x00\x05\x44\r", b"(208.4", STATUS]);
+
+        let status = device.interface().query_ups_status().unwrap();
+
+        assert!(status.ups_status.bypass_or_transformer_active());
+        assert!(device.sent().is_empty());
+    }
+
+    #[test]
+    fn rating_skips_information_test() {
+        let device = FakeHid::new(&[INFORMATION, RATING]);
+
+        let rating = device.interface().query_ups_rating().unwrap();
+
+        assert_eq!(rating.output_rating_current, 8);
+    }
+
+    #[test]
+    fn autonomy_test() {
+        let device = FakeHid::new(&[STATUS, RATING, b"(\x00\x00\x05\x44\r"]);
+
+        let autonomy = device.interface().query_ups_autonomy().unwrap();
+
+        assert_eq!(autonomy.time, Duration::from_secs(0x0544));
+        assert_eq!(device.sent(), vec![b"\x05At\r".to_vec()]);
+    }
+
+    #[test]
+    fn battery_life_test() {
+        let device = FakeHid::new(&[STATUS, b"!\x00\x00\x00\x64\r"]);
+
+        device.interface().query_ups_battery_life().unwrap();
+
+        assert_eq!(device.sent(), vec![b"\x05BL\r".to_vec()]);
+    }
+
+    #[test]
+    fn information_skips_rating_test() {
+        let device = FakeHid::new(&[RATING, STATUS, INFORMATION]);
+
+        let info = device.interface().query_ups_info().unwrap();
+
+        assert_eq!(info.manufacturer_name, "Company_Name01");
+        assert_eq!(device.sent(), vec![b"\x05I\r".to_vec()]);
+    }
+
+    #[test]
+    fn missing_frame_test() {
+        let device = FakeHid::new(&[STATUS, RATING]);
+
+        assert!(matches!(device.interface().query_ups_autonomy(), Err(crate::Error::Io(_))));
+    }
+
+    #[test]
+    fn test_commands_test() {
+        let device = FakeHid::new(&[]);
+        let mut interface = device.interface();
+
+        assert_eq!(interface.start_self_test().unwrap(), cplus::CommandAck::NoReply);
+        interface.start_timed_self_test(5).unwrap();
+        interface.start_test_until_battery_low().unwrap();
+        interface.cancel_test().unwrap();
+
+        assert_eq!(
+            device.sent(),
+            vec![b"\x05T\r".to_vec(), b"\x05T05\r".to_vec(), b"\x05TL\r".to_vec(), b"\x05CT\r".to_vec()]
+        );
+    }
+
+    #[test]
+    fn toggle_beeper_test() {
+        let device = FakeHid::new(&[STATUS, b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110001\r"]);
+
+        assert!(device.interface().toggle_beeper().unwrap());
+        assert_eq!(device.sent(), vec![b"\x05Q\r".to_vec()]);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_query_test() {
+        let device = FakeHid::cycling(&[STATUS, RATING, b"(\x00\x00\x05\x44\r"]);
+        let mut interface = device.async_interface();
+
+        let autonomy = interface.query_ups_autonomy().await.unwrap();
+        let rating = interface.query_ups_rating().await.unwrap();
+
+        assert_eq!(autonomy.time, Duration::from_secs(1348));
+        assert_eq!(rating.output_rating_current, 8);
+        assert_eq!(device.sent(), vec![b"\x05At\r".to_vec()]);
+    }
+
+    #[test]
+    fn snapshot_test() {
+        let device = FakeHid::cycling(&[STATUS, RATING, b"(\x00\x00\x05\x44\r"]);
+
+        let snapshot = device.interface().query_snapshot().unwrap();
+
+        assert!(snapshot.autonomy.is_some() && snapshot.rating.is_some());
+        // Not supported, so not queried
+        assert!(snapshot.extra_power_info.is_none());
+        assert!(snapshot.errors.is_empty());
+    }
+
+    /// Status on battery reporting the voltage of a 24 V pack, and its rating.
+    const PACK_FRAMES: [&[u8]; 3] = [
+        b"(208.4 140.0 208.4 034 59.9 25.2 35.0 10001000\r",
+        b"#230.0 004 024.0 50.0\r",
+        b"(\x00\x00\x05\x44\r",
+    ];
+
+    #[test]
+    fn battery_configuration_test() {
+        let device = FakeHid::cycling(&PACK_FRAMES);
+        let mut interface = device.interface();
+
+        assert_eq!(interface.query_ups_status().unwrap().battery_capacity, None);
+        assert_eq!(interface.query_ups_rating().unwrap().battery_voltage, 24.0);
+        assert_eq!(interface.battery_configuration().map(|battery| battery.blocks), Some(2));
+        assert_eq!(interface.query_ups_status().unwrap().battery_capacity, Some(72));
+
+        // The snapshot reads the rating before the status
+        let snapshot = device.interface().query_snapshot().unwrap();
+
+        assert_eq!(snapshot.status.battery_capacity, Some(72));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_battery_configuration_test() {
+        let device = FakeHid::cycling(&PACK_FRAMES);
+        let mut interface = device.async_interface();
+
+        assert_eq!(interface.query_ups_status().await.unwrap().battery_capacity, None);
+        assert_eq!(interface.query_ups_rating().await.unwrap().battery_voltage, 24.0);
+        assert_eq!(interface.query_ups_status().await.unwrap().battery_capacity, Some(72));
+    }
+
+    #[test]
+    fn capabilities_test() {
+        let device = FakeHid::cycling(&[STATUS, RATING, INFORMATION, b"(\x00\x00\x05\x44\r"]);
+        let mut interface = device.interface();
+
+        for &capability in Capability::ALL {
+            assert_eq!(capability.attempt(&mut interface), interface.supports(capability), "{capability:?}");
+        }
+
+        assert!(!interface.supports(Capability::ExtraPowerInfo));
+
+        // Advertised through the wrappers
+        let retrying = RetryingInterface::new(device.interface(), RetryPolicy::default());
+        let cached = CachedInterface::new(device.interface(), CacheTtl::default());
+        let shared = SharedInterface::new(device.interface());
+
+        assert_eq!(retrying.capabilities(), HID_CAPABILITIES);
+        assert_eq!(cached.capabilities(), HID_CAPABILITIES);
+        assert_eq!(shared.capabilities(), HID_CAPABILITIES);
+
+        // The poller skips the unsupported queries
+        let config = crate::device::poll::PollerConfig {
+            interval: Duration::from_millis(10),
+            commands: vec![crate::device::poll::PollCommand::Alarm, crate::device::poll::PollCommand::Status],
+        };
+        let poller = crate::device::poll::Poller::spawn(shared, config);
+
+        for _ in 0..2 {
+            assert!(matches!(poller.recv(), Some(Ok(crate::device::poll::PollResponse::Status(_)))));
+        }
+    }
+
+    #[test]
+    fn metrics_test() {
+        let device = FakeHid::new(&[STATUS, RATING, INFORMATION]);
+        let mut interface = device.interface();
+
+        interface.query_ups_status().unwrap();
+        interface.query_ups_info().unwrap();
+        assert!(interface.query_ups_autonomy().is_err());
+
+        let metrics = interface.metrics();
+        assert_eq!(metrics.iter().map(|(command, _)| command).collect::<Vec<_>>(), ["At", "I", "Q1"]);
+        assert_eq!((metrics.get("Q1").unwrap().count, metrics.get("At").unwrap().errors), (1, 1));
+    }
+
+    #[test]
+    fn all_operations_test() {
+        let device = FakeHid::cycling(&[STATUS, RATING, INFORMATION, b"(\x00\x00\x05\x44\r"]);
+        let mut interface = device.interface();
+
+        // None of them panics, the unsupported ones fail with an error
+        assert!(matches!(interface.query_extra_power_info(), Err(crate::Error::Unsupported { operation: "query_extra_power_info" })));
+        assert!(matches!(interface.query_alarm(), Err(crate::Error::Unsupported { operation: "query_alarm" })));
+
+        assert!(interface.query_ups_status().is_ok());
+        assert!(interface.query_ups_autonomy().is_ok());
+        assert!(interface.query_ups_info().is_ok());
+        assert!(interface.query_ups_rating().is_ok());
+        assert!(interface.query_firmware_version().is_ok());
+        assert!(interface.health_check().is_ok());
+        assert!(interface.query_snapshot().is_ok());
+        let _ = interface.query_ups_battery_life();
+        let _ = interface.query_test_result();
+
+        assert!(interface.shutdown(cplus::ShutdownDelay::from_minutes(1).unwrap()).is_ok());
+        assert!(interface.shutdown_and_restore(cplus::ShutdownDelay::from_minutes(1).unwrap(), 1).is_ok());
+        assert!(interface.cancel_shutdown().is_ok());
+        assert!(interface.start_self_test().is_ok());
+        assert!(interface.start_timed_self_test(1).is_ok());
+        assert!(interface.start_test_until_battery_low().is_ok());
+        assert!(interface.cancel_test().is_ok());
+        assert!(interface.set_outlet(cplus::Outlet::A, true).is_ok());
+        // The scripted beeper state doesn't change
+        assert!(interface.toggle_beeper().is_err());
+        assert!(interface.mute_beeper().is_ok());
+        assert!(interface.verify_shutdown_pending(Duration::ZERO, Duration::ZERO).is_ok());
+
+        let delay = cplus::ShutdownDelay::from_minutes(1).unwrap();
+        assert!(!interface.graceful_shutdown(GracefulShutdownOptions::new(delay)).unwrap().shutdown_issued);
+    }
+
+    #[test]
+    fn last_success_test() {
+        let device = FakeHid::new(&[STATUS, b"\0\0"]);
+        let mut interface = device.interface();
+
+        interface.query_ups_status().unwrap();
+        assert!(interface.query_ups_rating().is_err());
+
+        assert!(interface.last_success().is_some());
+        assert_eq!(interface.last_success_of("Q1"), interface.last_success());
+        assert_eq!(interface.last_success_of("F"), None);
+    }
+
+    #[test]
+    fn stale_data_watch_test() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut watch = StaleDataWatch::default();
+
+        assert_eq!(watch.unchanged_for(CarouselFrame::STATUS, at(0)), None);
+
+        // Jittering values restart the window, identical ones don't
+        watch.record(CarouselFrame::STATUS, b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000", at(0));
+        watch.record(CarouselFrame::STATUS, b"(208.5 140.0 208.4 034 59.9 2.05 35.0 00110000", at(10));
+        watch.record(CarouselFrame::STATUS, b"(208.5 140.0 208.4 034 59.9 2.05 35.0 00110000", at(20));
+        watch.record(CarouselFrame::RATING, b"#230.0 008 072.0 50.0", at(30));
+
+        assert_eq!(watch.unchanged_for(CarouselFrame::STATUS, at(40)), Some(Duration::from_secs(30)));
+        assert_eq!(watch.unchanged_for(CarouselFrame::RATING, at(40)), Some(Duration::from_secs(10)));
+
+        watch.record(CarouselFrame::STATUS, b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000", at(50));
+
+        assert_eq!(watch.unchanged_for(CarouselFrame::STATUS, at(50)), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn stale_data_test() {
+        let device = FakeHid::cycling(&[STATUS, RATING]);
+        let mut interface = device.interface();
+
+        // Every query reads the carousel
+        interface.set_frame_cache_window(Duration::ZERO);
+
+        assert!(!interface.is_data_stale(Duration::ZERO));
+
+        interface.set_stale_data_window(Some(Duration::from_millis(20)));
+        assert!(interface.query_ups_status().is_ok());
+
+        std::thread::sleep(Duration::from_millis(30));
+
+        assert!(interface.is_data_stale(Duration::from_millis(20)));
+        assert!(!interface.is_data_stale(Duration::from_secs(60)));
+        assert!(matches!(interface.query_ups_status(), Err(crate::Error::StaleData { .. })));
+
+        // A changed status is fresh again
+        device.0.lock().unwrap().frames = [b"(208.5 140.0 208.4 034 59.9 2.05 35.0 00110000\r".to_vec()].into();
+
+        assert!(interface.query_ups_status().is_ok());
+        assert!(!interface.is_data_stale(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn frame_cache_test() {
+        let device = FakeHid::cycling(&[RATING, INFORMATION, STATUS]);
+        let mut interface = device.interface();
+
+        // The rating passes by while waiting for the status
+        assert!(interface.query_ups_status().is_ok());
+        assert_eq!(device.reads(), 3);
+
+        assert_eq!(interface.query_ups_rating().unwrap().output_rating_current, 8);
+        assert!(interface.query_ups_status().is_ok());
+        assert_eq!(device.reads(), 3);
+
+        // A query doesn't empty the cache, a control command does
+        assert!(interface.query_ups_info().is_ok());
+        assert_eq!(device.reads(), 5);
+        assert!(interface.query_ups_rating().is_ok());
+        assert_eq!(device.reads(), 5);
+
+        interface.cancel_test().unwrap();
+
+        assert!(interface.query_ups_rating().is_ok());
+        assert_eq!(device.reads(), 7);
+
+        // Too old
+        interface.set_frame_cache_window(Duration::from_millis(10));
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert!(interface.query_ups_rating().is_ok());
+        assert_eq!(device.reads(), 10);
+
+        // Off
+        interface.set_frame_cache_window(Duration::ZERO);
+
+        assert!(interface.query_ups_status().is_ok());
+        assert!(interface.query_ups_status().is_ok());
+        assert_eq!(device.reads(), 15);
+    }
+
+    #[test]
+    fn snapshot_sweep_test() {
+        let autonomy: &[u8] = b"(\x00\x00\x05\x44\r";
+        let device = FakeHid::cycling(&[RATING, autonomy, STATUS]);
+        let mut interface = device.interface();
+
+        let snapshot = interface.query_snapshot().unwrap();
+
+        // One sweep for the status and rating, then the autonomy reply
+        assert_eq!(snapshot.rating.unwrap().output_rating_current, 8);
+        assert_eq!(snapshot.autonomy.unwrap().time, Duration::from_secs(0x0544));
+        assert_eq!(device.reads(), 5);
+
+        // Without the cache, the rating, the status and the autonomy reply are waited for in turn
+        let device = FakeHid::cycling(&[RATING, autonomy, STATUS]);
+        let mut interface = device.interface();
+        interface.set_frame_cache_window(Duration::ZERO);
+
+        assert!(interface.query_snapshot().unwrap().rating.is_some());
+        assert_eq!(device.reads(), 5);
+    }
+
+    #[test]
+    fn wrapped_snapshot_test() {
+        let autonomy: &[u8] = b"(\x00\x00\x05\x44\r";
+
+        // The wrappers use the sweep of the HID interface
+        let device = FakeHid::cycling(&[RATING, autonomy, STATUS]);
+        let mut retrying = RetryingInterface::new(device.interface(), RetryPolicy::default());
+
+        assert!(retrying.query_snapshot().unwrap().autonomy.is_some());
+        assert_eq!(device.reads(), 5);
+
+        let device = FakeHid::cycling(&[RATING, autonomy, STATUS]);
+        let mut cached = CachedInterface::new(device.interface(), CacheTtl::default());
+
+        assert!(cached.query_snapshot().unwrap().rating.is_some());
+        assert_eq!(device.reads(), 5);
+
+        // The replies in the snapshot are cached
+        assert_eq!(cached.query_ups_rating().unwrap().output_rating_current, 8);
+        assert_eq!(cached.query_ups_autonomy().unwrap().time, Duration::from_secs(0x0544));
+        assert_eq!(cached.stats(), CacheStats { hits: 2, misses: 0 });
+        assert_eq!(device.reads(), 5);
+    }
+
+    #[test]
+    fn raw_read_test() {
+        let device = FakeHid::new(&[b"\0\0", RATING]);
+
+        assert_eq!(device.interface().raw_read().unwrap(), b"#230.0 008 072.0 50.0");
+    }
+
+    /// Message of `len` bytes, including the prefix and the end byte.
+    fn message_of_len(len: usize) -> Vec<u8> {
+        let mut message = vec![b'('];
+        message.resize(len - 1, b'1');
+        message.push(END_BYTE);
+
+        message
+    }
+
+    #[test]
+    fn report_len_test() {
+        let frames = [message_of_len(47), message_of_len(48), message_of_len(64)];
+        let device = FakeHid::new(&[&frames[0], &frames[1], &frames[2]]);
+        let mut interface = device.interface();
+
+        for frame in &frames {
+            assert_eq!(Some(interface.raw_read().unwrap().as_slice()), frame.strip_suffix(b"\r"));
+        }
+
+        let device = FakeHid::new(&[&frames[0], &frames[1], &frames[2]]);
+        let mut interface = device.interface();
+        interface.set_report_len(48).unwrap();
+
+        assert_eq!(Some(interface.raw_read().unwrap().as_slice()), frames[0].strip_suffix(b"\r"));
+        // The end byte is the last byte of the report
+        assert_eq!(Some(interface.raw_read().unwrap().as_slice()), frames[1].strip_suffix(b"\r"));
+        // Cut off, failing right away instead of waiting for the rest
+        assert!(matches!(interface.raw_read(), Err(crate::Error::FrameTooLong { limit: 48 })));
+        assert_eq!(device.reads(), 3);
+
+        assert!(matches!(interface.set_report_len(1), Err(crate::Error::InvalidParameter(_))));
+    }
+
+    #[test]
+    fn feature_report_len_test() {
+        // Report ID 5 with 8 bytes as input, then 63 bytes as feature
+        let descriptor = [
+            0x06, 0x00, 0xff, 0x09, 0x01, 0xa1, 0x01, 0x85, 0x05, 0x75, 0x08, 0x95, 0x08, 0x81, 0x02,
+            0x95, 0x3f, 0x09, 0x01, 0xb1, 0x02, 0x85, 0x06, 0x95, 0x10, 0xb1, 0x02, 0xc0,
+        ];
+
+        assert_eq!(feature_report_len(&descriptor, 5), Some(64));
+        assert_eq!(feature_report_len(&descriptor, 6), Some(17));
+        assert_eq!(feature_report_len(&descriptor, 7), None);
+        // Cut off in the middle of an item
+        assert_eq!(feature_report_len(&descriptor[..4], 5), None);
+
+        let device = FakeHid::new(&[]);
+        device.0.lock().unwrap().descriptor = Some(descriptor.to_vec());
+        let api = FakeApi::new(vec![(hid_device("/dev/hidraw0", "A1"), device)]);
+
+        let interface = CPlusHidInterface::open_options(&api, HidConnectOptions::path("/dev/hidraw0")).unwrap();
+        assert_eq!(interface.report_len, 64);
+
+        let interface = CPlusHidInterface::open_options(&api, HidConnectOptions::path("/dev/hidraw0").report_len(100)).unwrap();
+        assert_eq!(interface.report_len, 100);
+    }
+
+    #[test]
+    fn interrupt_read_test() {
+        let device = FakeHid::new(&[]);
+        let mut interface = device.interface();
+        interface.read_mode = ReadMode::Interrupt;
+
+        device.stream(&[STATUS.get(20..).unwrap(), RATING, INFORMATION, STATUS]);
+
+        // The end of a status streamed before is skipped
+        assert!(interface.query_ups_info().is_ok());
+        assert!(interface.query_ups_status().is_ok());
+        // Cached while looking for the information
+        assert!(interface.query_ups_rating().is_ok());
+        assert_eq!(device.reads(), 0);
+        assert_eq!(interface.read_mode(), ReadMode::Interrupt);
+    }
+
+    #[test]
+    fn interrupt_binary_read_test() {
+        let device = FakeHid::new(&[]);
+        let mut interface = device.interface();
+        interface.read_mode = ReadMode::Interrupt;
+
+        device.stream(&[b"(\x00\x00\x05\x44\r", b"!\x00\x00\x00\x64\r"]);
+
+        // The NUL bytes of the binary replies are kept
+        assert_eq!(interface.query_ups_autonomy().unwrap().time, Duration::from_secs(0x0544));
+        assert_eq!(interface.query_ups_battery_life().unwrap().time, Duration::from_secs(100 * 60 * 60));
+        assert_eq!(device.reads(), 0);
+    }
+
+    #[test]
+    fn interrupt_fallback_test() {
+        let device = FakeHid::new(&[STATUS, STATUS]);
+        let mut interface = device.interface();
+        interface.read_mode = ReadMode::Interrupt;
+        interface.set_query_timeout(Duration::from_secs(5)).unwrap();
+        interface.set_frame_cache_window(Duration::ZERO);
+
+        device.stream(&[STATUS]);
+
+        assert!(interface.query_ups_status().is_ok());
+        assert_eq!(device.reads(), 0);
+
+        // The endpoint goes silent
+        assert!(interface.query_ups_status().is_ok());
+        assert_eq!(interface.read_mode(), ReadMode::FeatureReport);
+        assert_eq!(device.reads(), 1);
+    }
+
+    #[test]
+    fn deadline_test() {
+        // The carousel keeps cycling without the wanted message
+        let device = FakeHid::cycling(&[STATUS, RATING]);
+        let mut interface = device.interface();
+
+        let result = interface.query_ups_info();
+
+        assert!(matches!(result, Err(crate::Error::Timeout { waiting_for }) if waiting_for == "the information message"));
+        assert!(matches!(interface.set_query_timeout(Duration::ZERO), Err(crate::Error::InvalidParameter(_))));
+
+        interface.set_query_timeout(Duration::from_secs(1)).unwrap();
+
+        assert!(interface.query_ups_rating().is_ok());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_deadline_test() {
+        // The reply never shows up in the carousel
+        let device = FakeHid::cycling(&[STATUS, RATING]);
+        let mut interface = device.async_interface();
+
+        let result = interface.query_ups_info().await;
+
+        assert!(matches!(result, Err(crate::Error::Timeout { waiting_for }) if waiting_for == "the information message"));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_cancel_test() {
+        let device = FakeHid::cycling(&[STATUS, RATING]);
+        let mut interface = device.async_interface();
+
+        let cancelled = tokio::time::timeout(Duration::from_millis(10), interface.query_ups_info()).await;
+
+        assert!(cancelled.is_err());
+
+        // The interface remains usable after dropping the pending query
+        assert!(interface.query_ups_status().await.is_ok());
+    }
+
+    #[test]
+    fn filter_known_devices_test() {
+        let device = |path: &str, vendor_id, product_id| HidUpsInfo {
+            path: path.to_string(),
+            vendor_id,
+            product_id,
+            serial_number: None,
+            product: Some("UPS".to_string()),
+            release_number: 0x0100,
+        };
+        let devices = [
+            device("/dev/hidraw0", 0x046d, 0xc52b),
+            device("/dev/hidraw1", 0x0665, 0x5161),
+            device("/dev/hidraw1", 0x0665, 0x5161),
+            device("/dev/hidraw2", 0x1234, 0x0001),
+        ];
+
+        let found = filter_known_devices(devices.clone(), KNOWN_VID_PIDS);
+
+        assert_eq!(found, [devices[1].clone()]);
+
+        // The list of known devices can be overridden
+        let found = filter_known_devices(devices.clone(), &[(0x1234, 0x0001), (0x046d, 0xc52b)]);
+
+        assert_eq!(found, [devices[0].clone(), devices[3].clone()]);
+        assert!(filter_known_devices(devices, &[]).is_empty());
+    }
+
+    #[test]
+    fn find_serial_number_test() {
+        let device = |path: &str, serial_number: Option<&str>| HidUpsInfo {
+            path: path.to_string(),
+            vendor_id: 0x0665,
+            product_id: 0x5161,
+            serial_number: serial_number.map(str::to_string),
+            product: None,
+            release_number: 0x0100,
+        };
+        let devices = vec![device("/dev/hidraw0", Some("A1")), device("/dev/hidraw1", None), device("/dev/hidraw2", Some("B2"))];
+
+        assert_eq!(find_serial_number(devices.clone(), "B2").unwrap().path, "/dev/hidraw2");
+
+        let result = find_serial_number(devices, "C3");
+
+        assert!(matches!(result, Err(crate::Error::SerialNumberNotFound { serial, found }) if serial == "C3" && found == ["A1", "B2"]));
+    }
+
+    /// HID API handle listing fake devices, recording the exclusive access requests.
+    #[derive(Clone)]
+    struct FakeApi(Vec<(HidUpsInfo, FakeHid)>, Arc<Mutex<Vec<bool>>>);
+
+    impl FakeApi {
+        fn new(devices: Vec<(HidUpsInfo, FakeHid)>) -> Self {
+            Self(devices, Arc::default())
+        }
+    }
+
+    impl HidBackend for FakeApi {
+        fn devices(&self) -> Vec<HidUpsInfo> {
+            self.0.iter().map(|(info, _)| info.clone()).collect()
+        }
+
+        fn open_path(&self, path: &CStr) -> Result<Box<dyn FeatureReportDevice>> {
+            let (_, device) = self.0.iter().find(|(info, _)| info.path.as_bytes() == path.to_bytes())
+                .ok_or_else(|| hid_error("no such device"))?;
+
+            Ok(Box::new(device.clone()))
+        }
+
+        fn open(&self, vid: u16, pid: u16) -> Result<Box<dyn FeatureReportDevice>> {
+            let (_, device) = self.0.iter().find(|(info, _)| (info.vendor_id, info.product_id) == (vid, pid)).unwrap();
+
+            Ok(Box::new(device.clone()))
+        }
+
+        /// Records the requests, refusing exclusive access like hidraw.
+        fn set_open_exclusive(&self, exclusive: bool) -> Result<()> {
+            self.1.lock().unwrap().push(exclusive);
+
+            match exclusive {
+                true => Err(crate::Error::Unsupported { operation: "opening a HID device exclusively" }),
+                false => Ok(()),
+            }
+        }
+    }
+
+    #[test]
+    fn shared_api_test() {
+        let device = |path: &str, serial_number: &str| HidUpsInfo {
+            path: path.to_string(),
+            vendor_id: 0x0665,
+            product_id: 0x5161,
+            serial_number: Some(serial_number.to_string()),
+            product: None,
+            release_number: 0x0100,
+        };
+        let first = FakeHid::new(&[STATUS]);
+        let second = FakeHid::new(&[RATING]);
+        let api = FakeApi::new(vec![
+            (device("/dev/hidraw0", "A1"), first.clone()),
+            (device("/dev/hidraw1", "B2"), second.clone()),
+        ]);
+
+        // Both devices are opened through the same handle
+        let mut first_iface = CPlusHidInterface::open_path(&api, "/dev/hidraw0").unwrap();
+        let mut second_iface = CPlusHidInterface::open_serial(&api, 0x0665, 0x5161, "B2").unwrap();
+
+        assert!(first_iface.query_ups_status().is_ok());
+        assert!(second_iface.query_ups_rating().is_ok());
+        assert!(first.0.lock().unwrap().frames.is_empty());
+        assert!(second.0.lock().unwrap().frames.is_empty());
+    }
+
+    #[test]
+    fn serial_number_test() {
+        assert_eq!(FakeHid::default().interface().serial_number(), Some("FAKE0001"));
+    }
+
+    fn hid_device(path: &str, serial_number: &str) -> HidUpsInfo {
+        HidUpsInfo {
+            path: path.to_string(),
+            vendor_id: 0x0665,
+            product_id: 0x5161,
+            serial_number: Some(serial_number.to_string()),
+            product: None,
+            release_number: 0x0100,
+        }
+    }
+
+    #[test]
+    fn auto_reopen_test() {
+        let old = FakeHid::default();
+        let new = FakeHid::new(&[STATUS]);
+        let opens = Arc::new(Mutex::new(0));
+        let mut interface = old.interface();
+
+        let factory_new = new.clone();
+        let factory_opens = Arc::clone(&opens);
+        interface.api = Box::new(move || {
+            let mut opens = factory_opens.lock().unwrap();
+            *opens += 1;
+
+            // The device isn't back yet on the first attempt
+            if *opens == 1 {
+                return Err(hid_error("hidapi init failed"));
+            }
+
+            Ok(Box::new(FakeApi::new(vec![(hid_device("/dev/hidraw0", "FAKE0001"), factory_new.clone())])))
+        });
+
+        old.0.lock().unwrap().unplugged = true;
+
+        // Off by default
+        assert!(matches!(interface.query_ups_status(), Err(crate::Error::HidApi(_))));
+        assert_eq!(*opens.lock().unwrap(), 0);
+
+        interface.set_auto_reopen(true);
+
+        assert!(matches!(interface.query_ups_status(), Err(crate::Error::HidApi(_))));
+        assert!(matches!(interface.query_ups_status(), Err(crate::Error::HidApi(_))));
+        assert!(interface.query_ups_status().is_ok());
+        assert_eq!(*opens.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn transient_hid_error_test() {
+        let transient = [
+            hidapi::HidError::HidApiError { message: "ioctl (GFEATURE): Broken pipe".to_string() },
+            hidapi::HidError::HidApiErrorEmpty,
+            hidapi::HidError::IoError { error: std::io::ErrorKind::Interrupted.into() },
+        ];
+        let fatal = [
+            hidapi::HidError::HidApiError { message: "ioctl (GFEATURE): No such device".to_string() },
+            hidapi::HidError::HidApiError { message: "device disconnected".to_string() },
+            hidapi::HidError::IoError { error: std::io::ErrorKind::NotFound.into() },
+            hidapi::HidError::InitializationError,
+        ];
+
+        assert!(transient.iter().all(is_transient_hid_error));
+        assert!(!fatal.iter().any(is_transient_hid_error));
+    }
+
+    #[test]
+    fn read_retry_test() {
+        let device = FakeHid::new(&[STATUS]);
+        let mut interface = device.interface();
+        interface.set_read_retries(2, Duration::ZERO);
+
+        device.fail_reads([hid_error("Broken pipe"), hid_error("Broken pipe")]);
+
+        assert!(interface.query_ups_status().is_ok());
+        assert_eq!(device.reads(), 3);
+    }
+
+    #[test]
+    fn read_retries_exhausted_test() {
+        let device = FakeHid::new(&[STATUS]);
+        let mut interface = device.interface();
+        interface.set_read_retries(2, Duration::ZERO);
+
+        device.fail_reads((0..3).map(|_| hid_error("Broken pipe")));
+
+        let result = interface.query_ups_status();
+        assert!(
+            matches!(result, Err(crate::Error::RetriesExhausted { attempts: 3, ref source }) if matches!(**source, crate::Error::HidApi(_))),
+            "{result:?}"
+        );
+        assert_eq!(device.reads(), 3);
+
+        // Without retries the error is returned as is
+        interface.set_read_retries(0, Duration::ZERO);
+        device.fail_reads([hid_error("Broken pipe")]);
+
+        assert!(matches!(interface.query_ups_status(), Err(crate::Error::HidApi(_))));
+        assert!(interface.query_ups_status().is_ok());
+    }
+
+    #[test]
+    fn fatal_read_error_reopens_test() {
+        let old = FakeHid::new(&[]);
+        let new = FakeHid::new(&[STATUS]);
+        let mut interface = old.interface();
+        interface.set_read_retries(5, Duration::ZERO);
+        interface.set_auto_reopen(true);
+        interface.api = Box::new(move || Ok(Box::new(FakeApi::new(vec![(hid_device("/dev/hidraw0", "FAKE0001"), new.clone())]))));
+
+        old.fail_reads([hid_error("No such device"), hid_error("Broken pipe")]);
+
+        // Not retried, but reopened
+        assert!(matches!(interface.query_ups_status(), Err(crate::Error::HidApi(_))));
+        assert_eq!(old.reads(), 1);
+        assert!(interface.query_ups_status().is_ok());
+    }
+
+    #[test]
+    fn open_exclusive_test() {
+        let device = FakeHid::new(&[STATUS]);
+        let api = FakeApi::new(vec![(hid_device("/dev/hidraw0", "A1"), device.clone())]);
+        let requests = Arc::clone(&api.1);
+
+        // Left to the platform by default
+        let info = hid_device("/dev/hidraw0", "A1");
+        assert!(CPlusHidInterface::open_options(&api, info.connect_options()).is_ok());
+        assert!(requests.lock().unwrap().is_empty());
+
+        let result = CPlusHidInterface::open_options(&api, info.connect_options().open_exclusive(true));
+        assert!(matches!(result, Err(crate::Error::Unsupported { .. })));
+
+        let options = HidConnectOptions::vid_pid(0x0665, 0x5161).open_exclusive(false);
+        let mut interface = CPlusHidInterface::open_options(&api, options).unwrap();
+
+        // Requested again when reopening
+        interface.api = Box::new(move || Ok(Box::new(api.clone())));
+        interface.reopen().unwrap();
+
+        assert_eq!(*requests.lock().unwrap(), [true, false, false]);
+        assert!(interface.query_ups_status().is_ok());
+    }
+
+    #[test]
+    fn reopen_by_serial_test() {
+        let old = FakeHid::new(&[STATUS]);
+        let new = FakeHid::new(&[RATING]);
+        let api = FakeApi::new(vec![(hid_device("/dev/hidraw0", "A1"), old.clone())]);
+        let mut interface = CPlusHidInterface::open_serial(&api, 0x0665, 0x5161, "A1").unwrap();
+
+        // The device shows up at another path after being plugged back in
+        let factory_new = new.clone();
+        interface.api = Box::new(move || {
+            Ok(Box::new(FakeApi::new(vec![
+                (hid_device("/dev/hidraw1", "B2"), FakeHid::default()),
+                (hid_device("/dev/hidraw3", "A1"), factory_new.clone()),
+            ])))
+        });
+
+        interface.reopen().unwrap();
+
+        assert!(interface.query_ups_rating().is_ok());
+        assert_eq!(old.0.lock().unwrap().frames.len(), 1);
+    }
+}
+
+/// Tests running the asynchronous serial interface over an in-memory duplex stream.
+#[cfg(all(test, feature = "async"))]
+mod async_tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    fn interface() -> (AsyncCPlusSerialInterface<TokioIo<DuplexStream>>, DuplexStream) {
+        let (port, ups) = tokio::io::duplex(256);
+
+        (AsyncCPlusSerialInterface::with_transport(port, Duration::from_millis(100)), ups)
+    }
+
+    /// Reads everything the interface wrote so far.
+    async fn written(ups: &mut DuplexStream) -> Vec<u8> {
+        let mut buf = [0u8; 64];
+        let read = ups.read(&mut buf).await.unwrap();
+
+        buf.get(..read).unwrap().to_vec()
+    }
+
+    #[tokio::test]
+    async fn query_test() {
+        let (mut iface, mut ups) = interface();
+
+        let query = tokio::spawn(async move {
+            let status = iface.query_ups_status().await;
+            (iface, status)
+        });
+
+        assert_eq!(written(&mut ups).await, b"Q1\r");
+
+        // The reply arrives in several chunks
+        ups.write_all(b"(208.4 140.0 208.4 034 ").await.unwrap();
+        ups.write_all(b"59.9 2.05 35.0 00110001\r").await.unwrap();
+
+        let (_, status) = query.await.unwrap();
+
+        assert!(status.unwrap().ups_status.beeper_on());
+    }
+
+    #[tokio::test]
+    async fn binary_query_test() {
+        let (mut iface, mut ups) = interface();
+
+        ups.write_all(b"(\x00\x00\x05\x44\r").await.unwrap();
+
+        let autonomy = iface.query_ups_autonomy().await.unwrap();
+
+        assert_eq!(autonomy.time, Duration::from_secs(1348));
+        assert_eq!(written(&mut ups).await, b"At\r");
+    }
+
+    #[tokio::test]
+    async fn silent_ups_test() {
+        let (mut iface, _ups) = interface();
+
+        let result = iface.query_ups_status().await;
+
+        assert!(matches!(result, Err(crate::Error::Timeout { .. })));
+    }
+
+    #[tokio::test]
+    async fn incomplete_reply_test() {
+        let (mut iface, mut ups) = interface();
+
+        // The end byte never arrives
+        ups.write_all(b"(208.4 140.0").await.unwrap();
+
+        assert!(matches!(
+            iface.query_ups_status().await,
+            Err(crate::Error::IncompleteResponse { received }) if received == b"(208.4 140.0"
+        ));
+    }
+
+    #[tokio::test]
+    async fn stale_reply_test() {
+        let (mut iface, mut ups) = interface();
+
+        // A late reply to an earlier status query arrives first
+        ups.write_all(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r#230.0 008 072.0 50.0\r").await.unwrap();
+
+        assert_eq!(iface.query_ups_rating().await.unwrap().output_rating_current, 8);
+
+        ups.write_all(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r").await.unwrap();
+
+        assert!(matches!(iface.query_ups_rating().await, Err(crate::Error::UnexpectedResponse { .. })));
+    }
+
+    #[tokio::test]
+    async fn control_command_test() {
+        let (mut iface, mut ups) = interface();
+
+        assert_eq!(iface.start_self_test().await.unwrap(), cplus::CommandAck::NoReply);
+        assert_eq!(written(&mut ups).await, b"T\r");
+
+        ups.write_all(b"(NAK\r").await.unwrap();
+
+        assert!(matches!(iface.cancel_test().await, Err(crate::Error::CommandRejected)));
+    }
+}
+
+/// Tests running the asynchronous serial interface on several executors, over a
+/// `futures-io` stream playing back canned replies.
+#[cfg(all(test, feature = "async-core"))]
+mod executor_tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Stream returning the scripted chunks, then never anything more.
+    #[derive(Debug, Default)]
+    struct ScriptedStream {
+        chunks: VecDeque<Vec<u8>>,
+        written: Vec<u8>,
+    }
+
+    impl ScriptedStream {
+        fn new(chunks: &[&[u8]]) -> Self {
+            Self { chunks: chunks.iter().map(|c| c.to_vec()).collect(), written: vec![] }
+        }
+    }
+
+    impl futures_io::AsyncRead for ScriptedStream {
+        fn poll_read(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<std::io::Result<usize>> {
+            let Some(mut chunk) = self.chunks.pop_front() else {
+                // Silent, the timer wakes the task
+                return Poll::Pending;
+            };
+
+            let len = chunk.len().min(buf.len());
+            let rest = chunk.split_off(len);
+
+            buf.iter_mut().zip(&chunk).for_each(|(b, c)| *b = *c);
+
+            if !rest.is_empty() {
+                self.chunks.push_front(rest);
+            }
+
+            Poll::Ready(Ok(len))
+        }
+    }
+
+    impl futures_io::AsyncWrite for ScriptedStream {
+        fn poll_write(mut self: Pin<&mut Self>, _cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+            self.written.extend_from_slice(buf);
+
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncTransport for ScriptedStream {}
+
+    /// Runs the same queries whatever the executor, with its `timer`.
+    async fn run_queries(timer: impl AsyncTimer + Clone + 'static) {
+        let port = ScriptedStream::new(&[
+            b"(208.4 140.0 208.4 034 ",
+            b"59.9 2.05 35.0 00110001\r(\x00\x00",
+            b"\x05\x44\r#230.0 008",
+        ]);
+        let mut iface = AsyncCPlusSerialInterface::with_timer(port, timer.clone(), Duration::from_millis(50));
+
+        assert!(iface.query_ups_status().await.unwrap().ups_status.beeper_on());
+        assert_eq!(iface.query_ups_autonomy().await.unwrap().time, Duration::from_secs(1348));
+        // The rest of the reply never arrives
+        assert!(matches!(
+            iface.query_ups_rating().await,
+            Err(crate::Error::IncompleteResponse { received }) if received == b"#230.0 008"
+        ));
+        assert!(matches!(iface.query_ups_status().await, Err(crate::Error::Timeout { .. })));
+        assert_eq!(iface.port.written, b"Q1\rAt\rF\rQ1\r");
+
+        // Waiting between the polls
+        let started = Instant::now();
+        let status: &[u8] = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r";
+        let port = ScriptedStream::new(&[status, status]);
+        let mut iface = AsyncCPlusSerialInterface::with_timer(port, timer, Duration::from_millis(50));
+
+        assert!(!iface.verify_shutdown_pending(Duration::from_millis(30), Duration::from_millis(20)).await.unwrap());
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert_eq!(iface.port.written, b"Q1\rQ1\r");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn tokio_executor_test() {
+        run_queries(TokioTimer).await;
+    }
+
+    #[cfg(feature = "smol")]
+    #[test]
+    fn smol_executor_test() {
+        smol::block_on(run_queries(SmolTimer));
+    }
+}
+
+/// Tests running the TCP interface against a local listener playing back canned replies.
+#[cfg(all(test, feature = "tcp"))]
+mod tcp_tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    /// Serves one connection per entry of `connections`, answering each received message
+    /// with the next reply, then closing the connection.
+    fn serve(connections: Vec<Vec<&'static [u8]>>) -> std::net::SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            for replies in connections {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut writer = stream;
+
+                for reply in replies {
+                    let mut msg = vec![];
+                    reader.read_until(END_BYTE, &mut msg).unwrap();
+
+                    writer.write_all(reply).unwrap();
+                    writer.write_all(&[END_BYTE]).unwrap();
+                }
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    fn query_test() {
+        let addr = serve(vec![vec![
+            b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110001",
+            b"(\x00\x00\x05\x44",
+        ]]);
+
+        let mut iface = CPlusTcpInterface::connect(addr).unwrap();
+
+        assert!(iface.query_ups_status().unwrap().ups_status.beeper_on());
+        assert_eq!(iface.query_ups_autonomy().unwrap().time, Duration::from_secs(1348));
+    }
+
+    #[test]
+    fn reconnect_test() {
+        let rating: &[u8] = b"#230.0 008 072.0 50.0";
+        let addr = serve(vec![vec![rating], vec![rating]]);
+
+        let mut iface = CPlusTcpInterface::connect(addr).unwrap();
+
+        iface.query_ups_rating().unwrap();
+
+        // The first connection was closed after its only reply
+        assert!(matches!(iface.query_ups_rating(), Err(crate::Error::Io(_))));
+
+        iface.reconnect().unwrap();
+
+        assert_eq!(iface.query_ups_rating().unwrap().output_rating_current, 8);
+    }
+
+    #[test]
+    fn read_timeout_test() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+
+        // The connection is accepted by the OS, but nothing is ever sent
+        let mut iface = CPlusTcpInterface::connect_with_timeouts(
+            listener.local_addr().unwrap(),
+            Duration::from_secs(1),
+            Duration::from_millis(50),
+        ).unwrap();
+
+        assert!(matches!(iface.query_ups_status(), Err(crate::Error::Timeout { .. })));
+
+        // Silence after a control command is expected
+        assert_eq!(iface.start_self_test().unwrap(), cplus::CommandAck::NoReply);
+    }
+}
+
+/// Tests running the RFC 2217 transport against a scripted socket.
+#[cfg(all(test, feature = "rfc2217"))]
+mod rfc2217_tests {
+    use super::*;
+    use super::telnet::*;
+    use std::io::Read;
+
+    /// Socket returning the scripted server bytes a few at a time,
+    /// so telnet sequences get split across reads.
+    #[derive(Debug, Default)]
+    struct ScriptedSocket {
+        input: std::collections::VecDeque<u8>,
+        written: Vec<u8>,
+        /// Whether the connection is closed once the input runs out, instead of timing out.
+        closing: bool,
+    }
+
+    impl ScriptedSocket {
+        fn new(input: &[u8]) -> Self {
+            Self { input: input.iter().copied().collect(), ..Default::default() }
+        }
+
+        fn closing(input: &[u8]) -> Self {
+            Self { closing: true, ..Self::new(input) }
+        }
+    }
+
+    impl Read for ScriptedSocket {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.input.is_empty() {
+                return if self.closing { Ok(0) } else { Err(std::io::ErrorKind::TimedOut.into()) };
+            }
+
+            let read = buf.len().min(self.input.len()).min(3);
+
+            for (b, i) in buf.iter_mut().zip(self.input.drain(..read)) {
+                *b = i;
+            }
+
+            Ok(read)
+        }
+    }
+
+    impl Write for ScriptedSocket {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.written.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for ScriptedSocket {}
+
+    /// Server confirming the COM port options, asking for an unsupported option on the way.
+    const SERVER_NEGOTIATION: &[u8] = &[
+        IAC, DO, COM_PORT_OPTION,
+        IAC, WILL, SUPPRESS_GO_AHEAD,
+        IAC, DO, 24,
+        IAC, SB, COM_PORT_OPTION, SET_BAUDRATE + SERVER_OFFSET, 0x00, 0x00, 0x09, 0x60, IAC, SE,
+    ];
+
+    #[test]
+    fn negotiation_test() {
+        let transport = Rfc2217Transport::negotiate(ScriptedSocket::new(SERVER_NEGOTIATION)).unwrap();
+
+        let written = &transport.stream.written;
+
+        assert!(written.starts_with(&[IAC, WILL, COM_PORT_OPTION]));
+        assert!(written.windows(8).any(|w| w == [IAC, SB, COM_PORT_OPTION, SET_BAUDRATE, 0x00, 0x00, 0x09, 0x60]));
+        assert!(written.windows(5).any(|w| w == [IAC, SB, COM_PORT_OPTION, SET_DATASIZE, 8]));
+        // The unrequested terminal type option is refused
+        assert!(written.ends_with(&[IAC, WONT, 24]));
+        assert_eq!(Transport::baud_rate(&transport), Some(2_400));
+
+        // The timeout floor is computed for the confirmed baud rate
+        let iface = CPlusGenericInterface::new(transport);
+
+        assert_eq!(iface.min_query_timeout(), Some(min_read_timeout(2_400, 65)));
+    }
+
+    #[test]
+    fn negotiation_refused_test() {
+        let refused = Rfc2217Transport::negotiate(ScriptedSocket::new(&[IAC, DONT, COM_PORT_OPTION]));
+
+        assert!(matches!(refused, Err(crate::Error::UnexpectedResponse { .. })));
+
+        let wrong_baud_rate = Rfc2217Transport::negotiate(ScriptedSocket::new(&[
+            IAC, SB, COM_PORT_OPTION, SET_BAUDRATE + SERVER_OFFSET, 0x00, 0x00, 0x25, 0x80, IAC, SE,
+        ]));
+
+        assert!(matches!(wrong_baud_rate, Err(crate::Error::UnexpectedResponse { .. })));
+
+        // The server never answers
+        assert!(Rfc2217Transport::negotiate(ScriptedSocket::new(&[])).is_err());
+    }
+
+    #[test]
+    fn negotiation_closed_test() {
+        let closed = Rfc2217Transport::negotiate(ScriptedSocket::closing(&[IAC, DO, COM_PORT_OPTION]));
+
+        assert!(matches!(closed, Err(crate::Error::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof));
+    }
+
+    /// Server sending telnet NOPs forever, without confirming the baud rate.
+    #[derive(Debug)]
+    struct ChattySocket {
+        timeout: Duration,
+    }
+
+    impl Read for ChattySocket {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            std::thread::sleep(Duration::from_millis(5));
+
+            for (b, nop) in buf.iter_mut().zip([IAC, 241]) {
+                *b = nop;
+            }
+
+            Ok(buf.len().min(2))
+        }
+    }
+
+    impl Write for ChattySocket {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for ChattySocket {
+        fn timeout(&self) -> Option<Duration> {
+            Some(self.timeout)
+        }
+
+        fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+            self.timeout = timeout;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn negotiation_deadline_test() {
+        let started = Instant::now();
+        let result = Rfc2217Transport::negotiate(ChattySocket { timeout: Duration::from_millis(50) });
+
+        assert!(matches!(result, Err(crate::Error::Timeout { .. })));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn escaping_test() {
+        let mut transport = Rfc2217Transport::negotiate(ScriptedSocket::new(SERVER_NEGOTIATION)).unwrap();
+        // Autonomy containing an IAC byte, followed by a NOP command
+        transport.stream.input.extend([b'(', 0x00, IAC, IAC, 0x05, 0x44, IAC, 241, END_BYTE]);
+
+        let mut iface = CPlusGenericInterface::new(transport);
+
+        let autonomy = iface.query_ups_autonomy().unwrap();
+
+        assert_eq!(autonomy.time, Duration::from_secs(0x00FF_0544));
+
+        let mut transport = iface.into_inner();
+        transport.stream.written.clear();
+
+        transport.write_all(&[b'a', IAC, b'b']).unwrap();
+
+        assert_eq!(transport.stream.written, [b'a', IAC, IAC, b'b']);
+    }
+}
@@ -0,0 +1,495 @@
+use crate::Result;
+use crate::model::cplus;
+
+/// Most garbage runs and malformed binary replies skipped while looking for one reply,
+/// before giving up with [`crate::Error::InvalidFormat`].
+pub(crate) const MAX_RESYNCS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What the reply to a query looks like, for finding it in the input.
+pub(crate) struct ReplyFormat {
+    pub prefix: u8,
+    /// Length of the binary payload following the start byte, see [`cplus::binary_reply_len`].
+    /// A payload of ASCII digits is read until the end byte instead, whatever its length.
+    pub binary_len: Option<usize>,
+}
+
+impl ReplyFormat {
+    /// Returns the format of the reply to `query`, `None` if it isn't a known query.
+    pub(crate) fn of(query: &[u8]) -> Option<Self> {
+        Some(Self { prefix: cplus::reply_prefix(query)?, binary_len: cplus::binary_reply_len(query) })
+    }
+}
+
+/// Returns whether `byte` starts a reply to one of the known queries.
+fn is_start_byte(byte: u8) -> bool {
+    cplus::REPLY_PREFIXES.iter().any(|&(_, prefix)| prefix == byte)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Reading the bus address starting a frame, see [`FrameScanner::addressed`].
+    Address,
+    /// Skipping the bytes before the start of a frame, `skipped` of them so far.
+    Hunting { skipped: usize },
+    /// Reading the frame until the end byte.
+    Text,
+    /// Reading the binary payload, `remaining` bytes of it left, followed by the end byte.
+    Binary { remaining: usize },
+}
+
+#[derive(Debug)]
+/// Finds the frames in the bytes received from the UPS, fed in chunks of any length.
+///
+/// With a known reply format, the bytes before a start byte (e.g. the tail of a frame
+/// sent before the port was opened) are skipped, and a binary reply is read by its
+/// length, as its payload can contain the end byte. Without one, every byte except
+/// a stray line feed starts a frame.
+pub(crate) struct FrameScanner {
+    reply: Option<ReplyFormat>,
+    end_byte: u8,
+    max_frame_len: usize,
+    /// Whether each frame starts with a bus address, which is kept as the first byte of the
+    /// frame. It can have any value, so only the bytes between it and the start byte are skipped.
+    addressed: bool,
+    state: State,
+    /// Bytes of the frame read so far (without the address), excluding the skipped ones.
+    len: usize,
+    resyncs: usize,
+}
+
+impl FrameScanner {
+    pub(crate) fn new(reply: Option<ReplyFormat>, end_byte: u8, max_frame_len: usize) -> Self {
+        Self {
+            reply,
+            end_byte,
+            max_frame_len,
+            addressed: false,
+            state: State::Hunting { skipped: 0 },
+            len: 0,
+            resyncs: 0,
+        }
+    }
+
+    /// Reads a bus address at the start of each frame.
+    pub(crate) fn addressed(mut self) -> Self {
+        self.addressed = true;
+        self.state = self.frame_start();
+        self
+    }
+
+    /// The state at the start of a frame.
+    fn frame_start(&self) -> State {
+        if self.addressed { State::Address } else { State::Hunting { skipped: 0 } }
+    }
+
+    /// Bytes held since the end of the last frame, the skipped ones or the frame read so far.
+    pub(crate) fn pending(&self) -> usize {
+        match self.state {
+            State::Address => 0,
+            State::Hunting { skipped } => skipped,
+            State::Text | State::Binary { .. } => self.len,
+        }
+    }
+
+    /// Returns whether any bytes were skipped looking for the frame.
+    pub(crate) fn skipped_any(&self) -> bool {
+        self.resyncs > 0 || matches!(self.state, State::Hunting { skipped } if skipped > 0)
+    }
+
+    /// Appends the frame bytes of `bytes` to `frame`, without the end byte. Returns how many
+    /// bytes were used once the frame is complete, `None` if all of them were used before that.
+    ///
+    /// Fails with [`crate::Error::FrameTooLong`] if the frame (or the garbage before it) gets
+    /// longer than the limit, or with [`crate::Error::InvalidFormat`] after more than
+    /// [`MAX_RESYNCS`] garbage runs and malformed binary replies.
+    pub(crate) fn push(&mut self, bytes: &[u8], frame: &mut Vec<u8>) -> Result<Option<usize>> {
+        let mut i = 0;
+
+        while let Some(&byte) = bytes.get(i) {
+            match self.state {
+                // An empty line, or the line feed following the end byte
+                State::Address if byte == self.end_byte || (byte == b'\n' && self.end_byte != b'\n') => {}
+                State::Address => {
+                    frame.push(byte);
+                    self.state = State::Hunting { skipped: 0 };
+                }
+                State::Hunting { skipped } => match self.reply {
+                    _ if byte == b'\n' && skipped == 0 && self.end_byte != b'\n' => {}
+                    None => {
+                        self.state = State::Text;
+                        // Reprocessing the byte, an end byte makes an empty frame
+                        continue;
+                    }
+                    Some(reply) if is_start_byte(byte) => {
+                        if skipped > 0 {
+                            self.resync(skipped)?;
+                        }
+
+                        frame.push(byte);
+                        self.len = 1;
+                        self.state = match reply.binary_len {
+                            Some(remaining) if byte == reply.prefix => State::Binary { remaining },
+                            _ => State::Text,
+                        };
+                    }
+                    Some(_) if byte == self.end_byte => {
+                        self.resync(skipped + 1)?;
+                        // The address belonged to the garbage
+                        frame.clear();
+                        self.state = self.frame_start();
+                    }
+                    Some(_) => {
+                        if skipped >= self.max_frame_len {
+                            return Err(crate::Error::FrameTooLong { limit: self.max_frame_len });
+                        }
+
+                        self.state = State::Hunting { skipped: skipped + 1 };
+                    }
+                },
+                State::Text | State::Binary { .. } if byte == self.end_byte && self.ends_frame(frame) => {
+                    self.state = self.frame_start();
+                    self.len = 0;
+
+                    return Ok(Some(i + 1));
+                }
+                State::Binary { remaining: 0 } if byte.is_ascii_digit() && self.is_ascii_number(frame) => {
+                    // A number longer than the binary word, sent in ASCII by some firmwares
+                    self.state = State::Text;
+
+                    continue;
+                }
+                State::Binary { remaining: 0 } => {
+                    // The payload isn't followed by the end byte, it wasn't the reply
+                    self.resync(self.len)?;
+                    frame.truncate(frame.len().saturating_sub(self.len));
+                    self.len = 0;
+                    self.state = State::Hunting { skipped: 0 };
+
+                    continue;
+                }
+                State::Text | State::Binary { .. } => {
+                    if self.len >= self.max_frame_len {
+                        return Err(crate::Error::FrameTooLong { limit: self.max_frame_len });
+                    }
+
+                    frame.push(byte);
+                    self.len += 1;
+
+                    if let State::Binary { remaining } = &mut self.state {
+                        *remaining -= 1;
+                    }
+                }
+            }
+
+            i += 1;
+        }
+
+        Ok(None)
+    }
+
+    /// Returns whether an end byte ends the frame read so far, rather than being a part of it.
+    fn ends_frame(&self, frame: &[u8]) -> bool {
+        match self.state {
+            State::Binary { remaining } if remaining > 0 => self.is_ascii_number(frame),
+            _ => true,
+        }
+    }
+
+    /// Returns whether the binary payload read so far is a number in ASCII instead, see
+    /// [`cplus::NumberEncoding::detect`].
+    fn is_ascii_number(&self, frame: &[u8]) -> bool {
+        let payload = frame.get(frame.len().saturating_sub(self.len) + 1..).unwrap_or_default();
+
+        cplus::NumberEncoding::detect(payload) == cplus::NumberEncoding::Ascii
+    }
+
+    /// Counts skipping `skipped` bytes to find the frame.
+    fn resync(&mut self, skipped: usize) -> Result<()> {
+        debug!("Skipped {skipped} bytes looking for the start of a frame");
+
+        self.resyncs += 1;
+
+        if self.resyncs > MAX_RESYNCS {
+            return Err(crate::Error::invalid_format("frame", &[], format!("no frame after {MAX_RESYNCS} runs of garbage")));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "usb-hidapi")]
+#[derive(Debug)]
+/// Assembles the messages streamed on the interrupt endpoint of a HID UPS from the report
+/// payloads, fed as they are read. A message can be split over several reports, which are
+/// padded with NUL bytes after its end.
+///
+/// The NUL bytes are only dropped between the messages, as the binary replies (e.g. to `At`)
+/// contain them.
+pub(crate) struct MessageAssembler {
+    end_byte: u8,
+    max_len: usize,
+    /// Bytes of the message read so far.
+    partial: Vec<u8>,
+    /// Whether the bytes up to the next end byte are dropped, the message being too long.
+    discarding: bool,
+    /// Complete messages not taken yet, without the end byte.
+    messages: std::collections::VecDeque<Vec<u8>>,
+}
+
+#[cfg(feature = "usb-hidapi")]
+impl MessageAssembler {
+    /// Assembles messages of at most `max_len` bytes, including the end byte.
+    pub(crate) fn new(end_byte: u8, max_len: usize) -> Self {
+        Self {
+            end_byte,
+            max_len,
+            partial: Vec::new(),
+            discarding: false,
+            messages: std::collections::VecDeque::new(),
+        }
+    }
+
+    /// Adds the payload of a report, skipping the padding before the start of a message.
+    ///
+    /// Fails with [`crate::Error::FrameTooLong`] if a message gets longer than the limit.
+    /// The message is dropped, the ones after it are still assembled.
+    pub(crate) fn push(&mut self, payload: &[u8]) -> Result<()> {
+        let mut too_long = false;
+
+        for &byte in payload {
+            if byte == b'\0' && self.partial.is_empty() && !self.discarding {
+                continue;
+            }
+
+            if byte == self.end_byte {
+                if !std::mem::take(&mut self.discarding) {
+                    self.messages.push_back(std::mem::take(&mut self.partial));
+                }
+            } else if !self.discarding {
+                if self.partial.len() + 1 >= self.max_len {
+                    self.partial.clear();
+                    self.discarding = true;
+                    too_long = true;
+                } else {
+                    self.partial.push(byte);
+                }
+            }
+        }
+
+        if too_long {
+            return Err(crate::Error::FrameTooLong { limit: self.max_len });
+        }
+
+        Ok(())
+    }
+
+    /// Takes the oldest complete message, without the end byte.
+    pub(crate) fn pop(&mut self) -> Option<Vec<u8>> {
+        self.messages.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &[u8] = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000";
+
+    /// Feeds `input` in chunks of `chunk_len` bytes, returning the frames found and how the
+    /// last one ended (`Ok(None)` if the input ran out in the middle of it).
+    fn scan(reply: Option<ReplyFormat>, input: &[u8], chunk_len: usize) -> (Vec<Vec<u8>>, Result<Option<usize>>) {
+        scan_with(FrameScanner::new(reply, b'\r', 64), input, chunk_len)
+    }
+
+    fn scan_with(mut scanner: FrameScanner, input: &[u8], chunk_len: usize) -> (Vec<Vec<u8>>, Result<Option<usize>>) {
+        let mut frames = vec![];
+        let mut frame = vec![];
+
+        for chunk in input.chunks(chunk_len) {
+            let mut chunk = chunk;
+
+            loop {
+                match scanner.push(chunk, &mut frame) {
+                    Ok(Some(used)) => {
+                        frames.push(std::mem::take(&mut frame));
+                        chunk = chunk.get(used..).unwrap_or_default();
+                    }
+                    Ok(None) => break,
+                    error => return (frames, error),
+                }
+            }
+        }
+
+        (frames, Ok(None))
+    }
+
+    fn format(query: &[u8]) -> Option<ReplyFormat> {
+        ReplyFormat::of(query)
+    }
+
+    #[test]
+    fn reply_format_test() {
+        assert_eq!(format(b"Q1"), Some(ReplyFormat { prefix: b'(', binary_len: None }));
+        assert_eq!(format(b"At"), Some(ReplyFormat { prefix: b'(', binary_len: Some(4) }));
+        assert_eq!(format(b"BL"), Some(ReplyFormat { prefix: b'!', binary_len: Some(4) }));
+        assert_eq!(format(b"CT"), None);
+    }
+
+    #[test]
+    fn leading_garbage_test() {
+        // The tail of a frame, then garbage right before the next one
+        let input = [&b"35.0 00110000\r\n\x00\xff"[..], STATUS, b"\r#230.0\r"].concat();
+
+        for chunk_len in 1..=input.len() {
+            let (frames, end) = scan(format(b"Q1"), &input, chunk_len);
+
+            assert_eq!(frames, [STATUS, b"#230.0"], "chunks of {chunk_len}");
+            assert!(matches!(end, Ok(None)), "chunks of {chunk_len}");
+        }
+
+        // Without a reply format, everything is a frame
+        let (frames, _) = scan(None, b"35.0\r\n\r(208.4\r", 3);
+
+        assert_eq!(frames, [&b"35.0"[..], b"", b"(208.4"]);
+    }
+
+    #[test]
+    fn binary_reply_test() {
+        // The payload contains the end byte and a start byte
+        let input = b"\x00\r(\x00\x0d\x28\x10\r!\x00\x00\x00\x05\r";
+
+        for chunk_len in 1..=input.len() {
+            let (frames, _) = scan(format(b"At"), input, chunk_len);
+
+            assert_eq!(frames, [&b"(\x00\x0d\x28\x10"[..], b"!\x00\x00\x00\x05"], "chunks of {chunk_len}");
+        }
+
+        // A stale status reply isn't taken for the autonomy reply
+        let input = [STATUS, b"\r(\x00\x00\x0e\x10\r"].concat();
+
+        for chunk_len in 1..=input.len() {
+            let (frames, end) = scan(format(b"At"), &input, chunk_len);
+
+            assert_eq!(frames, [b"(\x00\x00\x0e\x10"], "chunks of {chunk_len}");
+            assert!(matches!(end, Ok(None)), "chunks of {chunk_len}");
+        }
+    }
+
+    #[test]
+    fn ascii_number_test() {
+        for input in [&b"(1348\r"[..], b"(7\r", b"(1234567\r"] {
+            for chunk_len in 1..=input.len() {
+                let (frames, end) = scan(format(b"At"), input, chunk_len);
+
+                assert_eq!(frames, [input.strip_suffix(b"\r").unwrap()], "chunks of {chunk_len}");
+                assert!(matches!(end, Ok(None)), "chunks of {chunk_len}");
+            }
+        }
+
+        // Binary payloads with digits ending in the end byte
+        let (frames, _) = scan(format(b"At"), b"(\x00\x00\x05\r\r", 3);
+
+        assert_eq!(frames, [b"(\x00\x00\x05\r"]);
+    }
+
+    #[test]
+    fn addressed_test() {
+        // Garbage between the address and the start byte, and a line of garbage
+        let input = [&b"\x01xx"[..], STATUS, b"\r\n\x02tail\r\x02#230.0\r"].concat();
+
+        for chunk_len in 1..=input.len() {
+            let scanner = FrameScanner::new(format(b"Q1"), b'\r', 64).addressed();
+            let (frames, end) = scan_with(scanner, &input, chunk_len);
+
+            assert_eq!(frames, [[&b"\x01"[..], STATUS].concat(), b"\x02#230.0".to_vec()], "chunks of {chunk_len}");
+            assert!(matches!(end, Ok(None)), "chunks of {chunk_len}");
+        }
+
+        // The address is kept when a malformed binary reply is skipped
+        let input = b"\x07(1\x00\x00\x00\x00(\x00\x0d\x05\x44\r";
+
+        for chunk_len in 1..=input.len() {
+            let scanner = FrameScanner::new(format(b"At"), b'\r', 64).addressed();
+            let (frames, _) = scan_with(scanner, input, chunk_len);
+
+            assert_eq!(frames, [b"\x07(\x00\x0d\x05\x44"], "chunks of {chunk_len}");
+        }
+
+        // Without a reply format, the frame is kept whole
+        let (frames, _) = scan_with(FrameScanner::new(None, b'\r', 64).addressed(), b"\r\x01\r\x01ACK\r", 1);
+
+        assert_eq!(frames, [&b"\x01"[..], b"\x01ACK"]);
+    }
+
+    #[test]
+    fn resync_limit_test() {
+        let garbage = b"xx\rxx\rxx\rxx\r";
+
+        let (frames, end) = scan(format(b"Q1"), &[&garbage[..], STATUS, b"\r"].concat(), 7);
+
+        assert_eq!(frames, [STATUS]);
+        assert!(matches!(end, Ok(None)));
+
+        let (frames, end) = scan(format(b"Q1"), &[&garbage[..], b"x", STATUS, b"\r"].concat(), 7);
+
+        assert!(frames.is_empty());
+        assert!(matches!(end, Err(crate::Error::InvalidFormat { parser: "frame", .. })));
+    }
+
+    #[test]
+    fn frame_too_long_test() {
+        let noise = [b'0'; 65];
+
+        let (_, end) = scan(format(b"Q1"), &noise[..64], 16);
+
+        assert!(matches!(end, Ok(None)));
+
+        for reply in [None, format(b"Q1")] {
+            let (_, end) = scan(reply, &noise, 16);
+
+            assert!(matches!(end, Err(crate::Error::FrameTooLong { limit: 64 })), "{reply:?}");
+        }
+    }
+
+    #[cfg(feature = "usb-hidapi")]
+    #[test]
+    fn message_assembler_test() {
+        let carousel = [STATUS, b"\r(\x00\x00\x05\x44\r#230.0 008 072.0 50.0\r"].concat();
+
+        for report_len in [1, 3, 8, 64] {
+            let mut assembler = MessageAssembler::new(b'\r', 64);
+            let mut messages = vec![];
+
+            for chunk in carousel.chunks(report_len) {
+                // The reports are padded to their full length
+                let mut report = chunk.to_vec();
+                report.resize(report_len, b'\0');
+
+                assembler.push(&report).unwrap();
+                messages.extend(std::iter::from_fn(|| assembler.pop()));
+            }
+
+            assert_eq!(
+                messages,
+                [STATUS.to_vec(), b"(\x00\x00\x05\x44".to_vec(), b"#230.0 008 072.0 50.0".to_vec()],
+                "{report_len}"
+            );
+        }
+    }
+
+    #[cfg(feature = "usb-hidapi")]
+    #[test]
+    fn message_assembler_too_long_test() {
+        let mut assembler = MessageAssembler::new(b'\r', 8);
+
+        assert!(assembler.push(b"(123456").is_ok());
+        assert!(matches!(assembler.push(b"78\r(12\r"), Err(crate::Error::FrameTooLong { limit: 8 })));
+        assert_eq!(assembler.pop(), Some(b"(12".to_vec()));
+        assert_eq!(assembler.pop(), None);
+
+        // A message of the full length fits
+        assert!(assembler.push(b"(123456\r").is_ok());
+        assert_eq!(assembler.pop(), Some(b"(123456".to_vec()));
+    }
+}
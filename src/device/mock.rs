@@ -0,0 +1,776 @@
+use crate::Result;
+use crate::device::cplus::{CPlusGenericInterface, SplitMix64, Transport};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::time::Duration;
+
+/// End byte of CPlus messages.
+const END_BYTE: u8 = b'\r';
+
+/// Interface running the regular framing and parsing code over a [`MockTransport`].
+pub type MockCPlusInterface = CPlusGenericInterface<MockTransport>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What the mock UPS does after receiving a command.
+pub enum MockResponse {
+    /// Replies with the given bytes, the end byte is appended.
+    Reply(Vec<u8>),
+    /// Sends the given bytes as they are, without appending the end byte.
+    Raw(Vec<u8>),
+    /// Doesn't reply at all, as the UPS does for most control commands.
+    Silence,
+    /// Fails the read of the reply with the given error.
+    Error(std::io::ErrorKind),
+    /// Responds after the given delay. If the delay exceeds the read timeout,
+    /// the read times out instead.
+    Delayed(Duration, Box<MockResponse>),
+}
+
+impl MockResponse {
+    /// Replies with the given bytes.
+    pub fn reply(bytes: impl AsRef<[u8]>) -> Self {
+        Self::Reply(bytes.as_ref().to_vec())
+    }
+
+    /// Responds with `self` after `delay`.
+    pub fn after(self, delay: Duration) -> Self {
+        Self::Delayed(delay, Box::new(self))
+    }
+
+    /// Responds with `self` altered by `fault`.
+    pub fn with_fault(self, fault: MockFault) -> Self {
+        match (fault, self) {
+            (MockFault::Delay(delay), response) => response.after(delay),
+            (MockFault::Error(kind), _) => Self::Error(kind),
+            (MockFault::Silence, _) => Self::Silence,
+            (fault, Self::Delayed(delay, response)) => Self::Delayed(delay, Box::new(response.with_fault(fault))),
+            (MockFault::Corrupt { count, seed }, Self::Reply(reply)) => Self::Reply(corrupt(reply, count, seed)),
+            (MockFault::Truncate { len }, Self::Reply(mut reply)) => {
+                reply.truncate(len);
+                Self::Raw(reply)
+            }
+            (MockFault::Swap(frame), Self::Reply(_)) => Self::Reply(frame),
+            // Nothing to corrupt, truncate or swap
+            (_, response) => response,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A fault injected into a response, see [`MockTransport::fault`] and [`MockResponse::with_fault`].
+pub enum MockFault {
+    /// Replaces `count` bytes of the reply (other than the end byte) with random ones,
+    /// the same ones for the same `seed`.
+    Corrupt { count: usize, seed: u64 },
+    /// Cuts the reply off after `len` bytes, without the end byte.
+    Truncate { len: usize },
+    /// Delays the response, making the read time out if it exceeds the read timeout.
+    Delay(Duration),
+    /// Fails the read of the response with the given error.
+    Error(std::io::ErrorKind),
+    /// Replies with the given frame instead, e.g. the reply to another command.
+    Swap(Vec<u8>),
+    /// Doesn't reply at all.
+    Silence,
+}
+
+/// Replaces `count` distinct bytes of `reply` with random ones, neither the end byte nor the original.
+fn corrupt(mut reply: Vec<u8>, count: usize, seed: u64) -> Vec<u8> {
+    let mut rng = SplitMix64(seed);
+    let mut positions: Vec<usize> = (0..reply.len()).collect();
+
+    for i in 0..count.min(positions.len()) {
+        let chosen = i + rng.below(positions.len() - i);
+        positions.swap(i, chosen);
+
+        let Some(byte) = positions.get(i).and_then(|&position| reply.get_mut(position)) else {
+            continue;
+        };
+
+        let original = *byte;
+
+        while *byte == original || *byte == END_BYTE {
+            *byte = rng.next() as u8;
+        }
+    }
+
+    reply
+}
+
+impl SplitMix64 {
+    /// A number below `bound`, which must not be zero.
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// How the mock handles a command which isn't the next expected one.
+pub enum UnexpectedCommand {
+    /// Fails the write of the command with [`std::io::ErrorKind::InvalidInput`].
+    Error,
+    /// Responds with the given response.
+    Respond(MockResponse),
+}
+
+#[derive(Debug)]
+/// Transport answering the expected commands, in order, with scripted responses.
+///
+/// ```
+/// # use alphamon_rs::device::cplus::CPlusInterface;
+/// # use alphamon_rs::device::mock::{MockCPlusInterface, MockResponse, MockTransport};
+/// let mut iface = MockCPlusInterface::new(
+///     MockTransport::new()
+///         .expect(b"Q1", MockResponse::reply(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000"))
+///         .expect(b"T", MockResponse::Silence),
+/// );
+///
+/// assert!(!iface.query_ups_status()?.ups_status.utility_fail());
+/// iface.start_self_test()?;
+///
+/// assert_eq!(iface.get_ref().issued(), [&b"Q1"[..], b"T"]);
+/// # Ok::<(), alphamon_rs::Error>(())
+/// ```
+pub struct MockTransport {
+    /// Expected commands (without the end byte) and their responses.
+    expectations: VecDeque<(Vec<u8>, MockResponse)>,
+    unexpected: UnexpectedCommand,
+    /// Commands received so far, without the end byte.
+    issued: Vec<Vec<u8>>,
+    /// The command currently being written.
+    pending: Vec<u8>,
+    /// Response to the last command, returned by the next read.
+    response: Option<MockResponse>,
+    /// Reply bytes waiting to be read.
+    input: VecDeque<u8>,
+    timeout: Duration,
+    /// Faults injected into the nth response to a command.
+    faults: Vec<(Vec<u8>, usize, MockFault)>,
+    /// How many times each command was received.
+    received: std::collections::HashMap<Vec<u8>, usize>,
+}
+
+impl Default for MockTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockTransport {
+    /// Creates a mock without expectations, failing on unexpected commands,
+    /// with a read timeout of 1 s.
+    pub fn new() -> Self {
+        Self {
+            expectations: VecDeque::new(),
+            unexpected: UnexpectedCommand::Error,
+            issued: vec![],
+            pending: vec![],
+            response: None,
+            input: VecDeque::new(),
+            timeout: Duration::from_secs(1),
+            faults: vec![],
+            received: std::collections::HashMap::new(),
+        }
+    }
+
+    /// Expects `command` (without the end byte) to be issued next, answering it with `response`.
+    pub fn expect(mut self, command: impl AsRef<[u8]>, response: MockResponse) -> Self {
+        self.expectations.push_back((command.as_ref().to_vec(), response));
+        self
+    }
+
+    /// Injects `fault` into the response to the `nth` (counted from 1) time `command` is received,
+    /// whether the command was expected or not.
+    ///
+    /// ```
+    /// # use alphamon_rs::device::cplus::CPlusInterface;
+    /// # use alphamon_rs::device::mock::{MockCPlusInterface, MockFault, MockResponse, MockTransport, UnexpectedCommand};
+    /// const STATUS: &[u8] = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000";
+    ///
+    /// // The third Q1 response is truncated
+    /// let mut iface = MockCPlusInterface::new(
+    ///     MockTransport::new()
+    ///         .on_unexpected(UnexpectedCommand::Respond(MockResponse::reply(STATUS)))
+    ///         .fault(b"Q1", 3, MockFault::Truncate { len: 10 }),
+    /// );
+    ///
+    /// assert!(iface.query_ups_status().is_ok());
+    /// assert!(iface.query_ups_status().is_ok());
+    /// assert!(matches!(iface.query_ups_status(), Err(alphamon_rs::Error::IncompleteResponse { .. })));
+    /// assert!(iface.query_ups_status().is_ok());
+    /// ```
+    pub fn fault(mut self, command: impl AsRef<[u8]>, nth: usize, fault: MockFault) -> Self {
+        self.faults.push((command.as_ref().to_vec(), nth, fault));
+        self
+    }
+
+    /// Sets how commands which weren't expected next are handled.
+    pub fn on_unexpected(mut self, unexpected: UnexpectedCommand) -> Self {
+        self.unexpected = unexpected;
+        self
+    }
+
+    /// Returns the commands issued so far, without the end byte.
+    pub fn issued(&self) -> &[Vec<u8>] {
+        &self.issued
+    }
+
+    /// Returns whether all expected commands were issued.
+    pub fn is_done(&self) -> bool {
+        self.expectations.is_empty()
+    }
+
+    fn command_received(&mut self) -> std::io::Result<()> {
+        let command = std::mem::take(&mut self.pending);
+
+        trace!("Mock received {:?}", String::from_utf8_lossy(&command));
+
+        let expected = self.expectations.front().is_some_and(|(expected, _)| *expected == command);
+
+        let received = self.received.entry(command.clone()).or_default();
+        *received += 1;
+        let nth = *received;
+
+        let mut response = if expected {
+            self.expectations.pop_front().map(|(_, response)| response)
+        } else {
+            match &self.unexpected {
+                UnexpectedCommand::Error => {
+                    self.issued.push(command);
+                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "unexpected command"));
+                }
+                UnexpectedCommand::Respond(response) => Some(response.clone()),
+            }
+        };
+
+        for (_, _, fault) in self.faults.iter().filter(|(faulty, n, _)| *faulty == command && *n == nth) {
+            response = response.map(|response| response.with_fault(fault.clone()));
+        }
+
+        self.issued.push(command);
+        self.response = response;
+
+        Ok(())
+    }
+
+    /// Applies the response to the last command, filling the input.
+    fn respond(&mut self, response: MockResponse) -> std::io::Result<()> {
+        match response {
+            MockResponse::Reply(reply) => {
+                self.input.extend(reply);
+                self.input.push_back(END_BYTE);
+            }
+            MockResponse::Raw(bytes) => self.input.extend(bytes),
+            MockResponse::Silence => {}
+            MockResponse::Error(kind) => return Err(kind.into()),
+            MockResponse::Delayed(delay, response) if delay > self.timeout => {
+                std::thread::sleep(self.timeout);
+
+                // The rest of the reply may still arrive later
+                self.response = Some(MockResponse::Delayed(delay - self.timeout, response));
+
+                return Err(std::io::ErrorKind::TimedOut.into());
+            }
+            MockResponse::Delayed(delay, response) => {
+                std::thread::sleep(delay);
+
+                return self.respond(*response);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if let Some(response) = self.response.take() {
+            self.respond(response)?;
+        }
+
+        if self.input.is_empty() {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+
+        let read = buf.len().min(self.input.len());
+
+        for (b, i) in buf.iter_mut().zip(self.input.drain(..read)) {
+            *b = i;
+        }
+
+        Ok(read)
+    }
+}
+
+impl Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for &byte in buf {
+            if byte == END_BYTE {
+                self.command_received()?;
+            } else {
+                self.pending.push(byte);
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MockTransport {
+    fn clear_input(&mut self) -> Result<()> {
+        self.input.clear();
+        self.response = None;
+
+        Ok(())
+    }
+
+    fn waiting_input(&mut self) -> Result<Option<usize>> {
+        Ok(Some(self.input.len()))
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        Some(self.timeout)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.timeout = timeout;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::cplus::{
+        CPlusInterface, CacheStats, Capability, CacheTtl, CachedInterface, CommandMetrics, MultiUpsManager, RetryPolicy,
+        RetryingInterface, SharedInterface,
+    };
+    use crate::model::cplus;
+
+    const STATUS: &[u8] = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000";
+
+    #[test]
+    fn scripted_test() {
+        let mut iface = MockCPlusInterface::new(
+            MockTransport::new()
+                .expect(b"Q1", MockResponse::reply(STATUS))
+                .expect(b"T", MockResponse::Silence)
+                .expect(b"CT", MockResponse::reply(b"(NAK")),
+        );
+
+        assert!(iface.query_ups_status().unwrap().ups_status.bypass_or_transformer_active());
+        assert_eq!(iface.start_self_test().unwrap(), cplus::CommandAck::NoReply);
+        assert!(matches!(iface.cancel_test(), Err(crate::Error::CommandRejected)));
+
+        assert!(iface.get_ref().is_done());
+        assert_eq!(iface.get_ref().issued(), [&b"Q1"[..], b"T", b"CT"]);
+    }
+
+    #[test]
+    fn unexpected_command_test() {
+        let mut iface = MockCPlusInterface::new(MockTransport::new().expect(b"Q1", MockResponse::reply(STATUS)));
+
+        assert!(matches!(iface.query_ups_rating(), Err(crate::Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidInput));
+
+        // The expectation is kept for the next command
+        assert!(iface.query_ups_status().is_ok());
+
+        let mut iface = MockCPlusInterface::new(
+            MockTransport::new().on_unexpected(UnexpectedCommand::Respond(MockResponse::Silence)),
+        );
+
+        assert_eq!(iface.cancel_shutdown().unwrap(), cplus::CommandAck::NoReply);
+        assert_eq!(iface.get_ref().issued(), [b"C"]);
+    }
+
+    #[test]
+    fn error_and_delay_test() {
+        let mut iface = MockCPlusInterface::new(
+            MockTransport::new()
+                .expect(b"F", MockResponse::Error(std::io::ErrorKind::BrokenPipe))
+                .expect(b"Q1", MockResponse::reply(STATUS).after(Duration::from_millis(10)))
+                .expect(b"T", MockResponse::reply(b"(ACK").after(Duration::from_secs(5))),
+        );
+
+        assert!(matches!(iface.query_ups_rating(), Err(crate::Error::Io(e)) if e.kind() == std::io::ErrorKind::BrokenPipe));
+        assert!(iface.query_ups_status().is_ok());
+
+        // The reply arrives after the control command timeout
+        assert_eq!(iface.start_self_test().unwrap(), cplus::CommandAck::NoReply);
+    }
+
+    #[test]
+    fn health_check_test() {
+        let mut iface = MockCPlusInterface::new(
+            MockTransport::new()
+                .expect(b"Q1", MockResponse::reply(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00000001").after(Duration::from_millis(20)))
+                .expect(b"Q1", MockResponse::reply(b"(208.4 140.0 208.4 034 59.9 2.05 35.0 11000000"))
+                .expect(b"Q1", MockResponse::Silence),
+        );
+
+        let report = iface.health_check().unwrap();
+
+        assert!(report.ok);
+        assert!(report.latency >= Duration::from_millis(20));
+
+        let report = iface.health_check().unwrap();
+
+        assert!(!report.ok);
+        assert!(report.utility_fail && report.battery_low && !report.battery_abnormal);
+        assert!(matches!(iface.health_check(), Err(crate::Error::Timeout { .. })));
+    }
+
+    #[test]
+    fn shared_test() {
+        const THREADS: usize = 8;
+        const QUERIES: usize = 5;
+
+        let mut mock = MockTransport::new();
+        for _ in 0..THREADS * QUERIES {
+            mock = mock.expect(b"Q1", MockResponse::reply(STATUS).after(Duration::from_millis(1)));
+        }
+
+        let mut iface = MockCPlusInterface::new(mock);
+        iface.set_command_gap(Duration::ZERO);
+
+        let shared = SharedInterface::new(iface);
+
+        let threads: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let mut iface = shared.clone();
+
+                std::thread::spawn(move || (0..QUERIES).try_for_each(|_| iface.query_ups_status().map(|_| ())))
+            })
+            .collect();
+
+        // Interleaved commands would garble the replies
+        for thread in threads {
+            thread.join().unwrap().unwrap();
+        }
+
+        let iface = shared.lock().unwrap();
+        assert!(iface.get_ref().is_done());
+        assert_eq!(iface.get_ref().issued().len(), THREADS * QUERIES);
+    }
+
+    #[test]
+    fn metrics_test() {
+        let mut iface = MockCPlusInterface::new(
+            MockTransport::new()
+                .expect(b"Q1", MockResponse::reply(STATUS).after(Duration::from_millis(30)))
+                .expect(b"Q1", MockResponse::reply(STATUS).after(Duration::from_millis(10)))
+                .expect(b"Q1", MockResponse::Error(std::io::ErrorKind::BrokenPipe))
+                .expect(b"F", MockResponse::reply(b"#230.0 008 072.0 50.0"))
+                .expect(b"X", MockResponse::reply(b"#?")),
+        );
+
+        for _ in 0..3 {
+            let _ = iface.query_ups_status();
+        }
+        iface.query_ups_rating().unwrap();
+        iface.raw_query(b"X\r").unwrap();
+
+        let status = *iface.metrics().get("Q1").unwrap();
+
+        assert_eq!((status.count, status.errors), (2, 1));
+        assert!(status.min >= Duration::from_millis(10) && status.min < Duration::from_millis(30));
+        assert!(status.max >= Duration::from_millis(30));
+        assert_eq!(status.last, status.min);
+        assert_eq!(status.mean(), Some((status.min + status.max) / 2));
+        assert_eq!(iface.metrics().get("F").unwrap().count, 1);
+        assert_eq!(iface.metrics().get("X").unwrap().count, 1);
+
+        iface.reset_metrics();
+
+        assert_eq!(iface.metrics().iter().count(), 0);
+        assert_eq!(CommandMetrics::default().mean(), None);
+    }
+
+    #[test]
+    fn capabilities_test() {
+        // Every command times out, none is unsupported
+        let mut iface = MockCPlusInterface::new(
+            MockTransport::new().on_unexpected(UnexpectedCommand::Respond(MockResponse::Silence)),
+        );
+        iface.set_command_gap(Duration::ZERO);
+
+        assert_eq!(iface.capabilities(), Capability::ALL);
+
+        for &capability in Capability::ALL {
+            assert!(capability.attempt(&mut iface), "{capability:?}");
+        }
+    }
+
+    #[test]
+    fn last_success_test() {
+        let mut iface = MockCPlusInterface::new(
+            MockTransport::new()
+                .expect(b"Q1", MockResponse::reply(STATUS))
+                .expect(b"F", MockResponse::Error(std::io::ErrorKind::BrokenPipe))
+                .expect(b"Q1", MockResponse::reply(b"(garbage"))
+                .expect(b"T", MockResponse::Silence)
+                .expect(b"F", MockResponse::reply(b"#230.0 008 072.0 50.0")),
+        );
+        iface.set_command_gap(Duration::ZERO);
+
+        assert_eq!((iface.last_success(), iface.staleness()), (None, None));
+
+        iface.query_ups_status().unwrap();
+        let status_at = iface.last_success().unwrap();
+
+        std::thread::sleep(Duration::from_millis(20));
+
+        // Neither failed nor unparsable replies, nor unanswered control commands count
+        assert!(iface.query_ups_rating().is_err());
+        assert!(iface.query_ups_status().is_err());
+        iface.start_self_test().unwrap();
+
+        assert_eq!(iface.last_success(), Some(status_at));
+        assert_eq!(iface.last_success_of("Q1"), Some(status_at));
+        assert_eq!(iface.last_success_of("F"), None);
+        assert!(iface.staleness().unwrap() >= Duration::from_millis(20));
+
+        iface.query_ups_rating().unwrap();
+
+        assert!(iface.last_success().unwrap() > status_at);
+        assert_eq!(iface.last_success_of("F"), iface.last_success());
+        assert!(iface.staleness().unwrap() < Duration::from_millis(20));
+    }
+
+    #[test]
+    fn command_gap_test() {
+        let mut mock = MockTransport::new();
+        for _ in 0..4 {
+            mock = mock.expect(b"Q1", MockResponse::reply(STATUS));
+        }
+        let mut iface = MockCPlusInterface::new(mock.expect(b"T", MockResponse::Silence));
+
+        iface.set_command_gap(Duration::from_millis(50));
+
+        let started = std::time::Instant::now();
+        iface.query_ups_status().unwrap();
+        iface.query_ups_status().unwrap();
+        iface.query_ups_status().unwrap();
+
+        assert!(started.elapsed() >= Duration::from_millis(100));
+
+        // The gap counts from the end of the last command, so time spent elsewhere isn't added
+        std::thread::sleep(Duration::from_millis(50));
+        let started = std::time::Instant::now();
+        iface.query_ups_status().unwrap();
+
+        assert!(started.elapsed() < Duration::from_millis(40));
+
+        iface.set_command_gap(Duration::ZERO);
+        let started = std::time::Instant::now();
+        iface.start_self_test().unwrap();
+
+        assert!(started.elapsed() < Duration::from_millis(40));
+    }
+
+    #[test]
+    fn cached_test() {
+        const RATING: &[u8] = b"#230.0 008 072.0 50.0";
+
+        let mut mock = MockCPlusInterface::new(
+            MockTransport::new()
+                .expect(b"Q1", MockResponse::Error(std::io::ErrorKind::BrokenPipe))
+                .expect(b"Q1", MockResponse::reply(STATUS))
+                .expect(b"F", MockResponse::reply(RATING))
+                .expect(b"Q1", MockResponse::reply(STATUS))
+                .expect(b"T", MockResponse::Silence)
+                .expect(b"Q1", MockResponse::reply(STATUS)),
+        );
+        mock.set_command_gap(Duration::ZERO);
+
+        let ttl = CacheTtl { status: Duration::from_millis(50), ..CacheTtl::default() };
+        let mut iface = CachedInterface::new(mock, ttl);
+
+        // Errors aren't cached
+        assert!(iface.query_ups_status().is_err());
+        iface.query_ups_status().unwrap();
+        iface.query_ups_status().unwrap();
+        iface.query_ups_rating().unwrap();
+
+        assert_eq!(iface.stats(), CacheStats { hits: 1, misses: 3 });
+
+        std::thread::sleep(Duration::from_millis(60));
+
+        iface.query_ups_status().unwrap();
+        iface.query_ups_rating().unwrap();
+
+        // The status might have changed after the command, the rating not
+        iface.start_self_test().unwrap();
+        iface.query_ups_status().unwrap();
+        iface.query_ups_rating().unwrap();
+
+        assert_eq!(iface.stats(), CacheStats { hits: 3, misses: 5 });
+        assert!(iface.get_ref().get_ref().is_done());
+    }
+
+    #[test]
+    fn snapshot_test() {
+        let mut iface = MockCPlusInterface::new(
+            MockTransport::new()
+                .expect(b"Q1", MockResponse::reply(STATUS))
+                .expect(b"Q5", MockResponse::Error(std::io::ErrorKind::BrokenPipe))
+                .expect(b"At", MockResponse::reply(b"(\x00\x00\x05\x44"))
+                .expect(b"F", MockResponse::reply(b"#230.0 008 072.0 50.0"))
+                .expect(b"Q1", MockResponse::Error(std::io::ErrorKind::BrokenPipe)),
+        );
+        iface.set_command_gap(Duration::ZERO);
+
+        let snapshot = iface.query_snapshot().unwrap();
+
+        assert!(snapshot.status.ups_status.bypass_or_transformer_active());
+        assert!(snapshot.extra_power_info.is_none());
+        assert_eq!(snapshot.autonomy.unwrap().time, Duration::from_secs(0x0544));
+        assert_eq!(snapshot.rating.unwrap().output_rating_current, 8);
+        assert_eq!(snapshot.errors.len(), 1);
+        assert_eq!(snapshot.errors.first().unwrap().section, "extra_power_info");
+
+        // Only the status is required
+        assert!(iface.query_snapshot().is_err());
+        assert!(iface.get_ref().is_done());
+    }
+
+    #[test]
+    fn multi_ups_test() {
+        let mut manager = MultiUpsManager::new();
+
+        for label in ["rack1-a", "rack1-b"] {
+            let mock = MockTransport::new()
+                .expect(b"Q1", MockResponse::reply(STATUS))
+                .expect(b"Q1", MockResponse::reply(STATUS));
+
+            manager.add(label, Box::new(MockCPlusInterface::new(mock)));
+        }
+
+        // Always fails, as it doesn't expect any command
+        manager.add("rack1-c", Box::new(MockCPlusInterface::new(MockTransport::new())));
+
+        let statuses = manager.query_status_all();
+
+        assert_eq!(statuses.len(), 3);
+        assert!(statuses.get("rack1-a").unwrap().is_ok() && statuses.get("rack1-b").unwrap().is_ok());
+        assert!(matches!(
+            statuses.get("rack1-c").unwrap(),
+            Err(crate::Error::Unit { label, source }) if label == "rack1-c" && matches!(**source, crate::Error::Io(_))
+        ));
+
+        assert!(manager.remove("rack1-c").is_some());
+        assert_eq!(manager.labels(), ["rack1-a", "rack1-b"]);
+
+        let statuses = manager.query_all(|iface| iface.query_ups_status().map(|s| s.ups_status.beeper_on()));
+
+        assert!(statuses.values().all(|beeper_on| matches!(beeper_on, Ok(false))));
+    }
+
+    #[test]
+    fn shared_try_lock_and_poison_test() {
+        let shared = SharedInterface::new(MockCPlusInterface::new(MockTransport::new()));
+
+        let guard = shared.lock().unwrap();
+        assert!(matches!(shared.try_lock(), Err(crate::Error::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock));
+        drop(guard);
+
+        let poisoner = shared.clone();
+        std::thread::spawn(move || {
+            let _guard = poisoner.lock().unwrap();
+            panic!("panicking while holding the interface");
+        })
+        .join()
+        .unwrap_err();
+
+        let mut iface = shared.clone();
+        assert!(matches!(iface.query_ups_status(), Err(crate::Error::Io(e)) if e.kind() == std::io::ErrorKind::Other));
+        assert!(matches!(shared.try_lock(), Err(crate::Error::Io(e)) if e.kind() == std::io::ErrorKind::Other));
+    }
+    #[test]
+    fn fault_test() {
+        let corrupted = MockResponse::reply(STATUS).with_fault(MockFault::Corrupt { count: 5, seed: 7 });
+
+        // Deterministic for a seed
+        assert_eq!(corrupted, MockResponse::reply(STATUS).with_fault(MockFault::Corrupt { count: 5, seed: 7 }));
+        assert_ne!(corrupted, MockResponse::reply(STATUS).with_fault(MockFault::Corrupt { count: 5, seed: 8 }));
+
+        let MockResponse::Reply(corrupted) = corrupted else {
+            panic!("the reply is still a reply");
+        };
+
+        assert_eq!(corrupted.len(), STATUS.len());
+        assert_eq!(corrupted.iter().zip(STATUS).filter(|(a, b)| a != b).count(), 5);
+        assert!(!corrupted.contains(&END_BYTE));
+
+        assert_eq!(
+            MockResponse::reply(STATUS).after(Duration::from_millis(5)).with_fault(MockFault::Truncate { len: 6 }),
+            MockResponse::Raw(b"(208.4".to_vec()).after(Duration::from_millis(5)),
+        );
+        assert_eq!(MockResponse::Silence.with_fault(MockFault::Swap(b"#230.0".to_vec())), MockResponse::Silence);
+        assert_eq!(MockResponse::reply(STATUS).with_fault(MockFault::Silence), MockResponse::Silence);
+
+        // Only the nth response to the command is affected
+        let mut iface = MockCPlusInterface::new(
+            MockTransport::new()
+                .on_unexpected(UnexpectedCommand::Respond(MockResponse::reply(STATUS)))
+                .fault(b"Q1", 2, MockFault::Error(std::io::ErrorKind::BrokenPipe))
+                .fault(b"F", 1, MockFault::Swap(STATUS.to_vec())),
+        );
+        iface.set_command_gap(Duration::ZERO);
+
+        assert!(iface.query_ups_status().is_ok());
+        assert!(matches!(iface.query_ups_rating(), Err(crate::Error::UnexpectedResponse { .. })));
+        assert!(matches!(iface.query_ups_status(), Err(crate::Error::Io(e)) if e.kind() == std::io::ErrorKind::BrokenPipe));
+        assert!(iface.query_ups_status().is_ok());
+    }
+
+    #[test]
+    fn retry_on_faults_test() {
+        let transport = MockTransport::new()
+            .on_unexpected(UnexpectedCommand::Respond(MockResponse::reply(STATUS)))
+            .fault(b"Q1", 1, MockFault::Truncate { len: 20 })
+            .fault(b"Q1", 2, MockFault::Corrupt { count: 8, seed: 1 })
+            .fault(b"Q1", 3, MockFault::Delay(Duration::from_secs(1)))
+            .fault(b"Q1", 4, MockFault::Silence);
+
+        let mut iface = MockCPlusInterface::new(transport);
+        iface.set_command_gap(Duration::ZERO);
+        iface.set_query_timeout(Duration::from_millis(50)).unwrap();
+
+        let policy = RetryPolicy { attempts: 5, backoff: Duration::ZERO, ..RetryPolicy::default() };
+        let mut iface = RetryingInterface::new(iface, policy);
+
+        assert!(iface.query_ups_status().is_ok());
+        assert_eq!(iface.get_ref().get_ref().issued().len(), 5);
+
+        let errors = iface.get_ref().metrics().get("Q1").unwrap().errors;
+        assert_eq!(errors, 4);
+    }
+
+    #[test]
+    fn resync_on_faults_test() {
+        let transport = MockTransport::new()
+            .on_unexpected(UnexpectedCommand::Respond(MockResponse::reply(STATUS)))
+            // The tail of a reply, then the reply, as if the port was opened in the middle of it
+            .fault(b"Q1", 1, MockFault::Swap([&b"0.0 00110000\r"[..], STATUS].concat()))
+            // A truncated reply doesn't spoil the next one
+            .fault(b"Q1", 2, MockFault::Truncate { len: 30 })
+            // Garbage only
+            .fault(b"Q1", 4, MockFault::Swap(b"\xfe\xff 0.0 00".to_vec()));
+
+        let mut iface = MockCPlusInterface::new(transport);
+        iface.set_command_gap(Duration::ZERO);
+        iface.set_query_timeout(Duration::from_millis(50)).unwrap();
+
+        assert!(iface.query_ups_status().is_ok());
+        assert!(matches!(iface.query_ups_status(), Err(crate::Error::IncompleteResponse { received }) if received.len() == 30));
+        assert!(iface.query_ups_status().is_ok());
+        assert!(matches!(iface.query_ups_status(), Err(crate::Error::InvalidFormat { parser: "frame", .. })));
+        assert!(iface.query_ups_status().is_ok());
+    }
+}
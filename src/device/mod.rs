@@ -1,2 +1,20 @@
 /// Module for interfacing with the Continuity Plus series UPS.
-pub mod cplus;
\ No newline at end of file
+pub mod cplus;
+
+/// Finding the replies in the bytes received from a UPS.
+pub(crate) mod framing;
+
+/// Scripted transport for testing code built on the interfaces without hardware.
+#[cfg(feature = "mock")]
+pub mod mock;
+
+/// Simulated UPS for testing the whole protocol stack without hardware.
+#[cfg(feature = "mock")]
+pub mod sim;
+
+/// Recording and replaying the communication with a UPS, for debugging.
+#[cfg(feature = "trace")]
+pub mod trace;
+
+/// Periodic polling of a UPS, on a background thread or as an asynchronous stream.
+pub mod poll;
@@ -0,0 +1,673 @@
+use crate::Result;
+use crate::device::cplus::{CPlusInterface, Capability};
+#[cfg(feature = "async")]
+use crate::device::cplus::AsyncCPlusInterface;
+use crate::model::cplus;
+use crate::model::{FromBytes, ToBytes};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, mpsc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Query made by a [`Poller`] on each tick.
+pub enum PollCommand {
+    /// See [`CPlusInterface::query_snapshot`].
+    Snapshot,
+    Status,
+    ExtraPowerInfo,
+    Alarm,
+    Autonomy,
+    BatteryLife,
+    Info,
+    Rating,
+    TestResult,
+}
+
+#[derive(Debug, Clone)]
+/// Reply to a [`PollCommand`].
+pub enum PollResponse {
+    Snapshot(cplus::UpsSnapshot),
+    Status(cplus::StatusInquiryResponse),
+    ExtraPowerInfo(cplus::ExtraPowerInfoResponse),
+    Alarm(cplus::AlarmInquiryResponse),
+    Autonomy(cplus::AutonomyResponse),
+    BatteryLife(cplus::BatteryLifeResponse),
+    Info(cplus::UPSInformation),
+    Rating(cplus::UPSRating),
+    TestResult(cplus::TestResultResponse),
+}
+
+impl PollCommand {
+    /// The capability the interface needs for this query, `None` if every interface can make it.
+    pub fn capability(self) -> Option<Capability> {
+        match self {
+            Self::Snapshot => None,
+            Self::Status => Some(Capability::Status),
+            Self::ExtraPowerInfo => Some(Capability::ExtraPowerInfo),
+            Self::Alarm => Some(Capability::Alarm),
+            Self::Autonomy => Some(Capability::Autonomy),
+            Self::BatteryLife => Some(Capability::BatteryLife),
+            Self::Info => Some(Capability::Info),
+            Self::Rating => Some(Capability::Rating),
+            Self::TestResult => Some(Capability::TestResult),
+        }
+    }
+
+    fn query(self, interface: &mut impl CPlusInterface) -> Result<PollResponse> {
+        Ok(match self {
+            Self::Snapshot => PollResponse::Snapshot(interface.query_snapshot()?),
+            Self::Status => PollResponse::Status(interface.query_ups_status()?),
+            Self::ExtraPowerInfo => PollResponse::ExtraPowerInfo(interface.query_extra_power_info()?),
+            Self::Alarm => PollResponse::Alarm(interface.query_alarm()?),
+            Self::Autonomy => PollResponse::Autonomy(interface.query_ups_autonomy()?),
+            Self::BatteryLife => PollResponse::BatteryLife(interface.query_ups_battery_life()?),
+            Self::Info => PollResponse::Info(interface.query_ups_info()?),
+            Self::Rating => PollResponse::Rating(interface.query_ups_rating()?),
+            Self::TestResult => PollResponse::TestResult(interface.query_test_result()?),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Settings of a [`Poller`].
+pub struct PollerConfig {
+    /// Time between the starts of two ticks. A tick taking longer delays the next one.
+    pub interval: Duration,
+    /// Queries made on each tick, in order. The ones the interface doesn't
+    /// [support](CPlusInterface::supports) are skipped.
+    pub commands: Vec<PollCommand>,
+}
+
+impl Default for PollerConfig {
+    /// A snapshot every second.
+    fn default() -> Self {
+        Self { interval: Duration::from_secs(1), commands: vec![PollCommand::Snapshot] }
+    }
+}
+
+/// Queries a UPS periodically on a background thread, delivering the replies over a channel.
+///
+/// The poller keeps going after failed queries, their errors are delivered like the replies.
+/// Dropping the poller stops the thread and waits for it to finish.
+///
+/// ```no_run
+/// # use alphamon_rs::device::cplus::CPlusSerialInterface;
+/// # use alphamon_rs::device::poll::{Poller, PollerConfig};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let poller = Poller::spawn(CPlusSerialInterface::connect("/dev/ttyUSB0")?, PollerConfig::default());
+///
+/// while let Some(reply) = poller.recv() {
+///     println!("{reply:?}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Poller<T> {
+    replies: mpsc::Receiver<Result<PollResponse>>,
+    /// Dropped to stop the thread.
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<T>>,
+    consecutive_errors: Arc<AtomicU32>,
+}
+
+impl<T> std::fmt::Debug for Poller<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Poller")
+            .field("consecutive_errors", &self.consecutive_errors())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T: CPlusInterface + Send + 'static> Poller<T> {
+    /// Starts polling `interface` on a new thread, which owns it until the poller is stopped.
+    pub fn spawn(interface: T, config: PollerConfig) -> Self {
+        Self::spawn_with_monitor(interface, config, EventMonitor::new())
+    }
+
+    /// Like [`Poller::spawn`], passing every snapshot to `monitor` before delivering it.
+    ///
+    /// The monitor's callbacks are invoked from the polling thread, a slow callback delays
+    /// the following ticks.
+    pub fn spawn_with_monitor(mut interface: T, mut config: PollerConfig, mut monitor: EventMonitor) -> Self {
+        let (reply_sender, replies) = mpsc::channel();
+        let (stop, stop_receiver) = mpsc::channel::<()>();
+        let consecutive_errors = Arc::new(AtomicU32::new(0));
+        let errors = Arc::clone(&consecutive_errors);
+
+        config.commands.retain(|command| {
+            let supported = command.capability().is_none_or(|capability| interface.supports(capability));
+
+            if !supported {
+                debug!("Not polling {command:?}, the interface doesn't support it");
+            }
+
+            supported
+        });
+
+        let thread = std::thread::spawn(move || {
+            let mut next_tick = Instant::now();
+
+            loop {
+                let mut failed = false;
+
+                for &command in &config.commands {
+                    let reply = command.query(&mut interface);
+
+                    match &reply {
+                        Ok(PollResponse::Snapshot(snapshot)) => monitor.process(snapshot),
+                        Ok(_) => {}
+                        Err(e) => {
+                            debug!("Polling {command:?} failed: {e}");
+                            failed = true;
+                        }
+                    }
+
+                    if reply_sender.send(reply).is_err() {
+                        // Nobody is listening anymore
+                        return interface;
+                    }
+                }
+
+                if failed {
+                    errors.fetch_add(1, Ordering::Relaxed);
+                } else {
+                    errors.store(0, Ordering::Relaxed);
+                }
+
+                // Scheduled from the previous tick so the ticks don't drift
+                next_tick = (next_tick + config.interval).max(Instant::now());
+
+                match stop_receiver.recv_timeout(next_tick.saturating_duration_since(Instant::now())) {
+                    Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                    _ => return interface,
+                }
+            }
+        });
+
+        Self { replies, stop: Some(stop), thread: Some(thread), consecutive_errors }
+    }
+}
+
+impl<T> Poller<T> {
+    /// Waits for the next reply. Returns `None` once the polling thread has stopped.
+    pub fn recv(&self) -> Option<Result<PollResponse>> {
+        self.replies.recv().ok()
+    }
+
+    /// Waits at most `timeout` for the next reply.
+    pub fn recv_timeout(&self, timeout: Duration) -> std::result::Result<Result<PollResponse>, mpsc::RecvTimeoutError> {
+        self.replies.recv_timeout(timeout)
+    }
+
+    /// Returns the number of ticks in a row with a failed query, zero after a successful tick.
+    pub fn consecutive_errors(&self) -> u32 {
+        self.consecutive_errors.load(Ordering::Relaxed)
+    }
+
+    /// Stops the polling thread, waiting for the current tick to finish, and returns the interface.
+    ///
+    /// Returns `None` if the thread panicked.
+    pub fn stop(mut self) -> Option<T> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Option<T> {
+        drop(self.stop.take());
+
+        self.thread.take()?.join().ok()
+    }
+}
+
+impl<T> Drop for Poller<T> {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+/// Invoked with an event and the snapshot it was detected in.
+pub type EventCallback = Box<dyn FnMut(&cplus::UpsEvent, &cplus::UpsSnapshot) + Send>;
+
+/// Detects [`UpsEvent`](cplus::UpsEvent)s in consecutive snapshots and invokes the callbacks
+/// registered for them.
+///
+/// A status bit has to keep its new value for the debounce time, measured between the
+/// snapshots' `queried_at`, before its event fires. A bit flapping faster than that
+/// fires no events at all. A panicking callback is logged and doesn't affect the others.
+///
+/// ```
+/// # use alphamon_rs::device::poll::EventMonitor;
+/// # use alphamon_rs::model::cplus::UpsEventKind;
+/// # use std::time::Duration;
+/// let mut monitor = EventMonitor::new();
+///
+/// monitor
+///     .set_debounce(Duration::from_secs(5))
+///     .on(UpsEventKind::MainsLost, |_, snapshot| match snapshot.status.battery_capacity {
+///         Some(capacity) => println!("On battery, {capacity} % left"),
+///         None => println!("On battery"),
+///     })
+///     .on_any(|event, _| println!("{event}"));
+/// ```
+#[derive(Default)]
+pub struct EventMonitor {
+    /// Callbacks in the order of registration, `None` matching every event.
+    callbacks: Vec<(Option<cplus::UpsEventKind>, EventCallback)>,
+    debounce: Duration,
+    capacity_thresholds: Vec<u32>,
+    /// Status the events were last fired for.
+    reported: Option<cplus::UPSStatus>,
+    /// Since when each status bit differs from the reported one.
+    changed_since: [Option<SystemTime>; 8],
+    previous_capacity: Option<u32>,
+}
+
+impl std::fmt::Debug for EventMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventMonitor")
+            .field("callbacks", &self.callbacks.len())
+            .field("debounce", &self.debounce)
+            .field("capacity_thresholds", &self.capacity_thresholds)
+            .field("reported", &self.reported)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EventMonitor {
+    /// Creates a monitor without callbacks, debouncing or capacity thresholds.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `callback` for the events of `kind`.
+    pub fn on(
+        &mut self,
+        kind: cplus::UpsEventKind,
+        callback: impl FnMut(&cplus::UpsEvent, &cplus::UpsSnapshot) + Send + 'static,
+    ) -> &mut Self {
+        self.callbacks.push((Some(kind), Box::new(callback)));
+        self
+    }
+
+    /// Registers `callback` for every event.
+    pub fn on_any(&mut self, callback: impl FnMut(&cplus::UpsEvent, &cplus::UpsSnapshot) + Send + 'static) -> &mut Self {
+        self.callbacks.push((None, Box::new(callback)));
+        self
+    }
+
+    /// Sets how long a status bit has to keep its new value before its event fires. Zero by default.
+    pub fn set_debounce(&mut self, debounce: Duration) -> &mut Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Sets the battery capacities (in %) firing an event when crossed, see [`cplus::diff_snapshot`].
+    pub fn set_capacity_thresholds(&mut self, thresholds: Vec<u32>) -> &mut Self {
+        self.capacity_thresholds = thresholds;
+        self
+    }
+
+    /// Compares `snapshot` with the previous ones and invokes the callbacks for the detected events.
+    ///
+    /// The first snapshot only sets the baseline.
+    pub fn process(&mut self, snapshot: &cplus::UpsSnapshot) {
+        for event in self.detect(snapshot) {
+            self.dispatch(&event, snapshot);
+        }
+    }
+
+    fn detect(&mut self, snapshot: &cplus::UpsSnapshot) -> Vec<cplus::UpsEvent> {
+        // An unknown capacity keeps the last known one for comparing
+        let capacity = snapshot.status.battery_capacity;
+        let previous_capacity = match capacity {
+            Some(capacity) => self.previous_capacity.replace(capacity),
+            None => None,
+        };
+
+        let Some(reported) = &self.reported else {
+            self.reported = Some(snapshot.status.ups_status);
+            return vec![];
+        };
+
+        let mut bits = reported.to_bytes();
+
+        for ((bit, new), changed_since) in bits.iter_mut().zip(snapshot.status.ups_status.to_bytes()).zip(&mut self.changed_since) {
+            if *bit == new {
+                *changed_since = None;
+                continue;
+            }
+
+            let since = *changed_since.get_or_insert(snapshot.queried_at);
+
+            if snapshot.queried_at.duration_since(since).unwrap_or_default() >= self.debounce {
+                *bit = new;
+                *changed_since = None;
+            }
+        }
+
+        let debounced = cplus::UPSStatus::from_bytes(&bits).expect("status bits are 0 or 1");
+        let mut events = cplus::diff_status(reported, &debounced);
+        self.reported = Some(debounced);
+
+        if let (Some(previous), Some(capacity)) = (previous_capacity, capacity) {
+            events.extend(cplus::diff_capacity(previous, capacity, &self.capacity_thresholds));
+        }
+
+        events
+    }
+
+    fn dispatch(&mut self, event: &cplus::UpsEvent, snapshot: &cplus::UpsSnapshot) {
+        let kind = event.kind();
+
+        for (_, callback) in self.callbacks.iter_mut().filter(|(filter, _)| filter.is_none_or(|filter| filter == kind)) {
+            if std::panic::catch_unwind(AssertUnwindSafe(|| callback(event, snapshot))).is_err() {
+                warn!("Callback for \"{event}\" panicked");
+            }
+        }
+    }
+}
+
+/// Polls `interface` for a snapshot every `interval`, starting immediately.
+///
+/// Failed snapshots are yielded as errors without ending the stream. When the consumer
+/// lags behind, the missed ticks are delayed, see [`snapshot_stream_with`] for the other
+/// options. Dropping the stream stops the polling.
+#[cfg(feature = "async")]
+pub fn snapshot_stream<I>(interface: I, interval: Duration) -> impl futures_core::Stream<Item = Result<cplus::UpsSnapshot>> + Send
+where
+    I: AsyncCPlusInterface + 'static,
+{
+    snapshot_stream_with(interface, interval, tokio::time::MissedTickBehavior::Delay)
+}
+
+/// Like [`snapshot_stream`], handling the ticks missed when the consumer lags behind
+/// (or a snapshot takes longer than `interval`) according to `missed_ticks`.
+#[cfg(feature = "async")]
+pub fn snapshot_stream_with<I>(
+    interface: I,
+    interval: Duration,
+    missed_ticks: tokio::time::MissedTickBehavior,
+) -> impl futures_core::Stream<Item = Result<cplus::UpsSnapshot>> + Send
+where
+    I: AsyncCPlusInterface + 'static,
+{
+    let mut interval = tokio::time::interval(interval);
+    interval.set_missed_tick_behavior(missed_ticks);
+
+    SnapshotStream { interval, interface: Some(interface), pending: None }
+}
+
+/// Snapshot query in progress, handing the interface back with the result.
+#[cfg(feature = "async")]
+type PendingSnapshot<I> = std::pin::Pin<Box<dyn Future<Output = (I, Result<cplus::UpsSnapshot>)> + Send>>;
+
+#[cfg(feature = "async")]
+struct SnapshotStream<I> {
+    interval: tokio::time::Interval,
+    /// The interface, while no snapshot is in progress.
+    interface: Option<I>,
+    pending: Option<PendingSnapshot<I>>,
+}
+
+// The interface is never pinned, it's moved in and out of the pending query.
+#[cfg(feature = "async")]
+impl<I> Unpin for SnapshotStream<I> {}
+
+#[cfg(feature = "async")]
+impl<I: AsyncCPlusInterface + 'static> futures_core::Stream for SnapshotStream<I> {
+    type Item = Result<cplus::UpsSnapshot>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        use std::task::Poll;
+
+        loop {
+            if let Some(pending) = &mut self.pending {
+                let Poll::Ready((interface, snapshot)) = pending.as_mut().poll(cx) else {
+                    return Poll::Pending;
+                };
+
+                self.pending = None;
+                self.interface = Some(interface);
+
+                return Poll::Ready(Some(snapshot));
+            }
+
+            if self.interval.poll_tick(cx).is_pending() {
+                return Poll::Pending;
+            }
+
+            let Some(mut interface) = self.interface.take() else {
+                return Poll::Ready(None);
+            };
+
+            self.pending = Some(Box::pin(async move {
+                let snapshot = interface.query_snapshot().await;
+                (interface, snapshot)
+            }));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::device::mock::{MockCPlusInterface, MockResponse, MockTransport, UnexpectedCommand};
+
+    const STATUS: &[u8] = b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000";
+
+    fn interface(response: Option<MockResponse>) -> MockCPlusInterface {
+        let mock = match response {
+            Some(response) => MockTransport::new().on_unexpected(UnexpectedCommand::Respond(response)),
+            None => MockTransport::new(),
+        };
+
+        let mut interface = MockCPlusInterface::new(mock);
+        interface.set_command_gap(Duration::ZERO);
+        interface
+    }
+
+    #[test]
+    fn poll_test() {
+        let config = PollerConfig { interval: Duration::from_millis(10), commands: vec![PollCommand::Status] };
+        let poller = Poller::spawn(interface(Some(MockResponse::reply(STATUS))), config);
+
+        for _ in 0..3 {
+            assert!(matches!(poller.recv(), Some(Ok(PollResponse::Status(_)))));
+        }
+
+        assert_eq!(poller.consecutive_errors(), 0);
+
+        let interface = poller.stop().unwrap();
+
+        assert!(interface.get_ref().issued().len() >= 3);
+    }
+
+    #[test]
+    fn errors_test() {
+        // The mock rejects every command
+        let config = PollerConfig { interval: Duration::from_millis(10), commands: vec![PollCommand::Snapshot] };
+        let poller = Poller::spawn(interface(None), config);
+
+        for _ in 0..3 {
+            assert!(matches!(poller.recv(), Some(Err(crate::Error::Io(_)))));
+        }
+
+        assert!(poller.consecutive_errors() >= 2);
+    }
+
+    #[test]
+    fn stop_test() {
+        let config = PollerConfig { interval: Duration::from_secs(60), commands: vec![PollCommand::Status] };
+        let poller = Poller::spawn(interface(Some(MockResponse::reply(STATUS))), config);
+
+        assert!(poller.recv().unwrap().is_ok());
+
+        // Doesn't wait for the next tick
+        let started = Instant::now();
+        assert!(poller.stop().is_some());
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    fn status_reply(bits: &str) -> MockResponse {
+        MockResponse::reply(format!("(208.4 140.0 208.4 034 59.9 2.05 35.0 {bits}"))
+    }
+
+    #[test]
+    fn monitor_test() {
+        // Only the status is scripted, the rest of each snapshot fails
+        let mock = ["00000000", "10000000", "11000000", "00000000"]
+            .into_iter()
+            .fold(MockTransport::new(), |mock, bits| mock.expect(b"Q1", status_reply(bits)));
+
+        let mut interface = MockCPlusInterface::new(mock);
+        interface.set_command_gap(Duration::ZERO);
+
+        let calls = Arc::new(std::sync::Mutex::new(vec![]));
+        let mut monitor = EventMonitor::new();
+
+        let on_lost = Arc::clone(&calls);
+        let on_any = Arc::clone(&calls);
+        monitor
+            .on_any(|_, _| panic!("broken callback"))
+            .on(cplus::UpsEventKind::MainsLost, move |_, snapshot| {
+                on_lost.lock().unwrap().push(format!("lost at {:?} %", snapshot.status.battery_capacity))
+            })
+            .on_any(move |event, _| on_any.lock().unwrap().push(format!("{event:?}")));
+
+        let config = PollerConfig { interval: Duration::from_millis(10), commands: vec![PollCommand::Snapshot] };
+        let poller = Poller::spawn_with_monitor(interface, config, monitor);
+
+        for _ in 0..4 {
+            assert!(matches!(poller.recv(), Some(Ok(PollResponse::Snapshot(_)))));
+        }
+
+        drop(poller.stop());
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            ["lost at Some(62) %", "MainsLost", "BatteryLow", "MainsRestored", "BatteryLowCleared"]
+        );
+    }
+
+    #[test]
+    fn debounce_test() {
+        let snapshot = |secs: u64, bits: &[u8]| {
+            let mut line = b"208.4 140.0 208.4 034 59.9 2.05 35.0 ".to_vec();
+            line.extend_from_slice(bits);
+            let status = cplus::StatusInquiryResponse::from_bytes(&line).unwrap();
+
+            cplus::UpsSnapshot::new(status, SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+        };
+
+        let events = Arc::new(std::sync::Mutex::new(vec![]));
+        let mut monitor = EventMonitor::new();
+
+        let on_any = Arc::clone(&events);
+        monitor
+            .set_debounce(Duration::from_secs(3))
+            .on_any(move |event, snapshot| on_any.lock().unwrap().push((event.clone(), snapshot.queried_at)));
+
+        // Flapping faster than the debounce time
+        for (secs, bits) in [(0, b"00000000"), (1, b"10000000"), (2, b"00000000"), (3, b"10000000"), (4, b"00000000")] {
+            monitor.process(&snapshot(secs, bits));
+        }
+
+        assert!(events.lock().unwrap().is_empty());
+
+        for secs in [5, 7, 8, 9] {
+            monitor.process(&snapshot(secs, b"10000000"));
+        }
+
+        monitor.process(&snapshot(10, b"00000000"));
+        monitor.process(&snapshot(13, b"00000000"));
+
+        let at = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [(cplus::UpsEvent::MainsLost, at(8)), (cplus::UpsEvent::MainsRestored, at(13))]
+        );
+    }
+}
+
+#[cfg(all(test, feature = "async", feature = "mock"))]
+mod stream_tests {
+    use super::*;
+    use crate::device::cplus::{AsyncCPlusSerialInterface, TokioIo};
+    use crate::device::sim::{SimState, UpsSimulator};
+    use futures_core::Stream;
+    use std::sync::atomic::AtomicBool;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+    /// Interface connected to a simulated UPS, which stops replying while the flag is set.
+    fn simulated() -> (AsyncCPlusSerialInterface<TokioIo<DuplexStream>>, Arc<AtomicBool>) {
+        let (port, mut ups) = tokio::io::duplex(256);
+        let silent = Arc::new(AtomicBool::new(false));
+        let sim = UpsSimulator::new(SimState::default());
+        let is_silent = Arc::clone(&silent);
+
+        tokio::spawn(async move {
+            let mut command = vec![];
+
+            while let Ok(byte) = ups.read_u8().await {
+                if byte != b'\r' {
+                    command.push(byte);
+                    continue;
+                }
+
+                let reply = sim.handle_command(&std::mem::take(&mut command));
+
+                if let Some(reply) = reply.filter(|_| !is_silent.load(Ordering::Relaxed))
+                    && ups.write_all(&reply).await.is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        (AsyncCPlusSerialInterface::with_transport(port, Duration::from_millis(50)), silent)
+    }
+
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        std::future::poll_fn(|cx| std::pin::Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    #[tokio::test]
+    async fn pacing_test() {
+        let (interface, _) = simulated();
+        let mut stream = Box::pin(snapshot_stream(interface, Duration::from_millis(50)));
+
+        let started = Instant::now();
+
+        for _ in 0..3 {
+            let snapshot = next(&mut stream).await.unwrap().unwrap();
+
+            assert!(snapshot.errors.is_empty());
+        }
+
+        // The first tick is immediate
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn errors_test() {
+        let (interface, silent) = simulated();
+        let mut stream = Box::pin(snapshot_stream_with(
+            interface,
+            Duration::from_millis(10),
+            tokio::time::MissedTickBehavior::Skip,
+        ));
+
+        silent.store(true, Ordering::Relaxed);
+
+        assert!(matches!(next(&mut stream).await, Some(Err(crate::Error::Timeout { .. }))));
+        assert!(matches!(next(&mut stream).await, Some(Err(crate::Error::Timeout { .. }))));
+
+        silent.store(false, Ordering::Relaxed);
+
+        assert!(next(&mut stream).await.unwrap().is_ok());
+    }
+}
@@ -0,0 +1,344 @@
+use crate::Result;
+use crate::device::cplus::Transport;
+use crate::model::ToBytes;
+use crate::model::cplus;
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// End byte of CPlus messages.
+const END_BYTE: u8 = b'\r';
+
+#[derive(Debug, Clone)]
+/// State of the simulated UPS, which its replies are generated from.
+///
+/// Note that the battery capacity reported by the status query is derived from
+/// `status.battery_capacity_parameter`, not `status.battery_capacity`.
+pub struct SimState {
+    pub status: cplus::StatusInquiryResponse,
+    pub alarm: cplus::AlarmInquiryResponse,
+    pub extra_power_info: cplus::ExtraPowerInfoResponse,
+    pub autonomy: cplus::AutonomyResponse,
+    pub battery_life: cplus::BatteryLifeResponse,
+    pub info: cplus::UPSInformation,
+    pub rating: cplus::UPSRating,
+    pub test_result: cplus::TestResultResponse,
+}
+
+impl Default for SimState {
+    /// An online UPS running on utility power, based on the protocol examples.
+    fn default() -> Self {
+        Self {
+            status: cplus::StatusInquiryResponse {
+                input_voltage: 230.0,
+                input_fault_voltage: 230.0,
+                output_voltage: 230.0,
+                output_load_percentage: 34,
+                input_frequency: 50.0,
+                battery_capacity: Some(100),
+                battery_capacity_parameter: "2.22".to_string(),
+                temperature: 35.0,
+                ups_status: cplus::UPSStatus::BEEPER_ON,
+                raw: vec![],
+            },
+            alarm: cplus::AlarmInquiryResponse {
+                inverter_on: true,
+                ups_alarm_on: false,
+            },
+            extra_power_info: cplus::ExtraPowerInfoResponse {
+                ups_output_freq: 50.0,
+                battery_voltage: 13.9,
+                battery_cut_voltage: 9.5,
+                ups_wattage: 533,
+                error_code: 0,
+                load_current: 3.3,
+                charger_current: None,
+                heatsink_temperature: None,
+                raw_words: [500, 0, 0, 1390, 950, 533, 0, 33, 0, 0],
+            },
+            autonomy: cplus::AutonomyResponse {
+                time: Duration::from_secs(1348),
+                encoding: cplus::NumberEncoding::Binary,
+            },
+            battery_life: cplus::BatteryLifeResponse {
+                time: Duration::from_secs(60 * 60 * 87600),
+                encoding: cplus::NumberEncoding::Binary,
+            },
+            info: cplus::UPSInformation {
+                manufacturer_name: "Alpha".to_string(),
+                model: "CPlus".to_string(),
+                version: "V1.00".to_string(),
+                raw: vec![],
+            },
+            rating: cplus::UPSRating {
+                output_rating_voltage: 230.0,
+                output_rating_current: 8,
+                battery_voltage: 72.0,
+                output_rating_frequency: 50.0,
+                raw: vec![],
+            },
+            test_result: cplus::TestResultResponse {
+                result: cplus::TestResult::Passed,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Simulated Continuity Plus UPS, answering queries from its [`SimState`]
+/// and changing the state on control commands.
+///
+/// The simulator can be shared: clones operate on the same state, so a test can
+/// change the state while the simulator serves a stream on another thread.
+///
+/// ```
+/// # use alphamon_rs::device::cplus::{CPlusGenericInterface, CPlusInterface};
+/// # use alphamon_rs::device::sim::UpsSimulator;
+/// # use alphamon_rs::model::cplus::UPSStatus;
+/// let sim = UpsSimulator::default();
+/// let mut iface = CPlusGenericInterface::new(sim.transport());
+///
+/// sim.state().status.ups_status.insert(UPSStatus::UTILITY_FAIL);
+///
+/// assert!(iface.query_ups_status()?.ups_status.utility_fail());
+/// # Ok::<(), alphamon_rs::Error>(())
+/// ```
+pub struct UpsSimulator {
+    state: Arc<Mutex<SimState>>,
+}
+
+impl UpsSimulator {
+    /// Creates a simulator starting in the given state.
+    pub fn new(state: SimState) -> Self {
+        Self { state: Arc::new(Mutex::new(state)) }
+    }
+
+    /// Locks the state of the simulator for reading or changing it.
+    pub fn state(&self) -> MutexGuard<'_, SimState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Handles a command (without the end byte), returning the reply (including
+    /// the prefix and the end byte) if the UPS replies to it.
+    pub fn handle_command(&self, command: &[u8]) -> Option<Vec<u8>> {
+        let mut state = self.state();
+        let ups_status = &mut state.status.ups_status;
+
+        let (prefix, reply) = match command {
+            b"Q1" => (b'(', state.status.to_bytes()),
+            b"Q4" => (b'(', state.alarm.to_bytes()),
+            b"Q5" => (b'(', state.extra_power_info.to_bytes()),
+            b"At" => (b'(', state.autonomy.to_bytes()),
+            b"BL" => (b'!', state.battery_life.to_bytes()),
+            b"I" => (b'#', state.info.to_bytes()),
+            b"F" => (b'#', state.rating.to_bytes()),
+            b"TR" => (b'#', state.test_result.to_bytes()),
+            b"Q" => {
+                ups_status.toggle(cplus::UPSStatus::BEEPER_ON);
+                return None;
+            }
+            b"C" => {
+                ups_status.remove(cplus::UPSStatus::SHUTDOWN_ACTIVE);
+                return None;
+            }
+            b"CT" => {
+                ups_status.remove(cplus::UPSStatus::TEST_IN_PROGRESS);
+                return None;
+            }
+            [b'T', ..] => {
+                ups_status.insert(cplus::UPSStatus::TEST_IN_PROGRESS);
+                return None;
+            }
+            [b'S', ..] => {
+                ups_status.insert(cplus::UPSStatus::SHUTDOWN_ACTIVE);
+                return None;
+            }
+            [b'p', b'a' | b'b', ..] => return None,
+            _ => {
+                debug!("Simulator ignoring unknown command {:?}", String::from_utf8_lossy(command));
+                return None;
+            }
+        };
+
+        let mut message = vec![prefix];
+        message.extend(reply);
+        message.push(END_BYTE);
+
+        Some(message)
+    }
+
+    /// Serves the commands received over `stream` until it's closed.
+    /// Read timeouts of the stream are ignored.
+    pub fn serve<S: Read + Write>(&self, mut stream: S) -> Result<()> {
+        let mut command = vec![];
+        let mut byte = [0u8];
+
+        loop {
+            match stream.read(&mut byte) {
+                Ok(0) => return Ok(()),
+                Ok(_) if byte[0] == END_BYTE => {
+                    if let Some(reply) = self.handle_command(&std::mem::take(&mut command)) {
+                        stream.write_all(&reply)?;
+                        stream.flush()?;
+                    }
+                }
+                Ok(_) => command.push(byte[0]),
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Returns an in-memory transport connected to the simulator.
+    pub fn transport(&self) -> SimTransport {
+        SimTransport {
+            sim: self.clone(),
+            pending: vec![],
+            input: VecDeque::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// In-memory transport connected to an [`UpsSimulator`].
+/// Reads time out immediately when the simulator didn't reply.
+pub struct SimTransport {
+    sim: UpsSimulator,
+    /// The command currently being written.
+    pending: Vec<u8>,
+    /// Reply bytes waiting to be read.
+    input: VecDeque<u8>,
+}
+
+impl Read for SimTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.input.is_empty() {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+
+        let read = buf.len().min(self.input.len());
+
+        for (b, i) in buf.iter_mut().zip(self.input.drain(..read)) {
+            *b = i;
+        }
+
+        Ok(read)
+    }
+}
+
+impl Write for SimTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for &byte in buf {
+            if byte == END_BYTE {
+                let command = std::mem::take(&mut self.pending);
+
+                self.input.extend(self.sim.handle_command(&command).unwrap_or_default());
+            } else {
+                self.pending.push(byte);
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for SimTransport {
+    fn clear_input(&mut self) -> Result<()> {
+        self.input.clear();
+
+        Ok(())
+    }
+
+    fn waiting_input(&mut self) -> Result<Option<usize>> {
+        Ok(Some(self.input.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::cplus::{CPlusGenericInterface, CPlusInterface, GracefulShutdownOptions};
+
+    #[test]
+    fn queries_test() {
+        let sim = UpsSimulator::default();
+        let mut iface = CPlusGenericInterface::new(sim.transport());
+
+        let status = iface.query_ups_status().unwrap();
+
+        assert_eq!(status.battery_capacity, Some(100));
+        assert_eq!(status.output_load_percentage, 34);
+        assert!(iface.query_alarm().unwrap().inverter_on);
+        assert_eq!(iface.query_extra_power_info().unwrap().ups_wattage, 533);
+        assert_eq!(iface.query_ups_autonomy().unwrap().time, Duration::from_secs(1348));
+        assert_eq!(iface.query_ups_battery_life().unwrap().time.as_secs(), 60 * 60 * 87600);
+        assert_eq!(iface.query_ups_info().unwrap().model, "CPlus");
+        assert_eq!(iface.query_ups_rating().unwrap().output_rating_current, 8);
+        assert_eq!(iface.query_test_result().unwrap().result, cplus::TestResult::Passed);
+        assert_eq!(iface.query_firmware_version().unwrap().major, Some(1));
+
+        // Firmwares replying with ASCII numbers
+        sim.state().autonomy.encoding = cplus::NumberEncoding::Ascii;
+        sim.state().battery_life.encoding = cplus::NumberEncoding::Ascii;
+
+        assert_eq!(iface.query_ups_autonomy().unwrap().encoding, cplus::NumberEncoding::Ascii);
+        assert_eq!(iface.query_ups_battery_life().unwrap().time.as_secs(), 60 * 60 * 87600);
+    }
+
+    #[test]
+    fn control_commands_test() {
+        let sim = UpsSimulator::default();
+        let mut iface = CPlusGenericInterface::new(sim.transport());
+
+        iface.start_timed_self_test(5).unwrap();
+        assert!(iface.query_ups_status().unwrap().ups_status.test_in_progress());
+
+        iface.cancel_test().unwrap();
+        assert!(!iface.query_ups_status().unwrap().ups_status.test_in_progress());
+
+        assert!(!iface.toggle_beeper().unwrap());
+
+        sim.state().status.ups_status.insert(cplus::UPSStatus::UTILITY_FAIL);
+
+        let mut opts = GracefulShutdownOptions::new(cplus::ShutdownDelay::from_minutes(1).unwrap());
+        opts.poll_interval = Duration::ZERO;
+
+        let report = iface.graceful_shutdown(opts).unwrap();
+
+        assert!(report.shutdown_issued && report.confirmed);
+
+        iface.cancel_shutdown().unwrap();
+        assert!(!sim.state().status.ups_status.shutdown_active());
+    }
+
+    #[test]
+    fn serve_test() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let sim = UpsSimulator::default();
+
+        let server = {
+            let sim = sim.clone();
+
+            std::thread::spawn(move || sim.serve(listener.accept().unwrap().0))
+        };
+
+        let mut stream = std::net::TcpStream::connect(addr).unwrap();
+        let mut reply = [0u8; 22];
+
+        stream.write_all(b"T\rF\r").unwrap();
+        stream.read_exact(&mut reply).unwrap();
+
+        assert_eq!(&reply, b"#230.0 008 072.0 50.0\r");
+        assert!(sim.state().status.ups_status.test_in_progress());
+
+        drop(stream);
+
+        assert!(server.join().unwrap().is_ok());
+    }
+}
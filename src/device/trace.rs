@@ -0,0 +1,414 @@
+use crate::Result;
+use crate::device::cplus::Transport;
+use std::collections::VecDeque;
+use std::io::{BufRead, Read, Write};
+use std::time::{Duration, Instant};
+
+/// End byte of CPlus messages.
+const END_BYTE: u8 = b'\r';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Direction of the traced bytes.
+enum Direction {
+    /// Written to the UPS, traced as `>`.
+    Write,
+    /// Read from the UPS, traced as `<`.
+    Read,
+}
+
+impl Direction {
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::Write => ">",
+            Self::Read => "<",
+        }
+    }
+}
+
+/// Transport logging every byte written to and read from the wrapped transport.
+///
+/// Each message is written to the sink as a line holding the seconds elapsed since the
+/// transport was created, the direction and the bytes in hex, e.g. `0.000012 > 51 31 0D`.
+/// A line ends after the end byte, when the direction changes or when the input is cleared.
+/// The trace can be played back by a [`ReplayTransport`].
+///
+/// ```
+/// # use alphamon_rs::device::cplus::{CPlusGenericInterface, CPlusInterface};
+/// # use alphamon_rs::device::trace::{ReplayTransport, TraceTransport};
+/// let ups = ReplayTransport::from_trace("0.0 > 51 31 0D\n0.1 < 28 32 30 38 2E 34 20 31 34 30 2E 30 20 32 30 38 2E 34 20 30 33 34 20 35 39 2E 39 20 32 2E 30 35 20 33 35 2E 30 20 30 30 31 31 30 30 30 30 0D")?;
+/// let mut iface = CPlusGenericInterface::new(TraceTransport::new(ups, Vec::new()));
+///
+/// iface.query_ups_status()?;
+///
+/// let trace = String::from_utf8(iface.into_inner().into_inner().1).unwrap();
+/// assert!(trace.lines().next().unwrap().ends_with("> 51 31 0D"));
+/// # Ok::<(), alphamon_rs::Error>(())
+/// ```
+pub struct TraceTransport<T, W> {
+    inner: T,
+    sink: W,
+    start: Instant,
+    /// The line currently being traced: its direction, when it started and its bytes.
+    line: Option<(Direction, Duration, Vec<u8>)>,
+}
+
+impl<T, W: Write> TraceTransport<T, W> {
+    /// Wraps `inner`, writing the trace to `sink` (e.g. a file or a `Vec<u8>`).
+    pub fn new(inner: T, sink: W) -> Self {
+        Self { inner, sink, start: Instant::now(), line: None }
+    }
+
+    /// Returns a reference to the wrapped transport.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Returns the wrapped transport and the sink, after writing out the pending line.
+    pub fn into_inner(mut self) -> (T, W) {
+        self.end_line();
+
+        (self.inner, self.sink)
+    }
+
+    fn trace(&mut self, direction: Direction, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.line.as_ref().is_some_and(|(dir, _, _)| *dir != direction) {
+                self.end_line();
+            }
+
+            let elapsed = self.start.elapsed();
+            let (_, _, line) = self.line.get_or_insert_with(|| (direction, elapsed, vec![]));
+            line.push(byte);
+
+            if byte == END_BYTE {
+                self.end_line();
+            }
+        }
+    }
+
+    /// Writes out the line being traced, if any.
+    fn end_line(&mut self) {
+        let Some((direction, elapsed, bytes)) = self.line.take() else {
+            return;
+        };
+
+        let hex: Vec<_> = bytes.iter().map(|b| format!("{b:02X}")).collect();
+        let line = format!("{:.6} {} {}\n", elapsed.as_secs_f64(), direction.symbol(), hex.join(" "));
+
+        // A broken trace sink shouldn't break the communication with the UPS
+        if let Err(e) = self.sink.write_all(line.as_bytes()).and_then(|()| self.sink.flush()) {
+            debug!("Couldn't write the trace: {e}");
+        }
+    }
+}
+
+impl<T: std::fmt::Debug, W> std::fmt::Debug for TraceTransport<T, W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TraceTransport").field("inner", &self.inner).finish_non_exhaustive()
+    }
+}
+
+impl<T: Read, W: Write> Read for TraceTransport<T, W> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        if let Some(bytes) = buf.get(..read) {
+            self.trace(Direction::Read, bytes);
+        }
+
+        Ok(read)
+    }
+}
+
+impl<T: Write, W: Write> Write for TraceTransport<T, W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+
+        if let Some(bytes) = buf.get(..written) {
+            self.trace(Direction::Write, bytes);
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<T: Transport, W: Write> Transport for TraceTransport<T, W> {
+    fn clear_input(&mut self) -> Result<()> {
+        self.end_line();
+
+        self.inner.clear_input()
+    }
+
+    fn waiting_input(&mut self) -> Result<Option<usize>> {
+        self.inner.waiting_input()
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.inner.timeout()
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+        self.inner.set_timeout(timeout)
+    }
+
+    fn baud_rate(&self) -> Option<u32> {
+        self.inner.baud_rate()
+    }
+}
+
+#[derive(Debug)]
+/// Transport playing back a trace written by a [`TraceTransport`], to reproduce a session offline.
+///
+/// The read bytes of the trace become readable once the written bytes preceding them were
+/// written, reads time out when there's nothing more to read (as they did in the traced session).
+/// Writes differing from the trace fail with [`std::io::ErrorKind::InvalidInput`].
+pub struct ReplayTransport {
+    /// The traced lines not played back yet.
+    lines: VecDeque<(Direction, VecDeque<u8>)>,
+    /// Bytes waiting to be read.
+    input: VecDeque<u8>,
+}
+
+impl ReplayTransport {
+    /// Parses the trace. Empty lines and lines starting with `#` are skipped.
+    pub fn from_trace(trace: &str) -> Result<Self> {
+        let mut lines = VecDeque::new();
+
+        for line in trace.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let mut fields = line.split_whitespace();
+
+            let invalid = |reason: String| crate::Error::invalid_format("trace", line.as_bytes(), reason);
+
+            let (Some(_elapsed), Some(direction)) = (fields.next(), fields.next()) else {
+                return Err(invalid("missing the direction".to_string()));
+            };
+
+            let direction = match direction {
+                ">" => Direction::Write,
+                "<" => Direction::Read,
+                _ => return Err(invalid(format!("unknown direction {direction:?}"))),
+            };
+
+            let bytes = fields
+                .enumerate()
+                .map(|(i, byte)| u8::from_str_radix(byte, 16).map_err(|_| invalid(format!("invalid byte {byte:?} at position {i}"))))
+                .collect::<Result<_>>()?;
+
+            lines.push_back((direction, bytes));
+        }
+
+        let mut replay = Self { lines, input: VecDeque::new() };
+        replay.release_reads();
+
+        Ok(replay)
+    }
+
+    /// Reads the trace from `reader`, see [`ReplayTransport::from_trace`].
+    pub fn from_reader(mut reader: impl BufRead) -> Result<Self> {
+        let mut trace = String::new();
+        reader.read_to_string(&mut trace)?;
+
+        Self::from_trace(&trace)
+    }
+
+    /// Whether the whole trace was played back.
+    pub fn is_done(&self) -> bool {
+        self.lines.is_empty() && self.input.is_empty()
+    }
+
+    /// Makes the read lines up to the next written line readable.
+    fn release_reads(&mut self) {
+        while let Some((Direction::Read, _)) = self.lines.front() {
+            if let Some((_, bytes)) = self.lines.pop_front() {
+                self.input.extend(bytes);
+            }
+        }
+    }
+}
+
+impl Read for ReplayTransport {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut read = 0;
+
+        for slot in buf.iter_mut() {
+            let Some(byte) = self.input.pop_front() else {
+                break;
+            };
+
+            *slot = byte;
+            read += 1;
+        }
+
+        if read == 0 && !buf.is_empty() {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+
+        Ok(read)
+    }
+}
+
+impl Write for ReplayTransport {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for &byte in buf {
+            let Some((Direction::Write, expected)) = self.lines.front_mut() else {
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "write not in the trace"));
+            };
+
+            if expected.pop_front() != Some(byte) {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("unexpected byte {byte:02X} written"),
+                ));
+            }
+
+            if expected.is_empty() {
+                self.lines.pop_front();
+                self.release_reads();
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn waiting_input(&mut self) -> Result<Option<usize>> {
+        Ok(Some(self.input.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device::cplus::{CPlusGenericInterface, CPlusInterface};
+
+    /// Transport answering each written message with the next reply.
+    #[derive(Debug, Default)]
+    struct Scripted {
+        replies: VecDeque<&'static [u8]>,
+        input: VecDeque<u8>,
+        timeout: Option<Duration>,
+        baud_rate: Option<u32>,
+    }
+
+    impl Read for Scripted {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match (self.input.pop_front(), buf.first_mut()) {
+                (Some(byte), Some(slot)) => {
+                    *slot = byte;
+                    Ok(1)
+                }
+                _ => Err(std::io::ErrorKind::TimedOut.into()),
+            }
+        }
+    }
+
+    impl Write for Scripted {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if buf.contains(&END_BYTE) && let Some(reply) = self.replies.pop_front() {
+                self.input.extend(reply);
+            }
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Transport for Scripted {
+        fn timeout(&self) -> Option<Duration> {
+            self.timeout
+        }
+
+        fn set_timeout(&mut self, timeout: Duration) -> Result<()> {
+            self.timeout = Some(timeout);
+            Ok(())
+        }
+
+        fn baud_rate(&self) -> Option<u32> {
+            self.baud_rate
+        }
+    }
+
+    #[test]
+    fn trace_format_test() {
+        let ups = Scripted { replies: [&b"#230.0 008 072.0 50.0\r"[..]].into(), ..Default::default() };
+        let mut iface = CPlusGenericInterface::new(TraceTransport::new(ups, Vec::new()));
+
+        iface.query_ups_rating().unwrap();
+
+        let trace = String::from_utf8(iface.into_inner().into_inner().1).unwrap();
+        let lines: Vec<_> = trace.lines().map(|l| l.split_once(' ').unwrap().1).collect();
+
+        assert_eq!(lines, ["> 46 0D", "< 23 32 33 30 2E 30 20 30 30 38 20 30 37 32 2E 30 20 35 30 2E 30 0D"]);
+    }
+
+    #[test]
+    fn min_query_timeout_test() {
+        let ups = Scripted { baud_rate: Some(2_400), ..Default::default() };
+        let mut iface = CPlusGenericInterface::new(TraceTransport::new(ups, Vec::new()));
+
+        // The floor for the baud rate of the traced transport still applies
+        iface.set_query_timeout(Duration::from_millis(1)).unwrap();
+
+        assert!(iface.min_query_timeout().is_some());
+        assert_eq!(iface.get_ref().timeout(), iface.min_query_timeout());
+    }
+
+    #[test]
+    fn round_trip_test() {
+        let ups = Scripted {
+            replies: [
+                &b"(208.4 140.0 208.4 034 59.9 2.05 35.0 00110000\r"[..],
+                b"#230.0 008 072.0 50.0\r",
+                // Truncated reply
+                b"(13",
+            ]
+            .into(),
+            ..Default::default()
+        };
+        let mut iface = CPlusGenericInterface::new(TraceTransport::new(ups, Vec::new()));
+
+        let status = iface.query_ups_status().unwrap();
+        let rating = iface.query_ups_rating().unwrap();
+        let autonomy = iface.query_ups_autonomy();
+
+        let trace = String::from_utf8(iface.into_inner().into_inner().1).unwrap();
+        let mut replay = CPlusGenericInterface::new(ReplayTransport::from_trace(&trace).unwrap());
+
+        assert_eq!(format!("{:?}", replay.query_ups_status().unwrap()), format!("{status:?}"));
+        assert_eq!(format!("{:?}", replay.query_ups_rating().unwrap()), format!("{rating:?}"));
+        assert!(matches!(
+            (replay.query_ups_autonomy(), autonomy),
+            (Err(crate::Error::IncompleteResponse { received }), Err(crate::Error::IncompleteResponse { received: traced }))
+                if received == traced
+        ));
+        assert!(replay.get_ref().is_done());
+    }
+
+    #[test]
+    fn replay_unexpected_write_test() {
+        let mut replay = CPlusGenericInterface::new(ReplayTransport::from_trace("0.0 > 51 31 0D\n").unwrap());
+
+        assert!(matches!(replay.query_ups_rating(), Err(crate::Error::Io(e)) if e.kind() == std::io::ErrorKind::InvalidInput));
+        assert!(matches!(
+            ReplayTransport::from_trace("0.0 ? 51"),
+            Err(crate::Error::InvalidFormat { parser: "trace", reason, .. }) if reason == "unknown direction \"?\""
+        ));
+        assert!(matches!(
+            ReplayTransport::from_trace("0.0 > 51 5Z"),
+            Err(crate::Error::InvalidFormat { parser: "trace", input, reason })
+                if input == b"0.0 > 51 5Z" && reason == "invalid byte \"5Z\" at position 1"
+        ));
+    }
+}
@@ -38,10 +38,19 @@ pub mod model;
 
 #[derive(thiserror::Error, Debug)]
 /// Main error enum for this library.
+#[non_exhaustive]
 pub enum Error {
-    #[error("Invalid format or length of response data")]
-    InvalidFormat,
+    /// `input` (its first [`MAX_ERROR_INPUT_LEN`] bytes) couldn't be parsed by `parser`.
+    #[error("Invalid format of the {parser} data ({reason}): {}", describe_input(.input))]
+    InvalidFormat { parser: &'static str, input: Vec<u8>, reason: String },
 
+    #[error("Invalid length of response data (expected at least {expected} bytes, received {received})")]
+    InvalidLength { expected: usize, received: usize },
+
+    #[error("Invalid status bit {:?} at position {position}", char::from(*.byte))]
+    InvalidStatusBit { position: usize, byte: u8 },
+
+    #[deprecated(note = "no longer returned, unknown battery capacities are `None`")]
     #[error("Invalid battery capacity parameter!")]
     InvalidBatteryCapacityParameter,
 
@@ -54,6 +63,42 @@ pub enum Error {
     #[error("Invalid length of message parameter")]
     InvalidParameterLength(#[from] std::array::TryFromSliceError),
 
+    #[error("Invalid command parameter: {0}")]
+    InvalidParameter(String),
+
+    #[error("Unexpected response from the UPS (expected {expected}, got {got:?})")]
+    UnexpectedResponse { expected: String, got: String },
+
+    #[error("The UPS rejected the command")]
+    CommandRejected,
+
+    #[error("The operation {operation} is not supported by this interface")]
+    Unsupported { operation: &'static str },
+
+    #[error("The received message is longer than {limit} bytes")]
+    FrameTooLong { limit: usize },
+
+    #[error("Timed out waiting for {waiting_for}")]
+    Timeout { waiting_for: String },
+
+    #[error("Timed out before the end of the reply (received {:?})", String::from_utf8_lossy(.received))]
+    IncompleteResponse { received: Vec<u8> },
+
+    #[error("The UPS has been reporting the same data for {unchanged_for:?}")]
+    StaleData { unchanged_for: std::time::Duration },
+
+    #[error("No UPS found")]
+    NoUpsFound,
+
+    #[error("No UPS with the serial number {serial} found (found: {found:?})")]
+    SerialNumberNotFound { serial: String, found: Vec<String> },
+
+    #[error("Giving up after {attempts} attempts: {source}")]
+    RetriesExhausted { attempts: u32, source: Box<Error> },
+
+    #[error("UPS {label}: {source}")]
+    Unit { label: String, source: Box<Error> },
+
     #[error("The buffer is too small (expected: {expected}, provided {provided})")]
     BufferTooSmall { expected: usize, provided: usize },
 
@@ -69,4 +114,34 @@ pub enum Error {
     HidApi(#[from] hidapi::HidError),
 }
 
-type Result<T> = std::result::Result<T, Error>;
\ No newline at end of file
+type Result<T> = std::result::Result<T, Error>;
+
+/// Longest part of the input kept by [`Error::InvalidFormat`].
+pub const MAX_ERROR_INPUT_LEN: usize = 64;
+
+/// Inputs up to this length are also hex-dumped by [`Error::InvalidFormat`].
+const HEX_DUMP_LEN: usize = 16;
+
+impl Error {
+    /// Error for `input` which `parser` couldn't parse because of `reason`.
+    pub(crate) fn invalid_format(parser: &'static str, input: &[u8], reason: impl Into<String>) -> Self {
+        Self::InvalidFormat {
+            parser,
+            input: input.get(..MAX_ERROR_INPUT_LEN).unwrap_or(input).to_vec(),
+            reason: reason.into(),
+        }
+    }
+}
+
+/// Formats `input` as text, along with the hex dump of a short one.
+fn describe_input(input: &[u8]) -> String {
+    let text = format!("{:?}", String::from_utf8_lossy(input));
+
+    if input.len() > HEX_DUMP_LEN {
+        return text;
+    }
+
+    let hex = input.iter().map(|byte| format!("{byte:02x}")).collect::<Vec<_>>().join(" ");
+
+    format!("{text} [{hex}]")
+}
\ No newline at end of file
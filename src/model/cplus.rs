@@ -1,9 +1,10 @@
-use serde::Serialize;
-use tokio::time;
+use serde::{Deserialize, Serialize};
+use std::time;
 use crate::{Error, Result};
 
-use crate::model::FromBytes;
+use crate::model::{FromBytes, ToBytes};
 
+#[cfg(any(feature = "serial", feature = "rfc2217"))]
 pub(crate) const SERIAL_BAUD_RATE: u32 = 2_400;
 
 pub(crate) static CMD_STATUS_INQUIRY: &[u8] = b"Q1";
@@ -22,7 +23,469 @@ pub(crate) static CMD_UPS_INFORMATION: &[u8] = b"I";
 // Queries the UPS for its information
 pub(crate) static CMD_RATING_INFORMATION: &[u8] = b"F";
 
-#[derive(Debug, Serialize, Clone)]
+// Queries the UPS for the result of the last self-test
+pub(crate) static CMD_TEST_RESULT: &[u8] = b"TR";
+
+// Switches programmable outlet A/B immediately, followed by 0 (off) or 1 (on)
+pub(crate) static CMD_OUTLET_A_SWITCH: &[u8] = b"pa";
+pub(crate) static CMD_OUTLET_B_SWITCH: &[u8] = b"pb";
+
+// Shuts the UPS output off after a delay, followed by the delay (`S.3`, `S05`, ...)
+pub(crate) static CMD_SHUTDOWN: &[u8] = b"S";
+
+// Restores the UPS output after a number of minutes, appended to the shutdown command (`S.3R0005`)
+pub(crate) static CMD_RESTORE: &[u8] = b"R";
+
+// Cancels a pending shutdown (and restore) command, turning the UPS output back on
+pub(crate) static CMD_CANCEL_SHUTDOWN: &[u8] = b"C";
+
+// Tests the battery for 10 seconds, then returns to utility
+pub(crate) static CMD_SELF_TEST: &[u8] = b"T";
+
+// Tests the battery until it runs low, then returns to utility
+pub(crate) static CMD_TEST_UNTIL_BATTERY_LOW: &[u8] = b"TL";
+
+// Cancels all running battery tests
+pub(crate) static CMD_CANCEL_TEST: &[u8] = b"CT";
+
+// Toggles the UPS beeper
+pub(crate) static CMD_TOGGLE_BEEPER: &[u8] = b"Q";
+
+/// Start byte of the reply to each query.
+pub(crate) static REPLY_PREFIXES: &[(&[u8], u8)] = &[
+    (CMD_STATUS_INQUIRY, b'('),
+    (CMD_ALARM_INQUIRY, b'('),
+    (CMD_EXTRA_POWER_PARAMETERS_INFO, b'('),
+    (CMD_AUTONOMY, b'('),
+    (CMD_BATTERY_LIFE, b'!'),
+    (CMD_UPS_INFORMATION, b'#'),
+    (CMD_RATING_INFORMATION, b'#'),
+    (CMD_TEST_RESULT, b'#'),
+];
+
+/// Returns the start byte of the reply to `command`, if it's a known query.
+pub(crate) fn reply_prefix(command: &[u8]) -> Option<u8> {
+    REPLY_PREFIXES.iter().find(|(cmd, _)| *cmd == command).map(|&(_, prefix)| prefix)
+}
+
+/// Length of the big-endian number following the start byte in the replies to the autonomy
+/// and battery life queries. Its bytes can have any value, including the end byte.
+pub(crate) const BINARY_REPLY_LEN: usize = 4;
+
+/// Returns the length of the binary payload of the reply to `command`, if it has one.
+pub(crate) fn binary_reply_len(command: &[u8]) -> Option<usize> {
+    [CMD_AUTONOMY, CMD_BATTERY_LIFE].contains(&command).then_some(BINARY_REPLY_LEN)
+}
+
+/// A command which can be sent to the UPS, along with the type of its response.
+///
+/// Besides the commands of the protocol implemented here, this allows sending
+/// vendor-specific commands through [`execute`](crate::device::cplus::CPlusSerialInterface::execute).
+pub trait Command {
+    /// The response the UPS answers the command with.
+    type Response: FromBytes;
+
+    /// Whether the UPS answers the command with a response starting with a start byte.
+    /// Control commands usually aren't answered at all, so their reply (parsed as a whole)
+    /// is only awaited for a short time.
+    const EXPECTS_REPLY: bool = true;
+
+    /// Encodes the command, without the end byte.
+    fn to_bytes(&self) -> Vec<u8>;
+}
+
+/// Implements [`Command`] for a unit struct representing a command without parameters.
+macro_rules! fixed_command {
+    ($(#[$meta:meta])* $name:ident, $bytes:expr, $response:ty, $expects_reply:expr) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct $name;
+
+        impl Command for $name {
+            type Response = $response;
+
+            const EXPECTS_REPLY: bool = $expects_reply;
+
+            fn to_bytes(&self) -> Vec<u8> {
+                $bytes.to_vec()
+            }
+        }
+    };
+}
+
+fixed_command!(
+    /// Queries the UPS status (`Q1`).
+    StatusInquiry, CMD_STATUS_INQUIRY, StatusInquiryResponse, true
+);
+fixed_command!(
+    /// Queries the UPS alarm state (`Q4`).
+    AlarmInquiry, CMD_ALARM_INQUIRY, AlarmInquiryResponse, true
+);
+fixed_command!(
+    /// Queries the extra power parameters (`Q5`).
+    ExtraPowerInfoInquiry, CMD_EXTRA_POWER_PARAMETERS_INFO, ExtraPowerInfoResponse, true
+);
+fixed_command!(
+    /// Queries the autonomy time (`At`).
+    Autonomy, CMD_AUTONOMY, AutonomyResponse, true
+);
+fixed_command!(
+    /// Queries the remaining battery life (`BL`).
+    BatteryLife, CMD_BATTERY_LIFE, BatteryLifeResponse, true
+);
+fixed_command!(
+    /// Queries the manufacturer, model and version of the UPS (`I`).
+    InformationInquiry, CMD_UPS_INFORMATION, UPSInformation, true
+);
+fixed_command!(
+    /// Queries the UPS rating (`F`).
+    RatingInquiry, CMD_RATING_INFORMATION, UPSRating, true
+);
+fixed_command!(
+    /// Queries the result of the last self-test (`TR`).
+    TestResultInquiry, CMD_TEST_RESULT, TestResultResponse, true
+);
+fixed_command!(
+    /// Cancels a pending shutdown and turns the UPS output back on (`C`).
+    CancelShutdown, CMD_CANCEL_SHUTDOWN, CommandAck, false
+);
+fixed_command!(
+    /// Tests the battery for 10 seconds (`T`).
+    SelfTest, CMD_SELF_TEST, CommandAck, false
+);
+fixed_command!(
+    /// Tests the battery until it runs low (`TL`).
+    TestUntilBatteryLow, CMD_TEST_UNTIL_BATTERY_LOW, CommandAck, false
+);
+fixed_command!(
+    /// Cancels all running battery tests (`CT`).
+    CancelTest, CMD_CANCEL_TEST, CommandAck, false
+);
+fixed_command!(
+    /// Toggles the UPS beeper (`Q`).
+    ToggleBeeper, CMD_TOGGLE_BEEPER, CommandAck, false
+);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Tests the battery for a number of minutes (`T<n>`).
+pub struct TimedSelfTest {
+    minutes: u8,
+}
+
+impl TimedSelfTest {
+    /// Creates the command for a test lasting `minutes` (1..=99) minutes.
+    pub fn new(minutes: u8) -> Result<Self> {
+        if !(1..=99).contains(&minutes) {
+            return Err(Error::InvalidParameter(format!(
+                "self-test duration of {minutes} minutes is out of range (1 to 99)"
+            )));
+        }
+
+        Ok(Self { minutes })
+    }
+}
+
+impl Command for TimedSelfTest {
+    type Response = CommandAck;
+
+    const EXPECTS_REPLY: bool = false;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        [CMD_SELF_TEST, format!("{:02}", self.minutes).as_bytes()].concat()
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+/// Programmable output segment (outlet) of the UPS.
+pub enum Outlet {
+    A,
+    B,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Switches a programmable outlet on or off immediately (`pa<n>`, `pb<n>`).
+pub struct OutletSwitch {
+    pub outlet: Outlet,
+    pub on: bool,
+}
+
+impl Command for OutletSwitch {
+    type Response = CommandAck;
+
+    const EXPECTS_REPLY: bool = false;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let head = match self.outlet {
+            Outlet::A => CMD_OUTLET_A_SWITCH,
+            Outlet::B => CMD_OUTLET_B_SWITCH,
+        };
+
+        [head, if self.on { b"1" } else { b"0" }].concat()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Shuts the UPS output off after a delay (`S<n>`).
+pub struct Shutdown {
+    pub delay: ShutdownDelay,
+}
+
+impl Command for Shutdown {
+    type Response = CommandAck;
+
+    const EXPECTS_REPLY: bool = false;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        [CMD_SHUTDOWN, &self.delay.to_bytes()].concat()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Shuts the UPS output off after a delay and restores it after a number of minutes (`S<n>R<m>`).
+pub struct ShutdownAndRestore {
+    delay: ShutdownDelay,
+    restore_minutes: u16,
+}
+
+impl ShutdownAndRestore {
+    /// Creates the command. The restore delay must be within 1..=9999 minutes.
+    pub fn new(delay: ShutdownDelay, restore_minutes: u16) -> Result<Self> {
+        if !(1..=9999).contains(&restore_minutes) {
+            return Err(Error::InvalidParameter(format!(
+                "restore delay of {restore_minutes} minutes is out of range (1 to 9999)"
+            )));
+        }
+
+        Ok(Self { delay, restore_minutes })
+    }
+}
+
+impl Command for ShutdownAndRestore {
+    type Response = CommandAck;
+
+    const EXPECTS_REPLY: bool = false;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        [
+            &Shutdown { delay: self.delay }.to_bytes()[..],
+            CMD_RESTORE,
+            format!("{:04}", self.restore_minutes).as_bytes(),
+        ].concat()
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+/// Reply of the UPS to a control command.
+///
+/// Most UPSes don't reply to control commands at all, while some firmwares reply with `ACK`.
+/// A `NAK` (or `NCK`) reply is turned into [`Error::CommandRejected`].
+pub enum CommandAck {
+    /// The UPS explicitly acknowledged the command.
+    Acknowledged,
+    /// The UPS didn't reply, which is how the protocol accepts control commands.
+    NoReply,
+}
+
+impl FromBytes for CommandAck {
+    type Err = crate::Error;
+
+    fn from_bytes(s: &[u8]) -> Result<Self> {
+        // Some firmwares prefix the reply with the usual start byte
+        let reply = s.strip_prefix(b"(").unwrap_or(s);
+
+        match reply {
+            b"" => Ok(Self::NoReply),
+            b"ACK" => Ok(Self::Acknowledged),
+            b"NAK" | b"NCK" => Err(Error::CommandRejected),
+            _ => Err(Error::UnexpectedResponse {
+                expected: "no reply, ACK or NAK".to_string(),
+                got: String::from_utf8_lossy(s).to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+/// Delay after which the UPS shuts its output off.
+///
+/// The protocol only accepts `.2` to `.9` minutes and whole minutes from `01` to `10`,
+/// so the value can only be constructed through the validating constructors.
+pub struct ShutdownDelay {
+    /// Tenths of a minute, either 2..=9 or a whole minute (10, 20, ..., 100).
+    tenths: u8,
+}
+
+impl ShutdownDelay {
+    /// Creates a sub-minute delay of `tenths` tenths of a minute (2..=9).
+    pub fn from_tenths(tenths: u8) -> Result<Self> {
+        if !(2..=9).contains(&tenths) {
+            return Err(Error::InvalidParameter(format!(
+                "shutdown delay of .{tenths} minutes is out of range (.2 to .9)"
+            )));
+        }
+
+        Ok(Self { tenths })
+    }
+
+    /// Creates a delay of whole `minutes` (1..=10).
+    pub fn from_minutes(minutes: u8) -> Result<Self> {
+        if !(1..=10).contains(&minutes) {
+            return Err(Error::InvalidParameter(format!(
+                "shutdown delay of {minutes} minutes is out of range (1 to 10)"
+            )));
+        }
+
+        Ok(Self { tenths: minutes * 10 })
+    }
+
+    /// The delay as a duration.
+    pub fn as_duration(&self) -> time::Duration {
+        time::Duration::from_secs(self.tenths as u64 * 6)
+    }
+
+    /// Encodes the delay as the UPS expects it, e.g. `.3` or `05`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self.tenths {
+            tenths @ 2..=9 => format!(".{tenths}"),
+            tenths => format!("{:02}", tenths / 10),
+        }.into_bytes()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Builder for the shutdown (and restore) command, which rounds the requested delay
+/// to the nearest delay accepted by the UPS.
+///
+/// ```
+/// use alphamon_rs::model::cplus::{Command as _, ShutdownSchedule};
+///
+/// let schedule = ShutdownSchedule::in_seconds(20)?.restore_after_minutes(30)?;
+///
+/// assert_eq!(schedule.delay().as_duration().as_secs(), 18);
+/// assert_eq!(schedule.to_bytes(), b"S.3R0030");
+/// # Ok::<(), alphamon_rs::Error>(())
+/// ```
+pub struct ShutdownSchedule {
+    delay: ShutdownDelay,
+    restore_minutes: Option<u16>,
+}
+
+impl ShutdownSchedule {
+    /// Schedules the shutdown in `seconds`, rounded to tenths of a minute below one minute
+    /// and to whole minutes above. The rounded delay must be between 12 seconds and 10 minutes.
+    pub fn in_seconds(seconds: u32) -> Result<Self> {
+        let tenths = seconds.saturating_add(3) / 6;
+
+        let delay = match tenths {
+            0..=1 => return Err(Error::InvalidParameter(format!(
+                "shutdown delay of {seconds} s is shorter than the shortest delay (12 s)"
+            ))),
+            2..=9 => ShutdownDelay::from_tenths(tenths as u8)?,
+            _ => {
+                let minutes = seconds.saturating_add(30) / 60;
+
+                if minutes > 10 {
+                    return Err(Error::InvalidParameter(format!(
+                        "shutdown delay of {seconds} s is longer than the longest delay (10 min)"
+                    )));
+                }
+
+                ShutdownDelay::from_minutes(minutes as u8)?
+            }
+        };
+
+        Ok(Self { delay, restore_minutes: None })
+    }
+
+    /// Schedules the shutdown in `minutes` (1..=10).
+    pub fn in_minutes(minutes: u8) -> Result<Self> {
+        Ok(Self {
+            delay: ShutdownDelay::from_minutes(minutes)?,
+            restore_minutes: None,
+        })
+    }
+
+    /// Restores the UPS output `minutes` (1..=9999) minutes after the shutdown.
+    pub fn restore_after_minutes(self, minutes: u16) -> Result<Self> {
+        ShutdownAndRestore::new(self.delay, minutes)?;
+
+        Ok(Self { restore_minutes: Some(minutes), ..self })
+    }
+
+    /// The delay which will actually be sent to the UPS.
+    pub fn delay(&self) -> ShutdownDelay {
+        self.delay
+    }
+
+    /// The restore delay in minutes, if the output should be restored.
+    pub fn restore_minutes(&self) -> Option<u16> {
+        self.restore_minutes
+    }
+}
+
+impl Command for ShutdownSchedule {
+    type Response = CommandAck;
+
+    const EXPECTS_REPLY: bool = false;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self.restore_minutes {
+            Some(restore_minutes) => ShutdownAndRestore { delay: self.delay, restore_minutes }.to_bytes(),
+            None => Shutdown { delay: self.delay }.to_bytes(),
+        }
+    }
+}
+
+/// Battery capacity (%) by the battery capacity parameter (V) reported in offline mode,
+/// sorted by the voltage.
+const OFFLINE_CAPACITY_CURVE: &[(f32, u32)] = &[
+    (10.0, 0), (10.1, 2), (10.2, 5), (10.3, 8), (10.4, 11), (10.5, 13),
+    (10.6, 16), (10.7, 19), (10.8, 22), (10.9, 24), (11.0, 27), (11.1, 30),
+    (11.2, 33), (11.3, 36), (11.4, 38), (11.5, 41), (11.6, 44), (11.7, 47),
+    (11.8, 49), (11.9, 52), (12.0, 55), (12.1, 58), (12.2, 61), (12.3, 63),
+    (12.4, 66), (12.5, 69), (12.6, 72), (12.7, 74), (12.8, 77), (12.9, 80),
+    (13.0, 83), (13.1, 86), (13.2, 88), (13.3, 90), (13.5, 100),
+];
+
+/// Battery capacity (%) by the battery capacity parameter (V per cell) reported in online mode,
+/// sorted by the voltage.
+const ONLINE_CAPACITY_CURVE: &[(f32, u32)] = &[
+    (1.67, 0), (1.68, 2), (1.69, 3), (1.70, 5), (1.71, 7), (1.72, 8),
+    (1.73, 10), (1.74, 12), (1.75, 13), (1.76, 15), (1.77, 17), (1.78, 18),
+    (1.79, 20), (1.80, 22), (1.81, 23), (1.82, 25), (1.83, 27), (1.84, 28),
+    (1.85, 30), (1.86, 32), (1.87, 33), (1.88, 35), (1.89, 37), (1.90, 38),
+    (1.91, 40), (1.92, 42), (1.93, 43), (1.94, 45), (1.95, 47), (1.96, 48),
+    (1.97, 50), (1.98, 52), (1.99, 53), (2.00, 55), (2.01, 55), (2.02, 58),
+    (2.03, 58), (2.04, 62), (2.05, 62), (2.06, 65), (2.07, 65), (2.08, 68),
+    (2.09, 70), (2.10, 72), (2.11, 73), (2.12, 75), (2.13, 77), (2.14, 78),
+    (2.15, 80), (2.16, 82), (2.17, 83), (2.18, 85), (2.19, 87), (2.20, 88),
+    (2.21, 90), (2.22, 100),
+];
+
+/// Battery capacity at `voltage`, interpolated linearly between the nearest points of `curve`
+/// and clamped to its ends. `None` for a voltage more than 10 % off the ends of the curve,
+/// which can't be a reading of the battery.
+fn interpolate_capacity(curve: &[(f32, u32)], voltage: f32) -> Option<u32> {
+    let (&(first, _), &(last, _)) = (curve.first()?, curve.last()?);
+
+    if !(first * 0.9..=last * 1.1).contains(&voltage) {
+        return None;
+    }
+
+    let upper = curve.partition_point(|&(point, _)| point < voltage);
+
+    match (upper.checked_sub(1).and_then(|lower| curve.get(lower)), curve.get(upper)) {
+        (Some(&(v0, c0)), Some(&(v1, c1))) => {
+            let t = (voltage - v0) / (v1 - v0);
+
+            Some((c0 as f32 + t * (c1 as f32 - c0 as f32)).round() as u32)
+        }
+        (None, Some(&(_, capacity))) | (Some(&(_, capacity)), None) => Some(capacity),
+        (None, None) => None,
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
 /// Response containing the UPS status info, such as the input/output voltage, 
 /// load percentage, battery capacity, etc.
 pub struct StatusInquiryResponse {
@@ -36,20 +499,75 @@ pub struct StatusInquiryResponse {
     pub output_load_percentage: u32,
     /// Hz
     pub input_frequency: f32,
-    /// %
-    pub battery_capacity: u32,
+    /// %, `None` if the battery capacity parameter isn't a voltage on the battery curve of
+    /// the UPS (online or offline), see `battery_capacity_parameter` for the raw value.
+    pub battery_capacity: Option<u32>,
     /// N/A
     pub battery_capacity_parameter: String,
     /// °C
     pub temperature: f32,
     /// Specific information about the UPS status, such as beeper state, alarm state, battery warning, etc.
-    pub ups_status: UPSStatus
+    pub ups_status: UPSStatus,
+    /// The reply as received, without the start and end bytes, for debugging.
+    /// Not serialized.
+    #[serde(skip)]
+    pub raw: Vec<u8>,
+}
+
+impl StatusInquiryResponse {
+    /// Estimates the apparent power (VA) of the load from the load percentage of the rated
+    /// output (voltage times current) of `rating`, for when the extra power info isn't available.
+    pub fn load_va_from_rating(&self, rating: &UPSRating) -> f32 {
+        let rated_va = rating.output_rating_voltage * rating.output_rating_current as f32;
+
+        rated_va * self.output_load_percentage as f32 / 100.0
+    }
+
+    /// Recomputes `battery_capacity` for the battery pack `config`, reading a parameter
+    /// above the range of a single 12 V block in offline mode as the voltage of the whole pack.
+    /// The per-cell parameter of the online mode doesn't depend on the pack.
+    pub fn apply_battery_configuration(&mut self, config: &BatteryConfiguration) {
+        self.battery_capacity = battery_capacity(&self.battery_capacity_parameter, self.ups_status.offline(), Some(config));
+    }
+
+    /// Applies the battery pack `config` if it's known, warning if the battery capacity
+    /// is unknown even so. The interfaces call this for every status they read.
+    pub(crate) fn resolve_battery_capacity(&mut self, config: Option<&BatteryConfiguration>) {
+        if let Some(config) = config {
+            self.apply_battery_configuration(config);
+        }
+
+        if self.battery_capacity.is_none() {
+            warn!("Unrecognized battery capacity parameter {:?}", self.battery_capacity_parameter);
+        }
+    }
+}
+
+/// Battery capacity for the battery capacity parameter, see [`StatusInquiryResponse::apply_battery_configuration`].
+fn battery_capacity(parameter: &str, offline: bool, config: Option<&BatteryConfiguration>) -> Option<u32> {
+    let voltage = parameter.parse::<f32>().ok()?;
+
+    if !offline {
+        return interpolate_capacity(ONLINE_CAPACITY_CURVE, voltage);
+    }
+
+    interpolate_capacity(OFFLINE_CAPACITY_CURVE, voltage).or_else(|| {
+        let blocks = config.filter(|config| config.blocks > 1)?.blocks;
+
+        interpolate_capacity(OFFLINE_CAPACITY_CURVE, voltage / blocks as f32)
+    })
 }
 
 impl FromBytes for StatusInquiryResponse {
     type Err = crate::Error;
 
     fn from_bytes(s: &[u8]) -> Result<Self> {
+        let fields = s
+            .split(|b| b.is_ascii_whitespace())
+            .filter(|x| !x.is_empty())
+            .map(|x| String::from_utf8_lossy(x))
+            .collect::<Vec<_>>();
+
         let [
             input_voltage,
             input_fault_voltage, 
@@ -59,112 +577,14 @@ impl FromBytes for StatusInquiryResponse {
             battery_capacity_parameter,
             temperature,
             ups_status
-        ] = &s
-            .split(|b| *b == b' ')
-            .map(|x| String::from_utf8_lossy(x))
-            .collect::<Vec<_>>()[..] else { return Err(Error::InvalidFormat) };
+        ] = &fields[..] else {
+            return Err(Error::invalid_format("StatusInquiryResponse", s, format!("expected 8 fields, got {}", fields.len())));
+        };
 
         let ups_status = UPSStatus::from_bytes(ups_status.as_bytes())?;
 
-        let battery_capacity = match ups_status.offline {
-            true => match battery_capacity_parameter as &str {
-                "13.5" => 100,
-                "13.3" => 90,
-                "13.2" => 88,
-                "13.1" => 86,
-                "13" => 83,
-                "12.9" => 80,
-                "12.8" => 77,
-                "12.7" => 74,
-                "12.6" => 72,
-                "12.5" => 69,
-                "12.4" => 66,
-                "12.3" => 63,
-                "12.2" => 61,
-                "12.1" => 58,
-                "12" => 55,
-                "11.9" => 52,
-                "11.8" => 49,
-                "11.7" => 47,
-                "11.6" => 44,
-                "11.5" => 41,
-                "11.4" => 38,
-                "11.3" => 36,
-                "11.2" => 33,
-                "11.1" => 30,
-                "11" => 27,
-                "10.9" => 24,
-                "10.8" => 22,
-                "10.7" => 19,
-                "10.6" => 16,
-                "10.5" => 13,
-                "10.4" => 11,
-                "10.3" => 8,
-                "10.2" => 5,
-                "10.1" => 2,
-                "10" => 0,
-                _ => return Err(Error::InvalidBatteryCapacityParameter),
-            },
-            false => match battery_capacity_parameter as &str {
-                "2.22" => 100,
-                "2.21" => 90,
-                "2.20" => 88,
-                "2.19" => 87,
-                "2.18" => 85,
-                "2.17" => 83,
-                "2.16" => 82,
-                "2.15" => 80,
-                "2.14" => 78,
-                "2.13" => 77,
-                "2.12" => 75,
-                "2.11" => 73,
-                "2.10" => 72,
-                "2.09" => 70,
-                "2.08" => 68,
-                "2.07" => 65,
-                "2.06" => 65,
-                "2.05" => 62,
-                "2.04" => 62,
-                "2.03" => 58,
-                "2.02" => 58,
-                "2.01" => 55,
-                "2.00" => 55,
-                "1.99" => 53,
-                "1.98" => 52,
-                "1.97" => 50,
-                "1.96" => 48,
-                "1.95" => 47,
-                "1.94" => 45,
-                "1.93" => 43,
-                "1.92" => 42,
-                "1.91" => 40,
-                "1.90" => 38,
-                "1.89" => 37,
-                "1.88" => 35,
-                "1.87" => 33,
-                "1.86" => 32,
-                "1.85" => 30,
-                "1.84" => 28,
-                "1.83" => 27,
-                "1.82" => 25,
-                "1.81" => 23,
-                "1.80" => 22,
-                "1.79" => 20,
-                "1.78" => 18,
-                "1.77" => 17,
-                "1.76" => 15,
-                "1.75" => 13,
-                "1.74" => 12,
-                "1.73" => 10,
-                "1.72" => 8,
-                "1.71" => 7,
-                "1.70" => 5,
-                "1.69" => 3,
-                "1.68" => 2,
-                "1.67" => 0,
-                _ => return Err(Error::InvalidBatteryCapacityParameter),
-            }    
-        };
+        // Without the battery pack, see `resolve_battery_capacity`
+        let battery_capacity = battery_capacity(battery_capacity_parameter, ups_status.offline(), None);
 
         Ok(Self {
             input_voltage: input_voltage.parse()?,
@@ -175,58 +595,300 @@ impl FromBytes for StatusInquiryResponse {
             battery_capacity_parameter: battery_capacity_parameter.to_string(),
             battery_capacity,
             temperature: temperature.parse()?,
-            ups_status
+            ups_status,
+            raw: s.to_vec(),
         })
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
+impl ToBytes for StatusInquiryResponse {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = format!(
+            "{:05.1} {:05.1} {:05.1} {:03} {:04.1} {} {:04.1} ",
+            self.input_voltage,
+            self.input_fault_voltage,
+            self.output_voltage,
+            self.output_load_percentage,
+            self.input_frequency,
+            self.battery_capacity_parameter,
+            self.temperature,
+        ).into_bytes();
+
+        bytes.extend(self.ups_status.to_bytes());
+
+        bytes
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(from = "UPSStatusFlags", into = "UPSStatusFlags")]
 /// Contains specific information about the UPS status, such as beeper state, alarm state, battery warning, etc.
+///
+/// The flags are the bits of the status in the order sent by the UPS, the first one being the
+/// most significant, see [`UPSStatus::raw`]. They combine like sets, e.g. `prev ^ next` are the
+/// flags which changed.
 pub struct UPSStatus {
-    pub utility_fail: bool,
-    pub battery_low: bool,
+    bits: u8,
+}
+
+impl UPSStatus {
+    pub const UTILITY_FAIL: Self = Self::from_raw(1 << 7);
+    pub const BATTERY_LOW: Self = Self::from_raw(1 << 6);
+    /// See [`UPSStatus::bypass_or_transformer_active`].
+    pub const BYPASS_OR_TRANSFORMER_ACTIVE: Self = Self::from_raw(1 << 5);
+    pub const BATTERY_ABNORMAL: Self = Self::from_raw(1 << 4);
+    pub const OFFLINE: Self = Self::from_raw(1 << 3);
+    pub const TEST_IN_PROGRESS: Self = Self::from_raw(1 << 2);
+    pub const SHUTDOWN_ACTIVE: Self = Self::from_raw(1 << 1);
+    pub const BEEPER_ON: Self = Self::from_raw(1);
+
+    /// The flags with their names, from the most significant bit.
+    const NAMED: [(Self, &'static str); 8] = [
+        (Self::UTILITY_FAIL, "UTILITY_FAIL"),
+        (Self::BATTERY_LOW, "BATTERY_LOW"),
+        (Self::BYPASS_OR_TRANSFORMER_ACTIVE, "BYPASS_OR_TRANSFORMER_ACTIVE"),
+        (Self::BATTERY_ABNORMAL, "BATTERY_ABNORMAL"),
+        (Self::OFFLINE, "OFFLINE"),
+        (Self::TEST_IN_PROGRESS, "TEST_IN_PROGRESS"),
+        (Self::SHUTDOWN_ACTIVE, "SHUTDOWN_ACTIVE"),
+        (Self::BEEPER_ON, "BEEPER_ON"),
+    ];
+
+    /// Creates the status from its bits, see [`UPSStatus::raw`].
+    pub const fn from_raw(bits: u8) -> Self {
+        Self { bits }
+    }
+
+    /// Returns the bits of the status, the first one sent by the UPS being the most significant.
+    pub const fn raw(&self) -> u8 {
+        self.bits
+    }
+
+    /// Returns the status with no flags set.
+    pub const fn empty() -> Self {
+        Self::from_raw(0)
+    }
+
+    /// Whether all the flags of `other` are set.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.bits & other.bits == other.bits
+    }
+
+    /// Whether any of the flags of `other` is set.
+    pub const fn intersects(&self, other: Self) -> bool {
+        self.bits & other.bits != 0
+    }
+
+    /// Sets the flags of `other`.
+    pub fn insert(&mut self, other: Self) {
+        self.bits |= other.bits;
+    }
+
+    /// Clears the flags of `other`.
+    pub fn remove(&mut self, other: Self) {
+        self.bits &= !other.bits;
+    }
+
+    /// Flips the flags of `other`.
+    pub fn toggle(&mut self, other: Self) {
+        self.bits ^= other.bits;
+    }
+
+    /// Sets or clears the flags of `other`.
+    pub fn set(&mut self, other: Self, value: bool) {
+        if value {
+            self.insert(other);
+        } else {
+            self.remove(other);
+        }
+    }
+
+    pub fn utility_fail(&self) -> bool {
+        self.contains(Self::UTILITY_FAIL)
+    }
+
+    pub fn battery_low(&self) -> bool {
+        self.contains(Self::BATTERY_LOW)
+    }
+
     /// If the UPS is in offline mode, the value signifies if the boost/buck converter is active,
     /// if the UPS is in online mode, it signifies if UPS is in bypass mode.
-    pub bypass_or_transformer_active: bool,
-    pub battery_abnormal: bool,
-    pub offline: bool,
-    pub test_in_progress: bool,
-    pub shutdown_active: bool,
-    pub beeper_on: bool
+    pub fn bypass_or_transformer_active(&self) -> bool {
+        self.contains(Self::BYPASS_OR_TRANSFORMER_ACTIVE)
+    }
+
+    pub fn battery_abnormal(&self) -> bool {
+        self.contains(Self::BATTERY_ABNORMAL)
+    }
+
+    pub fn offline(&self) -> bool {
+        self.contains(Self::OFFLINE)
+    }
+
+    pub fn test_in_progress(&self) -> bool {
+        self.contains(Self::TEST_IN_PROGRESS)
+    }
+
+    pub fn shutdown_active(&self) -> bool {
+        self.contains(Self::SHUTDOWN_ACTIVE)
+    }
+
+    pub fn beeper_on(&self) -> bool {
+        self.contains(Self::BEEPER_ON)
+    }
+
+    /// Returns the names of the set flags.
+    fn names(&self) -> impl Iterator<Item = &'static str> {
+        Self::NAMED.into_iter().filter(|(flag, _)| self.contains(*flag)).map(|(_, name)| name)
+    }
+}
+
+impl std::ops::BitOr for UPSStatus {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self::from_raw(self.bits | other.bits)
+    }
+}
+
+impl std::ops::BitAnd for UPSStatus {
+    type Output = Self;
+
+    fn bitand(self, other: Self) -> Self {
+        Self::from_raw(self.bits & other.bits)
+    }
+}
+
+impl std::ops::BitXor for UPSStatus {
+    type Output = Self;
+
+    fn bitxor(self, other: Self) -> Self {
+        Self::from_raw(self.bits ^ other.bits)
+    }
+}
+
+impl std::ops::Not for UPSStatus {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self::from_raw(!self.bits)
+    }
+}
+
+/// Renders the set flags by name, e.g. `UTILITY_FAIL | BEEPER_ON`, or `(empty)`.
+impl std::fmt::Display for UPSStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.names().collect::<Vec<_>>().join(" | ") {
+            names if names.is_empty() => write!(f, "(empty)"),
+            names => write!(f, "{names}"),
+        }
+    }
+}
+
+impl std::fmt::Debug for UPSStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "UPSStatus({self})")
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "UPSStatus")]
+/// (De)serialized form of [`UPSStatus`], an object of the named flags.
+/// See [`ups_status_bits`] for the compact form.
+struct UPSStatusFlags {
+    utility_fail: bool,
+    battery_low: bool,
+    bypass_or_transformer_active: bool,
+    battery_abnormal: bool,
+    offline: bool,
+    test_in_progress: bool,
+    shutdown_active: bool,
+    beeper_on: bool,
+}
+
+impl From<UPSStatus> for UPSStatusFlags {
+    fn from(status: UPSStatus) -> Self {
+        Self {
+            utility_fail: status.utility_fail(),
+            battery_low: status.battery_low(),
+            bypass_or_transformer_active: status.bypass_or_transformer_active(),
+            battery_abnormal: status.battery_abnormal(),
+            offline: status.offline(),
+            test_in_progress: status.test_in_progress(),
+            shutdown_active: status.shutdown_active(),
+            beeper_on: status.beeper_on(),
+        }
+    }
+}
+
+impl From<UPSStatusFlags> for UPSStatus {
+    fn from(flags: UPSStatusFlags) -> Self {
+        [
+            flags.utility_fail,
+            flags.battery_low,
+            flags.bypass_or_transformer_active,
+            flags.battery_abnormal,
+            flags.offline,
+            flags.test_in_progress,
+            flags.shutdown_active,
+            flags.beeper_on,
+        ]
+        .into_iter()
+        .zip(Self::NAMED)
+        .filter(|(set, _)| *set)
+        .fold(Self::empty(), |status, (_, (flag, _))| status | flag)
+    }
+}
+
+/// Compact serialization of [`UPSStatus`] as its raw bits, for
+/// `#[serde(with = "alphamon_rs::model::cplus::ups_status_bits")]`.
+pub mod ups_status_bits {
+    use super::UPSStatus;
+    use serde::Deserialize;
+
+    /// Serializes the status as its raw bits, see [`UPSStatus::raw`].
+    pub fn serialize<S: serde::Serializer>(status: &UPSStatus, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(status.raw())
+    }
+
+    /// Deserializes the status from its raw bits, see [`UPSStatus::from_raw`].
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<UPSStatus, D::Error> {
+        u8::deserialize(deserializer).map(UPSStatus::from_raw)
+    }
 }
 
 impl FromBytes for UPSStatus {
     type Err = crate::Error;
 
-    fn from_bytes(s: &[u8]) -> Result<Self> {
-        let [
-            utility_fail,
-            battery_low,
-            bypass_or_transformer_active,
-            battery_abnormal,
-            offline,
-            test_in_progress,
-            shutdown_active,
-            beeper_on
-        ] = s
-            .iter()
-            .map(|x| *x == b'1')
-            .collect::<Vec<bool>>()[..] else { return Err(Error::InvalidFormat) };
+    fn from_bytes(mut s: &[u8]) -> Result<Self> {
+        // Some firmwares pad the bits with a CR or a space
+        while let [rest @ .., b'\r' | b' '] = s {
+            s = rest;
+        }
 
-        Ok(Self {
-            utility_fail,
-            battery_low,
-            bypass_or_transformer_active,
-            battery_abnormal,
-            offline,
-            test_in_progress,
-            shutdown_active,
-            beeper_on,
+        if s.len() != 8 {
+            return Err(Error::invalid_format("UPSStatus", s, format!("expected 8 bits, got {}", s.len())));
+        }
+
+        s.iter().enumerate().try_fold(Self::empty(), |status, (position, &byte)| {
+            let bit = match byte {
+                b'0' => 0,
+                b'1' => 1,
+                _ => return Err(Error::InvalidStatusBit { position, byte }),
+            };
+
+            Ok(Self::from_raw((status.bits << 1) | bit))
         })
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
+impl ToBytes for UPSStatus {
+    fn to_bytes(&self) -> Vec<u8> {
+        format!("{:08b}", self.bits).into_bytes()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
 /// Response for the alarm inquiry command. 
 /// Specifies the state of the inverter and the UPS alarm.
 pub struct AlarmInquiryResponse {
@@ -243,10 +905,12 @@ impl FromBytes for AlarmInquiryResponse {
             ups_alarm_on
         ] = s
             .get(0..2)
-            .ok_or(Error::InvalidFormat)?
+            .unwrap_or_default()
             .iter()
             .map(|x| *x == b'1')
-            .collect::<Vec<bool>>()[..] else { return Err(Error::InvalidFormat) };
+            .collect::<Vec<bool>>()[..] else {
+            return Err(Error::invalid_format("AlarmInquiryResponse", s, format!("expected 2 bits, got {}", s.len())));
+        };
 
         Ok(Self {
             inverter_on,
@@ -254,8 +918,17 @@ impl FromBytes for AlarmInquiryResponse {
         })
     }
 }
+
+impl ToBytes for AlarmInquiryResponse {
+    fn to_bytes(&self) -> Vec<u8> {
+        [self.inverter_on, self.ups_alarm_on]
+            .iter()
+            .map(|&bit| if bit { b'1' } else { b'0' })
+            .collect()
+    }
+}
     
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
 /// Contains additional status info about the UPS, such as the UPS output frequency, 
 /// battery voltage, load in watts, etc.
 pub struct ExtraPowerInfoResponse {
@@ -267,20 +940,43 @@ pub struct ExtraPowerInfoResponse {
     pub battery_cut_voltage: f32,
     /// W
     pub ups_wattage: u32,
-    /// Undocumented error code.
+    /// Error code, undocumented by the protocol, see [`ExtraPowerInfoResponse::decoded_error`].
     pub error_code: u16,
     /// A
-    pub load_current: f32
+    pub load_current: f32,
+    /// A, read from the reserved word 2 where the UPS reports it (seen on AOEG 3 kVA units).
+    /// `None` if the word is zero or out of the plausible range.
+    pub charger_current: Option<f32>,
+    /// °C, read from the reserved word 3 where the UPS reports it (seen on AOEG 3 kVA units).
+    /// `None` if the word is zero or out of the plausible range.
+    pub heatsink_temperature: Option<f32>,
+    /// The ten words of the reply as received, including the reserved ones.
+    pub raw_words: [u16; 10],
 }
 
+/// Highest plausible charger current, in the 0.1 A of the reply.
+const MAX_CHARGER_CURRENT: u16 = 500;
+
+/// Highest plausible heatsink temperature, in the 0.1 °C of the reply.
+const MAX_HEATSINK_TEMPERATURE: u16 = 1500;
+
 impl FromBytes for ExtraPowerInfoResponse {
     type Err = crate::Error;
 
     fn from_bytes(s: &[u8]) -> Result<Self> {
+        let words = s
+            .chunks(2)
+            .map(|s| s.try_into().map(u16::from_be_bytes).map_err(|e| e.into()))
+            .collect::<Result<Vec<u16>>>()?;
+
+        let raw_words = <[u16; 10]>::try_from(words).map_err(|words| {
+            Error::invalid_format("ExtraPowerInfoResponse", s, format!("expected 10 words, got {}", words.len()))
+        })?;
+
         let [
-            fout, 
-            _,
-            _,
+            fout,
+            charger,
+            heatsink,
             vb,
             vbc,
             inv_w,
@@ -288,91 +984,284 @@ impl FromBytes for ExtraPowerInfoResponse {
             o_cur,
             _,
             _,
-        ] = &s
-            .chunks(2)
-            .map(|s| s.try_into().map(u16::from_be_bytes).map(f32::from).map_err(|e| e.into()))
-            .collect::<Result<Vec<f32>>>()?[..] else { return Err(Error::InvalidFormat) };
+        ] = raw_words;
+
+        let plausible = |word: u16, max: u16| (1..=max).contains(&word).then(|| f32::from(word) * 0.1);
 
         Ok(Self {
-            ups_output_freq: fout * 0.1,
-            battery_voltage: vb * 0.01,
-            battery_cut_voltage: vbc * 0.01,
-            ups_wattage: *inv_w as u32,
-            error_code: *ercode as u16,
-            load_current: o_cur * 0.1,
+            ups_output_freq: f32::from(fout) * 0.1,
+            battery_voltage: f32::from(vb) * 0.01,
+            battery_cut_voltage: f32::from(vbc) * 0.01,
+            ups_wattage: u32::from(inv_w),
+            error_code: ercode,
+            load_current: f32::from(o_cur) * 0.1,
+            charger_current: plausible(charger, MAX_CHARGER_CURRENT),
+            heatsink_temperature: plausible(heatsink, MAX_HEATSINK_TEMPERATURE),
+            raw_words,
         })
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
+/// Load currents below this (A) are read as no current, the reply being in 0.1 A.
+const MIN_LOAD_CURRENT: f32 = 0.05;
+
+impl ExtraPowerInfoResponse {
+    /// Returns the apparent power (VA) of the load at `output_voltage` (V, see
+    /// [`StatusInquiryResponse::output_voltage`]). `None` without any voltage or current.
+    pub fn apparent_power_va(&self, output_voltage: f32) -> Option<f32> {
+        (output_voltage > 0.0 && self.load_current >= MIN_LOAD_CURRENT).then_some(output_voltage * self.load_current)
+    }
+
+    /// Returns the power factor of the load at `output_voltage`, the wattage divided by the
+    /// apparent power. Clamped to 1 for a wattage above the apparent power because of the
+    /// rounding of the reply, `None` without any voltage or current.
+    pub fn power_factor(&self, output_voltage: f32) -> Option<f32> {
+        self.apparent_power_va(output_voltage)
+            .map(|va| (self.ups_wattage as f32 / va).clamp(0.0, 1.0))
+    }
+
+    /// Returns the error code as a [`UpsErrorCode`].
+    pub fn decoded_error(&self) -> UpsErrorCode {
+        UpsErrorCode::from(self.error_code)
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+/// Error reported in the extra power info.
+///
+/// The codes aren't documented by the protocol. Only zero, sent by a UPS without a fault,
+/// is known, the others are kept as [`UpsErrorCode::Unknown`] until their meaning is confirmed.
+#[non_exhaustive]
+pub enum UpsErrorCode {
+    NoError,
+    Unknown(u16),
+}
+
+impl UpsErrorCode {
+    /// Returns the raw error code.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::NoError => 0,
+            Self::Unknown(code) => *code,
+        }
+    }
+}
+
+impl From<u16> for UpsErrorCode {
+    fn from(code: u16) -> Self {
+        match code {
+            0 => Self::NoError,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
+impl std::fmt::Display for UpsErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoError => write!(f, "No error"),
+            Self::Unknown(code) => write!(f, "Unknown error ({code})"),
+        }
+    }
+}
+
+impl ToBytes for ExtraPowerInfoResponse {
+    fn to_bytes(&self) -> Vec<u8> {
+        let [_, charger, heatsink, _, _, _, _, _, reserved_9, reserved_10] = self.raw_words;
+        let tenths = |value: Option<f32>, raw: u16| value.map_or(raw, |value| (value * 10.0).round() as u16);
+
+        [
+            (self.ups_output_freq * 10.0).round() as u16,
+            tenths(self.charger_current, charger),
+            tenths(self.heatsink_temperature, heatsink),
+            (self.battery_voltage * 100.0).round() as u16,
+            (self.battery_cut_voltage * 100.0).round() as u16,
+            self.ups_wattage as u16,
+            self.error_code,
+            (self.load_current * 10.0).round() as u16,
+            reserved_9,
+            reserved_10,
+        ]
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect()
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+/// How the number in the reply to the autonomy or battery life query is encoded.
+pub enum NumberEncoding {
+    /// A big-endian 32-bit word, as documented.
+    #[default]
+    Binary,
+    /// ASCII decimal digits, sent by some firmware revisions.
+    Ascii,
+}
+
+impl NumberEncoding {
+    /// Detects the encoding of `s`: ASCII if it consists of digits only, binary otherwise.
+    ///
+    /// Four digits are a valid binary word too, but one of at least `0x30303030`, decades
+    /// of autonomy or battery life.
+    pub fn detect(s: &[u8]) -> Self {
+        if !s.is_empty() && s.iter().all(u8::is_ascii_digit) { Self::Ascii } else { Self::Binary }
+    }
+
+    fn parse(self, s: &[u8]) -> Result<u32> {
+        match self {
+            Self::Binary => Ok(u32::from_be_bytes(s.try_into()?)),
+            Self::Ascii => Ok(String::from_utf8_lossy(s).parse()?),
+        }
+    }
+
+    fn encode(self, number: u32) -> Vec<u8> {
+        match self {
+            Self::Binary => number.to_be_bytes().to_vec(),
+            Self::Ascii => number.to_string().into_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
 /// Contains the expected UPS runtime if power were to fail.
 /// 
 /// Note: some UPSes of this series tested do not return this message.
 pub struct AutonomyResponse {
     pub time: time::Duration,
+    /// How the UPS encoded the time, for debugging.
+    #[serde(skip)]
+    pub encoding: NumberEncoding,
 }
 
 impl FromBytes for AutonomyResponse {
     type Err = crate::Error;
 
     fn from_bytes(s: &[u8]) -> Result<Self> {    
+        let encoding = NumberEncoding::detect(s);
         let time = time::Duration::from_secs(
-            u32::from_be_bytes(s.try_into()?) as u64
+            encoding.parse(s)? as u64
         );
 
         Ok(Self {
-            time
+            time,
+            encoding,
         })
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
+impl ToBytes for AutonomyResponse {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.encoding.encode(self.time.as_secs() as u32)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
 /// Contains the expected longevity of the UPS battery.
 /// 
 /// Note: some UPSes of this series tested do not return this message.
 pub struct BatteryLifeResponse {
     pub time: time::Duration,
+    /// How the UPS encoded the time, for debugging.
+    #[serde(skip)]
+    pub encoding: NumberEncoding,
 }
 
 impl FromBytes for BatteryLifeResponse {
     type Err = crate::Error;
 
     fn from_bytes(s: &[u8]) -> Result<Self> {
+        let encoding = NumberEncoding::detect(s);
         let time = time::Duration::from_secs(
-            u32::from_be_bytes(s.try_into()?) as u64 * 60 * 60
+            encoding.parse(s)? as u64 * 60 * 60
         );
 
         Ok(Self {
-            time
+            time,
+            encoding,
         })
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
+impl ToBytes for BatteryLifeResponse {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.encoding.encode((self.time.as_secs() / 60 / 60) as u32)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
 /// Contains manufacturer information about the UPS, such as the manufacturer, the model and the revision.
 pub struct UPSInformation {
     pub manufacturer_name: String,
     pub model: String,
-    pub version: String
+    pub version: String,
+    /// The reply as received, without the start and end bytes, for debugging.
+    /// Not serialized.
+    #[serde(skip)]
+    pub raw: Vec<u8>,
 }
 
 impl FromBytes for UPSInformation {
     type Err = crate::Error;
 
     fn from_bytes(s: &[u8]) -> Result<Self> {
-        let (mfg_name, s) = s.split_at(15);
-        let (model, s) = s.split_at(10);
-        let (version, _) = s.split_at(10);
+        // Anything after the version is ignored
+        let (Some(mfg_name), Some(model), Some(version)) = (s.get(..15), s.get(15..25), s.get(25..35)) else {
+            return Err(Error::InvalidLength { expected: 35, received: s.len() });
+        };
 
         Ok(Self {
             manufacturer_name: String::from_utf8_lossy(mfg_name).trim().to_string(),
             model: String::from_utf8_lossy(model).trim().to_string(),
-            version: String::from_utf8_lossy(version).trim().to_string()
+            version: String::from_utf8_lossy(version).trim().to_string(),
+            raw: s.to_vec(),
         })
     }
 }
 
-#[derive(Debug, Serialize, Clone)]
+impl ToBytes for UPSInformation {
+    fn to_bytes(&self) -> Vec<u8> {
+        format!("{:<15.15}{:<10.10}{:<10.10}", self.manufacturer_name, self.model, self.version).into_bytes()
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+/// Contains the firmware version of the UPS.
+///
+/// The version formats differ between models (e.g. `V1.02` or `Ver 02.16`),
+/// so the major and minor numbers are only provided when the format is recognized.
+pub struct FirmwareVersion {
+    pub raw: String,
+    pub major: Option<u16>,
+    pub minor: Option<u16>,
+}
+
+impl FromBytes for FirmwareVersion {
+    type Err = crate::Error;
+
+    fn from_bytes(s: &[u8]) -> Result<Self> {
+        let raw = String::from_utf8_lossy(s).trim().to_string();
+
+        // Skips a textual prefix, such as "V" or "Ver "
+        let numbers = raw.trim_start_matches(|c: char| !c.is_ascii_digit());
+
+        let version = numbers
+            .split_once('.')
+            .and_then(|(major, rest)| {
+                let minor_len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+                let minor = rest.get(..minor_len)?;
+
+                Some((major.parse().ok()?, minor.parse().ok()?))
+            });
+
+        Ok(Self {
+            raw,
+            major: version.map(|(major, _)| major),
+            minor: version.map(|(_, minor)| minor),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
 /// Contains the rated UPS information (such as the output rated voltage/current, etc.)
 pub struct UPSRating {
     /// V
@@ -382,28 +1271,631 @@ pub struct UPSRating {
     /// V
     pub battery_voltage: f32,
     /// Hz
-    pub output_rating_frequency: f32
+    pub output_rating_frequency: f32,
+    /// The reply as received, without the start and end bytes, for debugging.
+    /// Not serialized.
+    #[serde(skip)]
+    pub raw: Vec<u8>,
 }
 
 impl FromBytes for UPSRating {
     type Err = crate::Error;
 
     fn from_bytes(s: &[u8]) -> Result<Self> {
+        let fields = s
+            .split(|byte| *byte == b' ')
+            .map(|bytes| String::from_utf8_lossy(bytes))
+            .collect::<Vec<_>>();
+
         let [
             output_rating_voltage,
             output_rating_current,
             battery_voltage,
             output_rating_frequency
-        ] = &s
-            .split(|byte| *byte == b' ')
-            .map(|bytes| String::from_utf8_lossy(bytes))
-            .collect::<Vec<_>>()[..] else { return Err(Error::InvalidFormat) };
+        ] = &fields[..] else {
+            return Err(Error::invalid_format("UPSRating", s, format!("expected 4 fields, got {}", fields.len())));
+        };
 
         Ok(Self {
             output_rating_voltage: output_rating_voltage.parse()?,
             output_rating_current: output_rating_current.parse()?,
             battery_voltage: battery_voltage.parse()?,
-            output_rating_frequency: output_rating_frequency.parse()?
+            output_rating_frequency: output_rating_frequency.parse()?,
+            raw: s.to_vec(),
+        })
+    }
+}
+
+impl UPSRating {
+    /// Returns the battery pack derived from the rated battery voltage, `None` if it's
+    /// below a single 12 V block.
+    pub fn battery_configuration(&self) -> Option<BatteryConfiguration> {
+        BatteryConfiguration::from_rated_voltage(self.battery_voltage)
+    }
+}
+
+/// Nominal voltage of a 12 V battery block (V).
+const BLOCK_VOLTAGE: f32 = 12.0;
+
+/// Lead-acid cells in a 12 V battery block.
+const CELLS_PER_BLOCK: u32 = 6;
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+/// Battery pack of the UPS, derived from the rated battery voltage reported by the rating
+/// inquiry (e.g. 24 V or 72 V), see [`StatusInquiryResponse::apply_battery_configuration`].
+pub struct BatteryConfiguration {
+    /// V
+    pub rated_voltage: f32,
+    /// 12 V blocks in series.
+    pub blocks: u32,
+    /// Lead-acid cells in series, 6 per block.
+    pub cells: u32,
+}
+
+impl BatteryConfiguration {
+    /// Derives the pack from its rated voltage, rounded to a whole number of 12 V blocks.
+    /// `None` if it's below a single block.
+    pub fn from_rated_voltage(rated_voltage: f32) -> Option<Self> {
+        let blocks = (rated_voltage / BLOCK_VOLTAGE).round();
+
+        (blocks.is_finite() && blocks >= 1.0).then(|| Self {
+            rated_voltage,
+            blocks: blocks as u32,
+            cells: blocks as u32 * CELLS_PER_BLOCK,
+        })
+    }
+}
+
+impl ToBytes for UPSRating {
+    fn to_bytes(&self) -> Vec<u8> {
+        format!(
+            "{:05.1} {:03} {:05.1} {:04.1}",
+            self.output_rating_voltage,
+            self.output_rating_current,
+            self.battery_voltage,
+            self.output_rating_frequency,
+        ).into_bytes()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+/// Result of the last battery self-test.
+pub enum TestResult {
+    Passed,
+    Failed,
+    /// Result not described by the protocol, containing the raw result.
+    Unknown(String),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+/// Contains the result of the last battery self-test.
+pub struct TestResultResponse {
+    pub result: TestResult,
+}
+
+impl FromBytes for TestResultResponse {
+    type Err = crate::Error;
+
+    fn from_bytes(s: &[u8]) -> Result<Self> {
+        let raw = String::from_utf8_lossy(s);
+
+        // The UPS answers with 4 characters, either "  OK" or "fail"
+        let result = match raw.trim() {
+            "OK" => TestResult::Passed,
+            "fail" => TestResult::Failed,
+            _ => TestResult::Unknown(raw.to_string()),
+        };
+
+        Ok(Self { result })
+    }
+}
+
+impl ToBytes for TestResultResponse {
+    fn to_bytes(&self) -> Vec<u8> {
+        match &self.result {
+            TestResult::Passed => b"  OK".to_vec(),
+            TestResult::Failed => b"fail".to_vec(),
+            TestResult::Unknown(raw) => raw.as_bytes().to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+/// A query which failed while taking an [`UpsSnapshot`].
+pub struct SnapshotError {
+    /// Name of the snapshot field the query was for, e.g. `"autonomy"`.
+    pub section: &'static str,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+/// The status of the UPS together with its power info, autonomy and rating,
+/// see [`crate::device::cplus::CPlusInterface::query_snapshot`].
+pub struct UpsSnapshot {
+    pub status: StatusInquiryResponse,
+    /// `None` if the query failed or isn't supported, see `errors`.
+    pub extra_power_info: Option<ExtraPowerInfoResponse>,
+    /// `None` if the query failed or isn't supported, see `errors`.
+    pub autonomy: Option<AutonomyResponse>,
+    /// `None` if the query failed or isn't supported, see `errors`.
+    pub rating: Option<UPSRating>,
+    /// When the queries were started.
+    pub queried_at: std::time::SystemTime,
+    /// The queries for the optional fields which failed.
+    pub errors: Vec<SnapshotError>,
+}
+
+impl UpsSnapshot {
+    pub(crate) fn new(status: StatusInquiryResponse, queried_at: std::time::SystemTime) -> Self {
+        Self { status, extra_power_info: None, autonomy: None, rating: None, queried_at, errors: vec![] }
+    }
+
+    /// Returns the apparent power (VA) of the load from the extra power info, or estimated from
+    /// the rating if it's missing, see [`StatusInquiryResponse::load_va_from_rating`].
+    pub fn apparent_power_va(&self) -> Option<f32> {
+        match &self.extra_power_info {
+            Some(info) => info.apparent_power_va(self.status.output_voltage),
+            None => self.rating.as_ref().map(|rating| self.status.load_va_from_rating(rating)),
+        }
+    }
+
+    /// Returns the power factor of the load, see [`ExtraPowerInfoResponse::power_factor`].
+    pub fn power_factor(&self) -> Option<f32> {
+        self.extra_power_info.as_ref()?.power_factor(self.status.output_voltage)
+    }
+
+    /// Returns the value of a successful query for an optional field, recording the error otherwise.
+    pub(crate) fn section<R>(&mut self, section: &'static str, result: Result<R>) -> Option<R> {
+        result
+            .inspect_err(|e| self.errors.push(SnapshotError { section, message: e.to_string() }))
+            .ok()
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+/// Change of the UPS state between two queries, see [`diff_status`] and [`diff_snapshot`].
+pub enum UpsEvent {
+    /// The utility power failed, the UPS runs on battery.
+    MainsLost,
+    MainsRestored,
+    BatteryLow,
+    BatteryLowCleared,
+    BatteryAbnormal,
+    BatteryAbnormalCleared,
+    /// See [`UPSStatus::bypass_or_transformer_active`].
+    BypassOrTransformerChanged { active: bool },
+    OfflineChanged { offline: bool },
+    TestStarted,
+    TestFinished,
+    ShutdownPending,
+    ShutdownCancelled,
+    BeeperChanged { on: bool },
+    /// The battery capacity dropped below `threshold` %.
+    CapacityBelow { threshold: u32, previous: u32, current: u32 },
+    /// The battery capacity rose to `threshold` % or above.
+    CapacityRecovered { threshold: u32, previous: u32, current: u32 },
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, Hash)]
+/// Kind of an [`UpsEvent`], without its values.
+pub enum UpsEventKind {
+    MainsLost,
+    MainsRestored,
+    BatteryLow,
+    BatteryLowCleared,
+    BatteryAbnormal,
+    BatteryAbnormalCleared,
+    BypassOrTransformerChanged,
+    OfflineChanged,
+    TestStarted,
+    TestFinished,
+    ShutdownPending,
+    ShutdownCancelled,
+    BeeperChanged,
+    CapacityBelow,
+    CapacityRecovered,
+}
+
+impl UpsEvent {
+    pub fn kind(&self) -> UpsEventKind {
+        match self {
+            Self::MainsLost => UpsEventKind::MainsLost,
+            Self::MainsRestored => UpsEventKind::MainsRestored,
+            Self::BatteryLow => UpsEventKind::BatteryLow,
+            Self::BatteryLowCleared => UpsEventKind::BatteryLowCleared,
+            Self::BatteryAbnormal => UpsEventKind::BatteryAbnormal,
+            Self::BatteryAbnormalCleared => UpsEventKind::BatteryAbnormalCleared,
+            Self::BypassOrTransformerChanged { .. } => UpsEventKind::BypassOrTransformerChanged,
+            Self::OfflineChanged { .. } => UpsEventKind::OfflineChanged,
+            Self::TestStarted => UpsEventKind::TestStarted,
+            Self::TestFinished => UpsEventKind::TestFinished,
+            Self::ShutdownPending => UpsEventKind::ShutdownPending,
+            Self::ShutdownCancelled => UpsEventKind::ShutdownCancelled,
+            Self::BeeperChanged { .. } => UpsEventKind::BeeperChanged,
+            Self::CapacityBelow { .. } => UpsEventKind::CapacityBelow,
+            Self::CapacityRecovered { .. } => UpsEventKind::CapacityRecovered,
+        }
+    }
+}
+
+impl std::fmt::Display for UpsEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let on_off = |on: bool| if on { "on" } else { "off" };
+
+        match self {
+            Self::MainsLost => write!(f, "Utility power lost, running on battery"),
+            Self::MainsRestored => write!(f, "Utility power restored"),
+            Self::BatteryLow => write!(f, "Battery low"),
+            Self::BatteryLowCleared => write!(f, "Battery no longer low"),
+            Self::BatteryAbnormal => write!(f, "Battery abnormal"),
+            Self::BatteryAbnormalCleared => write!(f, "Battery back to normal"),
+            Self::BypassOrTransformerChanged { active } => write!(f, "Bypass/transformer turned {}", on_off(*active)),
+            Self::OfflineChanged { offline } => write!(f, "Offline mode turned {}", on_off(*offline)),
+            Self::TestStarted => write!(f, "Battery test started"),
+            Self::TestFinished => write!(f, "Battery test finished"),
+            Self::ShutdownPending => write!(f, "Shutdown pending"),
+            Self::ShutdownCancelled => write!(f, "Shutdown cancelled"),
+            Self::BeeperChanged { on } => write!(f, "Beeper turned {}", on_off(*on)),
+            Self::CapacityBelow { threshold, previous, current } => {
+                write!(f, "Battery capacity dropped below {threshold} % ({previous} % -> {current} %)")
+            }
+            Self::CapacityRecovered { threshold, previous, current } => {
+                write!(f, "Battery capacity recovered to {threshold} % ({previous} % -> {current} %)")
+            }
+        }
+    }
+}
+
+/// Returns the events for the status bits which changed between `prev` and `next`,
+/// in the order of the bits.
+pub fn diff_status(prev: &UPSStatus, next: &UPSStatus) -> Vec<UpsEvent> {
+    let changed = *prev ^ *next;
+
+    UPSStatus::NAMED
+        .into_iter()
+        .filter(|(flag, _)| changed.contains(*flag))
+        .map(|(flag, _)| {
+            let set = next.contains(flag);
+
+            match flag {
+                UPSStatus::UTILITY_FAIL if set => UpsEvent::MainsLost,
+                UPSStatus::UTILITY_FAIL => UpsEvent::MainsRestored,
+                UPSStatus::BATTERY_LOW if set => UpsEvent::BatteryLow,
+                UPSStatus::BATTERY_LOW => UpsEvent::BatteryLowCleared,
+                UPSStatus::BYPASS_OR_TRANSFORMER_ACTIVE => UpsEvent::BypassOrTransformerChanged { active: set },
+                UPSStatus::BATTERY_ABNORMAL if set => UpsEvent::BatteryAbnormal,
+                UPSStatus::BATTERY_ABNORMAL => UpsEvent::BatteryAbnormalCleared,
+                UPSStatus::OFFLINE => UpsEvent::OfflineChanged { offline: set },
+                UPSStatus::TEST_IN_PROGRESS if set => UpsEvent::TestStarted,
+                UPSStatus::TEST_IN_PROGRESS => UpsEvent::TestFinished,
+                UPSStatus::SHUTDOWN_ACTIVE if set => UpsEvent::ShutdownPending,
+                UPSStatus::SHUTDOWN_ACTIVE => UpsEvent::ShutdownCancelled,
+                _ => UpsEvent::BeeperChanged { on: set },
+            }
         })
+        .collect()
+}
+
+/// Returns the events between two snapshots: the status changes (see [`diff_status`]),
+/// followed by the battery capacity crossing any of the `capacity_thresholds` (in %), if both
+/// capacities are known.
+pub fn diff_snapshot(prev: &UpsSnapshot, next: &UpsSnapshot, capacity_thresholds: &[u32]) -> Vec<UpsEvent> {
+    let mut events = diff_status(&prev.status.ups_status, &next.status.ups_status);
+
+    if let (Some(previous), Some(current)) = (prev.status.battery_capacity, next.status.battery_capacity) {
+        events.extend(diff_capacity(previous, current, capacity_thresholds));
+    }
+
+    events
+}
+
+/// Returns the events for the battery capacity crossing any of the `thresholds`.
+pub(crate) fn diff_capacity(previous: u32, current: u32, thresholds: &[u32]) -> impl Iterator<Item = UpsEvent> {
+    thresholds.iter().filter_map(move |&threshold| {
+        if previous >= threshold && current < threshold {
+            Some(UpsEvent::CapacityBelow { threshold, previous, current })
+        } else if previous < threshold && current >= threshold {
+            Some(UpsEvent::CapacityRecovered { threshold, previous, current })
+        } else {
+            None
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(bits: &[u8; 8]) -> UPSStatus {
+        UPSStatus::from_bytes(bits).unwrap()
+    }
+
+    fn snapshot(battery_capacity: u32) -> UpsSnapshot {
+        let mut status = StatusInquiryResponse::from_bytes(b"208.4 140.0 208.4 034 59.9 2.05 35.0 00000000").unwrap();
+        status.battery_capacity = Some(battery_capacity);
+
+        UpsSnapshot::new(status, std::time::SystemTime::UNIX_EPOCH)
+    }
+
+    #[test]
+    fn diff_status_test() {
+        let expected = [
+            (UpsEvent::MainsLost, UpsEvent::MainsRestored),
+            (UpsEvent::BatteryLow, UpsEvent::BatteryLowCleared),
+            (
+                UpsEvent::BypassOrTransformerChanged { active: true },
+                UpsEvent::BypassOrTransformerChanged { active: false },
+            ),
+            (UpsEvent::BatteryAbnormal, UpsEvent::BatteryAbnormalCleared),
+            (UpsEvent::OfflineChanged { offline: true }, UpsEvent::OfflineChanged { offline: false }),
+            (UpsEvent::TestStarted, UpsEvent::TestFinished),
+            (UpsEvent::ShutdownPending, UpsEvent::ShutdownCancelled),
+            (UpsEvent::BeeperChanged { on: true }, UpsEvent::BeeperChanged { on: false }),
+        ];
+
+        let cleared = status(b"00000000");
+
+        for (bit, (set_event, cleared_event)) in expected.into_iter().enumerate() {
+            let mut bits = *b"00000000";
+            *bits.get_mut(bit).unwrap() = b'1';
+            let set = status(&bits);
+
+            assert_eq!(diff_status(&cleared, &set), [set_event], "bit {bit} set");
+            assert_eq!(diff_status(&set, &cleared), [cleared_event], "bit {bit} cleared");
+            assert!(diff_status(&set, &set).is_empty());
+        }
+
+        assert!(diff_status(&cleared, &cleared).is_empty());
+        assert_eq!(
+            diff_status(&status(b"00000000"), &status(b"11000000")),
+            [UpsEvent::MainsLost, UpsEvent::BatteryLow]
+        );
+    }
+
+    #[test]
+    fn diff_snapshot_test() {
+        let thresholds = [50, 20];
+
+        assert_eq!(
+            diff_snapshot(&snapshot(60), &snapshot(15), &thresholds),
+            [
+                UpsEvent::CapacityBelow { threshold: 50, previous: 60, current: 15 },
+                UpsEvent::CapacityBelow { threshold: 20, previous: 60, current: 15 },
+            ]
+        );
+        assert_eq!(
+            diff_snapshot(&snapshot(15), &snapshot(20), &thresholds),
+            [UpsEvent::CapacityRecovered { threshold: 20, previous: 15, current: 20 }]
+        );
+        assert!(diff_snapshot(&snapshot(40), &snapshot(30), &thresholds).is_empty());
+        assert_eq!(
+            UpsEvent::CapacityBelow { threshold: 20, previous: 21, current: 19 }.to_string(),
+            "Battery capacity dropped below 20 % (21 % -> 19 %)"
+        );
     }
-}
\ No newline at end of file
+    #[test]
+    fn number_encoding_test() {
+        let binary = AutonomyResponse::from_bytes(b"\x00\x00\x05\x44").unwrap();
+
+        assert_eq!(binary.time, time::Duration::from_secs(1348));
+        assert_eq!(binary.encoding, NumberEncoding::Binary);
+        assert_eq!(binary.to_bytes(), b"\x00\x00\x05\x44");
+
+        let ascii = AutonomyResponse::from_bytes(b"1348").unwrap();
+
+        assert_eq!(ascii.time, time::Duration::from_secs(1348));
+        assert_eq!(ascii.encoding, NumberEncoding::Ascii);
+        assert_eq!(ascii.to_bytes(), b"1348");
+
+        let battery_life = BatteryLifeResponse::from_bytes(b"87600").unwrap();
+
+        assert_eq!(battery_life.time.as_secs(), 60 * 60 * 87600);
+        assert_eq!(battery_life.encoding, NumberEncoding::Ascii);
+        assert_eq!(BatteryLifeResponse::from_bytes(b"\x00\x01\x56\x99").unwrap().time.as_secs(), 60 * 60 * 87705);
+
+        // Shorter than a binary word, only digits can be parsed
+        assert_eq!(AutonomyResponse::from_bytes(b"7").unwrap().time.as_secs(), 7);
+        assert!(matches!(AutonomyResponse::from_bytes(b"\x05\x44"), Err(Error::InvalidParameterLength(_))));
+        assert!(matches!(AutonomyResponse::from_bytes(b"13 "), Err(Error::InvalidParameterLength(_))));
+        assert!(matches!(AutonomyResponse::from_bytes(b""), Err(Error::InvalidParameterLength(_))));
+        assert!(matches!(AutonomyResponse::from_bytes(b"99999999999"), Err(Error::IntParse(_))));
+    }
+
+    #[test]
+    fn battery_capacity_test() {
+        fn capacity(parameter: &str, offline: bool) -> Option<u32> {
+            let bits = if offline { "00001000" } else { "00000000" };
+            let frame = format!("208.4 140.0 208.4 034 59.9 {parameter} 35.0 {bits}");
+
+            StatusInquiryResponse::from_bytes(frame.as_bytes()).unwrap().battery_capacity
+        }
+
+        // The table points
+        for &(voltage, percent) in ONLINE_CAPACITY_CURVE {
+            assert_eq!(capacity(&format!("{voltage:.2}"), false), Some(percent), "{voltage}");
+        }
+        for &(voltage, percent) in OFFLINE_CAPACITY_CURVE {
+            assert_eq!(capacity(&format!("{voltage:.1}"), true), Some(percent), "{voltage}");
+        }
+        assert_eq!(capacity("13", true), Some(83));
+
+        // Between the points
+        assert_eq!(capacity("2.045", false), Some(62));
+        assert_eq!(capacity("2.215", false), Some(95));
+        assert_eq!(capacity("13.25", true), Some(89));
+        assert_eq!(capacity("13.4", true), Some(95));
+
+        // Outside the table
+        assert_eq!(capacity("1.6", false), Some(0));
+        assert_eq!(capacity("2.35", false), Some(100));
+        assert_eq!(capacity("9.8", true), Some(0));
+        assert_eq!(capacity("14.2", true), Some(100));
+
+        // Not a reading of the battery
+        assert_eq!(capacity("9.99", false), None);
+        assert_eq!(capacity("1.2", true), None);
+        assert_eq!(capacity("abc", false), None);
+        assert_eq!(capacity("NaN", false), None);
+    }
+
+    #[test]
+    fn unknown_battery_capacity_test() {
+        let status = StatusInquiryResponse::from_bytes(b"208.4 140.0 208.4 034 59.9 9.99 35.0 00110000").unwrap();
+
+        assert_eq!(status.battery_capacity, None);
+        assert_eq!(status.battery_capacity_parameter, "9.99");
+        assert_eq!(status.input_voltage, 208.4);
+        assert_eq!(status.input_fault_voltage, 140.0);
+        assert_eq!(status.output_voltage, 208.4);
+        assert_eq!(status.output_load_percentage, 34);
+        assert_eq!(status.input_frequency, 59.9);
+        assert_eq!(status.temperature, 35.0);
+        assert!(status.ups_status.bypass_or_transformer_active() && status.ups_status.battery_abnormal());
+        assert_eq!(status.to_bytes(), b"208.4 140.0 208.4 034 59.9 9.99 35.0 00110000");
+
+        // The thresholds aren't crossed by an unknown capacity
+        let unknown = UpsSnapshot::new(status, std::time::SystemTime::UNIX_EPOCH);
+
+        assert!(
+            diff_snapshot(&snapshot(62), &unknown, &[50, 80])
+                .iter()
+                .all(|event| !matches!(event, UpsEvent::CapacityBelow { .. }))
+        );
+    }
+
+    #[test]
+    fn battery_configuration_test() {
+        let small = UPSRating::from_bytes(b"230.0 004 024.0 50.0").unwrap().battery_configuration().unwrap();
+        let large = UPSRating::from_bytes(b"230.0 008 072.0 50.0").unwrap().battery_configuration().unwrap();
+
+        assert_eq!((small.blocks, small.cells), (2, 12));
+        assert_eq!((large.blocks, large.cells), (6, 36));
+        assert_eq!(BatteryConfiguration::from_rated_voltage(48.0).map(|config| config.cells), Some(24));
+        assert_eq!(BatteryConfiguration::from_rated_voltage(2.0), None);
+
+        let capacity = |parameter: &str, bits: &str, config: &BatteryConfiguration| {
+            let frame = format!("208.4 140.0 208.4 034 59.9 {parameter} 35.0 {bits}");
+            let mut status = StatusInquiryResponse::from_bytes(frame.as_bytes()).unwrap();
+            status.apply_battery_configuration(config);
+            status.battery_capacity
+        };
+
+        // The same voltage per cell
+        assert_eq!(capacity("2.10", "00000000", &small), Some(72));
+        assert_eq!(capacity("2.10", "00000000", &large), Some(72));
+        assert_eq!(capacity("25.2", "10001000", &small), Some(72));
+        assert_eq!(capacity("75.6", "10001000", &large), Some(72));
+        // A single block, as in the protocol
+        assert_eq!(capacity("12.6", "10001000", &large), Some(72));
+        assert_eq!(capacity("99.0", "10001000", &large), None);
+    }
+
+    #[test]
+    fn error_code_test() {
+        let codes = [
+            (0, UpsErrorCode::NoError, "No error"),
+            (3, UpsErrorCode::Unknown(3), "Unknown error (3)"),
+            (517, UpsErrorCode::Unknown(517), "Unknown error (517)"),
+        ];
+
+        for (code, error, text) in codes {
+            assert_eq!(UpsErrorCode::from(code), error);
+            assert_eq!(error.code(), code);
+            assert_eq!(error.to_string(), text);
+        }
+
+        let words = [0u16, 0, 0, 0, 0, 0, 3, 0, 0, 0].iter().flat_map(|word| word.to_be_bytes()).collect::<Vec<_>>();
+        let info = ExtraPowerInfoResponse::from_bytes(&words).unwrap();
+
+        assert_eq!((info.error_code, info.decoded_error()), (3, UpsErrorCode::Unknown(3)));
+    }
+}
+
+#[cfg(test)]
+mod equality_tests {
+    use super::*;
+
+    /// Parses `frame`, then parses the response encoded back.
+    fn reparsed<R: FromBytes<Err = Error> + ToBytes>(frame: &[u8]) -> (R, R) {
+        let response = R::from_bytes(frame).unwrap();
+        let again = R::from_bytes(&response.to_bytes()).unwrap();
+
+        (response, again)
+    }
+
+    #[test]
+    fn clone_eq_test() {
+        let status = StatusInquiryResponse::from_bytes(b"208.4 140.0 208.4 034 59.9 2.05 35.0 00110000").unwrap();
+        let rating = UPSRating::from_bytes(b"230.0 008 072.0 50.0").unwrap();
+        let mut snapshot = UpsSnapshot::new(status.clone(), std::time::SystemTime::UNIX_EPOCH);
+        snapshot.rating = Some(rating.clone());
+
+        assert_eq!(status.clone(), status);
+        assert_eq!(rating.clone(), rating);
+        assert_eq!(snapshot.clone(), snapshot);
+
+        let mut changed = status.clone();
+        changed.ups_status.insert(UPSStatus::UTILITY_FAIL);
+
+        assert_ne!(changed, status);
+        assert_eq!(StatusInquiryResponse::default().ups_status, UPSStatus::empty());
+    }
+
+    #[test]
+    fn reparsed_eq_test() {
+        let (status, again) = reparsed::<StatusInquiryResponse>(b"208.4 140.0 208.4 034 59.9 2.05 35.0 00110000");
+        assert_eq!(status, again);
+
+        let (alarm, again) = reparsed::<AlarmInquiryResponse>(b"10");
+        assert_eq!(alarm, again);
+
+        let words = [500u16, 0, 0, 1390, 950, 533, 0, 33, 0, 0].iter().flat_map(|word| word.to_be_bytes()).collect::<Vec<_>>();
+        let (info, again) = reparsed::<ExtraPowerInfoResponse>(&words);
+        assert_eq!(info, again);
+
+        let (autonomy, again) = reparsed::<AutonomyResponse>(b"0360");
+        assert_eq!(autonomy, again);
+
+        let (info, again) = reparsed::<UPSInformation>(b"Company_Name01 UPS_Model1Version001");
+        assert_eq!(info, again);
+
+        let (rating, again) = reparsed::<UPSRating>(b"230.0 008 072.0 50.0");
+        assert_eq!(rating, again);
+
+        let (result, again) = reparsed::<TestResultResponse>(b"fail");
+        assert_eq!(result, again);
+    }
+
+    /// Serializes `response` to JSON and back.
+    fn round_trip<R: Serialize + for<'de> Deserialize<'de>>(response: &R) -> R {
+        serde_json::from_str(&serde_json::to_string(response).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn serde_round_trip_test() {
+        let words = [500u16, 0, 15, 1390, 950, 533, 3, 33, 0, 0].iter().flat_map(|word| word.to_be_bytes()).collect::<Vec<_>>();
+        let mut status = StatusInquiryResponse::from_bytes(b"208.4 140.0 208.4 034 59.9 9.99 35.0 10110001").unwrap();
+        let mut info = UPSInformation::from_bytes(b"Company_Name01 UPS_Model1Version001").unwrap();
+        let mut rating = UPSRating::from_bytes(b"230.0 008 072.0 50.0").unwrap();
+        let autonomy = AutonomyResponse::from_bytes(&[0, 0, 5, 68]).unwrap();
+        let battery_life = BatteryLifeResponse::from_bytes(&[0, 1, 86, 16]).unwrap();
+
+        // The raw replies aren't serialized
+        status.raw.clear();
+        info.raw.clear();
+        rating.raw.clear();
+
+        assert_eq!(round_trip(&status), status);
+        assert_eq!(round_trip(&status.ups_status), status.ups_status);
+        assert_eq!(round_trip(&AlarmInquiryResponse::from_bytes(b"10").unwrap()), AlarmInquiryResponse::from_bytes(b"10").unwrap());
+        assert_eq!(round_trip(&ExtraPowerInfoResponse::from_bytes(&words).unwrap()), ExtraPowerInfoResponse::from_bytes(&words).unwrap());
+        assert_eq!(round_trip(&autonomy), autonomy);
+        assert_eq!(round_trip(&battery_life), battery_life);
+        assert_eq!(round_trip(&info), info);
+        assert_eq!(round_trip(&rating), rating);
+        assert_eq!(round_trip(&TestResultResponse::from_bytes(b"?!").unwrap()), TestResultResponse::from_bytes(b"?!").unwrap());
+
+        // The durations keep their serialized form
+        assert_eq!(serde_json::to_string(&autonomy).unwrap(), r#"{"time":{"secs":1348,"nanos":0}}"#);
+    }
+}
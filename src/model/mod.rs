@@ -10,10 +10,17 @@ pub trait FromBytes {
         where Self: Sized;
 }
 
+/// Trait for encoding command responses the way the UPS sends them (without the prefix
+/// and the end byte), so that [`FromBytes`] parses them back.
+pub trait ToBytes {
+    /// Converts a struct to bytes.
+    fn to_bytes(&self) -> Vec<u8>;
+}
+
 /// Test values taken directly from the protocol PDF.
 #[cfg(test)]
 mod tests {
-    use crate::model::FromBytes;
+    use crate::model::{FromBytes, ToBytes};
     use super::*;
     use std::{time::Duration};
 
@@ -23,8 +30,68 @@ mod tests {
         let status = cplus::StatusInquiryResponse::from_bytes(cmd_response).unwrap();
 
         assert_eq!(status.temperature, 35.0);
-        assert_eq!(status.battery_capacity, 62);
-        assert!(status.ups_status.battery_abnormal);
+        assert_eq!(status.battery_capacity, Some(62));
+        assert!(status.ups_status.battery_abnormal());
+    }
+
+    #[test]
+    fn status_inquiry_whitespace_test() {
+        let canonical: &[u8] = b"208.4 140.0 208.4 034 59.9 2.05 35.0 00110000";
+        let fields = canonical.split(|b| *b == b' ').collect::<Vec<_>>();
+
+        // Double spaces between each pair of adjacent fields in turn, then everywhere
+        let mut padded = (1..fields.len())
+            .map(|i| fields.split_at(i))
+            .map(|(head, tail)| [head.join(&b' '), tail.join(&b' ')].join(&b"  "[..]))
+            .collect::<Vec<_>>();
+        padded.push(fields.join(&b"  "[..]));
+        padded.push([&b"  "[..], canonical, b" \r"].concat());
+        padded.push([&b" "[..], canonical, b"\r"].concat());
+
+        for frame in padded {
+            let status = cplus::StatusInquiryResponse::from_bytes(&frame).unwrap();
+
+            assert_eq!(status.to_bytes(), canonical, "{}", String::from_utf8_lossy(&frame));
+            assert_eq!(status.output_load_percentage, 34);
+            assert_eq!(status.battery_capacity, Some(62));
+        }
+
+        assert!(matches!(
+            cplus::StatusInquiryResponse::from_bytes(b"208.4 140.0 208.4 034 59.9 2.05 00110000"),
+            Err(crate::Error::InvalidFormat { parser: "StatusInquiryResponse", reason, .. }) if reason == "expected 8 fields, got 7"
+        ));
+        assert!(matches!(
+            cplus::StatusInquiryResponse::from_bytes(b"208.4 140.0 208.4 034 59.9 2.05 35.0 00110000 1"),
+            Err(crate::Error::InvalidFormat { parser: "StatusInquiryResponse", reason, .. }) if reason == "expected 8 fields, got 9"
+        ));
+    }
+
+    #[test]
+    fn invalid_format_test() {
+        let short = cplus::UPSStatus::from_bytes(b"0011").unwrap_err();
+        let long = cplus::StatusInquiryResponse::from_bytes(&[b'0'; 100]).unwrap_err();
+
+        assert_eq!(
+            short.to_string(),
+            "Invalid format of the UPSStatus data (expected 8 bits, got 4): \"0011\" [30 30 31 31]"
+        );
+        assert!(matches!(&long, crate::Error::InvalidFormat { input, .. } if input.len() == crate::MAX_ERROR_INPUT_LEN));
+        assert!(!long.to_string().contains('['));
+    }
+
+    #[test]
+    fn raw_reply_test() {
+        let padded: &[u8] = b" 208.4  140.0 208.4 034 59.9 2.05 35.0 00110000 \r";
+        let status = cplus::StatusInquiryResponse::from_bytes(padded).unwrap();
+        let info = cplus::UPSInformation::from_bytes(b"Company_Name01 UPS_Model1Version001 tail").unwrap();
+        let rating = cplus::UPSRating::from_bytes(b"230.0 008 072.0 50.0").unwrap();
+
+        assert_eq!(status.raw, padded);
+        assert_eq!(info.raw, b"Company_Name01 UPS_Model1Version001 tail");
+        assert_eq!(rating.raw, b"230.0 008 072.0 50.0");
+
+        // Only the parsed fields are serialized
+        assert!(serde_json::to_value(&status).unwrap().get("raw").is_none());
     }
 
     #[test]
@@ -32,10 +99,74 @@ mod tests {
         let ups_status_string = b"00110000";
         let ups = cplus::UPSStatus::from_bytes(ups_status_string).unwrap();
 
-        assert!(!ups.shutdown_active);
-        assert!(ups.bypass_or_transformer_active);
-        assert!(ups.battery_abnormal);
-        assert!(!ups.offline);
+        assert!(!ups.shutdown_active());
+        assert!(ups.bypass_or_transformer_active());
+        assert!(ups.battery_abnormal());
+        assert!(!ups.offline());
+    }
+
+    #[test]
+    fn ups_status_strict_test() {
+        // A frame shifted by one byte
+        assert!(matches!(
+            cplus::UPSStatus::from_bytes(b"0110000("),
+            Err(crate::Error::InvalidStatusBit { position: 7, byte: b'(' })
+        ));
+        assert!(matches!(
+            cplus::UPSStatus::from_bytes(b"001100001"),
+            Err(crate::Error::InvalidFormat { parser: "UPSStatus", input, reason })
+                if input == b"001100001" && reason == "expected 8 bits, got 9"
+        ));
+        assert!(matches!(cplus::UPSStatus::from_bytes(b"0011000"), Err(crate::Error::InvalidFormat { parser: "UPSStatus", .. })));
+
+        let padded = cplus::UPSStatus::from_bytes(b"00110001 ").unwrap();
+        let terminated = cplus::UPSStatus::from_bytes(b"00110001\r").unwrap();
+
+        assert!(padded.battery_abnormal() && padded.beeper_on());
+        assert!(terminated.battery_abnormal() && terminated.beeper_on());
+    }
+
+    #[test]
+    fn ups_status_bits_test() {
+        let ups = cplus::UPSStatus::from_bytes(b"00110000").unwrap();
+
+        assert_eq!(ups.raw(), 0b0011_0000);
+        assert_eq!(ups, cplus::UPSStatus::BYPASS_OR_TRANSFORMER_ACTIVE | cplus::UPSStatus::BATTERY_ABNORMAL);
+        assert_eq!(ups.to_string(), "BYPASS_OR_TRANSFORMER_ACTIVE | BATTERY_ABNORMAL");
+        assert_eq!(format!("{:?}", cplus::UPSStatus::empty()), "UPSStatus((empty))");
+
+        for bits in 0..=u8::MAX {
+            let status = cplus::UPSStatus::from_raw(bits);
+            let parsed = cplus::UPSStatus::from_bytes(&status.to_bytes()).unwrap();
+
+            assert_eq!(parsed.raw(), bits);
+            assert_eq!(parsed.utility_fail(), bits & 0b1000_0000 != 0);
+            assert_eq!(parsed.beeper_on(), bits & 1 != 0);
+        }
+
+        let mut changed = ups;
+        changed.set(cplus::UPSStatus::BATTERY_ABNORMAL, false);
+        changed.toggle(cplus::UPSStatus::BEEPER_ON);
+
+        assert_eq!(ups ^ changed, cplus::UPSStatus::BATTERY_ABNORMAL | cplus::UPSStatus::BEEPER_ON);
+    }
+
+    #[test]
+    fn ups_status_serde_test() {
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Compact {
+            #[serde(with = "cplus::ups_status_bits")]
+            ups_status: cplus::UPSStatus,
+        }
+
+        let ups = cplus::UPSStatus::from_bytes(b"00110001").unwrap();
+        let named = serde_json::to_value(ups).unwrap();
+
+        assert_eq!(named.get("battery_abnormal"), Some(&serde_json::Value::Bool(true)));
+        assert_eq!(named.get("offline"), Some(&serde_json::Value::Bool(false)));
+        assert_eq!(serde_json::to_string(&Compact { ups_status: ups }).unwrap(), r#"{"ups_status":49}"#);
+        assert_eq!(serde_json::from_value::<cplus::UPSStatus>(named).unwrap(), ups);
+        assert_eq!(serde_json::from_str::<Compact>(r#"{"ups_status":49}"#).unwrap().ups_status, ups);
     }
 
     #[test]
@@ -60,9 +191,73 @@ mod tests {
         assert!(xpi.battery_cut_voltage == 9.5);
         assert!(xpi.ups_wattage == 533);
         assert!(xpi.load_current == 3.3);
+        assert_eq!(xpi.raw_words, [500, 0, 0, 1390, 950, 533, 0, 33, 0, 0]);
+        assert_eq!((xpi.charger_current, xpi.heatsink_temperature), (None, None));
         // Not testing for the error as there is no documentation provided for it.
     }
 
+    #[test]
+    fn apparent_power_test() {
+        let words = |wattage: u16, current: u16| {
+            [500u16, 0, 0, 1390, 950, wattage, 0, current, 0, 0].iter().flat_map(|word| word.to_be_bytes()).collect::<Vec<_>>()
+        };
+        let close = |value: Option<f32>, expected: f32| value.is_some_and(|value| (value - expected).abs() < 0.01);
+
+        let status = cplus::StatusInquiryResponse::from_bytes(b"208.4 140.0 208.4 034 59.9 2.05 35.0 00110000").unwrap();
+        let rating = cplus::UPSRating::from_bytes(b"230.0 008 072.0 50.0").unwrap();
+        let xpi = cplus::ExtraPowerInfoResponse::from_bytes(&words(533, 33)).unwrap();
+
+        // 208.4 V * 3.3 A
+        assert!(close(xpi.apparent_power_va(status.output_voltage), 687.72));
+        assert!(close(xpi.power_factor(status.output_voltage), 533.0 / 687.72));
+        // 34 % of 230 V * 8 A
+        assert!(close(Some(status.load_va_from_rating(&rating)), 625.6));
+
+        // No current or voltage
+        let idle = cplus::ExtraPowerInfoResponse::from_bytes(&words(0, 0)).unwrap();
+
+        assert_eq!(idle.apparent_power_va(status.output_voltage), None);
+        assert_eq!(idle.power_factor(status.output_voltage), None);
+        assert_eq!(xpi.apparent_power_va(0.0), None);
+
+        // The wattage rounded above the apparent power
+        let rounded = cplus::ExtraPowerInfoResponse::from_bytes(&words(700, 33)).unwrap();
+
+        assert_eq!(rounded.power_factor(status.output_voltage), Some(1.0));
+
+        // The snapshot falls back to the rating without the extra power info
+        let mut snapshot = cplus::UpsSnapshot::new(status, std::time::SystemTime::UNIX_EPOCH);
+        snapshot.rating = Some(rating);
+
+        assert!(close(snapshot.apparent_power_va(), 625.6));
+        assert_eq!(snapshot.power_factor(), None);
+
+        snapshot.extra_power_info = Some(xpi);
+
+        assert!(close(snapshot.apparent_power_va(), 687.72));
+        assert!(close(snapshot.power_factor(), 533.0 / 687.72));
+    }
+
+    #[test]
+    fn extra_power_info_reserved_test() {
+        let words = [500u16, 40, 385, 1390, 950, 533, 0, 33, 0xfffe, 0x8001];
+        let res = words.iter().flat_map(|word| word.to_be_bytes()).collect::<Vec<_>>();
+
+        let xpi = cplus::ExtraPowerInfoResponse::from_bytes(&res).unwrap();
+
+        assert_eq!(xpi.raw_words, words);
+        assert_eq!(xpi.charger_current, Some(4.0));
+        assert_eq!(xpi.heatsink_temperature, Some(38.5));
+        assert_eq!(xpi.to_bytes(), res);
+
+        // Implausible values
+        let res = [500u16, 9000, 0xffff, 1390, 950, 533, 0, 33, 0, 0].iter().flat_map(|word| word.to_be_bytes()).collect::<Vec<_>>();
+        let xpi = cplus::ExtraPowerInfoResponse::from_bytes(&res).unwrap();
+
+        assert_eq!((xpi.charger_current, xpi.heatsink_temperature), (None, None));
+        assert_eq!(xpi.to_bytes(), res);
+    }
+
     #[test]
     fn running_time_test() {
         let res = &[
@@ -107,4 +302,169 @@ mod tests {
         assert_eq!(rating.battery_voltage, 72.0);
         assert_eq!(rating.output_rating_frequency, 50.0);
     }
+
+    #[test]
+    fn shutdown_delay_test() {
+        assert_eq!(cplus::ShutdownDelay::from_tenths(3).unwrap().to_bytes(), b".3");
+        assert_eq!(cplus::ShutdownDelay::from_minutes(5).unwrap().to_bytes(), b"05");
+        assert_eq!(cplus::ShutdownDelay::from_minutes(10).unwrap().to_bytes(), b"10");
+
+        assert_eq!(cplus::ShutdownDelay::from_tenths(3).unwrap().as_duration(), Duration::from_secs(18));
+
+        assert!(cplus::ShutdownDelay::from_tenths(1).is_err());
+        assert!(cplus::ShutdownDelay::from_tenths(10).is_err());
+        assert!(cplus::ShutdownDelay::from_minutes(0).is_err());
+        assert!(cplus::ShutdownDelay::from_minutes(11).is_err());
+    }
+
+    #[test]
+    fn command_ack_test() {
+        assert_eq!(cplus::CommandAck::from_bytes(b"").unwrap(), cplus::CommandAck::NoReply);
+        assert_eq!(cplus::CommandAck::from_bytes(b"ACK").unwrap(), cplus::CommandAck::Acknowledged);
+        assert_eq!(cplus::CommandAck::from_bytes(b"(ACK").unwrap(), cplus::CommandAck::Acknowledged);
+
+        assert!(matches!(cplus::CommandAck::from_bytes(b"NAK"), Err(crate::Error::CommandRejected)));
+        assert!(matches!(cplus::CommandAck::from_bytes(b"NCK"), Err(crate::Error::CommandRejected)));
+        assert!(matches!(cplus::CommandAck::from_bytes(b"(NAK"), Err(crate::Error::CommandRejected)));
+
+        assert!(matches!(
+            cplus::CommandAck::from_bytes(b"(208.4"),
+            Err(crate::Error::UnexpectedResponse { .. })
+        ));
+    }
+
+    #[test]
+    fn command_encoding_test() {
+        use cplus::Command as _;
+
+        assert_eq!(cplus::StatusInquiry.to_bytes(), b"Q1");
+        assert_eq!(cplus::AlarmInquiry.to_bytes(), b"Q4");
+        assert_eq!(cplus::ExtraPowerInfoInquiry.to_bytes(), b"Q5");
+        assert_eq!(cplus::Autonomy.to_bytes(), b"At");
+        assert_eq!(cplus::BatteryLife.to_bytes(), b"BL");
+        assert_eq!(cplus::InformationInquiry.to_bytes(), b"I");
+        assert_eq!(cplus::RatingInquiry.to_bytes(), b"F");
+        assert_eq!(cplus::CancelShutdown.to_bytes(), b"C");
+        assert_eq!(cplus::SelfTest.to_bytes(), b"T");
+        assert_eq!(cplus::TestUntilBatteryLow.to_bytes(), b"TL");
+        assert_eq!(cplus::CancelTest.to_bytes(), b"CT");
+        assert_eq!(cplus::ToggleBeeper.to_bytes(), b"Q");
+        assert_eq!(cplus::TimedSelfTest::new(7).unwrap().to_bytes(), b"T07");
+
+        let delay = cplus::ShutdownDelay::from_tenths(2).unwrap();
+
+        assert_eq!(cplus::Shutdown { delay }.to_bytes(), b"S.2");
+        assert_eq!(cplus::ShutdownAndRestore::new(delay, 42).unwrap().to_bytes(), b"S.2R0042");
+
+        assert!(cplus::TimedSelfTest::new(0).is_err());
+        assert!(cplus::ShutdownAndRestore::new(delay, 0).is_err());
+    }
+
+    #[test]
+    fn shutdown_schedule_test() {
+        use cplus::Command as _;
+
+        let table: &[(u32, &[u8])] = &[
+            (12, b"S.2"), (18, b"S.3"), (24, b"S.4"), (30, b"S.5"),
+            (36, b"S.6"), (42, b"S.7"), (48, b"S.8"), (54, b"S.9"),
+            (60, b"S01"), (120, b"S02"), (180, b"S03"), (240, b"S04"), (300, b"S05"),
+            (360, b"S06"), (420, b"S07"), (480, b"S08"), (540, b"S09"), (600, b"S10"),
+        ];
+
+        for &(seconds, encoded) in table {
+            let schedule = cplus::ShutdownSchedule::in_seconds(seconds).unwrap();
+
+            assert_eq!(schedule.to_bytes(), encoded, "{seconds} s");
+            assert_eq!(schedule.delay().as_duration(), Duration::from_secs(seconds as u64));
+
+            if seconds % 60 == 0 {
+                let minutes = (seconds / 60) as u8;
+                assert_eq!(cplus::ShutdownSchedule::in_minutes(minutes).unwrap().to_bytes(), encoded);
+            }
+        }
+
+        // Rounding to the nearest legal delay
+        assert_eq!(cplus::ShutdownSchedule::in_seconds(9).unwrap().to_bytes(), b"S.2");
+        assert_eq!(cplus::ShutdownSchedule::in_seconds(20).unwrap().to_bytes(), b"S.3");
+        assert_eq!(cplus::ShutdownSchedule::in_seconds(57).unwrap().to_bytes(), b"S01");
+        assert_eq!(cplus::ShutdownSchedule::in_seconds(89).unwrap().to_bytes(), b"S01");
+        assert_eq!(cplus::ShutdownSchedule::in_seconds(90).unwrap().to_bytes(), b"S02");
+        assert_eq!(cplus::ShutdownSchedule::in_seconds(629).unwrap().to_bytes(), b"S10");
+
+        assert_eq!(
+            cplus::ShutdownSchedule::in_minutes(3).unwrap().restore_after_minutes(120).unwrap().to_bytes(),
+            b"S03R0120"
+        );
+
+        assert!(matches!(cplus::ShutdownSchedule::in_seconds(8), Err(crate::Error::InvalidParameter(_))));
+        assert!(matches!(cplus::ShutdownSchedule::in_seconds(630), Err(crate::Error::InvalidParameter(_))));
+        assert!(matches!(cplus::ShutdownSchedule::in_seconds(u32::MAX), Err(crate::Error::InvalidParameter(_))));
+        assert!(matches!(cplus::ShutdownSchedule::in_minutes(0), Err(crate::Error::InvalidParameter(_))));
+        assert!(cplus::ShutdownSchedule::in_minutes(1).unwrap().restore_after_minutes(10_000).is_err());
+    }
+
+    #[test]
+    fn test_result_test() {
+        let passed = cplus::TestResultResponse::from_bytes(b"  OK").unwrap();
+        let failed = cplus::TestResultResponse::from_bytes(b"fail").unwrap();
+        let unknown = cplus::TestResultResponse::from_bytes(b"  NA").unwrap();
+
+        assert_eq!(passed.result, cplus::TestResult::Passed);
+        assert_eq!(failed.result, cplus::TestResult::Failed);
+        assert_eq!(unknown.result, cplus::TestResult::Unknown("  NA".to_string()));
+    }
+
+    #[test]
+    fn firmware_version_test() {
+        let prefixed = cplus::FirmwareVersion::from_bytes(b"V1.02     ").unwrap();
+        let spaced = cplus::FirmwareVersion::from_bytes(b"Ver 02.16 ").unwrap();
+        let dotted = cplus::FirmwareVersion::from_bytes(b"3.1.24").unwrap();
+        let unknown = cplus::FirmwareVersion::from_bytes(b"Version001").unwrap();
+
+        assert_eq!((prefixed.major, prefixed.minor), (Some(1), Some(2)));
+        assert_eq!(prefixed.raw, "V1.02");
+        assert_eq!((spaced.major, spaced.minor), (Some(2), Some(16)));
+        assert_eq!((dotted.major, dotted.minor), (Some(3), Some(1)));
+        assert_eq!((unknown.major, unknown.minor), (None, None));
+        assert_eq!(unknown.raw, "Version001");
+    }
+
+    #[test]
+    fn ups_information_length_test() {
+        assert!(matches!(
+            cplus::UPSInformation::from_bytes(b""),
+            Err(crate::Error::InvalidLength { expected: 35, received: 0 })
+        ));
+        assert!(matches!(
+            cplus::UPSInformation::from_bytes(b"Company_Na"),
+            Err(crate::Error::InvalidLength { expected: 35, received: 10 })
+        ));
+
+        let exact = cplus::UPSInformation::from_bytes(b"Company_Name01 UPS_Model1Version001").unwrap();
+        let longer = cplus::UPSInformation::from_bytes(b"Company_Name01 UPS_Model1Version001 tail").unwrap();
+
+        assert_eq!(exact.manufacturer_name, "Company_Name01");
+        assert_eq!(exact.model, "UPS_Model1");
+        assert_eq!(exact.version, "Version001");
+        assert_eq!(longer.version, "Version001");
+    }
+
+    #[test]
+    fn to_bytes_round_trip_test() {
+        fn round_trip<T: FromBytes + ToBytes>(bytes: &[u8]) where T::Err: std::fmt::Debug {
+            assert_eq!(T::from_bytes(bytes).unwrap().to_bytes(), bytes, "{}", String::from_utf8_lossy(bytes));
+        }
+
+        round_trip::<cplus::StatusInquiryResponse>(b"208.4 140.0 208.4 034 59.9 2.05 35.0 00110000");
+        round_trip::<cplus::StatusInquiryResponse>(b"230.1 230.1 229.9 012 50.0 13.5 27.5 00001001");
+        round_trip::<cplus::UPSStatus>(b"10100101");
+        round_trip::<cplus::AlarmInquiryResponse>(b"10");
+        round_trip::<cplus::ExtraPowerInfoResponse>(&[1, 244, 0, 0, 0, 0, 5, 110, 3, 182, 2, 21, 0, 7, 0, 33, 0, 0, 0, 0]);
+        round_trip::<cplus::AutonomyResponse>(&[0, 0, 5, 68]);
+        round_trip::<cplus::BatteryLifeResponse>(&[0, 1, 86, 48]);
+        round_trip::<cplus::UPSInformation>(b"Company_Name01 UPS_Model1Version001");
+        round_trip::<cplus::UPSRating>(b"230.0 008 072.0 50.0");
+        round_trip::<cplus::TestResultResponse>(b"  OK");
+        round_trip::<cplus::TestResultResponse>(b"fail");
+    }
 }